@@ -0,0 +1,41 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+/// Split command line into words, supports single and double quotes and backslash escape
+pub fn split(line: &str) -> Result<Vec<String>, &'static str> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (Some(q), ch) if ch == q => quote = None,
+            (Some('\''), ch) => word.get_or_insert_with(String::new).push(ch),
+            (_, '\\') => {
+                let escaped = chars.next().ok_or("Trailing backslash")?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            (Some(_), ch) => word.get_or_insert_with(String::new).push(ch),
+            (None, '\'' | '"') => {
+                quote = Some(ch);
+                word.get_or_insert_with(String::new);
+            }
+            (None, '#') if word.is_none() => break,
+            (None, ch) if ch.is_whitespace() => words.extend(word.take()),
+            (None, ch) => word.get_or_insert_with(String::new).push(ch),
+        }
+    }
+    if quote.is_some() {
+        return Err("Unterminated quote");
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Read lines of script file, or stdin if not specified
+pub fn lines(script: Option<&str>) -> io::Result<Box<dyn Iterator<Item = io::Result<String>>>> {
+    Ok(match script {
+        Some("-") | None => Box::new(io::stdin().lock().lines()),
+        Some(path) => Box::new(BufReader::new(File::open(path)?).lines()),
+    })
+}
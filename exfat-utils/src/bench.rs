@@ -0,0 +1,251 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use exfat::error::{Error, InputError, OperationError};
+use exfat::types::SectorID;
+use exfat::{Directory, ExFAT, File, FileOrDirectory};
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Stats {
+    reads: u64,
+    writes: u64,
+    flushes: u64,
+    read_time: Duration,
+    write_time: Duration,
+    flush_time: Duration,
+}
+
+impl Stats {
+    fn io_time(&self) -> Duration {
+        self.read_time + self.write_time + self.flush_time
+    }
+}
+
+/// Measures time spent in underlying IO
+struct TimedIO<IO> {
+    io: IO,
+    stats: Rc<RefCell<Stats>>,
+}
+
+impl<IO: exfat::io::IO> exfat::io::IO for TimedIO<IO> {
+    type Error = IO::Error;
+    type Block = IO::Block;
+
+    fn set_sector_size_shift(&mut self, shift: u8) -> Result<(), Self::Error> {
+        self.io.set_sector_size_shift(shift)
+    }
+
+    fn read(&mut self, id: SectorID) -> Result<&[Self::Block], Self::Error> {
+        let now = Instant::now();
+        let result = self.io.read(id);
+        let mut stats = self.stats.borrow_mut();
+        stats.reads += 1;
+        stats.read_time += now.elapsed();
+        result
+    }
+
+    fn write(&mut self, id: SectorID, offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+        let now = Instant::now();
+        let result = self.io.write(id, offset, data);
+        let mut stats = self.stats.borrow_mut();
+        stats.writes += 1;
+        stats.write_time += now.elapsed();
+        result
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        let now = Instant::now();
+        let result = self.io.flush();
+        let mut stats = self.stats.borrow_mut();
+        stats.flushes += 1;
+        stats.flush_time += now.elapsed();
+        result
+    }
+
+    fn prefetch(&mut self, id: SectorID, count: u32) -> Result<(), Self::Error> {
+        self.io.prefetch(id, count)
+    }
+
+    fn invalidate(&mut self) -> Result<(), Self::Error> {
+        self.io.invalidate()
+    }
+
+    fn write_many(&mut self, id: SectorID, count: u32, data: &[u8]) -> Result<(), Self::Error> {
+        let now = Instant::now();
+        let result = self.io.write_many(id, count, data);
+        let mut stats = self.stats.borrow_mut();
+        stats.writes += 1;
+        stats.write_time += now.elapsed();
+        result
+    }
+
+    fn read_many(&mut self, id: SectorID, buf: &mut [u8]) -> Result<(), Self::Error> {
+        let now = Instant::now();
+        let result = self.io.read_many(id, buf);
+        let mut stats = self.stats.borrow_mut();
+        stats.reads += 1;
+        stats.read_time += now.elapsed();
+        result
+    }
+}
+
+struct Stage<'a> {
+    stats: &'a Rc<RefCell<Stats>>,
+    start: Stats,
+    now: Instant,
+}
+
+impl<'a> Stage<'a> {
+    fn new(stats: &'a Rc<RefCell<Stats>>) -> Self {
+        Self { stats, start: *stats.borrow(), now: Instant::now() }
+    }
+
+    fn report(self, name: &str, rate: impl FnOnce(f64) -> String) {
+        let elapsed = self.now.elapsed();
+        let stats = *self.stats.borrow();
+        let io_time = stats.io_time() - self.start.io_time();
+        let fs_time = elapsed.saturating_sub(io_time);
+        println!("{}: {}", name, rate(elapsed.as_secs_f64()));
+        println!(
+            "  total {:.3}s, filesystem {:.3}s, io {:.3}s",
+            elapsed.as_secs_f64(),
+            fs_time.as_secs_f64(),
+            io_time.as_secs_f64()
+        );
+        println!(
+            "  io read {} {:.3}s, write {} {:.3}s, flush {} {:.3}s",
+            stats.reads - self.start.reads,
+            (stats.read_time - self.start.read_time).as_secs_f64(),
+            stats.writes - self.start.writes,
+            (stats.write_time - self.start.write_time).as_secs_f64(),
+            stats.flushes - self.start.flushes,
+            (stats.flush_time - self.start.flush_time).as_secs_f64(),
+        );
+    }
+}
+
+fn throughput(size: u64) -> impl FnOnce(f64) -> String {
+    move |secs| format!("{:.2} MiB/s", size as f64 / secs / (1024.0 * 1024.0))
+}
+
+fn rate(count: usize) -> impl FnOnce(f64) -> String {
+    move |secs| format!("{:.1} files/s", count as f64 / secs)
+}
+
+fn open_file<E, IO>(dir: &mut Directory<E, IO>, name: &str) -> Result<File<E, IO>, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let entryset = dir.find(name)?.ok_or(Error::Operation(OperationError::NotFound))?;
+    match dir.open(&entryset)? {
+        FileOrDirectory::File(file) => Ok(file),
+        FileOrDirectory::Directory(_) => Err(OperationError::NotFile.into()),
+    }
+}
+
+pub struct Options<'a> {
+    pub path: &'a str,
+    pub size: u64,
+    pub block_size: usize,
+    pub files: usize,
+}
+
+/// Measure sequential write and read, small file create and delete under scratch directory
+fn run<E, IO>(
+    dir: &mut Directory<E, IO>,
+    stats: &Rc<RefCell<Stats>>,
+    options: &Options,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let buffer: Vec<u8> = (0..options.block_size).map(|i| i as u8).collect();
+    dir.create("sequential", false)?;
+    let mut file = open_file(dir, "sequential")?;
+    let stage = Stage::new(stats);
+    let mut remain = options.size;
+    while remain > 0 {
+        let length = remain.min(buffer.len() as u64) as usize;
+        file.write_all(&buffer[..length])?;
+        remain -= length as u64;
+    }
+    file.sync_all()?;
+    stage.report("Sequential write", throughput(options.size));
+    drop(file);
+
+    let mut file = open_file(dir, "sequential")?;
+    let mut buffer = vec![0u8; options.block_size];
+    let stage = Stage::new(stats);
+    let mut remain = options.size;
+    while remain > 0 {
+        let length = remain.min(buffer.len() as u64) as usize;
+        remain -= file.read(&mut buffer[..length])? as u64;
+    }
+    stage.report("Sequential read", throughput(options.size));
+    drop(file);
+
+    let names: Vec<String> = (0..options.files).map(|i| format!("small-{}", i)).collect();
+    let stage = Stage::new(stats);
+    for name in names.iter() {
+        dir.create(name, false)?;
+        open_file(dir, name)?.write_all(&buffer[..buffer.len().min(512)])?;
+    }
+    stage.report("Small file create", rate(options.files));
+
+    let stage = Stage::new(stats);
+    for name in names.iter() {
+        let entryset = dir.find(name)?.ok_or(Error::Operation(OperationError::NotFound))?;
+        dir.delete(&entryset)?;
+    }
+    stage.report("Small file delete", rate(options.files));
+    Ok(())
+}
+
+/// Create a scratch directory, run benchmarks, then remove the scratch directory
+pub fn bench<E, IO>(io: IO, options: Options) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    if options.block_size == 0 {
+        return Err(InputError::Size.into());
+    }
+    let stats = Rc::new(RefCell::new(Stats::default()));
+    let mut exfat = ExFAT::new(TimedIO { io, stats: stats.clone() })?;
+    let mut root = exfat.root_directory()?;
+    let mut root_dir = root.open()?;
+    let name = options.path.trim().trim_matches('/');
+    if root_dir.find(name)?.is_some() {
+        return Err(OperationError::AlreadyExists.into());
+    }
+    root_dir.create(name, true)?;
+    let entryset = root_dir.find(name)?.unwrap();
+    let mut dir = match root_dir.open(&entryset)? {
+        FileOrDirectory::Directory(dir) => dir,
+        FileOrDirectory::File(_) => unreachable!(),
+    };
+
+    let result = run(&mut dir, &stats, &options);
+    drop(dir);
+    // Scratch directory is removed even if benchmark failed
+    root_dir.delete_recursive(&entryset)?;
+    result?;
+
+    let stats = exfat.stats();
+    println!(
+        "Filesystem: {} sectors read by {} reads, {} written, {} flushes, lookup cache {}/{} hit, \
+         {} clusters allocated, {} released",
+        stats.sectors_read,
+        stats.reads,
+        stats.sectors_written,
+        stats.flushes,
+        stats.cache_hits,
+        stats.cache_hits + stats.cache_misses,
+        stats.allocations,
+        stats.frees
+    );
+    Ok(())
+}
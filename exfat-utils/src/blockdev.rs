@@ -0,0 +1,52 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+
+// Not exported by libc, _IO(0x12, 104) and _IOR(0x12, 114, size_t)
+const BLKSSZGET: u64 = 0x1268;
+const BLKGETSIZE64: u64 = 2 << 30 | (std::mem::size_of::<usize>() as u64) << 16 | 0x1272;
+
+/// Size and logical sector size of block device or file
+#[derive(Copy, Clone, Debug)]
+pub struct Geometry {
+    pub num_bytes: u64,
+    pub sector_size_shift: u8,
+}
+
+/// Open for read and write, block device opened with O_EXCL fails if mounted
+/// or opened exclusively elsewhere
+pub fn open(path: &str, exclusive: bool) -> io::Result<File> {
+    let mut options = File::options();
+    options.read(true).write(true);
+    if exclusive {
+        options.custom_flags(libc::O_EXCL);
+    }
+    options.open(path)
+}
+
+fn ioctl<T: Default>(file: &File, request: u64) -> io::Result<T> {
+    let mut value = T::default();
+    match unsafe { libc::ioctl(file.as_raw_fd(), request as _, &mut value as *mut T) } {
+        0 => Ok(value),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+impl Geometry {
+    pub fn of(file: &File) -> io::Result<Self> {
+        let file_type = file.metadata()?.file_type();
+        if !file_type.is_block_device() {
+            // Metadata length of character device is 0, seek to end instead
+            let num_bytes = (&*file).seek(SeekFrom::End(0))?;
+            return Ok(Self { num_bytes, sector_size_shift: 9 });
+        }
+        let num_bytes: u64 = ioctl(file, BLKGETSIZE64)?;
+        let sector_size: libc::c_int = ioctl(file, BLKSSZGET)?;
+        if !(512..=4096).contains(&sector_size) || sector_size.count_ones() != 1 {
+            let message = format!("Unsupported logical sector size {}", sector_size);
+            return Err(io::Error::new(io::ErrorKind::Unsupported, message));
+        }
+        Ok(Self { num_bytes, sector_size_shift: sector_size.trailing_zeros() as u8 })
+    }
+}
@@ -1,12 +1,17 @@
-use std::io;
 use std::io::Write;
 
-use exfat::error::{Error, OperationError};
-use exfat::{FileOrDirectory, RootDirectory as Root};
+use exfat::error::{Error, InputError, OperationError};
+use exfat::{FileOrDirectory, RootDirectory as Root, SeekFrom};
 
 use crate::filepath::open;
 
-pub fn cat<E, IO>(root: &mut Root<E, IO>, path: &str) -> Result<(), Error<E>>
+pub fn cat<E, IO>(
+    root: &mut Root<E, IO>,
+    path: &str,
+    offset: u64,
+    length: Option<u64>,
+    out: &mut dyn Write,
+) -> Result<(), Error<E>>
 where
     E: std::fmt::Debug,
     IO: exfat::io::IO<Error = E>,
@@ -15,17 +20,24 @@ where
         FileOrDirectory::File(f) => f,
         FileOrDirectory::Directory(_) => return Err(OperationError::NotFile.into()),
     };
-    if file.size() == 0 {
+    if offset > file.size() {
+        return Err(InputError::SeekPosition.into());
+    }
+    let mut remain = (file.size() - offset).min(length.unwrap_or(u64::MAX));
+    if remain == 0 {
         return Ok(());
     }
-    let mut stdout = io::stdout();
-    let mut buf = [0u8; 512];
-    loop {
-        let size = file.read(&mut buf)?;
-        stdout.write_all(&buf[..size]).unwrap();
-        if size < buf.len() {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buf = [0u8; 4096];
+    while remain > 0 {
+        let size = file.read(&mut buf[..remain.min(4096) as usize])?;
+        if size == 0 {
+            break;
+        }
+        if out.write_all(&buf[..size]).is_err() {
             break;
         }
+        remain -= size as u64;
     }
     Ok(())
 }
@@ -0,0 +1,72 @@
+use std::io::{self, Write};
+
+use exfat::error::{CopyError, Error, OperationError};
+use exfat::{File, FileOrDirectory, RootDirectory as Root, SeekFrom};
+use sha2::{Digest, Sha256};
+
+use crate::filepath::open;
+
+/// Passes everything written to a closure
+struct Sink<F>(F);
+
+impl<F: FnMut(&[u8])> Write for Sink<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (self.0)(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Stream file content from specified offset to end
+pub fn stream<E, IO, F>(file: &mut File<E, IO>, offset: u64, f: F) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+    F: FnMut(&[u8]),
+{
+    file.seek(SeekFrom::Start(offset))?;
+    match file.copy_to(&mut Sink(f)) {
+        Ok(_) => Ok(()),
+        Err(CopyError::Stream(_)) => unreachable!(),
+        Err(CopyError::Filesystem(e)) => Err(e),
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Algorithm {
+    SHA256,
+    CRC32,
+}
+
+pub fn checksum<E, IO>(
+    root: &mut Root<E, IO>,
+    path: &str,
+    algorithm: Algorithm,
+    out: &mut dyn Write,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let mut file = match open(root.open()?, path)? {
+        FileOrDirectory::File(f) => f,
+        FileOrDirectory::Directory(_) => return Err(OperationError::NotFile.into()),
+    };
+    let digest = match algorithm {
+        Algorithm::SHA256 => {
+            let mut hasher = Sha256::new();
+            stream(&mut file, 0, |bytes| hasher.update(bytes))?;
+            format!("{:x}", hasher.finalize())
+        }
+        Algorithm::CRC32 => {
+            let mut hasher = crc32fast::Hasher::new();
+            stream(&mut file, 0, |bytes| hasher.update(bytes))?;
+            format!("{:08x}", hasher.finalize())
+        }
+    };
+    writeln!(out, "{}  {}", digest, path).ok();
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use exfat::error::{Error, OperationError};
+use exfat::{Directory, File, FileOrDirectory, RootDirectory as Root};
+
+use crate::filepath::{directory, open};
+
+fn copy_file<E, IO>(
+    source: &mut File<E, IO>,
+    dir: &mut Directory<E, IO>,
+    name: &str,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    dir.create(name, false)?;
+    let entryset = dir.find(name)?.unwrap();
+    let mut file = match dir.open(&entryset)? {
+        FileOrDirectory::File(f) => f,
+        FileOrDirectory::Directory(_) => unreachable!(),
+    };
+    // Reading at end of file is an error, so stop by remaining size
+    let mut remain = source.size();
+    let mut buf = [0u8; 4096];
+    while remain > 0 {
+        let size = source.read(&mut buf)?;
+        if size == 0 {
+            break;
+        }
+        file.write_all(&buf[..size])?;
+        remain -= size as u64;
+    }
+    Ok(())
+}
+
+fn copy_directory<E, IO>(
+    source: &mut Directory<E, IO>,
+    dir: &mut Directory<E, IO>,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let mut entrysets = Vec::new();
+    source.walk(|entryset| {
+        if entryset.in_use() {
+            entrysets.push(entryset.clone());
+        }
+        false
+    })?;
+    for entryset in entrysets.iter() {
+        let name = entryset.name();
+        match source.open(entryset)? {
+            FileOrDirectory::File(mut file) => copy_file(&mut file, dir, name)?,
+            FileOrDirectory::Directory(mut subdir) => {
+                dir.create(name, true)?;
+                let entryset = dir.find(name)?.unwrap();
+                copy_directory(&mut subdir, &mut directory(dir.open(&entryset)?)?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn copy<E, IO>(
+    root: &mut Root<E, IO>,
+    source: &str,
+    target: &str,
+    recursive: bool,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let source = source.trim().trim_matches('/');
+    let source_name = source.rsplit_once('/').map(|(_, name)| name).unwrap_or(source);
+    let mut target = target.trim().trim_matches('/').to_owned();
+    // Copying to an existing directory keeps name of source
+    match open(root.open()?, &target) {
+        Ok(FileOrDirectory::Directory(_)) => {
+            target = format!("{}/{}", target, source_name).trim_matches('/').to_owned()
+        }
+        Ok(FileOrDirectory::File(_)) => return Err(OperationError::AlreadyExists.into()),
+        Err(Error::Operation(OperationError::NotFound)) => (),
+        Err(e) => return Err(e),
+    }
+    let (target_base, target_name) = target.rsplit_once('/').unwrap_or(("", &target));
+
+    match open(root.open()?, source)? {
+        FileOrDirectory::File(mut file) => {
+            let mut dir = directory(open(root.open()?, target_base)?)?;
+            if dir.find(target_name)?.is_some() {
+                return Err(OperationError::AlreadyExists.into());
+            }
+            copy_file(&mut file, &mut dir, target_name)
+        }
+        FileOrDirectory::Directory(_) if !recursive => Err(OperationError::NotFile.into()),
+        FileOrDirectory::Directory(mut source_dir) => {
+            // Target inside source would be copied endlessly
+            let prefix = format!("{}/", source.to_lowercase());
+            if source == "" || target.to_lowercase().starts_with(&prefix) {
+                return Err(OperationError::AlreadyOpen.into());
+            }
+            let mut dir = directory(open(root.open()?, target_base)?)?;
+            if dir.find(target_name)?.is_some() {
+                return Err(OperationError::AlreadyExists.into());
+            }
+            dir.create(target_name, true)?;
+            let entryset = dir.find(target_name)?.unwrap();
+            copy_directory(&mut source_dir, &mut directory(dir.open(&entryset)?)?)
+        }
+    }
+}
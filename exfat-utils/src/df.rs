@@ -0,0 +1,57 @@
+use std::io::Write;
+
+use exfat::error::Error;
+use exfat::volume::BootSectorInfo;
+use exfat::RootDirectory as Root;
+
+use super::du::human_readable;
+
+/// In bytes
+#[derive(serde::Serialize)]
+struct Usage {
+    label: String,
+    cluster_size: u64,
+    size: u64,
+    used: u64,
+    available: u64,
+}
+
+pub fn df<E, IO>(
+    root: &mut Root<E, IO>,
+    boot_sector: &BootSectorInfo,
+    human: bool,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let shift = boot_sector.bytes_per_sector_shift + boot_sector.sectors_per_cluster_shift;
+    let cluster_size = 1u64 << shift;
+    let mut available = 0;
+    for extent in root.free_extents() {
+        available += (extent?.num_clusters as u64) << shift;
+    }
+    let size = (boot_sector.cluster_count as u64) << shift;
+    let label = root.volumn_label().unwrap_or("").to_owned();
+    let usage = Usage { label, cluster_size, size, used: size - available, available };
+    if json {
+        writeln!(out, "{}", serde_json::to_string(&usage).unwrap()).ok();
+        return Ok(());
+    }
+    let format = |size| if human { human_readable(size) } else { size.to_string() };
+    let percent = (usage.used * 100).checked_div(size).unwrap_or(0);
+    writeln!(out, "{:>10} {:>10} {:>10} {:>5}  LABEL", "SIZE", "USED", "AVAILABLE", "USE%").ok();
+    writeln!(
+        out,
+        "{:>10} {:>10} {:>10} {:>4}%  {}",
+        format(size),
+        format(usage.used),
+        format(available),
+        percent,
+        usage.label
+    )
+    .ok();
+    Ok(())
+}
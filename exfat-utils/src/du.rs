@@ -0,0 +1,98 @@
+use std::io::Write;
+
+use exfat::error::Error;
+use exfat::{Directory, RootDirectory as Root};
+
+use crate::filepath::{directory, open};
+
+#[derive(Default, serde::Serialize)]
+pub struct Usage {
+    path: String,
+    /// Capacity allocated, counted by cluster
+    allocated: u64,
+    /// Logical size, i.e. valid data length
+    size: u64,
+}
+
+fn usage<E, IO>(
+    dir: &mut Directory<E, IO>,
+    path: &str,
+    allocated: u64,
+    usages: &mut Vec<Usage>,
+) -> Result<Usage, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let mut entrysets = Vec::new();
+    dir.walk(|entryset| {
+        if entryset.in_use() {
+            entrysets.push(entryset.clone());
+        }
+        false
+    })?;
+    // Allocation of directory itself counted as well
+    let mut total = Usage { path: path.to_owned(), allocated, size: 0 };
+    for entryset in entrysets.iter() {
+        if !entryset.is_dir() {
+            total.allocated += entryset.data_length();
+            total.size += entryset.valid_data_length();
+            continue;
+        }
+        let path = format!("{}/{}", path, entryset.name());
+        let mut subdir = directory(dir.open(entryset)?)?;
+        let subtree = usage(&mut subdir, &path, entryset.data_length(), usages)?;
+        total.allocated += subtree.allocated;
+        total.size += subtree.size;
+        usages.push(subtree);
+    }
+    Ok(total)
+}
+
+pub fn human_readable(size: u64) -> String {
+    let units = ["", "K", "M", "G", "T"];
+    let mut value = size as f64;
+    let mut index = 0;
+    while value >= 1024.0 && index < units.len() - 1 {
+        value /= 1024.0;
+        index += 1;
+    }
+    match index {
+        0 => format!("{}", size),
+        _ => format!("{:.1}{}", value, units[index]),
+    }
+}
+
+pub fn du<E, IO>(
+    root: &mut Root<E, IO>,
+    path: &str,
+    summarize: bool,
+    human: bool,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let mut dir = directory(open(root.open()?, path)?)?;
+    let path = path.trim().trim_end_matches('/');
+    let mut usages = Vec::new();
+    let allocated = dir.capacity()?;
+    let total = usage(&mut dir, path, allocated, &mut usages)?;
+    if summarize {
+        usages.clear();
+    }
+    usages.push(Usage { path: if path == "" { "/".into() } else { path.into() }, ..total });
+    if json {
+        writeln!(out, "{}", serde_json::to_string(&usages).unwrap()).ok();
+        return Ok(());
+    }
+    let format = |size| if human { human_readable(size) } else { size.to_string() };
+    writeln!(out, "{:>10} {:>10}  {}", "ALLOCATED", "SIZE", "PATH").ok();
+    for usage in usages.iter() {
+        writeln!(out, "{:>10} {:>10}  {}", format(usage.allocated), format(usage.size), usage.path)
+            .ok();
+    }
+    Ok(())
+}
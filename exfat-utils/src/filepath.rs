@@ -1,6 +1,7 @@
 use exfat::error::{Error, OperationError};
 use exfat::Directory as Dir;
 use exfat::FileOrDirectory as FileOrDir;
+use exfat::RootDirectory as Root;
 
 const NOT_FOUND: OperationError = OperationError::NotFound;
 
@@ -26,3 +27,86 @@ where
     let entryset = dir.find(name)?.ok_or(Error::Operation(NOT_FOUND))?;
     dir.open(&entryset)
 }
+
+pub fn directory<E, IO>(file_or_dir: FileOrDir<E, IO>) -> Result<Dir<E, IO>, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    match file_or_dir {
+        FileOrDir::Directory(dir) => Ok(dir),
+        FileOrDir::File(_) => Err(OperationError::NotDirectory.into()),
+    }
+}
+
+fn has_wildcard(path: &str) -> bool {
+    path.contains(['*', '?'])
+}
+
+fn expand_components<E, IO>(
+    dir: &mut Dir<E, IO>,
+    prefix: &str,
+    components: &[&str],
+    paths: &mut Vec<String>,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let (component, rest) = match components.split_first() {
+        Some((&component, rest)) => (component, rest),
+        None => return Ok(()),
+    };
+    let mut entrysets = Vec::new();
+    if component == "**" {
+        // Matches zero or more directories, trailing one matches everything below
+        let rest = if rest.is_empty() { &["*"][..] } else { rest };
+        expand_components(dir, prefix, rest, paths)?;
+        dir.walk(|entryset| {
+            if entryset.in_use() && entryset.is_dir() {
+                entrysets.push(entryset.clone());
+            }
+            false
+        })?;
+        for entryset in entrysets.iter() {
+            let prefix = format!("{}/{}", prefix, entryset.name());
+            let mut subdir = directory(dir.open(entryset)?)?;
+            expand_components(&mut subdir, &prefix, components, paths)?;
+        }
+        return Ok(());
+    }
+    if has_wildcard(component) {
+        dir.find_matching(component, |entryset| {
+            entrysets.push(entryset.clone());
+            false
+        })?;
+    } else if let Some(entryset) = dir.find(component)? {
+        entrysets.push(entryset);
+    }
+    for entryset in entrysets.iter() {
+        let path = format!("{}/{}", prefix, entryset.name());
+        if rest.is_empty() {
+            paths.push(path);
+        } else if entryset.is_dir() {
+            let mut subdir = directory(dir.open(entryset)?)?;
+            expand_components(&mut subdir, &path, rest, paths)?;
+        }
+    }
+    Ok(())
+}
+
+/// Expand `*`, `?` and `**` wildcards in path against image,
+/// path without wildcard is returned as is
+pub fn expand<E, IO>(root: &mut Root<E, IO>, path: &str) -> Result<Vec<String>, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    if !has_wildcard(path) {
+        return Ok(vec![path.to_owned()]);
+    }
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    let mut paths = Vec::new();
+    expand_components(&mut root.open()?, "", &components, &mut paths)?;
+    Ok(paths)
+}
@@ -0,0 +1,107 @@
+use std::io::Write;
+
+use chrono::{DateTime, Local};
+use exfat::error::Error;
+use exfat::{Directory, EntrySet, RootDirectory as Root};
+
+use crate::filepath::{directory, open};
+use crate::list::Entry;
+
+#[derive(Default)]
+pub struct Filter<'a> {
+    pub name: Option<&'a str>,
+    pub directory: Option<bool>,
+    pub newer_than: Option<DateTime<Local>>,
+    pub older_than: Option<DateTime<Local>>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+}
+
+impl<'a> Filter<'a> {
+    /// Name is filtered by directory pattern matching instead
+    fn matches(&self, entryset: &EntrySet) -> bool {
+        if self.directory.map(|directory| directory != entryset.is_dir()).unwrap_or(false) {
+            return false;
+        }
+        let size = entryset.valid_data_length();
+        if self.min_size.map(|min| size < min).unwrap_or(false) {
+            return false;
+        }
+        if self.max_size.map(|max| size > max).unwrap_or(false) {
+            return false;
+        }
+        if self.newer_than.is_none() && self.older_than.is_none() {
+            return true;
+        }
+        let modified = match entryset.modified().localtime() {
+            Ok(modified) => modified,
+            Err(_) => return false,
+        };
+        if self.newer_than.map(|newer| modified <= newer).unwrap_or(false) {
+            return false;
+        }
+        !self.older_than.map(|older| modified >= older).unwrap_or(false)
+    }
+}
+
+fn find_directory<E, IO>(
+    dir: &mut Directory<E, IO>,
+    path: &str,
+    filter: &Filter,
+    entries: &mut Vec<Entry>,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let mut matches = Vec::new();
+    match filter.name {
+        Some(pattern) => dir.find_matching(pattern, |entryset| {
+            if filter.matches(entryset) {
+                matches.push(entryset.clone());
+            }
+            false
+        })?,
+        None => dir.walk(|entryset| {
+            if entryset.in_use() && filter.matches(entryset) {
+                matches.push(entryset.clone());
+            }
+            false
+        })?,
+    };
+    entries.extend(matches.iter().map(|entryset| Entry::new(path, entryset)));
+
+    let mut subdirs = Vec::new();
+    dir.walk(|entryset| {
+        if entryset.in_use() && entryset.is_dir() {
+            subdirs.push(entryset.clone());
+        }
+        false
+    })?;
+    for entryset in subdirs.iter() {
+        let path = format!("{}/{}", path, entryset.name());
+        find_directory(&mut directory(dir.open(entryset)?)?, &path, filter, entries)?;
+    }
+    Ok(())
+}
+
+pub fn find<E, IO>(
+    root: &mut Root<E, IO>,
+    path: &str,
+    filter: &Filter,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let mut dir = directory(open(root.open()?, path)?)?;
+    let mut entries = Vec::new();
+    find_directory(&mut dir, path.trim().trim_end_matches('/'), filter, &mut entries)?;
+    match json {
+        true => writeln!(out, "{}", serde_json::to_string(&entries).unwrap()).ok(),
+        false => entries.iter().try_for_each(|entry| writeln!(out, "{}", entry.path)).ok(),
+    };
+    Ok(())
+}
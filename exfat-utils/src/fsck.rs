@@ -0,0 +1,40 @@
+use std::io::Write;
+
+use exfat::error::Error;
+use exfat::RootDirectory as Root;
+
+/// Returns false if file system inconsistent
+pub fn fsck<E, IO>(
+    root: &mut Root<E, IO>,
+    repair: bool,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<bool, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let report = root.check(repair)?;
+    if json {
+        let mut value = serde_json::to_value(&report).unwrap();
+        value["consistent"] = report.is_consistent().into();
+        value["repaired"] = (repair && !report.is_consistent()).into();
+        writeln!(out, "{}", value).ok();
+        return Ok(report.is_consistent());
+    }
+    let mismatch = if report.upcase_table_checksum { "mismatch" } else { "ok" };
+    writeln!(out, "Upcase table checksum: {}", mismatch).ok();
+    writeln!(out, "Entryset checksum mismatches: {}", report.checksum_mismatches).ok();
+    writeln!(out, "Bad cluster chains: {}", report.bad_chains).ok();
+    writeln!(out, "Cross linked clusters: {}", report.cross_linked_clusters).ok();
+    writeln!(out, "Orphan clusters: {}", report.orphan_clusters).ok();
+    writeln!(out, "Unallocated clusters in use: {}", report.unallocated_clusters).ok();
+    if report.is_consistent() {
+        writeln!(out, "File system is consistent").ok();
+    } else if repair {
+        writeln!(out, "File system was inconsistent, repaired where possible").ok();
+    } else {
+        writeln!(out, "File system is inconsistent").ok();
+    }
+    Ok(report.is_consistent())
+}
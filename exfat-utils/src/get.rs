@@ -0,0 +1,89 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use exfat::error::{CopyError, Error, OperationError};
+use exfat::{Directory, EntrySet, File, FileOrDirectory, RootDirectory as Root};
+
+use crate::filepath::{directory, open};
+
+fn get_file<E, IO>(file: &mut File<E, IO>, host_path: &Path) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let mut host_file = fs::File::create(host_path).expect("Unable to create");
+    match file.copy_to(&mut host_file) {
+        Ok(_) => Ok(()),
+        Err(CopyError::Stream(e)) => panic!("Unable to write: {}", e),
+        Err(CopyError::Filesystem(e)) => Err(e),
+    }
+}
+
+fn get_directory<E, IO>(dir: &mut Directory<E, IO>, host_path: &Path) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    fs::create_dir_all(host_path).expect("Unable to create directory");
+    let mut entrysets = Vec::new();
+    dir.walk(|entryset| {
+        if entryset.in_use() {
+            entrysets.push(entryset.clone());
+        }
+        false
+    })?;
+    for entryset in entrysets.iter() {
+        get_entry(dir, entryset, &host_path.join(entryset.name()), true)?;
+    }
+    Ok(())
+}
+
+fn get_entry<E, IO>(
+    dir: &mut Directory<E, IO>,
+    entryset: &EntrySet,
+    host_path: &Path,
+    recursive: bool,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    match dir.open(entryset)? {
+        FileOrDirectory::File(mut file) => get_file(&mut file, host_path)?,
+        FileOrDirectory::Directory(_) if !recursive => return Err(OperationError::NotFile.into()),
+        FileOrDirectory::Directory(mut subdir) => get_directory(&mut subdir, host_path)?,
+    }
+    if let Ok(datetime) = entryset.modified().localtime() {
+        let host_file = fs::File::open(host_path).expect("Unable to open");
+        host_file.set_modified(datetime.into()).expect("Unable to set modification time");
+    }
+    Ok(())
+}
+
+pub fn get<E, IO>(
+    root: &mut Root<E, IO>,
+    path: &str,
+    host_path: &str,
+    recursive: bool,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let path = path.trim().trim_matches('/');
+    if path == "" {
+        if !recursive {
+            return Err(OperationError::NotFile.into());
+        }
+        return get_directory(&mut root.open()?, Path::new(host_path));
+    }
+    let (base, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let mut dir = directory(open(root.open()?, base)?)?;
+    let entryset = dir.find(name)?.ok_or(Error::Operation(OperationError::NotFound))?;
+    // Getting into an existing host directory keeps name of source
+    let mut host_path = PathBuf::from(host_path);
+    if host_path.is_dir() {
+        host_path.push(entryset.name());
+    }
+    get_entry(&mut dir, &entryset, &host_path, recursive)
+}
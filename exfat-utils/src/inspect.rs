@@ -0,0 +1,179 @@
+use exfat::error::{Error, OperationError};
+use exfat::types::SectorID;
+use exfat::volume::BootSectorInfo;
+use exfat::ExFAT;
+use pretty_hex::{config_hex, HexConfig};
+
+use crate::filepath::{directory, open};
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_sector<E, IO>(io: &mut IO, sector_id: SectorID) -> Result<Vec<u8>, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let sector = io.read(sector_id).map_err(|e| Error::IO(e))?;
+    Ok(exfat::io::flatten(sector).to_vec())
+}
+
+fn print_boot_sector(boot_sector: &BootSectorInfo) {
+    println!("{:>24}: {:02X?}", "Jump boot", boot_sector.jump_boot);
+    let name = String::from_utf8_lossy(&boot_sector.filesystem_name);
+    println!("{:>24}: {:?}", "File system name", name);
+    println!("{:>24}: {}", "Partition offset", boot_sector.partition_offset);
+    println!("{:>24}: {}", "Volume length", boot_sector.volume_length);
+    println!("{:>24}: {}", "FAT offset", boot_sector.fat_offset);
+    println!("{:>24}: {}", "FAT length", boot_sector.fat_length);
+    println!("{:>24}: {}", "Cluster heap offset", boot_sector.cluster_heap_offset);
+    println!("{:>24}: {}", "Cluster count", boot_sector.cluster_count);
+    println!("{:>24}: {}", "Root directory cluster", boot_sector.first_cluster_of_root_directory);
+    let serial_number = boot_sector.volume_serial_number;
+    println!(
+        "{:>24}: {:04X}-{:04X}",
+        "Volume serial number",
+        serial_number >> 16,
+        serial_number & 0xFFFF
+    );
+    let (major, minor) = boot_sector.filesystem_revision;
+    println!("{:>24}: {}.{:02}", "File system revision", major, minor);
+    println!("{:>24}: {:#06x}", "Volume flags", boot_sector.volume_flags);
+    println!("{:>24}: {}", "Bytes per sector shift", boot_sector.bytes_per_sector_shift);
+    println!("{:>24}: {}", "Sectors per cluster shift", boot_sector.sectors_per_cluster_shift);
+    println!("{:>24}: {}", "Number of FATs", boot_sector.number_of_fats);
+    println!("{:>24}: {:#04x}", "Drive select", boot_sector.drive_select);
+    println!("{:>24}: {}", "Percent in use", boot_sector.percent_inuse);
+    println!("{:>24}: {:02X?}", "Boot signature", boot_sector.boot_signature);
+}
+
+fn utf16_name(bytes: &[u8]) -> String {
+    let units = bytes.chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).take_while(|&c| c != 0);
+    char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+}
+
+fn print_entry(index: usize, entry: &[u8; 32]) {
+    let in_use = if entry[0] & 0x80 > 0 { "in use" } else { "not in use" };
+    let entry_type = match entry[0] | 0x80 {
+        0x81 => "Allocation bitmap",
+        0x82 => "Upcase table",
+        0x83 => "Volumn label",
+        0x85 => "File directory",
+        0xA0 => "Volumn GUID",
+        0xC0 => "Stream extension",
+        0xC1 => "Filename",
+        0xE0 => "Vendor extension",
+        0xE1 => "Vendor allocation",
+        _ => "Unknown",
+    };
+    println!("Entry {}: {} ({}, {:#04x})", index, entry_type, in_use, entry[0]);
+    let config = HexConfig { title: false, ..Default::default() };
+    println!("{}", config_hex(&entry, config));
+    match entry[0] | 0x80 {
+        0x85 => {
+            println!("{:>20}: {}", "Secondary count", entry[1]);
+            println!("{:>20}: {:#06x}", "Set checksum", u16_at(entry, 2));
+            println!("{:>20}: {:#06x}", "File attributes", u16_at(entry, 4));
+            println!("{:>20}: {:#010x}", "Create timestamp", u32_at(entry, 8));
+            println!("{:>20}: {:#010x}", "Modified timestamp", u32_at(entry, 12));
+            println!("{:>20}: {:#010x}", "Accessed timestamp", u32_at(entry, 16));
+            println!("{:>20}: {} {}", "10ms increment", entry[20], entry[21]);
+            println!(
+                "{:>20}: {:#04x} {:#04x} {:#04x}",
+                "UTC offset", entry[22], entry[23], entry[24]
+            );
+        }
+        0xC0 => {
+            println!("{:>20}: {:#04x}", "Flags", entry[1]);
+            println!("{:>20}: {}", "Name length", entry[3]);
+            println!("{:>20}: {:#06x}", "Name hash", u16_at(entry, 4));
+            println!("{:>20}: {}", "Valid data length", u64_at(entry, 8));
+            println!("{:>20}: {}", "First cluster", u32_at(entry, 20));
+            println!("{:>20}: {}", "Data length", u64_at(entry, 24));
+        }
+        0xC1 => println!("{:>20}: {:?}", "Name", utf16_name(&entry[2..])),
+        _ => (),
+    }
+}
+
+fn print_entries<E, IO>(io: IO, path: &str) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let path = path.trim().trim_matches('/');
+    if path == "" {
+        return Err(OperationError::NotFound.into());
+    }
+    let mut exfat = ExFAT::new(io)?;
+    let mut root = exfat.root_directory()?;
+    let (base, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let mut dir = directory(open(root.open()?, base)?)?;
+    let entryset = dir.find(name)?.ok_or(Error::Operation(OperationError::NotFound))?;
+    let (sector_id, index) = dir.entry_location(&entryset);
+    println!("Entryset of {} at sector {} index {}", entryset.name(), sector_id, index);
+    for (index, entry) in dir.raw_entries(&entryset)?.iter().enumerate() {
+        print_entry(index, entry);
+    }
+    Ok(())
+}
+
+pub struct Options<'a> {
+    pub boot: bool,
+    pub sector: Option<u64>,
+    pub cluster: Option<u32>,
+    pub count: u64,
+    pub path: Option<&'a str>,
+}
+
+pub fn inspect<E, IO>(mut io: IO, options: Options) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let sector = read_sector(&mut io, 0.into())?;
+    let boot_sector = BootSectorInfo::decode(sector[..512].try_into().unwrap());
+    if options.boot {
+        print_boot_sector(&boot_sector);
+    }
+    let (heap_offset, sector_size_shift, sectors_per_cluster_shift) = match boot_sector.is_exfat() {
+        true => (
+            boot_sector.cluster_heap_offset as u64,
+            boot_sector.bytes_per_sector_shift,
+            boot_sector.sectors_per_cluster_shift,
+        ),
+        false => (0, 9, 0),
+    };
+    io.set_sector_size_shift(sector_size_shift).map_err(|e| Error::IO(e))?;
+    let range = match (options.sector, options.cluster) {
+        (Some(sector), _) => Some((sector, options.count)),
+        (None, Some(cluster)) if cluster >= 2 && boot_sector.is_exfat() => {
+            let sector = heap_offset + ((cluster as u64 - 2) << sectors_per_cluster_shift);
+            Some((sector, options.count << sectors_per_cluster_shift))
+        }
+        (None, Some(_)) => return Err(OperationError::NotFound.into()),
+        (None, None) => None,
+    };
+    if let Some((first, count)) = range {
+        for sector_id in first..first + count {
+            println!("Sector {}:", sector_id);
+            println!(
+                "{}",
+                config_hex(&read_sector(&mut io, sector_id.into())?, HexConfig::default())
+            );
+        }
+    }
+    if let Some(path) = options.path {
+        print_entries(io, path)?;
+    }
+    Ok(())
+}
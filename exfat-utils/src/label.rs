@@ -0,0 +1,27 @@
+use std::io::Write;
+
+use exfat::error::Error;
+use exfat::RootDirectory as Root;
+
+pub fn label<E, IO>(
+    root: &mut Root<E, IO>,
+    serial_number: u32,
+    label: Option<&str>,
+    serial: bool,
+    out: &mut dyn Write,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    match label {
+        Some(label) => root.set_volumn_label(label)?,
+        None => {
+            writeln!(out, "{}", root.volumn_label().unwrap_or("")).ok();
+        }
+    }
+    if serial {
+        writeln!(out, "{:04X}-{:04X}", serial_number >> 16, serial_number & 0xFFFF).ok();
+    }
+    Ok(())
+}
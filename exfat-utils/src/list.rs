@@ -1,37 +1,197 @@
+use std::io::Write;
+
 use exfat::error::{Error, OperationError};
-use exfat::{FileOrDirectory, RootDirectory as Root};
+use exfat::{Directory, EntrySet, FileOrDirectory, RootDirectory as Root};
+
+use super::filepath::{directory, open};
+use super::stat::{attributes, rfc3339};
 
-use super::filepath::open;
+#[derive(serde::Serialize)]
+pub struct Entry {
+    pub path: String,
+    name: String,
+    #[serde(rename = "type")]
+    type_: &'static str,
+    attributes: Vec<&'static str>,
+    size: u64,
+    modified: String,
+}
 
-pub fn list<E, IO>(root: &mut Root<E, IO>, path: &str) -> Result<(), Error<E>>
+impl Entry {
+    pub fn new(path: &str, entryset: &EntrySet) -> Self {
+        Self {
+            path: format!("{}/{}", path, entryset.name()),
+            name: entryset.name().to_owned(),
+            type_: if entryset.is_dir() { "directory" } else { "file" },
+            attributes: attributes(entryset),
+            size: entryset.valid_data_length(),
+            modified: rfc3339(entryset.modified()),
+        }
+    }
+}
+
+fn entrysets<E, IO>(directory: &mut Directory<E, IO>) -> Result<Vec<EntrySet>, Error<E>>
 where
     E: std::fmt::Debug,
     IO: exfat::io::IO<Error = E>,
 {
-    let mut directory = match open(root.open()?, &path)? {
-        FileOrDirectory::File(_) => return Err(OperationError::NotDirectory.into()),
-        FileOrDirectory::Directory(dir) => dir,
-    };
+    let mut entrysets = Vec::new();
     directory.walk(|entryset| -> bool {
-        if !entryset.in_use() {
-            return false;
-        }
-        let attrs = entryset.file_directory.file_attributes();
-        print!("{}", if attrs.directory() > 0 { "d" } else { "-" });
-        print!("{}", if attrs.read_only() > 0 { "r" } else { "-" });
-        print!("{}", if attrs.system() > 0 { "s" } else { "-" });
-        print!("{}", if attrs.hidden() > 0 { "h" } else { "-" });
-        print!("{}", if attrs.archive() > 0 { "a" } else { "-" });
-        print!(" {:8}", entryset.valid_data_length());
-        let modified_at = entryset.file_directory.last_modified_timestamp();
-        let localtime = modified_at.localtime().unwrap();
-        print!(" {}", localtime.format("%Y-%m-%d %H:%M:%S"));
-        if attrs.directory() > 0 {
-            println!(" {}/", entryset.name());
-        } else {
-            println!(" {}", entryset.name());
+        if entryset.in_use() {
+            entrysets.push(entryset.clone());
         }
         false
     })?;
+    Ok(entrysets)
+}
+
+fn print_details(entryset: &EntrySet, out: &mut dyn Write) {
+    let attrs = entryset.file_directory.file_attributes();
+    write!(out, "{}", if entryset.is_dir() { "d" } else { "-" }).ok();
+    write!(out, "{}", if entryset.is_read_only() { "r" } else { "-" }).ok();
+    write!(out, "{}", if attrs.system() > 0 { "s" } else { "-" }).ok();
+    write!(out, "{}", if attrs.hidden() > 0 { "h" } else { "-" }).ok();
+    write!(out, "{}", if attrs.archive() > 0 { "a" } else { "-" }).ok();
+    write!(out, " {:8}", entryset.valid_data_length()).ok();
+    let localtime = entryset.modified().localtime().unwrap();
+    write!(out, " {}", localtime.format("%Y-%m-%d %H:%M:%S")).ok();
+}
+
+fn collect_entries<E, IO>(
+    mut dir: Directory<E, IO>,
+    path: &str,
+    recursive: bool,
+    entries: &mut Vec<Entry>,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let entrysets = entrysets(&mut dir)?;
+    entries.extend(entrysets.iter().map(|entryset| Entry::new(path, entryset)));
+    if !recursive {
+        return Ok(());
+    }
+    for entryset in entrysets.iter().filter(|entryset| entryset.is_dir()) {
+        let path = format!("{}/{}", path, entryset.name());
+        collect_entries(directory(dir.open(entryset)?)?, &path, true, entries)?;
+    }
+    Ok(())
+}
+
+fn list_directory<E, IO>(
+    mut dir: Directory<E, IO>,
+    path: &str,
+    recursive: bool,
+    out: &mut dyn Write,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let entrysets = entrysets(&mut dir)?;
+    for entryset in entrysets.iter() {
+        print_details(entryset, out);
+        if entryset.is_dir() {
+            writeln!(out, " {}/", entryset.name()).ok();
+        } else {
+            writeln!(out, " {}", entryset.name()).ok();
+        }
+    }
+    if !recursive {
+        return Ok(());
+    }
+    for entryset in entrysets.iter().filter(|entryset| entryset.is_dir()) {
+        let path = format!("{}/{}", path, entryset.name());
+        writeln!(out, "\n{}:", path).ok();
+        list_directory(directory(dir.open(entryset)?)?, &path, true, out)?;
+    }
+    Ok(())
+}
+
+fn find_entryset<E, IO>(root: &mut Root<E, IO>, path: &str) -> Result<EntrySet, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let (base, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let mut dir = directory(open(root.open()?, base)?)?;
+    dir.find(name)?.ok_or(Error::Operation(OperationError::NotFound))
+}
+
+pub fn list<E, IO>(
+    root: &mut Root<E, IO>,
+    paths: &[String],
+    recursive: bool,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let mut entries = Vec::new();
+    for (index, path) in paths.iter().enumerate() {
+        let path = path.trim().trim_end_matches('/');
+        let dir = match open(root.open()?, path)? {
+            FileOrDirectory::Directory(dir) => dir,
+            FileOrDirectory::File(_) => {
+                let entryset = find_entryset(root, path)?;
+                match json {
+                    true => entries
+                        .push(Entry::new(path.rsplit_once('/').unwrap_or(("", "")).0, &entryset)),
+                    false => {
+                        print_details(&entryset, out);
+                        writeln!(out, " {}", path).ok();
+                    }
+                }
+                continue;
+            }
+        };
+        if json {
+            collect_entries(dir, path, recursive, &mut entries)?;
+            continue;
+        }
+        if paths.len() > 1 {
+            writeln!(out, "{}{}:", if index > 0 { "\n" } else { "" }, path).ok();
+        }
+        list_directory(dir, path, recursive, out)?;
+    }
+    if json {
+        writeln!(out, "{}", serde_json::to_string(&entries).unwrap()).ok();
+    }
     Ok(())
 }
+
+fn tree_directory<E, IO>(
+    mut dir: Directory<E, IO>,
+    prefix: &str,
+    out: &mut dyn Write,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let entrysets = entrysets(&mut dir)?;
+    for (index, entryset) in entrysets.iter().enumerate() {
+        let last = index == entrysets.len() - 1;
+        write!(out, "{}{}[", prefix, if last { "└── " } else { "├── " }).ok();
+        print_details(entryset, out);
+        writeln!(out, "] {}", entryset.name()).ok();
+        if entryset.is_dir() {
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            tree_directory(directory(dir.open(entryset)?)?, &prefix, out)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn tree<E, IO>(root: &mut Root<E, IO>, path: &str, out: &mut dyn Write) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let directory = directory(open(root.open()?, &path)?)?;
+    writeln!(out, "{}", path).ok();
+    tree_directory(directory, "", out)
+}
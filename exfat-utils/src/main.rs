@@ -2,32 +2,80 @@
 extern crate log;
 
 mod append;
+mod batch;
+mod bench;
+mod blockdev;
 mod cat;
+mod checksum;
+mod copy;
+mod df;
+mod du;
 pub(crate) mod filepath;
+mod find;
+mod fsck;
+mod get;
+mod inspect;
+mod label;
 mod list;
+mod mkdir;
+mod mkfs;
+mod mounted;
+mod mv;
+mod partition;
 mod put;
+mod raw;
 mod remove;
+mod resize;
 mod sdmmc;
+mod serve;
+mod stat;
 mod touch;
 mod truncate;
+mod undelete;
+mod verify;
 
 use std::fmt::Debug;
+use std::io::{self, Write};
 
+use checksum::Algorithm;
 use clap::Parser;
-use exfat::error::Error;
+use exfat::error::{Error, OperationError};
 use exfat::io::std::FileIO;
-use exfat::{DateTime, ExFAT};
+use exfat::io::trace::TracingIO;
+#[cfg(target_os = "linux")]
+use exfat::io::uring::UringIO;
+use exfat::volume::{BootSectorInfo, FlushPolicy, ParseMode, VolumeOptions};
+use exfat::{BootCode, DateTime, ExFAT, FileOrDirectory, RootDirectory as Root};
+use partition::{Partition, PartitionIO};
+
+const NOT_FOUND: OperationError = OperationError::NotFound;
 
 #[derive(Debug, clap::Args)]
 struct List {
-    /// Specify path to list, default to root directory
+    /// List sub directories recursively
+    #[clap(short = 'R', long)]
+    recursive: bool,
+    /// Specify path to list, default to root directory, wildcards allowed
+    #[clap(default_value = "/")]
+    path: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct Tree {
+    /// Specify path to show, default to root directory
     #[clap(default_value = "/")]
     path: String,
 }
 
 #[derive(Debug, clap::Args)]
 struct Cat {
-    /// Specify path to concatenate
+    /// Start from specified byte offset like 1024 or 1M
+    #[clap(long, default_value = "0", value_parser = parse_size)]
+    offset: u64,
+    /// Print at most specified number of bytes like 512 or 4K
+    #[clap(long, value_parser = parse_size)]
+    length: Option<u64>,
+    /// Specify path to concatenate, wildcards allowed
     path: String,
 }
 
@@ -41,8 +89,11 @@ struct Touch {
 struct Append {
     /// Specify path to touch
     path: String,
-    /// Specify source file to append
+    /// Specify source file to append, `-` for stdin
     source: String,
+    /// Read back and compare checksum against source after written
+    #[clap(long)]
+    verify: bool,
 }
 
 #[derive(Debug, clap::Args)]
@@ -55,21 +106,295 @@ struct Truncate {
 
 #[derive(Debug, clap::Args)]
 struct Put {
+    /// Put host directory and its contents recursively
+    #[clap(short = 'r', long)]
+    recursive: bool,
+    /// Number of threads reading host files ahead of writes when putting recursively
+    #[clap(long, default_value = "4")]
+    threads: usize,
+    /// Specify path to put
     path: String,
+    /// Specify source file, `-` for stdin, or source directory when putting recursively
     source: String,
+    /// Read back and compare checksum against source after written
+    #[clap(long)]
+    verify: bool,
 }
 
 #[derive(Debug, clap::Args)]
 struct Remove {
-    /// Specify path to delete
+    /// Remove directories and their contents recursively
+    #[clap(short = 'r', long)]
+    recursive: bool,
+    /// Ignore nonexistent files
+    #[clap(short, long)]
+    force: bool,
+    /// Specify path to delete, wildcards allowed
+    path: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct Undelete {
+    /// Specify path of deleted file or directory to restore
+    path: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct Compact {
+    /// Only release clusters after last entry without moving entries
+    #[clap(short, long)]
+    shrink: bool,
+    /// Specify directory to compact, default to root directory
+    #[clap(default_value = "/")]
+    path: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct Mkdir {
+    /// Make parent directories as needed, no error if existing
+    #[clap(short, long)]
+    parents: bool,
+    /// Specify path of directory to make
+    path: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct Move {
+    /// Overwrite existing file
+    #[clap(short, long)]
+    force: bool,
+    /// Specify path to move from
+    source: String,
+    /// Specify path or directory to move to
+    target: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct Copy {
+    /// Copy directories recursively
+    #[clap(short = 'r', long)]
+    recursive: bool,
+    /// Specify path to copy from
+    source: String,
+    /// Specify path or directory to copy to
+    target: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct Get {
+    /// Get directories recursively
+    #[clap(short = 'r', long)]
+    recursive: bool,
+    /// Specify path to get from
+    path: String,
+    /// Specify host path or directory to save to
+    host_path: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct Stat {
+    /// Specify path to show details, wildcards allowed
+    path: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct Label {
+    /// Show volumn serial number as well
+    #[clap(long)]
+    serial: bool,
+    /// Replace volumn serial number with a new one, e.g. for a cloned volumn
+    #[clap(long)]
+    regenerate_serial: bool,
+    /// Specify new volumn label, empty label removes volumn label
+    label: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+struct Fsck {
+    /// Repair inconsistencies found
+    #[clap(long)]
+    repair: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct Checksum {
+    /// Specify path to checksum, wildcards allowed
     path: String,
 }
 
+#[derive(Debug, clap::Args)]
+struct Inspect {
+    /// Decode boot sector fields
+    #[clap(long)]
+    boot: bool,
+    /// Hexdump sectors starting from specified sector
+    #[clap(long)]
+    sector: Option<u64>,
+    /// Hexdump clusters starting from specified cluster
+    #[clap(long, conflicts_with = "sector")]
+    cluster: Option<u32>,
+    /// Number of sectors or clusters to hexdump
+    #[clap(long, default_value = "1")]
+    count: u64,
+    /// Pretty print raw directory entries of specified path
+    path: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+struct RawRead {
+    /// Specify first cluster or sector to read
+    first: u64,
+    /// Number of clusters or sectors to read
+    #[clap(long, default_value = "1")]
+    count: u64,
+    /// Specify host file to save to, default to stdout
+    #[clap(short, long)]
+    output: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+struct RawWrite {
+    /// Specify first cluster or sector to overwrite
+    first: u64,
+    /// Specify host file to write, `-` for stdin, multiple of cluster or sector size
+    input: String,
+}
+
+#[derive(Debug, Copy, Clone, clap::ValueEnum)]
+enum FileType {
+    #[clap(name = "f")]
+    File,
+    #[clap(name = "d")]
+    Directory,
+}
+
+#[derive(Debug, clap::Args)]
+struct Find {
+    /// Specify directory to search in
+    #[clap(default_value = "/")]
+    path: String,
+    /// Match file name against pattern, wildcards allowed
+    #[clap(long)]
+    name: Option<String>,
+    /// Match file type, f for file and d for directory
+    #[clap(long = "type", value_enum)]
+    file_type: Option<FileType>,
+    /// Modified after specified date or datetime in localtime
+    #[clap(long, value_parser = parse_datetime)]
+    newer_than: Option<chrono::DateTime<chrono::Local>>,
+    /// Modified before specified date or datetime in localtime
+    #[clap(long, value_parser = parse_datetime)]
+    older_than: Option<chrono::DateTime<chrono::Local>>,
+    /// Minimum size like 512, 4K or 1M
+    #[clap(long, value_parser = parse_size)]
+    min_size: Option<u64>,
+    /// Maximum size like 512, 4K or 1M
+    #[clap(long, value_parser = parse_size)]
+    max_size: Option<u64>,
+}
+
+#[derive(Debug, clap::Args)]
+struct Du {
+    /// Display only a total for specified directory
+    #[clap(short, long)]
+    summarize: bool,
+    /// Print sizes in human readable format like 1.5M
+    #[clap(short = 'H', long)]
+    human_readable: bool,
+    /// Specify directory to summarize, default to root directory
+    #[clap(default_value = "/")]
+    path: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct Df {
+    /// Print sizes in human readable format like 1.5M
+    #[clap(short = 'H', long)]
+    human_readable: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct Bench {
+    /// Specify scratch directory under root directory, must not exist and is removed afterwards
+    #[clap(long, default_value = "/.bench")]
+    path: String,
+    /// Size of sequential read and write like 16M
+    #[clap(long, default_value = "16M", value_parser = parse_size)]
+    size: u64,
+    /// Size of each read or write call like 64K
+    #[clap(long, default_value = "64K", value_parser = parse_size)]
+    block_size: u64,
+    /// Number of small files to create and delete
+    #[clap(long, default_value = "20")]
+    files: usize,
+}
+
+#[derive(Debug, clap::Args)]
+struct Batch {
+    /// Specify script file of subcommands, one per line, default to stdin
+    script: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+struct Serve {
+    /// Specify path of unix socket to listen on
+    socket: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct Resize {
+    /// Specify new volumn size like 64G, at most size of partition or device
+    #[clap(long, value_parser = parse_size, required_unless_present = "fill-partition")]
+    size: Option<u64>,
+    /// Grow to size of device, or of partition which is grown till next partition or end of
+    /// device first, e.g. after cloned onto a larger card
+    #[clap(long, conflicts_with = "size")]
+    fill_partition: bool,
+    /// Check and show changes without writing anything
+    #[clap(long)]
+    dry_run: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct Mkfs {
+    /// Specify cluster size like 4K or 32K, default depends on volumn size
+    #[clap(long, value_parser = parse_size)]
+    cluster_size: Option<u64>,
+    /// Specify volumn label
+    #[clap(long)]
+    label: Option<String>,
+    /// Install boot code read from specified file, at most 390 bytes
+    #[clap(long, value_parser = parse_bootcode)]
+    bootcode: Option<Bootcode>,
+    /// Keep boot code of exFAT volumn being overwritten
+    #[clap(long, conflicts_with = "bootcode")]
+    keep_bootcode: bool,
+    /// Reserve FAT room so that volumn could be resized up to specified size like 64G later
+    #[clap(long, value_parser = parse_size)]
+    max_size: Option<u64>,
+}
+
+/// Boot code padded with zero
+#[derive(Clone, Debug)]
+struct Bootcode([u8; 390]);
+
+fn parse_bootcode(path: &str) -> Result<Bootcode, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    if bytes.len() > 390 {
+        return Err(format!("{} larger than 390 bytes", path));
+    }
+    let mut bootcode = [0u8; 390];
+    bootcode[..bytes.len()].copy_from_slice(&bytes);
+    Ok(Bootcode(bootcode))
+}
+
 #[derive(Debug, clap::Subcommand)]
 enum Action {
     /// List file and directory in specified path
     #[clap(name = "ls")]
     List(List),
+    /// Show file and directory hierarchy in specified path
+    Tree(Tree),
     /// Concatenate file and print on the standard output
     Cat(Cat),
     /// Change file timestamps
@@ -83,6 +408,81 @@ enum Action {
     /// Remove file
     #[clap(name = "rm")]
     Remove(Remove),
+    /// Restore deleted file or directory if not overwritten yet
+    Undelete(Undelete),
+    /// Pack entries of directory together and release unused clusters
+    Compact(Compact),
+    /// Make directory
+    Mkdir(Mkdir),
+    /// Move or rename file or directory
+    #[clap(name = "mv")]
+    Move(Move),
+    /// Copy file or directory
+    #[clap(name = "cp")]
+    Copy(Copy),
+    /// Get file or directory out of image, preserving modification time
+    Get(Get),
+    /// Show entryset details of file or directory
+    Stat(Stat),
+    /// Show or change volumn label
+    Label(Label),
+    /// Check and optionally repair file system
+    Fsck(Fsck),
+    /// Format device or partition as exFAT
+    Mkfs(Mkfs),
+    /// Grow or shrink file system in place
+    Resize(Resize),
+    /// Search for files and directories recursively
+    Find(Find),
+    /// Summarize allocated and logical size of each directory recursively
+    Du(Du),
+    /// Report size, used and available space of volumn
+    Df(Df),
+    /// Measure throughput of sequential IO and rates of small file operations
+    Bench(Bench),
+    /// Execute subcommands from script file or stdin against a single mount
+    Batch(Batch),
+    /// Keep volumn mounted and execute ls, stat, get, put, rm, mkdir, mv and touch
+    /// received line by line from unix socket, each replied with OK or ERROR line
+    Serve(Serve),
+    /// Hexdump sectors or clusters and decode on-disk structures
+    Inspect(Inspect),
+    /// Dump raw clusters, allocated or not
+    ClusterRead(RawRead),
+    /// Overwrite raw clusters, leaving allocation bitmap and FAT untouched
+    ClusterWrite(RawWrite),
+    /// Dump raw sectors of device or partition
+    SectorRead(RawRead),
+    /// Overwrite raw sectors of device or partition
+    SectorWrite(RawWrite),
+    /// Compute SHA256 digest of file
+    Sha256sum(Checksum),
+    /// Compute CRC32 checksum of file
+    Crc32(Checksum),
+}
+
+impl Action {
+    /// Whether action may write to device
+    fn is_write(&self) -> bool {
+        match self {
+            Self::List(_) | Self::Tree(_) | Self::Cat(_) | Self::Get(_) | Self::Stat(_) => false,
+            Self::Find(_) | Self::Du(_) | Self::Df(_) | Self::Inspect(_) => false,
+            Self::ClusterRead(_) | Self::SectorRead(_) => false,
+            Self::Sha256sum(_) | Self::Crc32(_) => false,
+            Self::Label(label) => label.label.is_some() || label.regenerate_serial,
+            Self::Fsck(fsck) => fsck.repair,
+            Self::Resize(resize) => !resize.dry_run,
+            _ => true,
+        }
+    }
+}
+
+/// Single line of batch script
+#[derive(Parser, Debug)]
+#[clap(no_binary_name = true)]
+struct Command {
+    #[clap(subcommand)]
+    action: Action,
 }
 
 #[derive(Parser, Debug)]
@@ -92,13 +492,41 @@ struct Args {
     quiet: bool,
     #[clap(short, action = clap::ArgAction::Count)]
     verbosity: u8,
+    /// Print machine readable JSON for ls, stat, find, du, df and fsck
+    #[clap(long)]
+    json: bool,
+    /// Log every device read, write and flush with latency
+    #[clap(long)]
+    trace_io: bool,
+    /// Access device or file through io_uring, overlapping reads and writes
+    #[cfg(target_os = "linux")]
+    #[clap(long)]
+    io_uring: bool,
+    /// Never write to device, skip damaged entries and checksum mismatches
+    /// instead of failing, and report them afterwards
+    #[clap(long)]
+    forensic: bool,
+    /// Refuse unrecognized benign entries, stale entryset checksums and nonzero reserved
+    /// bytes instead of logging and continuing
+    #[clap(long)]
+    strict: bool,
+    /// Skip validating upcase table checksum when mounted,
+    /// validate it right before first creating or renaming instead
+    #[clap(long)]
+    lazy_upcase_check: bool,
+    /// Write to device even if mounted by kernel, which likely corrupts it
+    #[clap(long)]
+    force: bool,
+    /// Open block device exclusively, failing if mounted or opened exclusively elsewhere
+    #[clap(long)]
+    exclusive: bool,
     /// Block device, SPI device or file
     #[clap(short, long)]
     device: String,
     /// Specify chip-select GPIO pin
     #[clap(long)]
     cs: Option<u16>,
-    /// Specify partition index
+    /// Specify partition index in GPT or MBR partition table
     #[clap(long)]
     partition: Option<u8>,
     #[clap(subcommand)]
@@ -111,25 +539,338 @@ fn exfat_datetime_now() -> DateTime {
     now.into()
 }
 
-fn action<E, IO>(io: IO, action: Action) -> Result<(), Error<E>>
+/// Parse size like 4096, 32K, 1M or 2G
+fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim().to_uppercase();
+    let (number, unit) = match size.char_indices().last() {
+        Some((index, 'K')) => (&size[..index], 1 << 10),
+        Some((index, 'M')) => (&size[..index], 1 << 20),
+        Some((index, 'G')) => (&size[..index], 1 << 30),
+        _ => (&size[..], 1),
+    };
+    let number: u64 = number.parse().map_err(|_| format!("Invalid size {}", size))?;
+    number.checked_mul(unit).ok_or(format!("Invalid size {}", size))
+}
+
+/// Parse date like 2024-06-01 or datetime like 2024-06-01T12:00:00 in localtime
+fn parse_datetime(datetime: &str) -> Result<chrono::DateTime<chrono::Local>, String> {
+    use chrono::{NaiveDate, NaiveDateTime, TimeZone};
+    let naive = match NaiveDate::parse_from_str(datetime, "%Y-%m-%d") {
+        Ok(date) => date.and_hms_opt(0, 0, 0).unwrap(),
+        Err(_) => NaiveDateTime::parse_from_str(datetime, "%Y-%m-%dT%H:%M:%S")
+            .map_err(|_| format!("Invalid datetime {}", datetime))?,
+    };
+    let option = chrono::Local.from_local_datetime(&naive).earliest();
+    option.ok_or(format!("Invalid datetime {}", datetime))
+}
+
+/// Expand wildcards in path, returns not found if nothing matches
+fn expand<E, IO>(root: &mut Root<E, IO>, path: &str) -> Result<Vec<String>, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    match filepath::expand(root, path)? {
+        paths if paths.is_empty() => Err(NOT_FOUND.into()),
+        paths => Ok(paths),
+    }
+}
+
+fn action<E, IO>(
+    mut io: IO,
+    partition: Partition,
+    action: Action,
+    json: bool,
+    forensic: bool,
+    options: VolumeOptions,
+) -> Result<(), Error<E>>
 where
     E: std::fmt::Debug,
     IO: exfat::io::IO<Error = E>,
 {
+    match action {
+        Action::Mkfs(args) => {
+            let bootcode = match (&args.bootcode, args.keep_bootcode) {
+                (Some(Bootcode(bootcode)), _) => BootCode::Custom(bootcode),
+                (None, true) => BootCode::Preserve,
+                (None, false) => BootCode::Zero,
+            };
+            let options = mkfs::Options {
+                cluster_size: args.cluster_size,
+                label: args.label.as_deref(),
+                bootcode,
+                max_size: args.max_size,
+            };
+            return mkfs::mkfs(&mut io, partition, options);
+        }
+        Action::Inspect(args) => {
+            let options = inspect::Options {
+                boot: args.boot,
+                sector: args.sector,
+                cluster: args.cluster,
+                count: args.count,
+                path: args.path.as_deref(),
+            };
+            return inspect::inspect(io, options);
+        }
+        Action::ClusterRead(args) => {
+            let output = args.output.as_deref();
+            return raw::read(io, raw::Unit::Cluster, args.first, args.count, output);
+        }
+        Action::SectorRead(args) => {
+            let output = args.output.as_deref();
+            return raw::read(io, raw::Unit::Sector, args.first, args.count, output);
+        }
+        Action::ClusterWrite(args) => {
+            return raw::write(io, raw::Unit::Cluster, args.first, &args.input, forensic);
+        }
+        Action::SectorWrite(args) => {
+            return raw::write(io, raw::Unit::Sector, args.first, &args.input, forensic);
+        }
+        Action::Resize(args) => {
+            let target = match (args.size, args.fill_partition) {
+                (Some(size), false) => resize::Target::Size(size),
+                (None, true) => resize::Target::FillPartition,
+                _ => unreachable!("Ensured by clap"),
+            };
+            let resize_options = resize::Options { target, dry_run: args.dry_run };
+            if !resize::resize(io, partition, options, resize_options)? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Action::Bench(args) => {
+            let options = bench::Options {
+                path: &args.path,
+                size: args.size,
+                block_size: args.block_size as usize,
+                files: args.files,
+            };
+            return bench::bench(io, options);
+        }
+        _ => (),
+    }
+    let mut options = options;
+    // Flushed once per file put instead of once per cluster allocated
+    if let Action::Put(Put { recursive: true, .. }) = action {
+        options.flush_policy = FlushPolicy::OnSync;
+    }
     let mut exfat = ExFAT::new(io)?;
+    exfat.set_forensic(forensic);
+    exfat.change_options(|o| *o = options);
     exfat.validate_checksum()?;
+    if let Action::Label(Label { regenerate_serial: true, .. }) = action {
+        exfat.regenerate_serial()?;
+    }
+    let boot_sector = exfat.boot_sector()?;
     let mut root = exfat.root_directory()?;
-    root.validate_upcase_table_checksum()?;
+    // Reported by fsck instead
+    if !matches!(action, Action::Fsck(_)) && !options.lazy_upcase_validation {
+        root.validate_upcase_table_checksum()?;
+    }
+    let mut quit = None;
+    let success = match action {
+        Action::Batch(args) => batch(&mut root, &boot_sector, args.script.as_deref(), json)?,
+        Action::Serve(args) => {
+            quit = serve::serve(&mut root, &boot_sector, &args.socket, json)?;
+            quit.is_some()
+        }
+        action => execute(&mut root, &boot_sector, action, json, &mut io::stdout())?,
+    };
+    exfat.flush()?;
+    if let Some(report) = exfat.forensic_report() {
+        if report.boot_checksum {
+            eprintln!("Ignored boot sector checksum mismatch");
+        }
+        if report.upcase_table_checksum {
+            eprintln!("Ignored upcase table checksum mismatch");
+        }
+        for location in report.locations.iter() {
+            eprintln!("Skipped damaged entryset{}", location);
+        }
+        if report.checksum_mismatches > 0 {
+            eprintln!("Ignored {} entryset checksum mismatches", report.checksum_mismatches);
+        }
+    }
+    if !success {
+        std::process::exit(1);
+    }
+    if let Some(mut stream) = quit {
+        drop(root);
+        let result = exfat.unmount().map(|_| ()).map_err(|(_, error)| error);
+        let status = match &result {
+            Ok(()) => "OK".to_string(),
+            Err(error) => format!("ERROR {:?}", error),
+        };
+        writeln!(stream, "{}", status).ok();
+        return result;
+    }
+    Ok(())
+}
+
+/// Returns false if executed but failed
+fn batch<E, IO>(
+    root: &mut Root<E, IO>,
+    boot_sector: &BootSectorInfo,
+    script: Option<&str>,
+    json: bool,
+) -> Result<bool, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let lines = batch::lines(script).expect("Unable to open script");
+    for (index, line) in lines.enumerate() {
+        let line = line.expect("Unable to read script");
+        let words = match batch::split(&line) {
+            Ok(words) if words.is_empty() => continue,
+            Ok(words) => words,
+            Err(error) => {
+                eprintln!("Line {}: {}", index + 1, error);
+                return Ok(false);
+            }
+        };
+        let action = match Command::try_parse_from(&words) {
+            Ok(command) => command.action,
+            Err(error) => {
+                eprintln!("Line {}: {}", index + 1, error);
+                return Ok(false);
+            }
+        };
+        let from_stdin = match &action {
+            Action::Put(Put { source, .. }) | Action::Append(Append { source, .. }) => {
+                source == "-"
+            }
+            _ => false,
+        };
+        if from_stdin && matches!(script, Some("-") | None) {
+            eprintln!("Line {}: stdin already used by script", index + 1);
+            return Ok(false);
+        }
+        if matches!(
+            action,
+            Action::Mkfs(_)
+                | Action::Resize(_)
+                | Action::Inspect(_)
+                | Action::ClusterRead(_)
+                | Action::ClusterWrite(_)
+                | Action::SectorRead(_)
+                | Action::SectorWrite(_)
+                | Action::Bench(_)
+                | Action::Batch(_)
+                | Action::Serve(_)
+        ) {
+            eprintln!("Line {}: {} not supported in batch", index + 1, words[0]);
+            return Ok(false);
+        }
+        debug!("Line {}: {}", index + 1, line);
+        match execute(root, boot_sector, action, json, &mut io::stdout()) {
+            Ok(true) => continue,
+            Ok(false) => return Ok(false),
+            Err(error) => {
+                eprintln!("Line {}: {:?}", index + 1, error);
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
 
+/// Returns false if executed but failed
+fn execute<E, IO>(
+    root: &mut Root<E, IO>,
+    boot_sector: &BootSectorInfo,
+    action: Action,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<bool, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    match action {
+        Action::Append(args) => return append::append(root, &args.path, &args.source, args.verify),
+        Action::Put(args) if args.recursive => {
+            let (path, source) = (&args.path, &args.source);
+            return put::put_recursive(root, path, source, args.threads, args.verify);
+        }
+        Action::Put(args) => return put::put(root, &args.path, &args.source, args.verify),
+        Action::Fsck(args) => return fsck::fsck(root, args.repair, json, out),
+        _ => (),
+    }
     match action {
-        Action::List(args) => list::list(&mut root, &args.path),
-        Action::Cat(args) => cat::cat(&mut root, &args.path),
-        Action::Touch(args) => touch::touch(&mut root, &args.path),
-        Action::Append(args) => append::append(&mut root, &args.path, &args.source),
-        Action::Truncate(args) => truncate::truncate(&mut root, &args.path, args.size),
-        Action::Put(args) => put::put(&mut root, &args.path, &args.source),
-        Action::Remove(args) => remove::remove(&mut root, &args.path),
+        Action::List(args) => {
+            let paths = expand(root, &args.path)?;
+            list::list(root, &paths, args.recursive, json, out)?
+        }
+        Action::Tree(args) => list::tree(root, &args.path, out)?,
+        Action::Cat(args) => expand(root, &args.path)?
+            .iter()
+            .try_for_each(|path| cat::cat(root, path, args.offset, args.length, out))?,
+        Action::Touch(args) => touch::touch(root, &args.path)?,
+        Action::Truncate(args) => truncate::truncate(root, &args.path, args.size)?,
+        Action::Remove(args) => {
+            let paths = match filepath::expand(root, &args.path)? {
+                paths if paths.is_empty() && !args.force => return Err(NOT_FOUND.into()),
+                paths => paths,
+            };
+            // Deeper paths first in case parent directory matches as well
+            for path in paths.iter().rev() {
+                remove::remove(root, path, args.recursive, args.force)?;
+            }
+        }
+        Action::Undelete(args) => undelete::undelete(root, &args.path)?,
+        Action::Compact(args) => match filepath::open(root.open()?, &args.path)? {
+            FileOrDirectory::Directory(mut dir) if args.shrink => dir.shrink()?,
+            FileOrDirectory::Directory(mut dir) => dir.compact()?,
+            FileOrDirectory::File(_) => return Err(OperationError::NotDirectory.into()),
+        },
+        Action::Mkdir(args) => mkdir::mkdir(root, &args.path, args.parents)?,
+        Action::Move(args) => mv::mv(root, &args.source, &args.target, args.force)?,
+        Action::Copy(args) => copy::copy(root, &args.source, &args.target, args.recursive)?,
+        Action::Get(args) => get::get(root, &args.path, &args.host_path, args.recursive)?,
+        Action::Stat(args) => expand(root, &args.path)?
+            .iter()
+            .try_for_each(|path| stat::stat(root, path, json, out))?,
+        Action::Label(args) => {
+            let serial_number = boot_sector.volume_serial_number;
+            label::label(root, serial_number, args.label.as_deref(), args.serial, out)?
+        }
+        Action::Find(args) => {
+            let filter = find::Filter {
+                name: args.name.as_deref(),
+                directory: args.file_type.map(|t| matches!(t, FileType::Directory)),
+                newer_than: args.newer_than,
+                older_than: args.older_than,
+                min_size: args.min_size,
+                max_size: args.max_size,
+            };
+            find::find(root, &args.path, &filter, json, out)?
+        }
+        Action::Du(args) => {
+            let (summarize, human_readable) = (args.summarize, args.human_readable);
+            du::du(root, &args.path, summarize, human_readable, json, out)?
+        }
+        Action::Df(args) => df::df(root, boot_sector, args.human_readable, json, out)?,
+        Action::Sha256sum(args) => expand(root, &args.path)?
+            .iter()
+            .try_for_each(|path| checksum::checksum(root, path, Algorithm::SHA256, out))?,
+        Action::Crc32(args) => expand(root, &args.path)?
+            .iter()
+            .try_for_each(|path| checksum::checksum(root, path, Algorithm::CRC32, out))?,
+        Action::Append(_) | Action::Put(_) | Action::Fsck(_) => unreachable!(),
+        Action::Mkfs(_)
+        | Action::Resize(_)
+        | Action::Inspect(_)
+        | Action::ClusterRead(_)
+        | Action::ClusterWrite(_)
+        | Action::SectorRead(_)
+        | Action::SectorWrite(_)
+        | Action::Bench(_)
+        | Action::Batch(_)
+        | Action::Serve(_) => unreachable!(),
     }
+    Ok(true)
 }
 
 fn display_error<E: std::fmt::Display>(error: E) -> () {
@@ -142,6 +883,26 @@ fn debug_error<E: std::fmt::Debug>(error: E) -> () {
     ()
 }
 
+fn trace_action<E, IO>(io: IO, partition: Partition, args: Args) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let parse_mode = match args.strict {
+        true => ParseMode::Strict,
+        false => ParseMode::Lenient,
+    };
+    let lazy_upcase_validation = args.lazy_upcase_check;
+    let options = VolumeOptions { parse_mode, lazy_upcase_validation, ..Default::default() };
+    match args.trace_io {
+        true => {
+            let io = TracingIO::new(io).with_level(log::Level::Info);
+            action(io, partition, args.action, args.json, args.forensic, options)
+        }
+        false => action(io, partition, args.action, args.json, args.forensic, options),
+    }
+}
+
 fn run(args: Args) -> Result<(), ()> {
     if args.device.starts_with("/dev/spidev") {
         let cs = args.cs.ok_or("CS is required for SPI device").map_err(display_error)?;
@@ -149,10 +910,44 @@ fn run(args: Args) -> Result<(), ()> {
         if let Some(partition) = args.partition {
             sdmmc.set_patition(partition as usize).map_err(display_error)?;
         }
-        action(sdmmc, args.action).map_err(debug_error)
+        let partition = sdmmc.partition();
+        trace_action(sdmmc, partition, args).map_err(debug_error)
     } else {
-        let file = FileIO::open(&args.device).map_err(display_error)?;
-        action(file, args.action).map_err(display_error)
+        if args.device.starts_with("/dev/") && args.action.is_write() && !args.force {
+            if let Some(mount_point) = mounted::mount_point(&args.device) {
+                eprintln!("{} is mounted on {}, refused unless --force", args.device, mount_point);
+                return Err(());
+            }
+        }
+        let file = blockdev::open(&args.device, args.exclusive).map_err(display_error)?;
+        let geometry = blockdev::Geometry::of(&file).map_err(display_error)?;
+        debug!(
+            "Device size {} sector size {}",
+            geometry.num_bytes,
+            1 << geometry.sector_size_shift
+        );
+        let mut partition = match args.partition {
+            Some(index) => Partition::lookup_file(&args.device, index as usize, geometry)
+                .map_err(display_error)?,
+            None => Partition::whole(geometry),
+        };
+        if let (Some(index), Action::Resize(resize)) = (args.partition, &args.action) {
+            if resize.fill_partition {
+                let (path, index) = (&args.device, index as usize);
+                let num_blocks = partition.num_blocks;
+                partition = Partition::grow_file(path, index, geometry, resize.dry_run)
+                    .map_err(display_error)?;
+                println!("Partition {} blocks {} -> {}", index, num_blocks, partition.num_blocks);
+            }
+        }
+        #[cfg(target_os = "linux")]
+        if args.io_uring {
+            let io = UringIO::new(file).map_err(display_error)?;
+            return trace_action(PartitionIO::new(io, partition), partition, args)
+                .map_err(display_error);
+        }
+        let file = FileIO::new(file);
+        trace_action(PartitionIO::new(file, partition), partition, args).map_err(display_error)
     }
 }
 
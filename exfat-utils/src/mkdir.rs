@@ -0,0 +1,39 @@
+use exfat::error::{Error, OperationError};
+use exfat::{FileOrDirectory, RootDirectory as Root};
+
+pub fn mkdir<E, IO>(root: &mut Root<E, IO>, path: &str, parents: bool) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let path = path.trim().trim_matches('/');
+    if path == "" {
+        return Err(OperationError::AlreadyExists.into());
+    }
+    let names: Vec<&str> = path.split('/').collect();
+    let mut directory = root.open()?;
+    for (index, &name) in names.iter().enumerate() {
+        let last = index == names.len() - 1;
+        let entryset = match directory.find(name)? {
+            Some(_) if last && !parents => return Err(OperationError::AlreadyExists.into()),
+            Some(entryset) => entryset,
+            None if last || parents => {
+                directory.create(name, true)?;
+                let entryset = directory.find(name)?.unwrap();
+                match directory.open(&entryset)? {
+                    FileOrDirectory::Directory(mut dir) => {
+                        dir.touch(chrono::Utc::now().into(), Default::default())?
+                    }
+                    FileOrDirectory::File(_) => unreachable!(),
+                }
+                directory.find(name)?.unwrap()
+            }
+            None => return Err(OperationError::NotFound.into()),
+        };
+        directory = match directory.open(&entryset)? {
+            FileOrDirectory::Directory(directory) => directory,
+            FileOrDirectory::File(_) => return Err(OperationError::NotDirectory.into()),
+        };
+    }
+    Ok(())
+}
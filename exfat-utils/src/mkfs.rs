@@ -0,0 +1,38 @@
+use exfat::error::{Error, InputError};
+use exfat::{BootCode, FormatOptions};
+
+use super::partition::Partition;
+
+pub struct Options<'a> {
+    /// In bytes, default depends on volumn size
+    pub cluster_size: Option<u64>,
+    pub label: Option<&'a str>,
+    pub bootcode: BootCode<'a>,
+    /// In bytes, size FAT room is reserved for
+    pub max_size: Option<u64>,
+}
+
+pub fn mkfs<E, IO>(io: &mut IO, partition: Partition, options: Options) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let now = chrono::Utc::now();
+    let serial_number = (now.timestamp() as u32) ^ now.timestamp_subsec_nanos();
+    let option = options.cluster_size.map(u32::try_from).transpose();
+    let cluster_size = option.map_err(|_| Error::Input(InputError::Size))?;
+    // Sector size follows logical sector size of device
+    let shift = partition.sector_size_shift;
+    let format_options = FormatOptions {
+        sector_size_shift: shift,
+        cluster_size,
+        label: options.label.unwrap_or(""),
+        serial_number,
+        partition_offset: partition.offset >> (shift - 9),
+        bootcode: options.bootcode,
+        max_num_sectors: options.max_size.map(|size| size >> shift),
+    };
+    exfat::format(io, partition.num_blocks >> (shift - 9), &format_options)?;
+    println!("Volumn serial number {:04X}-{:04X}", serial_number >> 16, serial_number & 0xFFFF);
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use std::fs;
+use std::path::Path;
+
+/// Whether source is device itself or one of its partitions like sdb1 or mmcblk0p1
+fn is_same_or_partition(device: &str, source: &str) -> bool {
+    let suffix = match source.strip_prefix(device) {
+        Some(suffix) => suffix,
+        None => return false,
+    };
+    let digits = match device.ends_with(|c: char| c.is_ascii_digit()) {
+        true => suffix.strip_prefix('p').unwrap_or(""),
+        false => suffix,
+    };
+    suffix.is_empty() || (!digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Mount point of device or any of its partitions according to /proc/mounts, if mounted
+pub fn mount_point(device: &str) -> Option<String> {
+    let canonical = |path: &str| fs::canonicalize(path).ok()?.to_str().map(str::to_owned);
+    let device = canonical(device).unwrap_or(device.to_owned());
+    let mounts = fs::read_to_string("/proc/mounts").ok()?;
+    for line in mounts.lines() {
+        let mut fields = line.split(' ');
+        let (source, target) = match (fields.next(), fields.next()) {
+            (Some(source), Some(target)) => (source, target),
+            _ => continue,
+        };
+        if !Path::new(source).is_absolute() {
+            continue;
+        }
+        let source = canonical(source).unwrap_or(source.to_owned());
+        if is_same_or_partition(&device, &source) {
+            // Spaces in mount point are escaped as octal
+            return Some(target.replace("\\040", " "));
+        }
+    }
+    None
+}
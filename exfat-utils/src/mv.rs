@@ -0,0 +1,85 @@
+use exfat::error::{Error, OperationError};
+use exfat::{Directory, FileOrDirectory, RootDirectory as Root};
+
+use crate::filepath::{directory, open};
+
+const NOT_FOUND: OperationError = OperationError::NotFound;
+
+/// Open a sub directory without closing specified directory
+fn open_within<E, IO>(dir: &mut Directory<E, IO>, path: &str) -> Result<Directory<E, IO>, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let (name, path) = path.split_once('/').unwrap_or((path, ""));
+    let entryset = dir.find(name)?.ok_or(Error::Operation(NOT_FOUND))?;
+    directory(open(directory(dir.open(&entryset)?)?, path)?)
+}
+
+/// Returns remaining path if base is ancestor of path
+fn strip_base<'a>(path: &'a str, base: &str) -> Option<&'a str> {
+    match base {
+        "" => Some(path),
+        _ => path.strip_prefix(base)?.strip_prefix('/'),
+    }
+}
+
+pub fn mv<E, IO>(
+    root: &mut Root<E, IO>,
+    source: &str,
+    target: &str,
+    force: bool,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let source = source.trim().trim_matches('/');
+    let (source_base, source_name) = source.rsplit_once('/').unwrap_or(("", source));
+    let mut target = target.trim().trim_matches('/').to_owned();
+    // Moving to an existing directory keeps name of source
+    match open(root.open()?, &target) {
+        Ok(FileOrDirectory::Directory(_)) => {
+            target = format!("{}/{}", target, source_name).trim_matches('/').to_owned()
+        }
+        Ok(FileOrDirectory::File(_)) | Err(Error::Operation(OperationError::NotFound)) => (),
+        Err(e) => return Err(e),
+    }
+    // Source directory would be opened when walking through target path
+    if strip_base(&target.to_lowercase(), &source.to_lowercase()).is_some() {
+        return Err(OperationError::AlreadyOpen.into());
+    }
+    let (target_base, target_name) = target.rsplit_once('/').unwrap_or(("", &target));
+
+    let (mut source_dir, mut target_dir) = if source_base == target_base {
+        (directory(open(root.open()?, source_base)?)?, None)
+    } else if let Some(path) = strip_base(target_base, source_base) {
+        let mut source_dir = directory(open(root.open()?, source_base)?)?;
+        let target_dir = open_within(&mut source_dir, path)?;
+        (source_dir, Some(target_dir))
+    } else if let Some(path) = strip_base(source_base, target_base) {
+        let mut target_dir = directory(open(root.open()?, target_base)?)?;
+        (open_within(&mut target_dir, path)?, Some(target_dir))
+    } else {
+        let source_dir = directory(open(root.open()?, source_base)?)?;
+        (source_dir, Some(directory(open(root.open()?, target_base)?)?))
+    };
+
+    let entryset = source_dir.find(source_name)?.ok_or(Error::Operation(NOT_FOUND))?;
+    let same_dir = target_dir.is_none();
+    let dir = target_dir.as_mut().unwrap_or(&mut source_dir);
+    if let Some(existing) = dir.find(target_name)? {
+        // Found source itself when name differs only in case
+        let itself = same_dir && dir.entry_location(&existing) == dir.entry_location(&entryset);
+        if !itself && (!force || existing.is_dir()) {
+            return Err(OperationError::AlreadyExists.into());
+        }
+        if !itself {
+            dir.delete(&existing)?;
+        }
+    }
+    match target_dir.as_mut() {
+        Some(target_dir) => source_dir.move_to(&entryset, target_dir, target_name),
+        None => source_dir.rename(&entryset, target_name),
+    }
+}
@@ -0,0 +1,326 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+use exfat::types::SectorID;
+use mbr_nostd::{MasterBootRecord, PartitionTable};
+
+use super::blockdev::Geometry;
+
+/// Partition of a file or block device, unit 512B block
+#[derive(Copy, Clone, Debug)]
+pub struct Partition {
+    pub offset: u64,
+    pub num_blocks: u64,
+    /// Logical sector size shift of underlying device, 9 for file
+    pub sector_size_shift: u8,
+}
+
+impl Partition {
+    pub fn whole(geometry: Geometry) -> Self {
+        let Geometry { num_bytes, sector_size_shift } = geometry;
+        Self { offset: 0, num_blocks: num_bytes / 512, sector_size_shift }
+    }
+
+    /// Look up partition of specified index in GPT or MBR partition table, `read_block` reads
+    /// 512B block of specified index regardless of logical sector size, while LBAs in
+    /// partition table and of partition returned are in unit of logical sector size
+    pub fn lookup<F>(mut read_block: F, index: usize, shift: u8) -> Result<Self, String>
+    where
+        F: FnMut(u64, &mut [u8; 512]) -> Result<(), String>,
+    {
+        let mut buffer = [0u8; 512];
+        read_block(1 << (shift - 9), &mut buffer)?;
+        let partition = match &buffer[..8] == b"EFI PART" {
+            true => Self::lookup_gpt(&buffer, read_block, index, shift)?,
+            false => {
+                read_block(0, &mut buffer)?;
+                let mbr = MasterBootRecord::from_bytes(&buffer).map_err(|_| "Not MBR")?;
+                let entries = mbr.partition_table_entries();
+                let entry = entries.get(index).ok_or("Partition out of range")?;
+                let (offset, num_blocks) = (entry.logical_block_address, entry.sector_count);
+                Self { offset: offset as u64, num_blocks: num_blocks as u64, sector_size_shift: 9 }
+            }
+        };
+        if partition.num_blocks == 0 {
+            return Err("Invalid partition".into());
+        }
+        trace!("Partition offset {} num-blocks {}", partition.offset, partition.num_blocks);
+        Ok(partition)
+    }
+
+    fn lookup_gpt<F>(
+        header: &[u8; 512],
+        mut read_block: F,
+        index: usize,
+        shift: u8,
+    ) -> Result<Self, String>
+    where
+        F: FnMut(u64, &mut [u8; 512]) -> Result<(), String>,
+    {
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+        let entries_lba = u64::from_le_bytes(header[72..80].try_into().unwrap());
+        let (num_entries, entry_size) = (u32_at(80) as usize, u32_at(84) as usize);
+        if index >= num_entries {
+            return Err("Partition out of range".into());
+        }
+        if !(128..=512).contains(&entry_size) || 512 % entry_size != 0 {
+            return Err("Unsupported GPT entry size".into());
+        }
+        let mut buffer = [0u8; 512];
+        // Entries never cross 512B blocks since entry size divides 512
+        let offset = (entries_lba << shift) + (index * entry_size) as u64;
+        read_block(offset / 512, &mut buffer)?;
+        let offset = (offset % 512) as usize;
+        let entry = &buffer[offset..offset + entry_size];
+        // Unused entry has zero partition type GUID
+        if entry[..16].iter().all(|&byte| byte == 0) {
+            return Err("Invalid partition".into());
+        }
+        let first = u64::from_le_bytes(entry[32..40].try_into().unwrap());
+        let last = u64::from_le_bytes(entry[40..48].try_into().unwrap());
+        let num_blocks = (last + 1).saturating_sub(first);
+        Ok(Self { offset: first, num_blocks, sector_size_shift: 9 })
+    }
+
+    /// Look up partition of file image or block device,
+    /// LBA of partition table is in unit of logical sector size of device
+    pub fn lookup_file(path: &str, index: usize, geometry: Geometry) -> Result<Self, String> {
+        let mut file = File::open(path).map_err(|e| e.to_string())?;
+        let shift = geometry.sector_size_shift;
+        let read_block = |block: u64, buffer: &mut [u8; 512]| {
+            file.seek(SeekFrom::Start(block * 512)).map_err(|e| e.to_string())?;
+            file.read_exact(buffer).map_err(|e| e.to_string())
+        };
+        let mut partition = Self::lookup(read_block, index, shift)?;
+        partition.offset <<= shift - 9;
+        partition.num_blocks <<= shift - 9;
+        partition.sector_size_shift = shift;
+        if partition.offset + partition.num_blocks > geometry.num_bytes / 512 {
+            return Err("Partition exceeds device".into());
+        }
+        Ok(partition)
+    }
+}
+
+/// Partition table of file image or block device being grown, unit logical sector
+struct Table {
+    file: File,
+    sector_size: u64,
+    num_sectors: u64,
+    dry_run: bool,
+}
+
+impl Table {
+    fn read(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), String> {
+        self.file.seek(SeekFrom::Start(lba * self.sector_size)).map_err(|e| e.to_string())?;
+        self.file.read_exact(buffer).map_err(|e| e.to_string())
+    }
+
+    fn write(&mut self, lba: u64, bytes: &[u8]) -> Result<(), String> {
+        if self.dry_run {
+            return Ok(());
+        }
+        self.file.seek(SeekFrom::Start(lba * self.sector_size)).map_err(|e| e.to_string())?;
+        self.file.write_all(bytes).map_err(|e| e.to_string())
+    }
+
+    /// Grow MBR entry of specified index till next partition or end of device,
+    /// returns first LBA and new number of sectors
+    fn grow_mbr(&mut self, index: usize) -> Result<(u64, u64), String> {
+        let mut mbr = [0u8; 512];
+        self.read(0, &mut mbr)?;
+        let entry = |i: usize| &mbr[446 + i * 16..446 + (i + 1) * 16];
+        let u32_at = |bytes: &[u8], offset: usize| {
+            u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as u64
+        };
+        let (first, count) = (u32_at(entry(index), 8), u32_at(entry(index), 12));
+        // Next partition or end of device, entries are not necessarily in order
+        let limit = (0..4)
+            .filter(|&i| i != index && entry(i)[4] != 0 && u32_at(entry(i), 8) > first)
+            .map(|i| u32_at(entry(i), 8))
+            .fold(self.num_sectors, u64::min);
+        let new_count = (limit - first).clamp(count, u32::MAX as u64);
+        let offset = 446 + index * 16 + 12;
+        mbr[offset..offset + 4].copy_from_slice(&(new_count as u32).to_le_bytes());
+        self.write(0, &mbr)?;
+        Ok((first, new_count))
+    }
+
+    /// Grow GPT entry of specified index till next partition or end of device, backup
+    /// header and entries are moved to end of device, returns first LBA and number of sectors
+    fn grow_gpt(&mut self, index: usize) -> Result<(u64, u64), String> {
+        let mut header = vec![0u8; self.sector_size as usize];
+        self.read(1, &mut header)?;
+        let u32_at = |bytes: &[u8], offset: usize| {
+            u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+        };
+        let u64_at = |bytes: &[u8], offset: usize| {
+            u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+        };
+        let header_size = u32_at(&header, 12) as usize;
+        let (num_entries, entry_size) =
+            (u32_at(&header, 80) as usize, u32_at(&header, 84) as usize);
+        if header_size < 92 || header_size > header.len() || entry_size < 128 {
+            return Err("Invalid GPT header".into());
+        }
+        let num_sectors = ((num_entries * entry_size) as u64).div_ceil(self.sector_size);
+        let mut entries = vec![0u8; (num_sectors * self.sector_size) as usize];
+        self.read(u64_at(&header, 72), &mut entries)?;
+
+        // Backup entries followed by backup header occupy the end of device
+        let last_lba = self.num_sectors - 1;
+        let last_usable = last_lba - num_sectors - 1;
+        if last_usable < u64_at(&header, 48) {
+            return Err("Device smaller than partition table".into());
+        }
+        let entry = |i: usize| &entries[i * entry_size..(i + 1) * entry_size];
+        let used = |i: usize| entry(i)[..16].iter().any(|&byte| byte != 0);
+        let (first, last) = (u64_at(entry(index), 32), u64_at(entry(index), 40));
+        let limit = (0..num_entries)
+            .filter(|&i| i != index && used(i) && u64_at(entry(i), 32) > first)
+            .map(|i| u64_at(entry(i), 32) - 1)
+            .fold(last_usable, u64::min);
+        let new_last = limit.max(last);
+        let offset = index * entry_size + 40;
+        entries[offset..offset + 8].copy_from_slice(&new_last.to_le_bytes());
+
+        let entries_crc = crc32fast::hash(&entries[..num_entries * entry_size]);
+        header[32..40].copy_from_slice(&last_lba.to_le_bytes());
+        header[48..56].copy_from_slice(&last_usable.to_le_bytes());
+        header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+        let mut backup = header.clone();
+        backup[24..32].copy_from_slice(&last_lba.to_le_bytes());
+        backup[32..40].copy_from_slice(&1u64.to_le_bytes());
+        backup[72..80].copy_from_slice(&(last_lba - num_sectors).to_le_bytes());
+        for header in [&mut header, &mut backup] {
+            header[16..20].fill(0);
+            let crc = crc32fast::hash(&header[..header_size]);
+            header[16..20].copy_from_slice(&crc.to_le_bytes());
+        }
+        self.write(last_lba - num_sectors, &entries)?;
+        self.write(last_lba, &backup)?;
+        self.write(u64_at(&header, 72), &entries)?;
+        self.write(1, &header)?;
+
+        // Protective MBR covers whole device
+        let mut mbr = [0u8; 512];
+        self.read(0, &mut mbr)?;
+        if mbr[446 + 4] == 0xEE {
+            let count = last_lba.min(u32::MAX as u64) as u32;
+            mbr[446 + 12..446 + 16].copy_from_slice(&count.to_le_bytes());
+            self.write(0, &mbr)?;
+        }
+        Ok((first, new_last + 1 - first))
+    }
+}
+
+impl Partition {
+    /// Grow partition of specified index in GPT or MBR partition table till next partition
+    /// or end of device, e.g. after an image cloned onto a larger card,
+    /// nothing written if `dry_run`; returns partition grown
+    pub fn grow_file(
+        path: &str,
+        index: usize,
+        geometry: Geometry,
+        dry_run: bool,
+    ) -> Result<Self, String> {
+        let file = File::options().read(true).write(!dry_run).open(path);
+        let file = file.map_err(|e| e.to_string())?;
+        let shift = geometry.sector_size_shift;
+        let sector_size = 1u64 << shift;
+        let num_sectors = geometry.num_bytes >> shift;
+        let mut table = Table { file, sector_size, num_sectors, dry_run };
+        let mut buffer = [0u8; 8];
+        table.read(1, &mut buffer)?;
+        let (first, count) = match &buffer == b"EFI PART" {
+            true => table.grow_gpt(index)?,
+            false => table.grow_mbr(index)?,
+        };
+        table.file.flush().map_err(|e| e.to_string())?;
+        let partition = Self {
+            offset: first << (shift - 9),
+            num_blocks: count << (shift - 9),
+            sector_size_shift: shift,
+        };
+        trace!(
+            "Partition grown to offset {} num-blocks {}",
+            partition.offset,
+            partition.num_blocks
+        );
+        Ok(partition)
+    }
+}
+
+/// Offsets sector id of underlying IO by partition offset,
+/// sectors beyond partition end are rejected
+pub struct PartitionIO<IO> {
+    io: IO,
+    offset: u64,
+    num_blocks: u64,
+    sector_size_shift: u8,
+}
+
+impl<IO> PartitionIO<IO> {
+    pub fn new(io: IO, partition: Partition) -> Self {
+        let (offset, num_blocks) = (partition.offset, partition.num_blocks);
+        Self { io, offset, num_blocks, sector_size_shift: 9 }
+    }
+
+    fn sector_id(&self, id: SectorID, count: u64) -> Result<SectorID, Error> {
+        let shift = self.sector_size_shift - 9;
+        if u64::from(id) + count > self.num_blocks >> shift {
+            return Err(Error::new(ErrorKind::InvalidInput, "Sector beyond partition end"));
+        }
+        Ok(id + (self.offset >> shift))
+    }
+}
+
+impl<IO: exfat::io::IO<Error = Error>> exfat::io::IO for PartitionIO<IO> {
+    type Error = Error;
+    type Block = IO::Block;
+
+    fn set_sector_size_shift(&mut self, shift: u8) -> Result<(), Self::Error> {
+        if !self.offset.is_multiple_of(1 << (shift - 9)) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Partition not aligned to sector size",
+            ));
+        }
+        self.sector_size_shift = shift;
+        self.io.set_sector_size_shift(shift)
+    }
+
+    fn read(&mut self, id: SectorID) -> Result<&[Self::Block], Self::Error> {
+        let id = self.sector_id(id, 1)?;
+        self.io.read(id)
+    }
+
+    fn write(&mut self, id: SectorID, offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+        let id = self.sector_id(id, 1)?;
+        self.io.write(id, offset, data)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.io.flush()
+    }
+
+    fn prefetch(&mut self, id: SectorID, count: u32) -> Result<(), Self::Error> {
+        let id = self.sector_id(id, count as u64)?;
+        self.io.prefetch(id, count)
+    }
+
+    fn invalidate(&mut self) -> Result<(), Self::Error> {
+        self.io.invalidate()
+    }
+
+    fn write_many(&mut self, id: SectorID, count: u32, data: &[u8]) -> Result<(), Self::Error> {
+        let id = self.sector_id(id, count as u64)?;
+        self.io.write_many(id, count, data)
+    }
+
+    fn read_many(&mut self, id: SectorID, buf: &mut [u8]) -> Result<(), Self::Error> {
+        let id = self.sector_id(id, (buf.len() >> self.sector_size_shift) as u64)?;
+        self.io.read_many(id, buf)
+    }
+}
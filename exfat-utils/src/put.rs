@@ -1,12 +1,56 @@
-use std::fs::File;
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
-use exfat::error::{Error, OperationError};
-use exfat::{FileOrDirectory, RootDirectory as Root};
+use exfat::error::{CopyError, Error, OperationError};
+use exfat::{File as ExFile, FileOrDirectory, RootDirectory as Root};
+use sha2::{Digest, Sha256};
 
 use crate::filepath::open;
+use crate::mkdir::mkdir;
+use crate::verify::verify;
 
-pub fn put<E, IO>(root: &mut Root<E, IO>, mut path: &str, source: &str) -> Result<(), Error<E>>
+/// Open host source file, `-` for stdin
+pub fn open_source(source: &str) -> Box<dyn Read> {
+    match source {
+        "-" => Box::new(io::stdin().lock()),
+        _ => Box::new(File::open(source).expect("No such file")),
+    }
+}
+
+/// Feeds everything read through into hasher
+struct Hashing<R> {
+    reader: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.reader.read(buf)?;
+        self.hasher.update(&buf[..size]);
+        Ok(size)
+    }
+}
+
+/// Copy host source into file at cursor, returns hasher of bytes copied
+pub fn copy_source<E, IO>(file: &mut ExFile<E, IO>, source: &str) -> Result<Sha256, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let mut reader = Hashing { reader: open_source(source), hasher: Sha256::new() };
+    match file.copy_from(&mut reader) {
+        Ok(_) => Ok(reader.hasher),
+        Err(CopyError::Stream(e)) => panic!("Unable to read: {}", e),
+        Err(CopyError::Filesystem(e)) => Err(e),
+    }
+}
+
+/// Create file of specified path, which must not exist yet
+fn create<E, IO>(root: &mut Root<E, IO>, mut path: &str) -> Result<ExFile<E, IO>, Error<E>>
 where
     E: std::fmt::Debug,
     IO: exfat::io::IO<Error = E>,
@@ -22,20 +66,190 @@ where
     if directory.find(name)?.is_some() {
         return Err(OperationError::AlreadyExists.into());
     }
-    let mut source_file = File::open(&source).expect("No such file");
-    let mut buffer = [0u8; 4096];
     directory.create(name, false)?;
     let entryset = directory.find(name)?.unwrap();
-    let mut file = match directory.open(&entryset)? {
-        FileOrDirectory::File(f) => f,
+    match directory.open(&entryset)? {
+        FileOrDirectory::File(file) => Ok(file),
         FileOrDirectory::Directory(_) => unreachable!(),
-    };
-    loop {
-        let size = source_file.read(&mut buffer).expect("Unable to read");
-        if size == 0 {
-            break;
+    }
+}
+
+/// Returns false if verification after write fails
+pub fn put<E, IO>(
+    root: &mut Root<E, IO>,
+    path: &str,
+    source: &str,
+    verify_written: bool,
+) -> Result<bool, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let mut file = create(root, path)?;
+    let hasher = copy_source(&mut file, source)?;
+    match verify_written {
+        true => verify(&mut file, 0, hasher),
+        false => Ok(true),
+    }
+}
+
+/// Host files are read by workers in chunks of this size
+const CHUNK_SIZE: u64 = 1 << 20;
+/// Number of chunks each worker reads ahead of file being written
+const CHUNKS_AHEAD: usize = 4;
+
+type Job = (PathBuf, SyncSender<io::Result<Vec<u8>>>);
+
+/// Files and directories under host directory, parents before children, as paths relative
+/// to it. Symbolic links are followed, refused if leading to a directory being walked through.
+fn walk(
+    base: &Path,
+    relative: &Path,
+    ancestors: &mut Vec<PathBuf>,
+    entries: &mut Vec<(PathBuf, bool)>,
+) -> io::Result<()> {
+    ancestors.push(base.join(relative).canonicalize()?);
+    let mut children = fs::read_dir(base.join(relative))?.collect::<io::Result<Vec<_>>>()?;
+    children.sort_by_key(|child| child.file_name());
+    for child in children {
+        let path = relative.join(child.file_name());
+        // Unlike file type of directory entry, metadata follows symbolic links
+        let is_dir = fs::metadata(child.path())?.is_dir();
+        if is_dir && ancestors.contains(&child.path().canonicalize()?) {
+            return Err(io::Error::other(format!("{:?} links to its parent", path)));
+        }
+        entries.push((path.clone(), is_dir));
+        if is_dir {
+            walk(base, &path, ancestors, entries)?;
         }
-        file.write_all(&buffer[..size])?;
     }
+    ancestors.pop();
     Ok(())
 }
+
+/// Read host files picked from jobs chunk by chunk, blocked once too far ahead
+fn read_ahead(jobs: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = jobs.lock().unwrap().recv();
+        let (path, sender) = match job {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                sender.send(Err(e)).ok();
+                continue;
+            }
+        };
+        loop {
+            let mut chunk = Vec::new();
+            let result = (&mut file).take(CHUNK_SIZE).read_to_end(&mut chunk);
+            let last = !matches!(result, Ok(size) if size as u64 == CHUNK_SIZE);
+            // Receiver dropped once writing failed
+            if sender.send(result.map(|_| chunk)).is_err() || last {
+                break;
+            }
+        }
+    }
+}
+
+/// Write entries into image one after another, contents of files received from workers,
+/// returns false if reading any host file or verification fails
+fn write_entries<E, IO>(
+    root: &mut Root<E, IO>,
+    path: &str,
+    source: &Path,
+    entries: &[(PathBuf, bool)],
+    receivers: Vec<Receiver<io::Result<Vec<u8>>>>,
+    verify_written: bool,
+) -> Result<bool, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let mut receivers = receivers.into_iter();
+    for (relative, is_dir) in entries.iter() {
+        let names: Vec<_> = relative.iter().map(|name| name.to_string_lossy()).collect();
+        let path = format!("{}/{}", path, names.join("/"));
+        if *is_dir {
+            mkdir(root, &path, true)?;
+            continue;
+        }
+        debug!("Put {}", path);
+        let mut file = create(root, &path)?;
+        let mut hasher = Sha256::new();
+        for chunk in receivers.next().unwrap() {
+            match chunk {
+                Ok(chunk) => {
+                    hasher.update(&chunk);
+                    file.write_all(&chunk)?;
+                }
+                Err(e) => {
+                    eprintln!("Unable to read {:?}: {}", source.join(relative), e);
+                    // Keep what has been put so far, including this file partially
+                    file.sync_all()?;
+                    return Ok(false);
+                }
+            }
+        }
+        if verify_written && !verify(&mut file, 0, hasher)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Put host directory recursively, host files are read by specified number of threads
+/// ahead of written into image one after another, returns false if reading host files
+/// or verification fails
+pub fn put_recursive<E, IO>(
+    root: &mut Root<E, IO>,
+    path: &str,
+    source: &str,
+    threads: usize,
+    verify_written: bool,
+) -> Result<bool, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let source = Path::new(source);
+    let mut entries = Vec::new();
+    if let Err(e) = walk(source, Path::new(""), &mut Vec::new(), &mut entries) {
+        eprintln!("Unable to read host directory {:?}: {}", source, e);
+        return Ok(false);
+    }
+    let mut path = path.trim().trim_matches('/').to_owned();
+    // Putting into an existing directory keeps name of source
+    if path.is_empty() || open(root.open()?, &path).is_ok() {
+        let name = source.canonicalize().expect("No such directory");
+        let name = name.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        path = format!("{}/{}", path, name).trim_matches('/').to_owned();
+    }
+    mkdir(root, &path, true)?;
+
+    let (job_sender, job_receiver) = mpsc::channel();
+    let mut receivers = Vec::new();
+    for (relative, _) in entries.iter().filter(|(_, is_dir)| !is_dir) {
+        let (sender, receiver) = mpsc::sync_channel(CHUNKS_AHEAD);
+        job_sender.send((source.join(relative), sender)).unwrap();
+        receivers.push(receiver);
+    }
+    drop(job_sender);
+    let jobs = Arc::new(Mutex::new(job_receiver));
+    let workers: Vec<_> = (0..threads.max(1))
+        .map(|_| {
+            let jobs = jobs.clone();
+            thread::spawn(move || read_ahead(jobs))
+        })
+        .collect();
+
+    let result = write_entries(root, &path, source, &entries, receivers, verify_written);
+    // Files not picked yet are discarded, those being read are abandoned once sending fails
+    while jobs.lock().unwrap().try_recv().is_ok() {}
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    result
+}
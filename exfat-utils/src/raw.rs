@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+use exfat::error::{Error, InputError, OperationError};
+use exfat::volume::BootSectorInfo;
+use exfat::ExFAT;
+
+use crate::put::open_source;
+
+/// Unit of raw read and write
+#[derive(Copy, Clone, Debug)]
+pub enum Unit {
+    Cluster,
+    Sector,
+}
+
+/// Sector size shift and sectors per cluster shift, 512B sectors if not exFAT
+fn geometry<E, IO>(io: &mut IO) -> Result<(u8, u8), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let sector = io.read(0.into()).map_err(|e| Error::IO(e))?;
+    let boot_sector = BootSectorInfo::decode(exfat::io::flatten(sector)[..512].try_into().unwrap());
+    Ok(match boot_sector.is_exfat() {
+        true => (boot_sector.bytes_per_sector_shift, boot_sector.sectors_per_cluster_shift),
+        false => (9, 0),
+    })
+}
+
+fn cluster_id(index: u64) -> Result<exfat::types::ClusterID, InputError> {
+    u32::try_from(index).map(Into::into).map_err(|_| InputError::ClusterID)
+}
+
+/// Dump specified number of clusters or sectors starting from first into output,
+/// `-` or none for stdout
+pub fn read<E, IO>(
+    mut io: IO,
+    unit: Unit,
+    first: u64,
+    count: u64,
+    output: Option<&str>,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let (sector_size_shift, sectors_per_cluster_shift) = geometry(&mut io)?;
+    let mut output: Box<dyn Write> = match output {
+        Some("-") | None => Box::new(io::stdout().lock()),
+        Some(path) => Box::new(File::create(path).expect("Unable to create")),
+    };
+    match unit {
+        Unit::Sector => {
+            io.set_sector_size_shift(sector_size_shift).map_err(|e| Error::IO(e))?;
+            for sector_id in first..first + count {
+                let sector = io.read(sector_id.into()).map_err(|e| Error::IO(e))?;
+                output.write_all(exfat::io::flatten(sector)).expect("Unable to write");
+            }
+        }
+        Unit::Cluster => {
+            let mut exfat = ExFAT::new(io)?;
+            let mut root = exfat.root_directory()?;
+            let mut buf = vec![0u8; 1 << (sector_size_shift + sectors_per_cluster_shift)];
+            for index in first..first + count {
+                root.read_cluster(cluster_id(index)?, &mut buf)?;
+                output.write_all(&buf).expect("Unable to write");
+            }
+        }
+    }
+    output.flush().expect("Unable to write");
+    Ok(())
+}
+
+/// Overwrite clusters or sectors starting from first with input, `-` for stdin,
+/// which must be multiple of cluster or sector size.
+/// Allocation bitmap and FAT are left untouched.
+pub fn write<E, IO>(
+    mut io: IO,
+    unit: Unit,
+    first: u64,
+    input: &str,
+    forensic: bool,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    if forensic {
+        return Err(OperationError::ReadOnly.into());
+    }
+    let (sector_size_shift, sectors_per_cluster_shift) = geometry(&mut io)?;
+    let size = match unit {
+        Unit::Sector => 1 << sector_size_shift,
+        Unit::Cluster => 1 << (sector_size_shift + sectors_per_cluster_shift),
+    };
+    let mut data = Vec::new();
+    open_source(input).read_to_end(&mut data).expect("Unable to read");
+    if data.len() % size != 0 {
+        eprintln!("Input of {} bytes is not multiple of {} bytes", data.len(), size);
+        return Err(InputError::Size.into());
+    }
+    match unit {
+        Unit::Sector => {
+            io.set_sector_size_shift(sector_size_shift).map_err(|e| Error::IO(e))?;
+            for (index, sector) in data.chunks(size).enumerate() {
+                for (offset, chunk) in sector.chunks(512).enumerate() {
+                    let sector_id = (first + index as u64).into();
+                    io.write(sector_id, offset * 512, chunk).map_err(|e| Error::IO(e))?;
+                }
+            }
+            io.flush().map_err(|e| Error::IO(e))
+        }
+        Unit::Cluster => {
+            let mut exfat = ExFAT::new(io)?;
+            let mut root = exfat.root_directory()?;
+            for (index, cluster) in data.chunks(size).enumerate() {
+                root.write_cluster(cluster_id(first + index as u64)?, cluster)?;
+            }
+            exfat.flush()
+        }
+    }
+}
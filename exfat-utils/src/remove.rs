@@ -3,19 +3,33 @@ use exfat::{FileOrDirectory, RootDirectory as Root};
 
 use crate::filepath::open;
 
-pub fn remove<E, IO>(root: &mut Root<E, IO>, mut path: &str) -> Result<(), Error<E>>
+pub fn remove<E, IO>(
+    root: &mut Root<E, IO>,
+    mut path: &str,
+    recursive: bool,
+    force: bool,
+) -> Result<(), Error<E>>
 where
     E: std::fmt::Debug,
     IO: exfat::io::IO<Error = E>,
 {
     path = path.trim_end_matches('/');
     let (mut directory, name) = match path.rsplit_once('/') {
-        Some((base, name)) => match open(root.open()?, &base)? {
-            FileOrDirectory::File(_) => return Err(OperationError::NotDirectory.into()),
-            FileOrDirectory::Directory(directory) => (directory, name),
+        Some((base, name)) => match open(root.open()?, &base) {
+            Ok(FileOrDirectory::File(_)) => return Err(OperationError::NotDirectory.into()),
+            Ok(FileOrDirectory::Directory(directory)) => (directory, name),
+            Err(Error::Operation(OperationError::NotFound)) if force => return Ok(()),
+            Err(e) => return Err(e),
         },
         None => (root.open()?, path),
     };
-    let entryset = directory.find(name)?.ok_or(Error::Operation(OperationError::NotFound))?;
-    directory.delete(&entryset)
+    let entryset = match directory.find(name)? {
+        Some(entryset) => entryset,
+        None if force => return Ok(()),
+        None => return Err(OperationError::NotFound.into()),
+    };
+    match recursive {
+        true => directory.delete_recursive(&entryset),
+        false => directory.delete(&entryset),
+    }
 }
@@ -0,0 +1,76 @@
+use exfat::error::Error;
+use exfat::volume::VolumeOptions;
+use exfat::ExFAT;
+
+use super::partition::Partition;
+
+pub enum Target {
+    /// In bytes
+    Size(u64),
+    /// Size of partition or device
+    FillPartition,
+}
+
+pub struct Options {
+    pub target: Target,
+    pub dry_run: bool,
+}
+
+/// Returns false if refused
+pub fn resize<E, IO>(
+    io: IO,
+    partition: Partition,
+    volume_options: VolumeOptions,
+    options: Options,
+) -> Result<bool, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let mut exfat = ExFAT::new(io)?;
+    exfat.change_options(|o| *o = volume_options);
+    exfat.validate_checksum()?;
+    let boot_sector = exfat.boot_sector()?;
+
+    // Partition offset of boot sector is in unit of sector instead of 512B block
+    let offset = partition.offset >> (partition.sector_size_shift - 9);
+    let partition_offset = boot_sector.partition_offset;
+    if partition_offset != 0 && partition_offset != offset {
+        warn!("Partition offset {} differs from {} in boot sector", offset, partition_offset);
+    }
+    let available = partition.num_blocks * 512;
+    let size = match options.target {
+        Target::Size(size) => size,
+        Target::FillPartition => available,
+    };
+    if size > available {
+        eprintln!("Size {} exceeds partition or device of {} bytes", size, available);
+        return Ok(false);
+    }
+    let (sector_size_shift, shift) =
+        (boot_sector.bytes_per_sector_shift, boot_sector.sectors_per_cluster_shift);
+    let num_sectors = size >> sector_size_shift;
+    let heap_offset = boot_sector.cluster_heap_offset as u64;
+    let num_clusters = num_sectors.saturating_sub(heap_offset) >> shift;
+    println!("Volume length {} -> {} sectors", boot_sector.volume_length, num_sectors);
+    println!("Cluster count {} -> {}", boot_sector.cluster_count, num_clusters);
+
+    let limits = exfat.resize_limits()?;
+    if num_sectors > limits.max_sectors {
+        let max_size = limits.max_sectors << sector_size_shift;
+        eprintln!("FAT room before cluster heap could only address {} bytes", max_size);
+        eprintln!("Reserve room for larger size by mkfs --max-size");
+        return Ok(false);
+    }
+    if num_sectors < limits.min_sectors {
+        let min_size = limits.min_sectors << sector_size_shift;
+        eprintln!("Clusters in use beyond new end, could shrink to {} bytes", min_size);
+        return Ok(false);
+    }
+    if options.dry_run {
+        println!("Dry run, nothing written");
+        return Ok(true);
+    }
+    exfat.resize(num_sectors)?;
+    Ok(true)
+}
@@ -2,7 +2,6 @@ use std::fmt::Display;
 use std::mem::{transmute, MaybeUninit};
 
 use exfat::types::SectorID;
-use mbr_nostd::{MasterBootRecord, PartitionTable};
 use sdmmc::bus::linux::{CountDown, GPIO, SPI};
 use sdmmc::bus::spi::bus;
 use sdmmc::bus::spi::{BUSError, Bus};
@@ -10,6 +9,8 @@ use sdmmc::delay::std::Delay;
 use sdmmc::SD;
 use spidev::SpidevOptions;
 
+use super::partition::Partition;
+
 pub struct SDMMC {
     sd: SD<Bus<SPI, GPIO, CountDown>>,
     offset: u32, // unit block
@@ -23,6 +24,7 @@ pub struct SDMMC {
 
 impl exfat::io::IO for SDMMC {
     type Error = BUSError<std::io::Error, std::io::Error>;
+    type Block = exfat::io::Block;
 
     fn set_sector_size_shift(&mut self, shift: u8) -> Result<(), Self::Error> {
         if !(self.block_size_shift <= shift && shift <= 12) {
@@ -32,7 +34,7 @@ impl exfat::io::IO for SDMMC {
         Ok(())
     }
 
-    fn read<'a>(&'a mut self, id: SectorID) -> Result<&'a [exfat::io::Block], Self::Error> {
+    fn read(&mut self, id: SectorID) -> Result<&[Self::Block], Self::Error> {
         let length = 1 << (self.sector_size_shift - self.block_size_shift);
         let address = u64::from(id) * length as u64;
         if address > self.num_blocks {
@@ -77,7 +79,7 @@ impl exfat::io::IO for SDMMC {
 
 pub enum Error {
     SDMMC(BUSError<std::io::Error, std::io::Error>),
-    String(&'static str),
+    String(String),
 }
 
 impl Display for Error {
@@ -124,18 +126,20 @@ impl SDMMC {
         Ok(sdmmc)
     }
 
+    pub fn partition(&self) -> Partition {
+        Partition { offset: self.offset as u64, num_blocks: self.num_blocks, sector_size_shift: 9 }
+    }
+
     pub fn set_patition(&mut self, partition: usize) -> Result<(), Error> {
-        let mut buffer = [0u8; 512];
-        self.sd.read(0, std::slice::from_mut(&mut buffer).iter_mut())?;
-        let mbr = MasterBootRecord::from_bytes(&buffer).map_err(|_| Error::String("Not MBR"))?;
-        let entries = mbr.partition_table_entries();
-        let entry = entries.get(partition).ok_or(Error::String("Partition out of range"))?;
-        if entry.sector_count == 0 {
-            return Err(Error::String("Invalid partition"));
-        }
-        self.offset = entry.logical_block_address;
-        self.num_blocks = entry.sector_count as u64;
-        trace!("Partition offset {} num-blocks {}", self.offset, self.num_blocks);
+        let sd = &mut self.sd;
+        let read_block = |block: u64, buffer: &mut [u8; 512]| {
+            let address = u32::try_from(block).map_err(|_| "Address out of range")?;
+            let result = sd.read(address, core::slice::from_mut(buffer).iter_mut());
+            result.map_err(|error| format!("{:?}", error))
+        };
+        let partition = Partition::lookup(read_block, partition, 9).map_err(Error::String)?;
+        self.offset = partition.offset as u32;
+        self.num_blocks = partition.num_blocks;
         Ok(())
     }
 }
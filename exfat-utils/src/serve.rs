@@ -0,0 +1,119 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+
+use clap::Parser;
+use exfat::error::Error;
+use exfat::volume::BootSectorInfo;
+use exfat::RootDirectory as Root;
+
+use crate::{batch, execute, Action, Command, Put};
+
+/// Parse and execute a single line, returns error message if failed
+fn handle<E, IO>(
+    root: &mut Root<E, IO>,
+    boot_sector: &BootSectorInfo,
+    stream: &mut UnixStream,
+    line: &str,
+    json: bool,
+) -> Result<(), String>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let words = batch::split(line)?;
+    let action = Command::try_parse_from(&words).map_err(|e| e.to_string())?.action;
+    match &action {
+        Action::Put(Put { source, .. }) if source == "-" => {
+            return Err("stdin not supported".into())
+        }
+        // Checked beforehand since put creates file before opening source
+        Action::Put(Put { source, .. }) if !Path::new(source).is_file() => {
+            return Err(format!("{}: no such file", source))
+        }
+        Action::List(_)
+        | Action::Stat(_)
+        | Action::Get(_)
+        | Action::Put(_)
+        | Action::Remove(_)
+        | Action::Mkdir(_)
+        | Action::Move(_)
+        | Action::Touch(_) => (),
+        _ => return Err(format!("{} not supported by serve", words[0])),
+    }
+    match execute(root, boot_sector, action, json, stream) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("Failed".into()),
+        Err(error) => Err(format!("{:?}", error)),
+    }
+}
+
+/// Keep volume mounted and execute commands received from unix socket.
+///
+/// Each line sent by client is a subcommand like `ls /`, `get /a /tmp/a`, `put /a /tmp/a`
+/// or `rm /a`, host paths are resolved by server. Output of subcommand is sent back,
+/// followed by a line of `OK` or `ERROR <reason>`. `quit` stops serving and returns its
+/// connection, over which status is sent once volume unmounted; `None` if unable to listen.
+/// Connections are served one after another, so concurrent clients never interleave.
+/// Process aborts if any subcommand panics, since volume may be left halfway modified.
+pub fn serve<E, IO>(
+    root: &mut Root<E, IO>,
+    boot_sector: &BootSectorInfo,
+    socket: &str,
+    json: bool,
+) -> Result<Option<UnixStream>, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    // Left behind by a server no longer running, anything else there is reported by bind
+    let metadata = std::fs::symlink_metadata(socket);
+    let is_socket = metadata.is_ok_and(|metadata| metadata.file_type().is_socket());
+    if is_socket && UnixStream::connect(socket).is_err() {
+        std::fs::remove_file(socket).ok();
+    }
+    let listener = match UnixListener::bind(socket) {
+        Ok(listener) => listener,
+        Err(error) => {
+            eprintln!("{}: {}", socket, error);
+            return Ok(None);
+        }
+    };
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        std::process::abort();
+    }));
+    info!("Serving on {}", socket);
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                warn!("Accept failed: {}", error);
+                continue;
+            }
+        };
+        let reader = BufReader::new(stream.try_clone().expect("Unable to clone socket"));
+        for line in reader.lines() {
+            let line = match line {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            debug!("Serve {}", line);
+            if line.trim() == "quit" {
+                std::fs::remove_file(socket).ok();
+                return Ok(Some(stream));
+            }
+            let status = match handle(root, boot_sector, &mut stream, &line, json) {
+                Ok(()) => "OK".to_string(),
+                Err(reason) => format!("ERROR {}", reason.replace('\n', " ").trim()),
+            };
+            if writeln!(stream, "{}", status).is_err() {
+                break;
+            }
+        }
+    }
+    unreachable!("Incoming connections never end")
+}
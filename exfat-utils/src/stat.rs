@@ -0,0 +1,117 @@
+use std::io::Write;
+
+use exfat::error::{Error, OperationError};
+use exfat::{DateTime, EntrySet, RootDirectory as Root};
+
+use crate::filepath::{directory, open};
+
+fn format_datetime(datetime: DateTime) -> String {
+    let naive: chrono::NaiveDateTime = datetime.timestamp.into();
+    let minutes = datetime.utc_offset.minutes();
+    let sign = if minutes < 0 { '-' } else { '+' };
+    let (hours, minutes) = (minutes.abs() / 60, minutes.abs() % 60);
+    let millis = datetime.millisecond;
+    format!(
+        "{}.{:03} UTC{}{:02}:{:02}",
+        naive.format("%Y-%m-%d %H:%M:%S"),
+        millis,
+        sign,
+        hours,
+        minutes
+    )
+}
+
+pub fn rfc3339(datetime: DateTime) -> String {
+    datetime.localtime().map(|datetime| datetime.to_rfc3339()).unwrap_or_default()
+}
+
+pub fn attributes(entryset: &EntrySet) -> Vec<&'static str> {
+    let attrs = entryset.file_directory.file_attributes();
+    let mut attributes = Vec::new();
+    for (set, attribute) in [
+        (attrs.read_only(), "read-only"),
+        (attrs.hidden(), "hidden"),
+        (attrs.system(), "system"),
+        (attrs.directory(), "directory"),
+        (attrs.archive(), "archive"),
+    ] {
+        if set > 0 {
+            attributes.push(attribute);
+        }
+    }
+    attributes
+}
+
+#[derive(serde::Serialize)]
+struct Stat {
+    file: String,
+    #[serde(rename = "type")]
+    type_: &'static str,
+    attributes: Vec<&'static str>,
+    size: u64,
+    allocated: u64,
+    first_cluster: u32,
+    no_fat_chain: bool,
+    entry_sector: u64,
+    entry_index: u8,
+    created: String,
+    modified: String,
+    accessed: String,
+}
+
+pub fn stat<E, IO>(
+    root: &mut Root<E, IO>,
+    path: &str,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    let path = path.trim().trim_matches('/');
+    if path == "" {
+        if json {
+            writeln!(out, "{}", serde_json::json!({"file": "/", "type": "root directory"})).ok();
+            return Ok(());
+        }
+        writeln!(out, "{:>13}: /", "File").ok();
+        writeln!(out, "{:>13}: root directory", "Type").ok();
+        return Ok(());
+    }
+    let (base, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let mut dir = directory(open(root.open()?, base)?)?;
+    let entryset = dir.find(name)?.ok_or(Error::Operation(OperationError::NotFound))?;
+    let attributes = attributes(&entryset);
+    let (sector_id, index) = dir.entry_location(&entryset);
+    if json {
+        let stat = Stat {
+            file: entryset.name().to_owned(),
+            type_: if entryset.is_dir() { "directory" } else { "file" },
+            attributes,
+            size: entryset.valid_data_length(),
+            allocated: entryset.data_length(),
+            first_cluster: entryset.first_cluster().into(),
+            no_fat_chain: !entryset.fat_chain(),
+            entry_sector: sector_id.into(),
+            entry_index: index,
+            created: rfc3339(entryset.created()),
+            modified: rfc3339(entryset.modified()),
+            accessed: rfc3339(entryset.accessed()),
+        };
+        writeln!(out, "{}", serde_json::to_string(&stat).unwrap()).ok();
+        return Ok(());
+    }
+    writeln!(out, "{:>13}: {}", "File", entryset.name()).ok();
+    writeln!(out, "{:>13}: {}", "Type", if entryset.is_dir() { "directory" } else { "file" }).ok();
+    writeln!(out, "{:>13}: {}", "Attributes", attributes.join(" ")).ok();
+    writeln!(out, "{:>13}: {} (valid data length)", "Size", entryset.valid_data_length()).ok();
+    writeln!(out, "{:>13}: {} (data length)", "Allocated", entryset.data_length()).ok();
+    writeln!(out, "{:>13}: {}", "First cluster", entryset.first_cluster()).ok();
+    writeln!(out, "{:>13}: {}", "NoFatChain", if entryset.fat_chain() { "no" } else { "yes" }).ok();
+    writeln!(out, "{:>13}: sector {} index {}", "Entry", sector_id, index).ok();
+    writeln!(out, "{:>13}: {}", "Created", format_datetime(entryset.created())).ok();
+    writeln!(out, "{:>13}: {}", "Modified", format_datetime(entryset.modified())).ok();
+    writeln!(out, "{:>13}: {}", "Accessed", format_datetime(entryset.accessed())).ok();
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use exfat::error::{Error, OperationError};
+use exfat::{FileOrDirectory, RootDirectory as Root};
+
+use crate::filepath::open;
+
+/// Restore the first deleted file or directory with specified path which is still recoverable
+pub fn undelete<E, IO>(root: &mut Root<E, IO>, mut path: &str) -> Result<(), Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    path = path.trim_end_matches('/');
+    let (mut directory, name) = match path.rsplit_once('/') {
+        Some((base, name)) => match open(root.open()?, &base)? {
+            FileOrDirectory::File(_) => return Err(OperationError::NotDirectory.into()),
+            FileOrDirectory::Directory(directory) => (directory, name),
+        },
+        None => (root.open()?, path),
+    };
+    let mut entrysets = Vec::new();
+    directory.walk(|entryset| {
+        if !entryset.in_use() && entryset.name() == name {
+            entrysets.push(entryset.clone());
+        }
+        false
+    })?;
+    let mut result = Err(OperationError::NotFound.into());
+    for entryset in entrysets.iter() {
+        result = directory.undelete(entryset);
+        match result {
+            Ok(_) => break,
+            Err(ref e) => debug!("Entryset at {:?} not recoverable: {:?}", entryset.position(), e),
+        }
+    }
+    result
+}
@@ -0,0 +1,23 @@
+use exfat::error::Error;
+use exfat::File;
+use sha2::{Digest, Sha256};
+
+use crate::checksum::stream;
+
+/// Read file back from specified offset to end, returns false if digest mismatches
+pub fn verify<E, IO>(file: &mut File<E, IO>, offset: u64, source: Sha256) -> Result<bool, Error<E>>
+where
+    E: std::fmt::Debug,
+    IO: exfat::io::IO<Error = E>,
+{
+    file.sync_all()?;
+    let mut hasher = Sha256::new();
+    stream(file, offset, |bytes| hasher.update(bytes))?;
+    let (expected, actual) = (source.finalize(), hasher.finalize());
+    if expected != actual {
+        eprintln!("Verify failed, expected sha256 {:x} actual {:x}", expected, actual);
+        return Ok(false);
+    }
+    debug!("Verified sha256 {:x}", actual);
+    Ok(true)
+}
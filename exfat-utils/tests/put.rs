@@ -0,0 +1,44 @@
+use std::os::unix::fs::symlink;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+const EXFAT: &str = env!("CARGO_BIN_EXE_exfat");
+
+fn exfat(image: &PathBuf, args: &[&str]) -> Output {
+    Command::new(EXFAT).arg("-d").arg(image).args(args).output().unwrap()
+}
+
+fn stdout(output: Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_put_recursive_symlinks() {
+    let dir = std::env::temp_dir();
+    let image = dir.join("exfat-utils-test-put.img");
+    let source = dir.join("exfat-utils-test-put");
+    std::fs::remove_dir_all(&source).ok();
+    std::fs::create_dir_all(source.join("real")).unwrap();
+    std::fs::write(source.join("real/file"), b"content").unwrap();
+    symlink(source.join("real"), source.join("linked")).unwrap();
+    std::fs::File::create(&image).unwrap().set_len(16 << 20).unwrap();
+    stdout(exfat(&image, &["mkfs"]));
+
+    // Symbolic link to directory is put as directory
+    stdout(exfat(&image, &["put", "-r", "/", source.to_str().unwrap()]));
+    let listed = stdout(exfat(&image, &["ls", "/exfat-utils-test-put/linked"]));
+    assert!(listed.contains(" file"));
+    let content = stdout(exfat(&image, &["cat", "/exfat-utils-test-put/linked/file"]));
+    assert_eq!(content, "content");
+
+    // Link back to parent is refused without panicking
+    symlink(&source, source.join("real/loop")).unwrap();
+    let output = exfat(&image, &["put", "-r", "/again", source.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("links to its parent"));
+    assert!(stdout(exfat(&image, &["fsck"])).contains("File system is consistent"));
+
+    std::fs::remove_dir_all(&source).ok();
+    std::fs::remove_file(&image).ok();
+}
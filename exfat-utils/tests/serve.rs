@@ -0,0 +1,88 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+
+const EXFAT: &str = env!("CARGO_BIN_EXE_exfat");
+
+fn exfat(image: &PathBuf, args: &[&str]) -> String {
+    let output = Command::new(EXFAT).arg("-d").arg(image).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Lines replied to a command, till status line
+fn request(stream: &mut UnixStream, reader: &mut impl BufRead, line: &str) -> Vec<String> {
+    writeln!(stream, "{}", line).unwrap();
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        assert!(reader.read_line(&mut line).unwrap() > 0, "Connection closed");
+        let line = line.trim_end().to_string();
+        let done = line == "OK" || line.starts_with("ERROR");
+        lines.push(line);
+        if done {
+            return lines;
+        }
+    }
+}
+
+#[test]
+fn test_serve() {
+    let dir = std::env::temp_dir();
+    let image = dir.join("exfat-utils-test-serve.img");
+    let socket = dir.join("exfat-utils-test-serve.sock");
+    std::fs::File::create(&image).unwrap().set_len(16 << 20).unwrap();
+    exfat(&image, &["mkfs"]);
+
+    let mut server = Command::new(EXFAT)
+        .arg("-d")
+        .arg(&image)
+        .arg("serve")
+        .arg(&socket)
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stream = (0..100)
+        .find_map(|_| {
+            sleep(Duration::from_millis(50));
+            UnixStream::connect(&socket).ok()
+        })
+        .expect("Server not listening");
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    assert_eq!(request(&mut stream, &mut reader, "mkdir /a"), ["OK"]);
+    let lines = request(&mut stream, &mut reader, "ls /");
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with('d') && lines[0].ends_with(" a/"));
+    let lines = request(&mut stream, &mut reader, "stat /b");
+    assert!(lines[0].starts_with("ERROR"));
+    let lines = request(&mut stream, &mut reader, "fsck");
+    assert_eq!(lines, ["ERROR fsck not supported by serve"]);
+    assert_eq!(request(&mut stream, &mut reader, "quit"), ["OK"]);
+    assert!(server.wait().unwrap().success());
+    assert!(!socket.exists());
+
+    // Unmounted cleanly
+    assert!(exfat(&image, &["ls", "/"]).contains(" a/"));
+    std::fs::remove_file(&image).ok();
+}
+
+#[test]
+fn test_serve_keeps_regular_file() {
+    let dir = std::env::temp_dir();
+    let image = dir.join("exfat-utils-test-serve-regular-file.img");
+    let socket = dir.join("exfat-utils-test-serve-regular-file.sock");
+    std::fs::File::create(&image).unwrap().set_len(16 << 20).unwrap();
+    exfat(&image, &["mkfs"]);
+    std::fs::write(&socket, b"not a socket").unwrap();
+
+    let mut command = Command::new(EXFAT);
+    let output = command.arg("-d").arg(&image).arg("serve").arg(&socket).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(socket.to_str().unwrap()));
+    assert_eq!(std::fs::read(&socket).unwrap(), b"not a socket");
+    std::fs::remove_file(&socket).ok();
+    std::fs::remove_file(&image).ok();
+}
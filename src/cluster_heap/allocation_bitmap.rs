@@ -1,16 +1,13 @@
-use core::mem::{size_of, transmute};
+use core::mem::size_of;
 
-use memoffset::offset_of;
-
-use crate::error::{AllocationError, DataError, Error};
+use crate::error::{AllocationError, DataError, Error, Location};
 use crate::fat;
 use crate::io::IOWrapper;
 use crate::region::boot::BootSector;
 use crate::region::fat::Entry;
 use crate::sync::{acquire, Shared};
 use crate::types::{ClusterID, SectorID};
-
-const ARRAY_SIZE: usize = 512 / size_of::<usize>();
+use crate::volume::PercentInuseUpdate;
 
 #[inline]
 fn first_zero_bit(bits: u8) -> u8 {
@@ -33,8 +30,22 @@ fn bit_to_offset(bit: u8) -> u8 {
     }
 }
 
+/// Progress of `RootDirectory::update_usage`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct UsageProgress {
+    pub counted_sectors: u32,
+    /// Number of sectors of allocation bitmap
+    pub num_sectors: u32,
+}
+
+impl UsageProgress {
+    pub fn is_done(&self) -> bool {
+        self.counted_sectors >= self.num_sectors
+    }
+}
+
 #[derive(Clone)]
-pub struct DumbAllocator<IO> {
+pub struct DumbAllocator<IO: crate::io::Lifetime> {
     io: Shared<IOWrapper<IO>>,
     base: SectorID,
     fat_info: fat::Info,
@@ -44,29 +55,75 @@ pub struct DumbAllocator<IO> {
     percent_inuse: u8,
     maybe_available_offset: u32,
     num_inuse_clusters: u32,
+    /// Counters are reloaded once caches invalidated
+    generation: u32,
+    /// Bytes of bitmap counted so far and allocated clusters within them,
+    /// while counting is in progress
+    counting: Option<(u32, u32)>,
 }
 
 #[cfg_attr(not(feature = "async"), deasync::deasync)]
 impl<E, IO: crate::io::IO<Error = E>> DumbAllocator<IO> {
     async fn init(&mut self) -> Result<(), Error<E>> {
-        let mut sector_id = self.base;
+        self.counting = None;
+        self.count_inuse(u32::MAX).await?;
+        Ok(())
+    }
+
+    /// Count allocated clusters within up to `max_sectors` sectors of bitmap,
+    /// resuming from where last call stopped, counter updated once whole bitmap counted
+    async fn count_inuse(&mut self, max_sectors: u32) -> Result<UsageProgress, Error<E>> {
+        let sector_size = 1u32 << self.sector_size_shift;
+        let (mut offset, mut num_inuse) = self.counting.unwrap_or((0, 0));
         let mut io = acquire!(self.io);
-        let mut sector = io.read(sector_id).await?;
-        let mut array: &[[usize; ARRAY_SIZE]] = unsafe { transmute(sector) };
-        let sector_size = 1 << self.sector_size_shift;
-        let mut num_inuse = 0;
-        for i in 0..(self.length as usize / size_of::<usize>()) {
-            let index = i % (sector_size / size_of::<usize>());
-            if i > 0 && index == 0 {
-                sector_id += 1u64;
-                sector = io.read(sector_id).await?;
-                array = unsafe { transmute(sector) };
+        for i in 0..max_sectors {
+            if offset >= self.length {
+                break;
+            }
+            if i > 0 {
+                io.ensure_not_cancelled()?;
+            }
+            let sector = io.read(self.base + offset / sector_size).await?;
+            let bytes = &sector[..(self.length - offset).min(sector_size) as usize];
+            let chunks = bytes.chunks_exact(size_of::<usize>());
+            num_inuse += chunks.remainder().iter().map(|byte| byte.count_ones()).sum::<u32>();
+            for chunk in chunks {
+                num_inuse += usize::from_ne_bytes(chunk.try_into().unwrap()).count_ones();
+            }
+            offset += bytes.len() as u32;
+        }
+        drop(io);
+        self.counting = Some((offset, num_inuse));
+        if offset >= self.length {
+            self.counting = None;
+            self.num_inuse_clusters = num_inuse;
+            debug!("Num inuse clusters is {}/{}", num_inuse, self.num_clusters);
+        }
+        let counted_sectors = offset.div_ceil(sector_size);
+        Ok(UsageProgress { counted_sectors, num_sectors: self.length.div_ceil(sector_size) })
+    }
+
+    /// Clusters allocated or released within part of bitmap already counted
+    fn track(&mut self, byte_offset: u32, allocated: bool) {
+        if let Some((offset, num_inuse)) = self.counting.as_mut() {
+            if byte_offset < *offset {
+                match allocated {
+                    true => *num_inuse += 1,
+                    false => *num_inuse = num_inuse.saturating_sub(1),
+                }
             }
-            num_inuse += array[index / ARRAY_SIZE][index % ARRAY_SIZE].count_ones();
         }
-        self.num_inuse_clusters = num_inuse;
-        debug!("Num inuse clusters is {}/{}", num_inuse, self.num_clusters);
-        Ok(())
+    }
+
+    /// Recount allocated clusters incrementally, see `RootDirectory::update_usage`
+    pub async fn update_usage(&mut self, num_sectors: u32) -> Result<UsageProgress, Error<E>> {
+        self.revalidate().await?;
+        let progress = self.count_inuse(num_sectors).await?;
+        if progress.is_done() {
+            self.ensure_percent_inuse().await?;
+            acquire!(self.io).auto_flush().await?;
+        }
+        Ok(progress)
     }
 
     pub(crate) async fn new(
@@ -75,35 +132,59 @@ impl<E, IO: crate::io::IO<Error = E>> DumbAllocator<IO> {
         fat_info: fat::Info,
         length: u32,
     ) -> Result<Self, Error<E>> {
-        let mut borrow_io = acquire!(io);
-        let blocks = borrow_io.read(0.into()).await?;
-        let boot_sector: &BootSector = unsafe { transmute(&blocks[0]) };
-        let sector_size_shift = boot_sector.bytes_per_sector_shift;
-        let num_clusters = boot_sector.cluster_count.to_ne();
-        let percent_inuse = boot_sector.percent_inuse;
-        drop(borrow_io);
-
+        let generation = acquire!(io).generation;
         let mut bitmap = Self {
             io,
             base,
             fat_info,
             length,
-            num_clusters,
-            sector_size_shift,
-            percent_inuse,
+            num_clusters: 0,
+            sector_size_shift: 0,
+            percent_inuse: 0,
             maybe_available_offset: 0,
-            num_inuse_clusters: ((percent_inuse + 1) as u64 * num_clusters as u64 / 100) as u32 - 1,
+            num_inuse_clusters: 0,
+            generation,
+            counting: None,
         };
-        if cfg!(feature = "precise-allocation-counter") {
-            bitmap.init().await?;
-        }
+        bitmap.load().await?;
         Ok(bitmap)
     }
 
+    async fn load(&mut self) -> Result<(), Error<E>> {
+        let mut io = acquire!(self.io);
+        let blocks = io.read(0.into()).await?;
+        let boot_sector: &BootSector = bytemuck::from_bytes(&blocks[..512]);
+        self.sector_size_shift = boot_sector.bytes_per_sector_shift;
+        self.num_clusters = boot_sector.cluster_count.to_ne();
+        self.percent_inuse = boot_sector.percent_inuse;
+        drop(io);
+
+        let (percent_inuse, num_clusters) = (self.percent_inuse as u64, self.num_clusters as u64);
+        self.num_inuse_clusters = ((percent_inuse.min(100) + 1) * num_clusters / 100) as u32 - 1;
+        self.maybe_available_offset = 0;
+        self.counting = None;
+        // Counted instead of estimated if percent-in-use not available
+        if cfg!(feature = "precise-allocation-counter") || percent_inuse > 100 {
+            self.init().await?;
+        }
+        Ok(())
+    }
+
+    /// Volume may have been modified elsewhere since caches invalidated
+    async fn revalidate(&mut self) -> Result<(), Error<E>> {
+        let generation = acquire!(self.io).generation;
+        if generation != self.generation {
+            debug!("Reload allocation counters");
+            self.generation = generation;
+            self.load().await?;
+        }
+        Ok(())
+    }
+
     async fn is_available(&mut self, cluster_id: ClusterID) -> Result<Option<u8>, Error<E>> {
         let offset = u32::from(cluster_id) - 2;
         let (byte_offset, bit_offset) = (offset / 8, offset as u8 % 8);
-        if byte_offset >= self.length {
+        if offset >= self.num_clusters || byte_offset >= self.length {
             return Ok(None);
         }
         let sector_size = 1 << self.sector_size_shift;
@@ -111,8 +192,8 @@ impl<E, IO: crate::io::IO<Error = E>> DumbAllocator<IO> {
         let mut io = acquire!(self.io);
         let sector = io.read(sector_id).await?;
         let index = (byte_offset % sector_size) as usize;
-        let bits = sector[index / 512][index % 512];
-        Ok(if bits & (1 << bit_offset) > 0 { Some(bits) } else { None })
+        let bits = sector[index];
+        Ok(if bits & (1 << bit_offset) == 0 { Some(bits) } else { None })
     }
 
     async fn find_available(&mut self) -> Result<(u32, u8), Error<E>> {
@@ -126,7 +207,7 @@ impl<E, IO: crate::io::IO<Error = E>> DumbAllocator<IO> {
                 sector = io.read(sector_id).await?;
             }
             let index = (i % sector_size) as usize;
-            let bits = sector[index / 512][index % 512];
+            let bits = sector[index];
             if bits != u8::MAX {
                 return Ok((i, bits));
             }
@@ -139,21 +220,52 @@ impl<E, IO: crate::io::IO<Error = E>> DumbAllocator<IO> {
     }
 
     async fn ensure_percent_inuse(&mut self) -> Result<(), Error<E>> {
-        let offset = offset_of!(BootSector, percent_inuse);
         let percent_inuse = Self::ratio(self.num_inuse_clusters, self.num_clusters);
-        if percent_inuse as u8 == self.percent_inuse {
+        if percent_inuse == self.percent_inuse {
             return Ok(());
         }
-        self.percent_inuse = percent_inuse as u8;
-        let bytes: [u8; 1] = [self.percent_inuse];
-        acquire!(self.io).write(0.into(), offset, &bytes).await
+        let drift = percent_inuse.abs_diff(self.percent_inuse);
+        let mut io = acquire!(self.io);
+        match io.options.percent_inuse_update {
+            PercentInuseUpdate::OnChange => (),
+            PercentInuseUpdate::Delta(delta) if drift >= delta => (),
+            PercentInuseUpdate::Delta(_) => {
+                io.drifted_percent_inuse = Some(percent_inuse);
+                return Ok(());
+            }
+            // Percent-in-use not available
+            PercentInuseUpdate::Never if self.percent_inuse == 0xFF => return Ok(()),
+            PercentInuseUpdate::Never => {
+                self.percent_inuse = 0xFF;
+                (io.percent_inuse, io.drifted_percent_inuse) = (None, None);
+                return io.write_percent_inuse(0xFF).await;
+            }
+            PercentInuseUpdate::OnFlush => {
+                self.percent_inuse = percent_inuse;
+                io.percent_inuse = Some(percent_inuse);
+                return Ok(());
+            }
+        }
+        self.percent_inuse = percent_inuse;
+        (io.percent_inuse, io.drifted_percent_inuse) = (None, None);
+        io.write_percent_inuse(percent_inuse).await
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "allocate",
+            level = "trace",
+            skip_all,
+            fields(last = %last, frag, cluster = tracing::field::Empty)
+        )
+    )]
     pub async fn allocate(&mut self, last: ClusterID, frag: bool) -> Result<ClusterID, Error<E>> {
+        self.revalidate().await?;
         if self.maybe_available_offset >= self.length {
             return Err(AllocationError::NoMoreCluster.into());
         }
-        let offset = u32::from(last + 1u32) - 2;
+        let offset = u32::from(last + 1u32).wrapping_sub(2);
         let mut byte_offset = offset / 8;
         let mut bit_offset = offset as u8 % 8;
         let mut bits = 0xFFu8;
@@ -169,12 +281,20 @@ impl<E, IO: crate::io::IO<Error = E>> DumbAllocator<IO> {
         if bits == 0xFF {
             (byte_offset, bits) = self.find_available().await?;
             bit_offset = bit_to_offset(first_zero_bit(bits));
+            // Padding bits of last byte are not clusters
+            if byte_offset * 8 + bit_offset as u32 >= self.num_clusters {
+                return Err(AllocationError::NoMoreCluster.into());
+            }
         };
         let cluster_id = ClusterID::from(byte_offset as u32 * 8 + bit_offset as u32 + 2);
         let sector_id = self.base + byte_offset / sector_size;
         let offset = byte_offset % sector_size;
         bits |= 1 << bit_offset;
-        acquire!(self.io).write(sector_id, offset as usize, &[bits; 1]).await?;
+        let mut io = acquire!(self.io);
+        io.write(sector_id, offset as usize, &[bits; 1]).await?;
+        io.stats.allocations += 1;
+        drop(io);
+        self.track(byte_offset, true);
         self.num_inuse_clusters += 1;
         self.maybe_available_offset = byte_offset + (bits == 0xFF) as u32;
         if !cfg!(feature = "precise-allocation-counter") {
@@ -184,16 +304,148 @@ impl<E, IO: crate::io::IO<Error = E>> DumbAllocator<IO> {
         }
         self.ensure_percent_inuse().await?;
         trace!("Allocated cluster {}", cluster_id);
+        crate::instrument::record!(cluster = u32::from(cluster_id));
         Ok(cluster_id)
     }
 
+    /// First run of free clusters starting from specified cluster index,
+    /// as cluster index and number of clusters
+    pub async fn free_extent(&mut self, from: u32) -> Result<Option<(u32, u32)>, Error<E>> {
+        let sector_size = 1u32 << self.sector_size_shift;
+        let bits_per_sector = sector_size * 8;
+        let total = self.num_clusters.min(self.length * 8);
+        let (mut index, mut start) = (from, None);
+        let mut io = acquire!(self.io);
+        while index < total {
+            let sector_index = index / bits_per_sector;
+            let sector = io.read(self.base + sector_index).await?;
+            let end = ((sector_index + 1) * bits_per_sector).min(total);
+            while index < end {
+                let byte = sector[(index / 8 % sector_size) as usize];
+                // Whole byte allocated or free
+                match (start, byte) {
+                    (None, u8::MAX) | (Some(_), 0) if index % 8 == 0 => {
+                        index = (index + 8).min(end);
+                        continue;
+                    }
+                    _ => (),
+                }
+                let free = byte & (1 << (index % 8)) == 0;
+                match (free, start) {
+                    (true, None) => start = Some(index),
+                    (false, Some(start)) => return Ok(Some((start, index - start))),
+                    _ => (),
+                }
+                index += 1;
+            }
+        }
+        Ok(start.map(|start| (start, total - start)))
+    }
+
+    pub fn num_clusters(&self) -> u32 {
+        self.num_clusters
+    }
+
+    /// Whether specified cluster is marked as allocated
+    #[cfg(feature = "alloc")]
+    pub async fn is_allocated(&mut self, cluster_id: ClusterID) -> Result<bool, Error<E>> {
+        if (u32::from(cluster_id) - 2) / 8 >= self.length {
+            return Ok(false);
+        }
+        Ok(self.is_available(cluster_id).await?.is_none())
+    }
+
+    /// Mark specified cluster as allocated regardless of FAT
+    #[cfg(feature = "alloc")]
+    pub async fn mark_allocated(&mut self, cluster_id: ClusterID) -> Result<(), Error<E>> {
+        trace!("Mark cluster id {} allocated", cluster_id);
+        self.revalidate().await?;
+        self.mark_one(cluster_id).await?;
+        self.ensure_percent_inuse().await?;
+        acquire!(self.io).auto_flush().await
+    }
+
+    async fn mark_one(&mut self, cluster_id: ClusterID) -> Result<(), Error<E>> {
+        let index = u32::from(cluster_id) - 2;
+        let byte_offset = index / 8;
+        if byte_offset >= self.length {
+            warn!("Cluster ID {} out of range", cluster_id);
+            return Err(DataError::FATChain(Location::cluster(cluster_id)).into());
+        }
+        let mut io = acquire!(self.io);
+        let sector_size = 1 << self.sector_size_shift;
+        let sector_id = self.base + byte_offset / sector_size;
+        let sector = io.read(sector_id).await?;
+        let offset = (byte_offset % sector_size) as usize;
+        let byte = sector[offset] | (1 << (index % 8));
+        io.write(sector_id, offset, &[byte; 1]).await?;
+        drop(io);
+        self.track(byte_offset, true);
+        self.num_inuse_clusters += 1;
+        Ok(())
+    }
+
+    /// Cluster following specified one in a released cluster chain, if still chained
+    async fn released_next(
+        &mut self,
+        cluster_id: ClusterID,
+        chain: bool,
+    ) -> Result<Option<ClusterID>, Error<E>> {
+        if !chain {
+            return Ok(Some(cluster_id + 1u32));
+        }
+        let sector_id = match self.fat_info.fat_sector_id(cluster_id) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let mut io = acquire!(self.io);
+        let sector = io.read(sector_id).await?;
+        match self.fat_info.next_cluster_id(sector, cluster_id) {
+            Ok(Entry::Next(id)) => Ok(Some(id)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Mark released clusters allocated again, nothing changed and false returned
+    /// if any of them has been allocated since or cluster chain no longer intact
+    pub async fn restore(
+        &mut self,
+        first_cluster: ClusterID,
+        num_clusters: u32,
+        chain: bool,
+    ) -> Result<bool, Error<E>> {
+        trace!("Restore {} clusters starts with cluster id {}", num_clusters, first_cluster);
+        self.revalidate().await?;
+        // Validate all clusters at first pass, mark them at second pass
+        for mark in [false, true] {
+            let mut cluster_id = first_cluster;
+            for i in 0..num_clusters {
+                if i > 0 {
+                    cluster_id = match self.released_next(cluster_id, chain).await? {
+                        Some(id) => id,
+                        None => return Ok(false),
+                    };
+                }
+                match mark {
+                    false if !cluster_id.valid() => return Ok(false),
+                    false if self.is_available(cluster_id).await?.is_none() => return Ok(false),
+                    false => (),
+                    true => self.mark_one(cluster_id).await?,
+                }
+            }
+        }
+        self.ensure_percent_inuse().await?;
+        acquire!(self.io).auto_flush().await?;
+        Ok(true)
+    }
+
     async fn release_one(&mut self, cluster_id: ClusterID) -> Result<(), Error<E>> {
         trace!("Release cluster id {}", cluster_id);
         let index = u32::from(cluster_id) - 2;
         let byte_offset = index / 8;
         if byte_offset >= self.length {
             warn!("Cluster ID {} out of range", cluster_id);
-            return Err(DataError::FATChain.into());
+            return Err(DataError::FATChain(Location::cluster(cluster_id)).into());
         }
         let mut io = acquire!(self.io);
         let sector_size = 1 << self.sector_size_shift;
@@ -202,20 +454,48 @@ impl<E, IO: crate::io::IO<Error = E>> DumbAllocator<IO> {
         let sector = io.read(sector_id).await?;
         let offset = (byte_offset % sector_size) as usize;
         let bit_offset = index % 8;
-        let byte = sector[offset / 512][offset % 512] & !(1 << bit_offset);
+        let byte = sector[offset] & !(1 << bit_offset);
         io.write(sector_id, offset, &[byte; 1]).await?;
+        io.stats.frees += 1;
         if byte_offset < self.maybe_available_offset {
             self.maybe_available_offset = byte_offset;
         }
+        drop(io);
+        self.track(byte_offset, false);
         Ok(())
     }
 
+    pub async fn release_contiguous(
+        &mut self,
+        cluster_id: ClusterID,
+        num_clusters: u32,
+    ) -> Result<(), Error<E>> {
+        trace!("Release {} clusters starts with cluster id {}", num_clusters, cluster_id);
+        self.revalidate().await?;
+        for i in 0..num_clusters {
+            self.release_one(cluster_id + i).await?;
+            self.num_inuse_clusters -= 1;
+        }
+        self.ensure_percent_inuse().await?;
+        acquire!(self.io).auto_flush().await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "release",
+            level = "trace",
+            skip_all,
+            fields(cluster = %cluster_id, chain)
+        )
+    )]
     pub async fn release(&mut self, cluster_id: ClusterID, chain: bool) -> Result<(), Error<E>> {
         trace!("Release clusters starts with cluster id {}", cluster_id);
+        self.revalidate().await?;
         if !chain {
             self.release_one(cluster_id).await?;
             self.ensure_percent_inuse().await?;
-            return acquire!(self.io).flush().await;
+            return acquire!(self.io).auto_flush().await;
         }
         let mut cluster_id = cluster_id;
         while cluster_id.valid() {
@@ -231,7 +511,9 @@ impl<E, IO: crate::io::IO<Error = E>> DumbAllocator<IO> {
                 Ok(entry) => entry,
                 Err(value) => {
                     warn!("Invalid next entry {:X} for cluster id {}", value, cluster_id);
-                    return Err(DataError::FATChain.into());
+                    let location =
+                        Location { sector_id: Some(sector_id), ..Location::cluster(cluster_id) };
+                    return Err(DataError::FATChain(location).into());
                 }
             };
             match entry {
@@ -245,8 +527,77 @@ impl<E, IO: crate::io::IO<Error = E>> DumbAllocator<IO> {
         }
         self.ensure_percent_inuse().await?;
         let mut io = acquire!(self.io);
-        return io.flush().await;
+        io.auto_flush().await
     }
 }
 
 pub type AllocationBitmap<IO> = DumbAllocator<IO>;
+
+#[cfg(all(test, feature = "std", not(feature = "async")))]
+mod test {
+    use crate::fixture::Image;
+    use crate::sync::acquire;
+    use crate::FileOrDirectory;
+
+    #[test]
+    fn test_update_usage() {
+        let image =
+            Image::with_options("exfat-test-update-usage.img", 64 << 20, &Default::default());
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+
+        let progress = root.update_usage(1).unwrap();
+        assert_eq!((progress.counted_sectors, progress.num_sectors), (1, 4));
+        assert!(!progress.is_done());
+        // Clusters allocated within counted part of bitmap meanwhile
+        directory.create("file", false).unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(mut file) => file.write_all(&[0xA5; 1 << 20]).unwrap(),
+            FileOrDirectory::Directory(_) => unreachable!(),
+        }
+        let percent_inuse = exfat.percent_inuse().unwrap();
+        while !root.update_usage(1).unwrap().is_done() {}
+        assert_eq!(exfat.percent_inuse().unwrap(), percent_inuse);
+    }
+
+    #[test]
+    fn test_free_extents() {
+        let image = Image::new("exfat-test-free-extents.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        for name in ["a", "b", "c"] {
+            directory.create(name, false).unwrap();
+            let entryset = directory.find(name).unwrap().unwrap();
+            match directory.open(&entryset).unwrap() {
+                FileOrDirectory::File(mut file) => file.write_all(&[0; 12288]).unwrap(),
+                FileOrDirectory::Directory(_) => unreachable!(),
+            }
+        }
+        let entryset = directory.find("b").unwrap().unwrap();
+        directory.delete(&entryset).unwrap();
+
+        let extents: Vec<_> = root.free_extents().map(Result::unwrap).collect();
+        let extents: Vec<_> =
+            extents.iter().map(|e| (u32::from(e.first_cluster), e.num_clusters)).collect();
+        let mut expected: Vec<(u32, u32)> = Vec::new();
+        let mut context = acquire!(directory.meta.context);
+        let bitmap = &mut context.allocation_bitmap;
+        for index in 0..bitmap.num_clusters() {
+            if bitmap.is_allocated((index + 2).into()).unwrap() {
+                continue;
+            }
+            match expected.last_mut() {
+                Some((first, count)) if *first + *count == index + 2 => *count += 1,
+                _ => expected.push((index + 2, 1)),
+            }
+        }
+        drop(context);
+        assert_eq!(extents, expected);
+        // Hole left by "b" and the rest
+        assert_eq!(extents.len(), 2);
+        assert_eq!(extents[0].1, 3);
+    }
+}
@@ -0,0 +1,233 @@
+use core::fmt::Debug;
+use core::mem::ManuallyDrop;
+use core::ptr;
+
+use alloc::vec::Vec;
+
+use super::file::File;
+use crate::error::{Error, OperationError};
+
+/// Accumulates small writes and issues sector aligned writes to underlying file
+pub struct BufWriter<E: Debug, IO: crate::io::IO<Error = E>> {
+    file: File<E, IO>,
+    buffer: Vec<u8>,
+    capacity: usize,
+}
+
+impl<E: Debug, IO: crate::io::IO<Error = E>> BufWriter<E, IO> {
+    /// Buffer size defaults to sector size
+    pub fn new(file: File<E, IO>) -> Self {
+        let capacity = file.meta.fs_info.sector_size() as usize;
+        Self::with_capacity(file, capacity)
+    }
+
+    /// Capacity will be rounded up to multiple of sector size
+    pub fn with_capacity(file: File<E, IO>, capacity: usize) -> Self {
+        let sector_size = file.meta.fs_info.sector_size() as usize;
+        let capacity = core::cmp::max(capacity.div_ceil(sector_size), 1) * sector_size;
+        Self { file, buffer: Vec::with_capacity(capacity), capacity }
+    }
+
+    pub fn get_ref(&self) -> &File<E, IO> {
+        &self.file
+    }
+
+    /// Bytes buffered so far is written untill buffer full,
+    /// so that buffer flush ends at sector boundary
+    fn limit(&self) -> usize {
+        let sector_size = self.file.meta.fs_info.sector_size() as u64;
+        // Buffered bytes not yet written, cursor is where buffer starts
+        let offset = self.file.cursor % sector_size;
+        self.capacity - offset as usize
+    }
+}
+
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+impl<E: Debug, IO: crate::io::IO<Error = E>> BufWriter<E, IO> {
+    async fn flush_buffer(&mut self) -> Result<(), Error<E>> {
+        if !self.buffer.is_empty() {
+            self.file.write_all(&self.buffer).await?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+
+    /// Buffer some bytes, returns number of bytes buffered
+    pub async fn write(&mut self, bytes: &[u8]) -> Result<usize, Error<E>> {
+        let limit = self.limit();
+        let length = core::cmp::min(bytes.len(), limit - self.buffer.len());
+        self.buffer.extend_from_slice(&bytes[..length]);
+        if self.buffer.len() == limit {
+            self.flush_buffer().await?;
+        }
+        Ok(length)
+    }
+
+    pub async fn write_all(&mut self, mut bytes: &[u8]) -> Result<(), Error<E>> {
+        while !bytes.is_empty() {
+            let written = self.write(bytes).await?;
+            bytes = &bytes[written..];
+        }
+        Ok(())
+    }
+
+    /// Write buffered bytes and flush underlying file
+    pub async fn flush(&mut self) -> Result<(), Error<E>> {
+        self.flush_buffer().await?;
+        self.file.flush().await
+    }
+
+    /// Write buffered bytes and unwrap underlying file
+    pub async fn into_inner(mut self) -> Result<File<E, IO>, Error<E>> {
+        self.flush_buffer().await?;
+        let this = ManuallyDrop::new(self);
+        drop(unsafe { ptr::read(&this.buffer) });
+        Ok(unsafe { ptr::read(&this.file) })
+    }
+}
+
+#[cfg(any(not(feature = "async"), feature = "async-std"))]
+impl<E: Debug, IO: crate::io::IO<Error = E>> Drop for BufWriter<E, IO> {
+    fn drop(&mut self) {
+        match () {
+            #[cfg(all(feature = "async", not(feature = "async-std")))]
+            () => panic!("Into inner must be explicit called"),
+            #[cfg(feature = "async-std")]
+            () => async_std::task::block_on(self.flush_buffer()).unwrap(),
+            #[cfg(not(feature = "async"))]
+            () => self.flush_buffer().unwrap(),
+        }
+    }
+}
+
+/// Reads ahead whole clusters from underlying file,
+/// so that small reads are served from buffer instead of device
+pub struct BufReader<E: Debug, IO: crate::io::IO<Error = E>> {
+    file: File<E, IO>,
+    buffer: Vec<u8>,
+    capacity: usize,
+    consumed: usize,
+}
+
+impl<E: Debug, IO: crate::io::IO<Error = E>> BufReader<E, IO> {
+    /// Buffer size defaults to cluster size
+    pub fn new(file: File<E, IO>) -> Self {
+        let capacity = file.meta.fs_info.cluster_size() as usize;
+        Self::with_capacity(file, capacity)
+    }
+
+    /// Capacity will be rounded up to multiple of sector size
+    pub fn with_capacity(file: File<E, IO>, capacity: usize) -> Self {
+        let sector_size = file.meta.fs_info.sector_size() as usize;
+        let capacity = core::cmp::max(capacity.div_ceil(sector_size), 1) * sector_size;
+        Self { file, buffer: Vec::with_capacity(capacity), capacity, consumed: 0 }
+    }
+
+    pub fn get_ref(&self) -> &File<E, IO> {
+        &self.file
+    }
+
+    /// Bytes buffered but not yet consumed
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer[self.consumed..]
+    }
+
+    /// Unwrap underlying file, bytes buffered but not consumed will be lost
+    pub fn into_inner(self) -> File<E, IO> {
+        self.file
+    }
+}
+
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+impl<E: Debug, IO: crate::io::IO<Error = E>> BufReader<E, IO> {
+    async fn fill_buffer(&mut self) -> Result<(), Error<E>> {
+        let offset = (self.file.cursor % self.capacity as u64) as usize;
+        self.buffer.resize(self.capacity - offset, 0);
+        self.consumed = 0;
+        match self.file.read(&mut self.buffer).await {
+            Ok(size) => self.buffer.truncate(size),
+            Err(e) => {
+                self.buffer.clear();
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Read some bytes, returns EOF error when no more bytes available
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error<E>> {
+        if self.consumed == self.buffer.len() {
+            self.fill_buffer().await?;
+        }
+        let length = core::cmp::min(buf.len(), self.buffer.len() - self.consumed);
+        buf[..length].copy_from_slice(&self.buffer[self.consumed..self.consumed + length]);
+        self.consumed += length;
+        Ok(length)
+    }
+
+    /// Read exactly buf.len() bytes
+    pub async fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<(), Error<E>> {
+        while !buf.is_empty() {
+            let size = self.read(buf).await?;
+            buf = &mut buf[size..];
+        }
+        Ok(())
+    }
+
+    /// Read until specified byte found or end of file, returns number of bytes pushed
+    pub async fn read_until(&mut self, byte: u8, buf: &mut Vec<u8>) -> Result<usize, Error<E>> {
+        let mut total = 0;
+        loop {
+            if self.consumed == self.buffer.len() {
+                match self.fill_buffer().await {
+                    Ok(_) => (),
+                    Err(Error::Operation(OperationError::EOF)) if total > 0 => return Ok(total),
+                    Err(e) => return Err(e),
+                }
+            }
+            let available = &self.buffer[self.consumed..];
+            let (length, found) = match available.iter().position(|&b| b == byte) {
+                Some(index) => (index + 1, true),
+                None => (available.len(), false),
+            };
+            buf.extend_from_slice(&available[..length]);
+            self.consumed += length;
+            total += length;
+            if found {
+                return Ok(total);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std", not(feature = "async")))]
+mod test {
+    use crate::fixture::Image;
+    use crate::FileOrDirectory;
+
+    #[test]
+    fn test_buf_writer_unaligned() {
+        let image = Image::new("exfat-test-buf-writer.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("file", false).unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        let mut file = match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(file) => file,
+            FileOrDirectory::Directory(_) => unreachable!(),
+        };
+        let data: Vec<u8> = (0..3000).map(|i| (i * 7 % 251) as u8).collect();
+        file.write_all(&data[..100]).unwrap();
+        let mut writer = super::BufWriter::new(file);
+        for chunk in data[100..].chunks(300) {
+            writer.write_all(chunk).unwrap();
+        }
+        let mut file = writer.into_inner().unwrap();
+        assert_eq!(file.size(), data.len() as u64);
+        file.seek(crate::SeekFrom::Start(0)).unwrap();
+        let mut buf = Vec::new();
+        file.copy_to(&mut buf).unwrap();
+        assert_eq!(buf, data);
+    }
+}
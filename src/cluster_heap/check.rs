@@ -0,0 +1,270 @@
+use core::fmt::Debug;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::directory::{Directory, FileOrDirectory};
+use super::entryset::EntrySet;
+use crate::error::{DataError, Error, Location};
+use crate::region::data::entryset::{checksum, ENTRY_SIZE};
+use crate::region::fat::Entry;
+use crate::sync::acquire;
+use crate::types::ClusterID;
+
+/// Inconsistencies found by `RootDirectory::check`
+#[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CheckReport {
+    /// Upcase table checksum mismatches
+    pub upcase_table_checksum: bool,
+    /// Number of entrysets with mismatched set checksum
+    pub checksum_mismatches: usize,
+    /// Number of files or directories with broken cluster chain
+    pub bad_chains: usize,
+    /// Number of clusters used by more than one file or directory
+    pub cross_linked_clusters: usize,
+    /// Number of clusters allocated but not used by any file or directory
+    pub orphan_clusters: usize,
+    /// Number of clusters used but not allocated
+    pub unallocated_clusters: usize,
+}
+
+impl CheckReport {
+    pub fn is_consistent(&self) -> bool {
+        !self.upcase_table_checksum
+            && self.checksum_mismatches == 0
+            && self.bad_chains == 0
+            && self.cross_linked_clusters == 0
+            && self.orphan_clusters == 0
+            && self.unallocated_clusters == 0
+    }
+}
+
+/// Clusters referenced by files, directories and system regions
+struct Usage {
+    bits: Vec<u8>,
+    num_clusters: u32,
+}
+
+impl Usage {
+    fn new(num_clusters: u32) -> Self {
+        Self { bits: vec![0; num_clusters.div_ceil(8) as usize], num_clusters }
+    }
+
+    fn in_range(&self, cluster_id: ClusterID) -> bool {
+        let index = u32::from(cluster_id);
+        index >= 2 && index - 2 < self.num_clusters
+    }
+
+    /// Returns false if already marked
+    fn mark(&mut self, cluster_id: ClusterID) -> bool {
+        let index = (u32::from(cluster_id) - 2) as usize;
+        let marked = self.bits[index / 8] & (1 << (index % 8)) > 0;
+        self.bits[index / 8] |= 1 << (index % 8);
+        !marked
+    }
+
+    fn is_marked(&self, index: u32) -> bool {
+        self.bits[index as usize / 8] & (1 << (index % 8)) > 0
+    }
+}
+
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+impl<E: Debug, IO: crate::io::IO<Error = E>, const N: usize> Directory<E, IO, N> {
+    /// Follow a cluster chain of specified number of clusters or until last cluster,
+    /// returns false if chain is broken
+    async fn check_chain(
+        &mut self,
+        first_cluster: ClusterID,
+        num_clusters: Option<u32>,
+        fat_chain: bool,
+        usage: &mut Usage,
+        report: &mut CheckReport,
+    ) -> Result<bool, Error<E>> {
+        let mut cluster_id = first_cluster;
+        let mut count = 0;
+        loop {
+            // Cycle also exceeds number of clusters
+            if !usage.in_range(cluster_id) || count >= usage.num_clusters {
+                report.bad_chains += 1;
+                return Ok(false);
+            }
+            if !usage.mark(cluster_id) {
+                report.cross_linked_clusters += 1;
+            }
+            count += 1;
+            if num_clusters == Some(count) {
+                return Ok(true);
+            }
+            if !fat_chain {
+                cluster_id += 1u32;
+                continue;
+            }
+            let option = self.meta.fat_info.fat_sector_id(cluster_id);
+            let sector_id = option.ok_or(DataError::FATChain(Location::cluster(cluster_id)))?;
+            let mut io = acquire!(self.meta.io);
+            io.ensure_not_cancelled()?;
+            let sector = io.read(sector_id).await?;
+            match self.meta.fat_info.next_cluster_id(sector, cluster_id) {
+                Ok(Entry::Next(next)) => cluster_id = next,
+                Ok(Entry::Last) if num_clusters.is_none() => return Ok(true),
+                _ => {
+                    warn!("Broken cluster chain starts with cluster id {}", first_cluster);
+                    report.bad_chains += 1;
+                    return Ok(false);
+                }
+            }
+        }
+    }
+
+    /// Check entrysets of this directory, sub directories are opened and pushed to pending
+    async fn check_entrysets(
+        &mut self,
+        usage: &mut Usage,
+        report: &mut CheckReport,
+        pending: &mut Vec<Directory<E, IO, N>>,
+        repair: bool,
+    ) -> Result<(), Error<E>> {
+        let mut entrysets: Vec<EntrySet<N>> = Vec::new();
+        self.walk(|entryset| {
+            if entryset.in_use() {
+                entrysets.push(entryset.clone());
+            }
+            false
+        })
+        .await?;
+        let cluster_size = self.meta.fs_info.cluster_size() as u64;
+        for entryset in entrysets.iter() {
+            let sum =
+                checksum(&entryset.file_directory, &entryset.stream_extension, entryset.name());
+            let set_checksum = entryset.file_directory.set_checksum.to_ne();
+            if sum != set_checksum {
+                warn!("Checksum mismatch for {}", entryset.name());
+                report.checksum_mismatches += 1;
+                if repair {
+                    let (sector_id, index) = self.entry_location(entryset);
+                    let offset = index as usize * ENTRY_SIZE + 2;
+                    let mut io = acquire!(self.meta.io);
+                    io.write(sector_id, offset, &u16::to_le_bytes(sum)).await?;
+                    io.flush().await?;
+                }
+            }
+            let (cluster_id, length) = (entryset.first_cluster(), entryset.data_length());
+            let mut intact = true;
+            if cluster_id.valid() && length > 0 {
+                let num_clusters = Some(length.div_ceil(cluster_size) as u32);
+                let fat_chain = entryset.fat_chain();
+                intact =
+                    self.check_chain(cluster_id, num_clusters, fat_chain, usage, report).await?;
+            }
+            if entryset.is_dir() && intact {
+                match self.open(entryset).await? {
+                    FileOrDirectory::Directory(directory) => pending.push(directory),
+                    FileOrDirectory::File(_) => unreachable!(),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check all files and directories under this directory along with specified system
+    /// cluster chains, then check allocation bitmap against clusters used
+    pub(crate) async fn check(
+        &mut self,
+        system_chains: &[(ClusterID, Option<u32>)],
+        repair: bool,
+        report: &mut CheckReport,
+    ) -> Result<(), Error<E>> {
+        let num_clusters = acquire!(self.meta.context).allocation_bitmap.num_clusters();
+        let mut usage = Usage::new(num_clusters);
+        for &(cluster_id, num_clusters) in system_chains.iter() {
+            self.check_chain(cluster_id, num_clusters, true, &mut usage, report).await?;
+        }
+
+        let mut pending: Vec<Directory<E, IO, N>> = Vec::new();
+        self.check_entrysets(&mut usage, report, &mut pending, repair).await?;
+        while let Some(mut directory) = pending.pop() {
+            acquire!(self.meta.io).ensure_not_cancelled()?;
+            directory.check_entrysets(&mut usage, report, &mut pending, repair).await?;
+            #[cfg(all(feature = "async", not(feature = "async-std")))]
+            directory.close().await?;
+        }
+
+        // Entrysets under a directory with broken chain are never walked through,
+        // clusters they use would be released as orphans otherwise
+        let release_orphans = repair && report.bad_chains == 0;
+        let mut context = acquire!(self.meta.context);
+        let bitmap = &mut context.allocation_bitmap;
+        for index in 0..num_clusters {
+            if index % 4096 == 0 {
+                acquire!(self.meta.io).ensure_not_cancelled()?;
+            }
+            let cluster_id = ClusterID::from(index + 2);
+            match (bitmap.is_allocated(cluster_id).await?, usage.is_marked(index)) {
+                (true, false) => {
+                    report.orphan_clusters += 1;
+                    if release_orphans {
+                        bitmap.release_contiguous(cluster_id, 1).await?;
+                    }
+                }
+                (false, true) => {
+                    report.unallocated_clusters += 1;
+                    if repair {
+                        bitmap.mark_allocated(cluster_id).await?;
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std", not(feature = "async")))]
+mod test {
+    use std::io::{Seek, SeekFrom, Write};
+
+    use crate::fixture::Image;
+    use crate::FileOrDirectory;
+
+    #[test]
+    fn test_repair_keeps_orphans_under_broken_chain() {
+        let image = Image::new("exfat-test-check-broken-chain.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("dir", true).unwrap();
+        let entryset = directory.find("dir").unwrap().unwrap();
+        match directory.open(&entryset).unwrap() {
+            FileOrDirectory::Directory(mut subdir) => {
+                subdir.create("file", false).unwrap();
+                let entryset = subdir.find("file").unwrap().unwrap();
+                match subdir.open(&entryset).unwrap() {
+                    FileOrDirectory::File(mut file) => file.write_all(&[0xA5; 8192]).unwrap(),
+                    FileOrDirectory::Directory(_) => unreachable!(),
+                }
+            }
+            FileOrDirectory::File(_) => unreachable!(),
+        }
+        let id = entryset.id(&directory.meta.fs_info);
+        let sector_size = directory.meta.fs_info.sector_size() as u64;
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        // First cluster of directory out of cluster heap
+        let offset = u64::from(id.sector_id) * sector_size + (id.index as u64 + 1) * 32 + 20;
+        let mut file = std::fs::File::options().write(true).open(image.path()).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&0x0FFF_FFF0u32.to_le_bytes()).unwrap();
+        drop(file);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let report = root.check(true).unwrap();
+        assert_eq!(report.bad_chains, 1);
+        // Directory itself and file under it
+        assert_eq!(report.orphan_clusters, 3);
+        assert_eq!(root.check(false).unwrap().orphan_clusters, 3);
+    }
+}
@@ -0,0 +1,73 @@
+use core::fmt::Debug;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use futures_core::Stream;
+
+use super::file::File;
+use crate::error::{Error, OperationError};
+
+type Output<'a, E, IO> = (&'a mut File<E, IO>, Result<Vec<u8>, Error<E>>);
+#[cfg(feature = "sync")]
+type Pending<'a, E, IO> = Pin<Box<dyn Future<Output = Output<'a, E, IO>> + Send + 'a>>;
+#[cfg(not(feature = "sync"))]
+type Pending<'a, E, IO> = Pin<Box<dyn Future<Output = Output<'a, E, IO>> + 'a>>;
+
+/// Stream of file content from cursor in chunks, see `File::chunks`
+pub struct Chunks<'a, E: Debug, IO: crate::io::IO<Error = E>> {
+    file: Option<&'a mut File<E, IO>>,
+    pending: Option<Pending<'a, E, IO>>,
+    size: usize,
+}
+
+impl<E: Debug, IO: crate::io::IO<Error = E>> File<E, IO> {
+    /// Read from cursor until end of file in chunks of at most `size` bytes,
+    /// e.g. to pipe file content into stream based sinks
+    pub fn chunks(&mut self, size: usize) -> Chunks<'_, E, IO> {
+        Chunks { file: Some(self), pending: None, size: size.max(1) }
+    }
+}
+
+impl<'a, E, IO> Stream for Chunks<'a, E, IO>
+where
+    E: Debug + crate::io::MaybeSend + 'a,
+    IO: crate::io::IO<Error = E> + 'a,
+{
+    type Item = Result<Vec<u8>, Error<E>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.pending.is_none() {
+            let file = match this.file.take() {
+                Some(file) => file,
+                None => return Poll::Ready(None),
+            };
+            let size = this.size;
+            this.pending = Some(Box::pin(async move {
+                let mut buf = vec![0u8; size];
+                let result = file.read(&mut buf).await.map(|length| {
+                    buf.truncate(length);
+                    buf
+                });
+                (file, result)
+            }));
+        }
+        let (file, result) = match this.pending.as_mut().unwrap().as_mut().poll(cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => return Poll::Pending,
+        };
+        this.pending = None;
+        match result {
+            Err(Error::Operation(OperationError::EOF)) => Poll::Ready(None),
+            Err(error) => Poll::Ready(Some(Err(error))),
+            Ok(chunk) => {
+                this.file = Some(file);
+                Poll::Ready(Some(Ok(chunk)))
+            }
+        }
+    }
+}
@@ -1,33 +1,123 @@
+#[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 
 use super::{allocation_bitmap::AllocationBitmap, entryset::EntryID};
+use crate::error::OperationError;
+use crate::region;
+#[cfg(not(feature = "alloc"))]
+use crate::storage::StaticVec;
+use crate::types::{ClusterID, SectorID};
 
+/// Default number of files and directories could be opened at the same time
+/// without `alloc` feature, see [`Storage`](crate::Storage)
+pub const MAX_OPENED_ENTRIES: usize = 16;
+
+/// A file or directory currently opened, helps finding out leaked handles
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OpenEntry {
+    /// Same as `Directory::entry_location` of its entryset, `None` for root directory
+    pub location: Option<(SectorID, u8)>,
+    /// Number of handles sharing this entry
+    pub handles: usize,
+}
+
+#[cfg(feature = "alloc")]
+pub type OpenEntries = Vec<OpenEntry>;
+/// At most `MAX_OPENED_ENTRIES` of them are listed without `alloc` feature
+#[cfg(not(feature = "alloc"))]
+pub type OpenEntries = heapless::Vec<OpenEntry, MAX_OPENED_ENTRIES>;
+
+#[derive(Default)]
 pub struct OpenedEntries {
-    pub(crate) entries: Vec<EntryID>,
+    // Sorted by entry id, along with number of handles sharing the entry
+    #[cfg(feature = "alloc")]
+    pub(crate) entries: Vec<(EntryID, usize)>,
+    #[cfg(not(feature = "alloc"))]
+    pub(crate) entries: StaticVec<(EntryID, usize)>,
 }
 
 impl OpenedEntries {
-    pub(crate) fn add(&mut self, id: EntryID) -> bool {
-        let index = match self.entries.binary_search(&id) {
-            Ok(_) => return false,
+    #[cfg(not(feature = "alloc"))]
+    pub(crate) fn new(buffer: &'static mut [(EntryID, usize)]) -> Self {
+        Self { entries: StaticVec::new(buffer) }
+    }
+
+    pub(crate) fn add(&mut self, id: EntryID) -> Result<(), OperationError> {
+        let index = match self.entries.binary_search_by_key(&id, |&(id, _)| id) {
+            Ok(_) => return Err(OperationError::AlreadyOpen),
             Err(index) => index,
         };
-        self.entries.insert(index, id);
+        match () {
+            #[cfg(feature = "alloc")]
+            () => self.entries.insert(index, (id, 1)),
+            #[cfg(not(feature = "alloc"))]
+            () => self.entries.insert(index, (id, 1)).map_err(|_| OperationError::TooManyOpened)?,
+        }
+        Ok(())
+    }
+
+    /// Add one more handle to an already opened entry
+    pub(crate) fn share(&mut self, id: EntryID) -> bool {
+        match self.entries.binary_search_by_key(&id, |&(id, _)| id) {
+            Ok(index) => self.entries[index].1 += 1,
+            Err(_) => return false,
+        };
         true
     }
 
+    /// Root directory has no entryset, identified by `root`
+    pub(crate) fn list(&self, root: EntryID) -> OpenEntries {
+        let entries = self.entries.iter().map(|&(id, handles)| {
+            let location = if id != root { Some((id.sector_id, id.index)) } else { None };
+            OpenEntry { location, handles }
+        });
+        match () {
+            #[cfg(feature = "alloc")]
+            () => entries.collect(),
+            #[cfg(not(feature = "alloc"))]
+            () => entries.take(MAX_OPENED_ENTRIES).collect(),
+        }
+    }
+
+    pub(crate) fn contains(&self, id: EntryID) -> bool {
+        self.entries.binary_search_by_key(&id, |&(id, _)| id).is_ok()
+    }
+
     pub(crate) fn remove(&mut self, id: EntryID) -> bool {
-        let index = match self.entries.binary_search(&id) {
+        let index = match self.entries.binary_search_by_key(&id, |&(id, _)| id) {
             Ok(index) => index,
             Err(_) => return false,
         };
-        self.entries.remove(index);
+        self.entries[index].1 -= 1;
+        if self.entries[index].1 == 0 {
+            self.entries.remove(index);
+        }
         true
     }
 }
 
-pub struct Context<IO> {
+pub struct Context<IO: crate::io::Lifetime> {
     pub allocation_bitmap: AllocationBitmap<IO>,
     // Stores first cluster of opened file entry
     pub opened_entries: OpenedEntries,
+    /// Upcase table whose checksum is validated before first write relying on it,
+    /// see `VolumeOptions::lazy_upcase_validation`
+    pub pending_upcase_table: Option<region::data::UpcaseTable>,
+    /// Generations of entry positions, increased once entries of a directory relocated,
+    /// directories are hashed into buckets by first cluster
+    pub relocations: [u32; RELOCATION_BUCKETS],
+}
+
+const RELOCATION_BUCKETS: usize = 16;
+
+impl<IO: crate::io::Lifetime> Context<IO> {
+    pub(crate) fn relocations(&self, directory: ClusterID) -> u32 {
+        self.relocations[u32::from(directory) as usize % RELOCATION_BUCKETS]
+    }
+
+    /// Invalidate walk positions taken within specified directory
+    pub(crate) fn relocate(&mut self, directory: ClusterID) {
+        let bucket = &mut self.relocations[u32::from(directory) as usize % RELOCATION_BUCKETS];
+        *bucket = bucket.wrapping_add(1);
+    }
 }
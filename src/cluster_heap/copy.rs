@@ -0,0 +1,95 @@
+use core::fmt::Debug;
+
+#[cfg(feature = "async")]
+use async_std::io::{Read, ReadExt, Write, WriteExt};
+#[cfg(not(feature = "async"))]
+use std::io::{Read, Write};
+
+use super::file::File;
+use crate::error::{CopyError, Error, OperationError};
+use crate::io::MaybeUnpin;
+
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+impl<E: Debug, IO: crate::io::IO<Error = E>> File<E, IO> {
+    /// Write everything read from reader until its end at cursor, returns number of
+    /// bytes copied. Data is transferred cluster by cluster so that whole sectors
+    /// of a cluster are written at once.
+    pub async fn copy_from<R: Read + MaybeUnpin + ?Sized>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<u64, CopyError<E>> {
+        let mut buffer = vec![0u8; self.meta.fs_info.cluster_size() as usize];
+        let mut copied = 0;
+        loop {
+            let mut length = 0;
+            while length < buffer.len() {
+                match reader.read(&mut buffer[length..]).await {
+                    Ok(0) => break,
+                    Ok(size) => length += size,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(CopyError::Stream(e)),
+                }
+            }
+            self.write_all(&buffer[..length]).await?;
+            copied += length as u64;
+            if length < buffer.len() {
+                return Ok(copied);
+            }
+        }
+    }
+
+    /// Write everything from cursor until end of file into writer, returns number of
+    /// bytes copied. Data is transferred cluster by cluster so that whole sectors
+    /// of a cluster are read at once.
+    pub async fn copy_to<W: Write + MaybeUnpin + ?Sized>(
+        &mut self,
+        writer: &mut W,
+    ) -> Result<u64, CopyError<E>> {
+        let mut buffer = vec![0u8; self.meta.fs_info.cluster_size() as usize];
+        let mut copied = 0;
+        loop {
+            let length = match self.read(&mut buffer).await {
+                Ok(length) => length,
+                Err(Error::Operation(OperationError::EOF)) => break,
+                Err(e) => return Err(e.into()),
+            };
+            writer.write_all(&buffer[..length]).await.map_err(CopyError::Stream)?;
+            copied += length as u64;
+        }
+        writer.flush().await.map_err(CopyError::Stream)?;
+        Ok(copied)
+    }
+}
+
+#[cfg(all(test, feature = "std", not(feature = "async")))]
+mod test {
+    use crate::fixture::Image;
+    use crate::FileOrDirectory;
+
+    #[test]
+    fn test_copy_from_and_to() {
+        let image = Image::new("exfat-test-copy-from.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("file", false).unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        let mut file = match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(file) => file,
+            FileOrDirectory::Directory(_) => unreachable!(),
+        };
+        // Unaligned head followed by several clusters and a partial sector
+        let data: Vec<u8> = (0..3 * 4096 + 700).map(|i| (i * 7 % 251) as u8).collect();
+        file.write_all(&data[..100]).unwrap();
+        let copied = file.copy_from(&mut &data[100..]).unwrap();
+        assert_eq!(copied, data.len() as u64 - 100);
+        assert_eq!(file.size(), data.len() as u64);
+        file.seek(crate::SeekFrom::Start(0)).unwrap();
+        let mut buf = Vec::new();
+        assert_eq!(file.copy_to(&mut buf).unwrap(), data.len() as u64);
+        assert_eq!(buf, data);
+        drop(file);
+        drop(directory);
+        assert!(root.check(false).unwrap().is_consistent());
+    }
+}
@@ -0,0 +1,242 @@
+use core::fmt::Debug;
+
+use super::super::meta::MetaFileDirectory;
+use super::entry_iter::EntryIter;
+use super::{is_secondary, Directory};
+use crate::cluster_heap::entryset::{EntryID, EntryRef};
+use crate::error::{DataError, Error, OperationError};
+use crate::region::data::entry_type::{EntryType, RawEntryType};
+use crate::region::data::entryset::primary::FileDirectory;
+use crate::region::data::entryset::{RawEntry, ENTRY_SIZE};
+use crate::sync::acquire;
+
+/// In-use entries of an entryset along with where they are read from
+type Pending = heapless::Vec<(EntryRef, RawEntry), 19>;
+
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+impl<E: Debug, IO: crate::io::IO<Error = E>, const N: usize> Directory<E, IO, N> {
+    /// Validate structure of every inuse entryset before anything is moved,
+    /// none of them could be opened since opened handles refer to their position
+    async fn check_compactable(&mut self) -> Result<(), Error<E>> {
+        let context = self.meta.context.clone();
+        let fs_info = self.meta.fs_info;
+        let mut iter = EntryIter::new(&mut self.meta).await?;
+        loop {
+            let entry = match iter.next().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) | Err(Error::Operation(OperationError::EOF)) => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let entry_type = RawEntryType::from(entry[0]);
+            if !entry_type.in_use() || entry_type.entry_type() != Ok(EntryType::FileDirectory) {
+                continue;
+            }
+            let id = EntryID { sector_id: iter.sector_ref.id(&fs_info), index: iter.index };
+            if acquire!(context).opened_entries.contains(id) {
+                return Err(OperationError::AlreadyOpen.into());
+            }
+            let location = iter.location();
+            let file_directory: FileDirectory = bytemuck::cast(entry);
+            let secondary_count = file_directory.secondary_count as usize;
+            if secondary_count < 2 || secondary_count >= Pending::new().capacity() {
+                return Err(DataError::Metadata(location).into());
+            }
+            for i in 0..secondary_count {
+                match iter.next().await? {
+                    Some(entry) if is_secondary(&entry, i == 0, true) => (),
+                    _ => return Err(DataError::Metadata(location).into()),
+                }
+            }
+        }
+    }
+
+    /// Rewrite this directory with inuse entrysets packed together in order,
+    /// entries after them are zeroed and clusters no longer needed are released.
+    /// Like newly created ones, entrysets are moved to next sector instead of
+    /// spanning sectors, unless they are larger than a sector.
+    /// Critical entries of root directory stay where they are.
+    ///
+    /// Fails with `AlreadyOpen` if any file or directory in this directory is opened.
+    pub async fn compact(&mut self) -> Result<(), Error<E>> {
+        debug!("Compact directory at cluster {}", self.meta.sector_ref.cluster_id);
+        self.check_compactable().await?;
+        self.lookup_cache.clear();
+        self.relocate().await;
+
+        let fs_info = self.meta.fs_info;
+        let entries_per_sector = fs_info.sector_size() as usize / ENTRY_SIZE;
+        let io = self.meta.io.clone();
+        // Writer never overtakes reader, so entries are moved towards beginning in place
+        let mut writer = self.meta.clone();
+        let mut write_ref = EntryRef::new(self.meta.sector_ref, 0);
+        let mut num_clusters = 1;
+        let mut iter = EntryIter::new(&mut self.meta).await?;
+        let mut pending = Pending::new();
+        let unused: u8 = RawEntryType::new(EntryType::Filename, false).into();
+        let end_ref = loop {
+            let entry = match iter.next().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break Some(EntryRef::new(iter.sector_ref, iter.index)),
+                // Directory full without end of directory entry
+                Err(Error::Operation(OperationError::EOF)) => break None,
+                Err(e) => return Err(e),
+            };
+            let entry_type = RawEntryType::from(entry[0]);
+            if !entry_type.in_use() {
+                continue;
+            }
+            let read_ref = EntryRef::new(iter.sector_ref, iter.index);
+            if entry_type.is_critical() {
+                // Critical entries of root directory are never moved,
+                // entries skipped before them are marked as unused instead
+                let read_id =
+                    EntryID { sector_id: read_ref.sector_ref.id(&fs_info), index: read_ref.index };
+                loop {
+                    if write_ref.index as usize == entries_per_sector {
+                        let sector_ref = writer.next(write_ref.sector_ref).await?;
+                        num_clusters +=
+                            (sector_ref.cluster_id != write_ref.sector_ref.cluster_id) as u32;
+                        write_ref = EntryRef::new(sector_ref, 0);
+                    }
+                    let sector_id = write_ref.sector_ref.id(&fs_info);
+                    if (EntryID { sector_id, index: write_ref.index }) == read_id {
+                        break;
+                    }
+                    let offset = write_ref.index as usize * ENTRY_SIZE;
+                    acquire!(io).write(sector_id, offset, &[unused]).await?;
+                    write_ref.index += 1;
+                }
+                write_ref.index += 1;
+                continue;
+            }
+            pending.push((read_ref, entry)).ok();
+            if entry_type.entry_type() == Ok(EntryType::FileDirectory) {
+                let file_directory: FileDirectory = bytemuck::cast(entry);
+                for _ in 0..file_directory.secondary_count {
+                    let entry = iter.next().await?.unwrap();
+                    pending.push((EntryRef::new(iter.sector_ref, iter.index), entry)).ok();
+                }
+            }
+
+            let index = write_ref.index as usize;
+            let same_sector = read_ref.sector_ref.id(&fs_info) == write_ref.sector_ref.id(&fs_info);
+            let fits = index + pending.len() <= entries_per_sector;
+            if index > 0 && !fits && pending.len() <= entries_per_sector && !same_sector {
+                let sector_id = write_ref.sector_ref.id(&fs_info);
+                for i in index..entries_per_sector {
+                    acquire!(io).write(sector_id, i * ENTRY_SIZE, &[unused]).await?;
+                }
+                write_ref.index = entries_per_sector as u8 - 1;
+            } else {
+                write_ref.index = write_ref.index.wrapping_sub(1);
+            }
+            for (read_ref, entry) in pending.iter() {
+                write_ref.index = write_ref.index.wrapping_add(1);
+                if write_ref.index as usize == entries_per_sector {
+                    let sector_ref = writer.next(write_ref.sector_ref).await?;
+                    num_clusters +=
+                        (sector_ref.cluster_id != write_ref.sector_ref.cluster_id) as u32;
+                    write_ref = EntryRef::new(sector_ref, 0);
+                }
+                let sector_id = write_ref.sector_ref.id(&fs_info);
+                if sector_id != read_ref.sector_ref.id(&fs_info)
+                    || write_ref.index != read_ref.index
+                {
+                    let offset = write_ref.index as usize * ENTRY_SIZE;
+                    acquire!(io).write(sector_id, offset, entry).await?;
+                }
+            }
+            write_ref.index += 1;
+            pending.clear();
+        };
+        drop(iter);
+
+        self.release_after(writer, write_ref, num_clusters, end_ref).await
+    }
+
+    /// Release clusters after the one containing last inuse entry, entries remained after
+    /// last inuse entry are zeroed so that directory ends there.
+    /// Entries are not moved, so this is allowed even if some of them are opened.
+    pub async fn shrink(&mut self) -> Result<(), Error<E>> {
+        debug!("Shrink directory at cluster {}", self.meta.sector_ref.cluster_id);
+        let writer = self.meta.clone();
+        let mut next_ref = EntryRef::new(self.meta.sector_ref, 0);
+        let (mut num_clusters, mut cluster_id) = (1, self.meta.sector_ref.cluster_id);
+        let mut iter = EntryIter::new(&mut self.meta).await?;
+        let mut num_seen = 1;
+        let end_ref = loop {
+            let entry = match iter.next().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break Some(EntryRef::new(iter.sector_ref, iter.index)),
+                Err(Error::Operation(OperationError::EOF)) => break None,
+                Err(e) => return Err(e),
+            };
+            if iter.sector_ref.cluster_id != cluster_id {
+                cluster_id = iter.sector_ref.cluster_id;
+                num_seen += 1;
+            }
+            if RawEntryType::from(entry[0]).in_use() {
+                next_ref = EntryRef::new(iter.sector_ref, iter.index + 1);
+                num_clusters = num_seen;
+            }
+        };
+        drop(iter);
+        self.release_after(writer, next_ref, num_clusters, end_ref).await
+    }
+
+    /// Zero entries from `entry_ref` until old end of directory, then release clusters
+    /// beyond first `num_clusters` clusters
+    async fn release_after(
+        &mut self,
+        mut writer: MetaFileDirectory<IO>,
+        mut entry_ref: EntryRef,
+        num_clusters: u32,
+        end_ref: Option<EntryRef>,
+    ) -> Result<(), Error<E>> {
+        let fs_info = self.meta.fs_info;
+        let entries_per_sector = fs_info.sector_size() as usize / ENTRY_SIZE;
+        let io = self.meta.io.clone();
+        let empty = [0u8; ENTRY_SIZE];
+        let mut total_clusters = num_clusters;
+        let mut zeroing = true;
+        loop {
+            if entry_ref.index as usize == entries_per_sector {
+                let sector_ref = match writer.next(entry_ref.sector_ref).await {
+                    Ok(sector_ref) => sector_ref,
+                    Err(Error::Operation(OperationError::EOF)) => break,
+                    Err(e) => return Err(e),
+                };
+                total_clusters += (sector_ref.cluster_id != entry_ref.sector_ref.cluster_id) as u32;
+                entry_ref = EntryRef::new(sector_ref, 0);
+            }
+            if let Some(end_ref) = end_ref {
+                let end_id =
+                    EntryID { sector_id: end_ref.sector_ref.id(&fs_info), index: end_ref.index };
+                let id = EntryID {
+                    sector_id: entry_ref.sector_ref.id(&fs_info),
+                    index: entry_ref.index,
+                };
+                zeroing &= id != end_id;
+            }
+            if !zeroing {
+                // Only counting clusters from now on
+                entry_ref.index = entries_per_sector as u8;
+                continue;
+            }
+            let sector_id = entry_ref.sector_ref.id(&fs_info);
+            let offset = entry_ref.index as usize * ENTRY_SIZE;
+            acquire!(io).write(sector_id, offset, &empty).await?;
+            entry_ref.index += 1;
+        }
+
+        trace!("Directory uses {} of {} clusters", num_clusters, total_clusters);
+        let cluster_size = fs_info.cluster_size() as u64;
+        let metadata = &mut self.meta.metadata;
+        metadata.stream_extension.data_length = (total_clusters as u64 * cluster_size).into();
+        metadata.set_length(num_clusters as u64 * cluster_size);
+        self.meta.shrink_to_fit().await?;
+        self.meta.sync().await?;
+        acquire!(io).auto_flush().await?;
+        Ok(())
+    }
+}
@@ -1,18 +1,38 @@
 use core::fmt::Debug;
-use core::mem;
 
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use super::super::entryset::EntryRef;
 use super::super::meta::MetaFileDirectory;
-use crate::error::Error;
+use crate::error::{Error, Location};
 use crate::fs::SectorRef;
 use crate::region::data::entry_type::RawEntryType;
 use crate::region::data::entryset::{RawEntry, ENTRY_SIZE};
 use crate::sync::acquire;
 
-pub(crate) struct EntryIter<'a, IO> {
+/// Sectors of a cluster fetched by a single IO call are limited to this size
+#[cfg(feature = "alloc")]
+const MAX_BATCH_SIZE: usize = 64 * 1024;
+#[cfg(not(feature = "alloc"))]
+const MAX_BATCH_SIZE: usize = 4096;
+
+#[cfg(feature = "alloc")]
+type Entries = Vec<RawEntry>;
+// Max batch size / entry size = 4096 / 32 = 128
+#[cfg(not(feature = "alloc"))]
+type Entries = heapless::Vec<RawEntry, { MAX_BATCH_SIZE / ENTRY_SIZE }>;
+
+pub(crate) struct EntryIter<'a, IO: crate::io::Lifetime> {
     meta: &'a mut MetaFileDirectory<IO>,
-    entries: &'a [[RawEntry; 16]],
+    // Copy of sectors fetched, so that entries stay valid while IO is used elsewhere
+    entries: Entries,
+    /// Index of first sector fetched within current cluster
+    batch_start: u32,
     pub sector_ref: SectorRef,
     pub index: u8,
+    #[cfg(feature = "tracing")]
+    num_sectors: u32,
 }
 
 #[cfg_attr(not(feature = "async"), deasync::deasync)]
@@ -20,12 +40,57 @@ impl<'a, E: Debug, IO: crate::io::IO<Error = E>> EntryIter<'a, IO> {
     pub(crate) async fn new(
         meta: &'a mut MetaFileDirectory<IO>,
     ) -> Result<EntryIter<'a, IO>, Error<E>> {
-        let sector_ref = meta.sector_ref;
-        let mut io = acquire!(meta.io);
-        let sector = io.read(sector_ref.id(&meta.fs_info)).await?;
-        let entries = unsafe { mem::transmute(sector) };
-        drop(io);
-        Ok(Self { meta, entries, sector_ref, index: u8::MAX })
+        let entry_ref = EntryRef::new(meta.sector_ref, 0);
+        Self::starts_at(meta, entry_ref).await
+    }
+
+    /// Next entry yielded will be the entry specified by entry_ref
+    pub(crate) async fn starts_at(
+        meta: &'a mut MetaFileDirectory<IO>,
+        entry_ref: EntryRef,
+    ) -> Result<EntryIter<'a, IO>, Error<E>> {
+        let sector_ref = entry_ref.sector_ref;
+        let entries = Entries::new();
+        let index = entry_ref.index.wrapping_sub(1);
+        let batch_start = sector_ref.sector_index;
+        #[cfg(feature = "tracing")]
+        let mut iter = Self { meta, entries, batch_start, sector_ref, index, num_sectors: 0 };
+        #[cfg(not(feature = "tracing"))]
+        let mut iter = Self { meta, entries, batch_start, sector_ref, index };
+        // Most likely only a few entries are needed by caller
+        iter.fetch(false).await?;
+        Ok(iter)
+    }
+
+    /// Fetch current sector, or sectors from current one until end of cluster in one go
+    /// if `batch`, since sectors of a cluster are always contiguous
+    async fn fetch(&mut self, batch: bool) -> Result<(), Error<E>> {
+        let fs_info = self.meta.fs_info;
+        let sector_size = fs_info.sector_size() as usize;
+        let remain = fs_info.sectors_per_cluster() - self.sector_ref.sector_index;
+        let count = match batch {
+            true => remain.min((MAX_BATCH_SIZE / sector_size) as u32).max(1),
+            false => 1,
+        };
+        let sector_id = self.sector_ref.id(&fs_info);
+        self.batch_start = self.sector_ref.sector_index;
+        self.entries.clear();
+        let mut io = acquire!(self.meta.io);
+        if count == 1 {
+            let sector = io.read(sector_id).await?;
+            self.entries.extend(bytemuck::cast_slice::<_, RawEntry>(sector).iter().copied());
+        } else {
+            let length = count as usize * sector_size / ENTRY_SIZE;
+            self.entries.extend(core::iter::repeat_n([0; ENTRY_SIZE], length));
+            let buf = bytemuck::cast_slice_mut(&mut self.entries[..]);
+            io.read_many(sector_id, count as usize, buf).await?;
+        }
+        #[cfg(feature = "tracing")]
+        {
+            self.num_sectors += count;
+            crate::instrument::record!(sectors = self.num_sectors);
+        }
+        Ok(())
     }
 
     pub(crate) async fn skip(&mut self, num_entries: u8) -> Result<(), Error<E>> {
@@ -33,18 +98,28 @@ impl<'a, E: Debug, IO: crate::io::IO<Error = E>> EntryIter<'a, IO> {
         let sector_size = self.meta.fs_info.sector_size() as usize;
         if (self.index as usize * ENTRY_SIZE) >= sector_size {
             self.index -= (sector_size / ENTRY_SIZE) as u8;
-            self.sector_ref = self.meta.next(self.sector_ref).await?;
-            let mut io = acquire!(self.meta.io);
-            let sector = io.read(self.sector_ref.id(&self.meta.fs_info)).await?;
-            self.entries = unsafe { mem::transmute(sector) };
+            let sector_ref = self.meta.next(self.sector_ref).await?;
+            let same_cluster = sector_ref.cluster_id == self.sector_ref.cluster_id;
+            self.sector_ref = sector_ref;
+            let num_batched = (self.entries.len() * ENTRY_SIZE / sector_size) as u32;
+            if !same_cluster || sector_ref.sector_index >= self.batch_start + num_batched {
+                self.fetch(true).await?;
+            }
         }
         Ok(())
     }
 
-    pub async fn next(&mut self) -> Result<Option<&'a RawEntry>, Error<E>> {
+    /// Location of entry last yielded
+    pub fn location(&self) -> Location {
+        let sector_id = self.sector_ref.id(&self.meta.fs_info);
+        Location::entry(self.meta.sector_ref.cluster_id, sector_id, self.index)
+    }
+
+    pub async fn next(&mut self) -> Result<Option<RawEntry>, Error<E>> {
         self.skip(1).await?;
-        let index = self.index as usize;
-        let entry = &self.entries[index / 16][index % 16];
+        let sector_size = self.meta.fs_info.sector_size() as usize;
+        let offset = (self.sector_ref.sector_index - self.batch_start) as usize * sector_size;
+        let entry = self.entries[offset / ENTRY_SIZE + self.index as usize];
         let entry_type: RawEntryType = entry[0].into();
         Ok(if !entry_type.is_end_of_directory() { Some(entry) } else { None })
     }
@@ -0,0 +1,136 @@
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use super::super::entryset::EntryRef;
+#[cfg(not(feature = "alloc"))]
+use crate::region::data::entryset::primary::name_hash;
+
+/// Bounded upcased-name to entry-ref cache, least recently used entry evicted first
+#[cfg(feature = "alloc")]
+#[derive(Default)]
+pub(crate) struct LookupCache {
+    capacity: usize,
+    entries: Vec<(String, EntryRef)>,
+    /// Generation of IO caches when entries cached
+    generation: u32,
+}
+
+#[cfg(feature = "alloc")]
+impl LookupCache {
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        if self.entries.len() > capacity {
+            self.entries.drain(..self.entries.len() - capacity);
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn get(&mut self, upcase_name: &str) -> Option<EntryRef> {
+        let index = self.entries.iter().position(|(name, _)| name == upcase_name)?;
+        let entry = self.entries.remove(index);
+        let entry_ref = entry.1;
+        self.entries.push(entry);
+        Some(entry_ref)
+    }
+
+    pub fn insert(&mut self, upcase_name: &str, entry_ref: EntryRef) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.remove(upcase_name);
+        if self.entries.len() >= self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push((String::from(upcase_name), entry_ref));
+    }
+
+    pub fn remove(&mut self, upcase_name: &str) {
+        self.entries.retain(|(name, _)| name != upcase_name)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear()
+    }
+
+    /// Entries cached before caches invalidated are discarded
+    pub fn revalidate(&mut self, generation: u32) {
+        if self.generation != generation {
+            self.generation = generation;
+            self.entries.clear();
+        }
+    }
+}
+
+/// Maximum number of name lookups cached by a directory handle without `alloc` feature
+#[cfg(not(feature = "alloc"))]
+pub const MAX_LOOKUP_CACHE_ENTRIES: usize = 4;
+
+/// Without `alloc` feature names are not kept, entries are keyed by name hash and length
+/// instead and stored inline, a hash collision is ruled out when caller verifies the entry
+#[cfg(not(feature = "alloc"))]
+#[derive(Default)]
+pub(crate) struct LookupCache {
+    capacity: usize,
+    entries: heapless::Vec<((u16, usize), EntryRef), MAX_LOOKUP_CACHE_ENTRIES>,
+    /// Generation of IO caches when entries cached
+    generation: u32,
+}
+
+#[cfg(not(feature = "alloc"))]
+impl LookupCache {
+    fn key(upcase_name: &str) -> (u16, usize) {
+        (name_hash(upcase_name), upcase_name.len())
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.min(MAX_LOOKUP_CACHE_ENTRIES);
+        while self.entries.len() > self.capacity {
+            self.entries.remove(0);
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn get(&mut self, upcase_name: &str) -> Option<EntryRef> {
+        let key = Self::key(upcase_name);
+        let index = self.entries.iter().position(|&(k, _)| k == key)?;
+        let entry = self.entries.remove(index);
+        self.entries.push(entry).ok();
+        Some(entry.1)
+    }
+
+    pub fn insert(&mut self, upcase_name: &str, entry_ref: EntryRef) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.remove(upcase_name);
+        if self.entries.len() >= self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push((Self::key(upcase_name), entry_ref)).ok();
+    }
+
+    pub fn remove(&mut self, upcase_name: &str) {
+        let key = Self::key(upcase_name);
+        self.entries.retain(|&(k, _)| k != key)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear()
+    }
+
+    /// Entries cached before caches invalidated are discarded
+    pub fn revalidate(&mut self, generation: u32) {
+        if self.generation != generation {
+            self.generation = generation;
+            self.entries.clear();
+        }
+    }
+}
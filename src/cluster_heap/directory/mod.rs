@@ -1,51 +1,138 @@
+mod compact;
 mod entry_iter;
+pub(crate) mod lookup_cache;
 
 use core::fmt::Debug;
-use core::mem::{self, MaybeUninit};
-use core::slice;
 
-use alloc::rc::Rc;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 
-use super::entryset::{EntryRef, EntrySet};
+use super::entryset::{EntryRef, EntrySet, WalkPosition};
 use super::file::File;
 use super::meta::MetaFileDirectory;
 use super::metadata::Metadata;
-use crate::error::{DataError, Error, ImplementationError, InputError, OperationError};
-use crate::file::{FileOptions, TouchOptions, MAX_FILENAME_SIZE};
+use crate::error::{DataError, Error, InputError, Location, OperationError};
+use crate::file::{FileOptions, FindOptions, TouchOptions, MAX_FILENAME_SIZE};
 use crate::fs::SectorRef;
+use crate::glob;
 use crate::region::data::entry_type::{EntryType, RawEntryType};
-use crate::region::data::entryset::primary::{name_hash, DateTime, FileDirectory};
+use crate::region::data::entryset::primary::{name_hash, Checksum, DateTime, FileDirectory};
 use crate::region::data::entryset::secondary::{Filename, Secondary, StreamExtension};
-use crate::region::data::entryset::{checksum, RawEntry, ENTRY_SIZE};
-use crate::sync::acquire;
-use crate::types::ClusterID;
+use crate::region::data::entryset::{checksum, primary_checksum, RawEntry, ENTRY_SIZE};
+use crate::sync::{acquire, Ref};
+use crate::types::{ClusterID, SectorID};
 use crate::upcase_table::UpcaseTable;
+use crate::volume::ParseMode;
 use entry_iter::EntryIter;
+use lookup_cache::LookupCache;
 
-pub struct Directory<E: Debug, IO: crate::io::IO<Error = E>> {
+/// Entries of an entryset, file directory and stream extension entry
+/// followed by up to 17 filename entries
+type Entries = heapless::Vec<RawEntry, 19>;
+
+/// Secondary entries of an entryset walked through, up to 255 including vendor extensions
+#[cfg(feature = "alloc")]
+type Secondaries = Vec<RawEntry>;
+#[cfg(not(feature = "alloc"))]
+type Secondaries = heapless::Vec<RawEntry, 255>;
+
+/// Whether entry is a secondary entry of an entryset with specified inuse state,
+/// either stream extension or one following stream extension
+fn is_secondary(entry: &RawEntry, stream_extension: bool, in_use: bool) -> bool {
+    let entry_type = RawEntryType::from(entry[0]);
+    let expected = match entry_type.entry_type() {
+        Ok(EntryType::StreamExtension) => stream_extension,
+        Ok(EntryType::Filename | EntryType::VendorExtension | EntryType::VendorAllocation) => {
+            !stream_extension
+        }
+        _ => false,
+    };
+    expected && entry_type.in_use() == in_use
+}
+
+/// Directory handle, names of entrysets walked through are stored in buffers of `N` bytes,
+/// walking through a name that doesn't fit returns `InputError::NameTooLong`
+pub struct Directory<E: Debug, IO: crate::io::IO<Error = E>, const N: usize = MAX_FILENAME_SIZE> {
     pub(crate) meta: MetaFileDirectory<IO>,
-    pub(crate) upcase_table: Rc<UpcaseTable>,
+    pub(crate) upcase_table: Ref<UpcaseTable>,
+    pub(crate) lookup_cache: LookupCache,
 }
 
-pub enum FileOrDirectory<E: Debug, IO: crate::io::IO<Error = E>> {
+pub enum FileOrDirectory<E: Debug, IO: crate::io::IO<Error = E>, const N: usize = MAX_FILENAME_SIZE>
+{
     File(File<E, IO>),
-    Directory(Directory<E, IO>),
+    Directory(Directory<E, IO, N>),
+}
+
+impl<E: Debug, IO: crate::io::IO<Error = E>, const N: usize> Debug for FileOrDirectory<E, IO, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::File(file) => file.fmt(f),
+            Self::Directory(directory) => directory.fmt(f),
+        }
+    }
+}
+
+impl<E: Debug, IO: crate::io::IO<Error = E>, const N: usize> Debug for Directory<E, IO, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut debug = f.debug_struct("Directory");
+        #[cfg(feature = "alloc")]
+        debug.field("name", &self.meta.metadata.name);
+        debug
+            .field("entry_ref", &format_args!("{}", self.meta.metadata.entry_ref))
+            .field("first_cluster", &format_args!("{}", self.meta.sector_ref.cluster_id))
+            .field("size", &self.meta.metadata.length())
+            .finish()
+    }
 }
 
 #[cfg_attr(not(feature = "async"), deasync::deasync)]
-impl<E: Debug, IO: crate::io::IO<Error = E>> Directory<E, IO> {
-    async fn walk_matches<F, H, R>(&mut self, f: F, mut h: H) -> Result<Option<R>, Error<E>>
+impl<E: Debug, IO: crate::io::IO<Error = E>, const N: usize> Directory<E, IO, N> {
+    async fn walk_matches<F, H, R>(&mut self, f: F, h: H) -> Result<Option<R>, Error<E>>
     where
         F: Fn(&FileDirectory, &Secondary<StreamExtension>) -> bool,
-        H: FnMut(&EntrySet) -> Option<R>,
+        H: FnMut(&EntrySet<N>) -> Option<R>,
     {
-        let mut iter = EntryIter::new(&mut self.meta).await?;
+        let entry_ref = EntryRef::new(self.meta.sector_ref, 0);
+        self.walk_matches_from(entry_ref, f, h).await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "walk",
+            level = "debug",
+            skip_all,
+            fields(directory = %self.meta.sector_ref.cluster_id, sectors = 1)
+        )
+    )]
+    async fn walk_matches_from<F, H, R>(
+        &mut self,
+        entry_ref: EntryRef,
+        f: F,
+        mut h: H,
+    ) -> Result<Option<R>, Error<E>>
+    where
+        F: Fn(&FileDirectory, &Secondary<StreamExtension>) -> bool,
+        H: FnMut(&EntrySet<N>) -> Option<R>,
+    {
+        let io = self.meta.io.clone();
+        let (forensic, strict, tolerant) = {
+            let io = acquire!(io);
+            let forensic = io.forensic.is_some();
+            let tolerant = forensic || io.options.on_damaged.is_some();
+            (forensic, io.options.parse_mode == ParseMode::Strict, tolerant)
+        };
+        let relocations = self.relocations().await;
+        let mut iter = EntryIter::starts_at(&mut self.meta, entry_ref).await?;
         let mut file_directory: FileDirectory;
         let mut stream_extension: Secondary<StreamExtension>;
         loop {
-            let entry = match iter.next().await? {
-                Some(entry) => entry,
-                None => break,
+            let entry = match iter.next().await {
+                Ok(Some(entry)) => entry,
+                // Directory full without end of directory entry
+                Ok(None) | Err(Error::Operation(OperationError::EOF)) => break,
+                Err(e) => return Err(e),
             };
             let entry_type: RawEntryType = entry[0].into();
             match entry_type.entry_type() {
@@ -55,49 +142,115 @@ impl<E: Debug, IO: crate::io::IO<Error = E>> Directory<E, IO> {
                 }
                 Err(t) => {
                     warn!("Unexpected entry type {}", t);
-                    return Err(DataError::Metadata.into());
+                    let broken = strict || (entry_type.in_use() && !entry_type.is_benign());
+                    if forensic || (tolerant && broken) {
+                        acquire!(io).skipped(iter.location());
+                    } else if broken {
+                        return Err(DataError::Metadata(iter.location()).into());
+                    }
+                    continue;
                 }
             };
-            file_directory = unsafe { mem::transmute(*entry) };
+            file_directory = bytemuck::cast(entry);
+            let location = iter.location();
             if file_directory.secondary_count < 2 {
-                return Err(DataError::Metadata.into());
+                if !tolerant {
+                    return Err(DataError::Metadata(location).into());
+                }
+                acquire!(io).skipped(location);
+                continue;
             }
             let entryset_sector_ref = iter.sector_ref;
             let entryset_index = iter.index;
-            let entry = iter.next().await?.unwrap();
-            stream_extension = unsafe { mem::transmute(*entry) };
+            // Types of secondary entries are validated in tolerant or strict mode only
+            let mut secondaries = Secondaries::new();
+            let mut damaged = false;
+            for i in 0..file_directory.secondary_count {
+                let entry = match iter.next().await? {
+                    Some(entry) => entry,
+                    None if tolerant => {
+                        acquire!(io).skipped(location);
+                        return Ok(None);
+                    }
+                    None => return Err(DataError::Metadata(location).into()),
+                };
+                if (tolerant || strict) && !is_secondary(&entry, i == 0, entry_type.in_use()) {
+                    damaged = true;
+                    break;
+                }
+                // Never full since secondary count is at most 255
+                secondaries.extend(Some(entry));
+            }
+            if damaged {
+                if !tolerant {
+                    return Err(DataError::Metadata(location).into());
+                }
+                acquire!(io).skipped(location);
+                // Unexpected entry may start another entryset
+                let entry_ref = EntryRef::new(iter.sector_ref, iter.index);
+                iter = EntryIter::starts_at(&mut self.meta, entry_ref).await?;
+                continue;
+            }
+            stream_extension = bytemuck::cast(secondaries[0]);
             if !f(&file_directory, &stream_extension) {
-                iter.skip(file_directory.secondary_count - 2).await?;
                 continue;
             }
-            let array: MaybeUninit<[u16; MAX_FILENAME_SIZE / 2]> = MaybeUninit::uninit();
-            let mut array: [u16; MAX_FILENAME_SIZE / 2] = unsafe { array.assume_init() };
-            for i in 0..(file_directory.secondary_count - 1) as usize {
-                if cfg!(feature = "limit-max-filename-size") && (i + 1) * 15 > array.len() {
-                    continue;
+            // Checksum of a deleted entryset covers its entry types before deletion
+            let mut checksum = primary_checksum(&file_directory, &stream_extension);
+            for entry in secondaries[1..].iter() {
+                entry.iter().for_each(|&byte| checksum.write(byte as u16));
+            }
+            let mismatched =
+                entry_type.in_use() && checksum.sum() != file_directory.set_checksum.to_ne();
+            let reserved = !file_directory.reserved_clear()
+                || !stream_extension.custom_defined.reserved_clear();
+            if strict && !forensic && (mismatched || reserved) {
+                if !tolerant {
+                    return Err(DataError::Metadata(location).into());
                 }
-                let entry: &Filename = unsafe { mem::transmute(iter.next().await?.unwrap()) };
-                let slice = &unsafe { entry.filename.assume_init_ref() }[..];
-                array[i * 15..(i + 1) * 15].copy_from_slice(slice);
+                acquire!(io).skipped(location);
+                continue;
             }
-            let name_length = stream_extension.custom_defined.name_length as usize;
-            for i in 0..name_length {
-                array[i] = u16::from_le(array[i]);
+            if mismatched {
+                warn!("Ignored checksum mismatch of entryset{}", location);
+                if let Some(report) = acquire!(io).forensic.as_mut() {
+                    report.checksum_mismatches += 1;
+                }
             }
-            let slice = unsafe { slice::from_raw_parts(&array[0], name_length) };
-            let mut buf: [u8; MAX_FILENAME_SIZE] = unsafe { mem::transmute(array) };
-            let mut cursor = 0;
-            for &ch in slice {
-                let ch = unsafe { char::from_u32_unchecked(ch as u32) };
-                ch.encode_utf8(&mut buf[cursor..]);
-                cursor += ch.len_utf8();
+            if reserved {
+                warn!("Ignored nonzero reserved bytes of entryset{}", location);
+            }
+            let name_length = stream_extension.custom_defined.name_length as usize;
+            let mut buf = [0u8; N];
+            let (mut cursor, mut remain) = (0, name_length);
+            // High surrogate at the end of a filename entry pairs with the next one
+            let mut surrogate: Option<u16> = None;
+            for &entry in secondaries[1..].iter() {
+                let entry: Filename = bytemuck::cast(entry);
+                let filename = &entry.filename;
+                let chunk = &filename[..remain.min(filename.len())];
+                remain -= chunk.len();
+                let mut units: heapless::Vec<u16, 16> = surrogate.take().into_iter().collect();
+                units.extend(chunk.iter().map(|unit| unit.to_ne()));
+                if remain > 0 && matches!(units.last(), Some(0xD800..=0xDBFF)) {
+                    surrogate = units.pop();
+                }
+                for ch in char::decode_utf16(units) {
+                    let ch = ch.unwrap_or(char::REPLACEMENT_CHARACTER);
+                    if cursor + ch.len_utf8() > N {
+                        return Err(InputError::NameTooLong.into());
+                    }
+                    ch.encode_utf8(&mut buf[cursor..]);
+                    cursor += ch.len_utf8();
+                }
             }
             let entryset = EntrySet {
                 name_bytes: buf,
-                name_length: cursor as u8,
+                name_length: cursor as u16,
                 file_directory,
                 stream_extension,
                 entry_ref: EntryRef::new(entryset_sector_ref, entryset_index as u8),
+                relocations,
             };
             if let Some(retval) = h(&entryset) {
                 return Ok(Some(retval));
@@ -107,9 +260,9 @@ impl<E: Debug, IO: crate::io::IO<Error = E>> Directory<E, IO> {
     }
 
     /// Walk through directory, including not inuse entries
-    pub async fn walk<H>(&mut self, mut h: H) -> Result<Option<EntrySet>, Error<E>>
+    pub async fn walk<H>(&mut self, mut h: H) -> Result<Option<EntrySet<N>>, Error<E>>
     where
-        H: FnMut(&EntrySet) -> bool,
+        H: FnMut(&EntrySet<N>) -> bool,
     {
         self.walk_matches(
             |_, _| true,
@@ -124,26 +277,212 @@ impl<E: Debug, IO: crate::io::IO<Error = E>> Directory<E, IO> {
         .await
     }
 
+    /// Check if directory contains no inuse file or directory,
+    /// stops at the first inuse entryset instead of walking through whole directory
+    pub async fn is_empty(&mut self) -> Result<bool, Error<E>> {
+        let mut iter = EntryIter::new(&mut self.meta).await?;
+        while let Some(entry) = iter.next().await? {
+            let entry_type: RawEntryType = entry[0].into();
+            if entry_type.in_use() && entry_type.entry_type() == Ok(EntryType::FileDirectory) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Number of bytes allocated to this directory, size of root directory is not recorded
+    /// anywhere so that its cluster chain is followed
+    pub async fn capacity(&mut self) -> Result<u64, Error<E>> {
+        let capacity = self.meta.metadata.capacity();
+        if capacity > 0 {
+            return Ok(capacity);
+        }
+        let max_clusters = acquire!(self.meta.context).allocation_bitmap.num_clusters();
+        let last_sector = self.meta.fs_info.sectors_per_cluster() - 1;
+        let first_cluster = self.meta.sector_ref.cluster_id;
+        let mut sector_ref = SectorRef::new(first_cluster, last_sector);
+        let mut num_clusters = 1;
+        loop {
+            match self.meta.next(sector_ref).await {
+                Ok(next) => sector_ref = SectorRef::new(next.cluster_id, last_sector),
+                Err(Error::Operation(OperationError::EOF)) => break,
+                Err(e) => return Err(e),
+            }
+            num_clusters += 1;
+            // Cycle also exceeds number of clusters
+            if num_clusters > max_clusters {
+                return Err(DataError::FATChain(Location::cluster(first_cluster)).into());
+            }
+        }
+        Ok(num_clusters as u64 * self.meta.fs_info.cluster_size() as u64)
+    }
+
+    /// Relocation generation of this directory, see `walk_from`
+    async fn relocations(&self) -> u32 {
+        acquire!(self.meta.context).relocations(self.meta.sector_ref.cluster_id)
+    }
+
+    /// Walk positions taken earlier are no longer valid
+    async fn relocate(&mut self) {
+        acquire!(self.meta.context).relocate(self.meta.sector_ref.cluster_id)
+    }
+
+    /// Walk through directory like `walk` but starts from specified position,
+    /// so that a stopped walk could be resumed from position of the returned entryset.
+    ///
+    /// Files and directories created or deleted in between leave others where they are,
+    /// so that each entryset existing throughout is returned exactly once, while deleted
+    /// ones are returned as not inuse and new ones may or may not be returned. Once entries
+    /// are relocated by `compact`, `rename` or `move_to`, resuming from an earlier position
+    /// fails with `Relocated`, walk should be restarted.
+    pub async fn walk_from<H>(
+        &mut self,
+        position: WalkPosition,
+        mut h: H,
+    ) -> Result<Option<EntrySet<N>>, Error<E>>
+    where
+        H: FnMut(&EntrySet<N>) -> bool,
+    {
+        if position.1 != self.relocations().await {
+            return Err(OperationError::Relocated.into());
+        }
+        self.walk_matches_from(
+            position.0,
+            |_, _| true,
+            |entryset| if h(entryset) { Some(entryset.clone()) } else { None },
+        )
+        .await
+    }
+
     /// Find a file or directory matching specified name
-    pub async fn find(&mut self, name: &str) -> Result<Option<EntrySet>, Error<E>> {
-        let name_length = name.chars().count();
+    pub async fn find(&mut self, name: &str) -> Result<Option<EntrySet<N>>, Error<E>> {
+        self.find_with_options(name, FindOptions::default()).await
+    }
+
+    /// Find a file or directory matching specified name with specified options
+    pub async fn find_with_options(
+        &mut self,
+        name: &str,
+        opts: FindOptions,
+    ) -> Result<Option<EntrySet<N>>, Error<E>> {
+        if name.len() > N {
+            return Err(InputError::NameTooLong.into());
+        }
         let upcase_table = self.upcase_table.clone();
-        let hash = name_hash(&self.upcase_table.to_upper(name));
-        self.walk_matches(
-            |file_directory, stream_extension| -> bool {
-                let entry_type = file_directory.entry_type;
-                if !entry_type.in_use() {
-                    return false;
-                }
-                let length = stream_extension.custom_defined.name_length;
-                let name_hash = stream_extension.custom_defined.name_hash.to_ne();
-                if length as usize != name_length || name_hash != hash {
-                    return false;
+        let upcase_name = upcase_table.to_upper(name);
+        // Length and hash of a name differs in another normalization form
+        let normalize = cfg!(feature = "unicode-normalization") && !name.is_ascii();
+        let matches = |entryset: &EntrySet<N>| match opts.case_sensitive {
+            #[cfg(feature = "unicode-normalization")]
+            case_sensitive if normalize => {
+                upcase_table.normalized_equals(name, entryset.name(), case_sensitive)
+            }
+            true => name == entryset.name(),
+            false => upcase_table.equals(name, entryset.name()),
+        };
+        let generation = acquire!(self.meta.io).generation;
+        self.lookup_cache.revalidate(generation);
+        if let Some(entry_ref) = self.lookup_cache.get(&upcase_name) {
+            let option =
+                self.walk_matches_from(entry_ref, |_, _| true, |e| Some(e.clone())).await?;
+            match option {
+                Some(entryset) if entryset.in_use() && matches(&entryset) => {
+                    acquire!(self.meta.io).stats.cache_hits += 1;
+                    return Ok(Some(entryset));
                 }
-                true
-            },
+                _ => self.lookup_cache.remove(&upcase_name),
+            }
+        }
+        if self.lookup_cache.enabled() {
+            acquire!(self.meta.io).stats.cache_misses += 1;
+        }
+        let name_length = name.encode_utf16().count();
+        let hash = name_hash(&upcase_name);
+        let option = self
+            .walk_matches(
+                |file_directory, stream_extension| -> bool {
+                    let entry_type = file_directory.entry_type;
+                    if !entry_type.in_use() {
+                        return false;
+                    }
+                    let length = stream_extension.custom_defined.name_length;
+                    let name_hash = stream_extension.custom_defined.name_hash.to_ne();
+                    if !normalize && (length as usize != name_length || name_hash != hash) {
+                        return false;
+                    }
+                    true
+                },
+                |entryset| if matches(entryset) { Some(entryset.clone()) } else { None },
+            )
+            .await?;
+        if let Some(entryset) = option.as_ref() {
+            self.lookup_cache.insert(&upcase_name, entryset.entry_ref);
+        }
+        Ok(option)
+    }
+
+    /// Sector and entry index within sector where specified entryset starts
+    pub fn entry_location(&self, entryset: &EntrySet<N>) -> (SectorID, u8) {
+        let entry_ref = entryset.entry_ref;
+        (entry_ref.sector_ref.id(&self.meta.fs_info), entry_ref.index)
+    }
+
+    /// Whether specified file or directory in this directory is opened by any handle
+    pub async fn is_open(&self, entryset: &EntrySet<N>) -> bool {
+        let id = entryset.id(&self.meta.fs_info);
+        acquire!(self.meta.context).opened_entries.contains(id)
+    }
+
+    /// Read raw directory entries of specified entryset, mainly for debugging purpose
+    #[cfg(feature = "alloc")]
+    pub async fn raw_entries(&mut self, entryset: &EntrySet<N>) -> Result<Vec<RawEntry>, Error<E>> {
+        let fs_info = self.meta.fs_info;
+        let sector_size = fs_info.sector_size() as usize;
+        let count = entryset.file_directory.secondary_count as usize + 1;
+        let mut sector_ref = entryset.entry_ref.sector_ref;
+        let mut offset = entryset.entry_ref.index as usize * ENTRY_SIZE;
+        let mut entries = Vec::with_capacity(count);
+        loop {
+            let mut io = acquire!(self.meta.io);
+            let sector = io.read(sector_ref.id(&fs_info)).await?;
+            while offset < sector_size && entries.len() < count {
+                entries.push(sector[offset..offset + ENTRY_SIZE].try_into().unwrap());
+                offset += ENTRY_SIZE;
+            }
+            if entries.len() == count {
+                return Ok(entries);
+            }
+            drop(io);
+            sector_ref = self.meta.next(sector_ref).await?;
+            offset = 0;
+        }
+    }
+
+    /// Cache up to specified number of name lookups of this directory handle,
+    /// so that finding the same name repeatedly avoids walking through whole directory,
+    /// disabled by default and specifying zero disables it.
+    /// Without `alloc` feature capacity is limited to `MAX_LOOKUP_CACHE_ENTRIES`.
+    pub fn set_lookup_cache_capacity(&mut self, capacity: usize) {
+        self.lookup_cache.set_capacity(capacity)
+    }
+
+    /// Walk through inuse entries whose name matches specified pattern,
+    /// `*` matches any sequence of characters and `?` matches exactly one character,
+    /// case is folded through upcase table
+    pub async fn find_matching<H>(
+        &mut self,
+        pattern: &str,
+        mut h: H,
+    ) -> Result<Option<EntrySet<N>>, Error<E>>
+    where
+        H: FnMut(&EntrySet<N>) -> bool,
+    {
+        let upcase_table = self.upcase_table.clone();
+        self.walk_matches(
+            |file_directory, _| file_directory.entry_type.in_use(),
             |entryset| {
-                if upcase_table.equals(name, &entryset.name()) {
+                let equals = |left, right| upcase_table.char_equals(left, right);
+                if glob::matches(pattern, entryset.name(), equals) && h(entryset) {
                     Some(entryset.clone())
                 } else {
                     None
@@ -153,35 +492,67 @@ impl<E: Debug, IO: crate::io::IO<Error = E>> Directory<E, IO> {
         .await
     }
 
+    /// Find all files and directories whose name starts with specified prefix
+    #[cfg(feature = "alloc")]
+    pub async fn find_all(&mut self, prefix: &str) -> Result<Vec<EntrySet<N>>, Error<E>> {
+        let prefix_length = prefix.encode_utf16().count();
+        let upcase_table = self.upcase_table.clone();
+        let mut entrysets = Vec::new();
+        self.walk_matches(
+            |file_directory, stream_extension| {
+                let name_length = stream_extension.custom_defined.name_length as usize;
+                file_directory.entry_type.in_use() && name_length >= prefix_length
+            },
+            |entryset| -> Option<()> {
+                if upcase_table.starts_with(entryset.name(), prefix) {
+                    entrysets.push(entryset.clone());
+                }
+                None
+            },
+        )
+        .await?;
+        Ok(entrysets)
+    }
+
+    /// Change options of current directory,
+    /// files and directories opened from current directory inherit these options
+    pub fn change_options(&mut self, f: impl FnOnce(&mut FileOptions)) {
+        f(&mut self.meta.options)
+    }
+
     /// Change current directory timestamp
     pub async fn touch(&mut self, datetime: DateTime, opts: TouchOptions) -> Result<(), Error<E>> {
         self.meta.touch(datetime, opts).await?;
-        acquire!(self.meta.io).flush().await
+        acquire!(self.meta.io).auto_flush().await
     }
 
     /// Open a file or directory
-    pub async fn open(&mut self, entryset: &EntrySet) -> Result<FileOrDirectory<E, IO>, Error<E>> {
+    pub async fn open(
+        &mut self,
+        entryset: &EntrySet<N>,
+    ) -> Result<FileOrDirectory<E, IO, N>, Error<E>> {
         trace!("Open {} on entry-ref {}", entryset.name(), entryset.entry_ref);
         let mut context = acquire!(self.meta.context);
-        if !context.opened_entries.add(entryset.id(&self.meta.fs_info)) {
-            return Err(OperationError::AlreadyOpen.into());
-        }
+        context.opened_entries.add(entryset.id(&self.meta.fs_info))?;
         let cluster_id = entryset.stream_extension.first_cluster.to_ne();
         let file_attributes = entryset.file_directory.file_attributes();
         let sector_ref = SectorRef::new(cluster_id.into(), 0);
+        let fat_chain = self.meta.metadata.stream_extension.general_secondary_flags.fat_chain();
         let meta = MetaFileDirectory {
             io: self.meta.io.clone(),
             context: self.meta.context.clone(),
-            metadata: Metadata::new(entryset.clone()),
-            options: FileOptions::default(),
+            metadata: Metadata::new(entryset.clone(), fat_chain),
+            options: self.meta.options,
             sector_ref,
+            fat_window: Default::default(),
             ..self.meta
         };
         let (length, capacity) = (meta.metadata.length(), meta.metadata.capacity());
         trace!("Cluster id {} length {} capacity {}", cluster_id, length, capacity);
         if file_attributes.directory() > 0 {
             let upcase_table = self.upcase_table.clone();
-            Ok(FileOrDirectory::Directory(Directory { meta, upcase_table }))
+            let lookup_cache = Default::default();
+            Ok(FileOrDirectory::Directory(Directory { meta, upcase_table, lookup_cache }))
         } else {
             Ok(FileOrDirectory::File(File::new(meta, sector_ref)))
         }
@@ -200,7 +571,7 @@ impl<E: Debug, IO: crate::io::IO<Error = E>> Directory<E, IO> {
         loop {
             let mut io = acquire!(self.meta.io);
             let sector = io.read(sector_ref.id(&self.meta.fs_info)).await?;
-            let entries: &[[RawEntry; 16]] = unsafe { mem::transmute(sector) };
+            let entries: &[[RawEntry; 16]] = bytemuck::cast_slice(sector);
             for (i, entry) in entries.iter().map(|e| e.iter()).flatten().enumerate() {
                 if skip > 0 {
                     skip -= 1;
@@ -228,161 +599,1244 @@ impl<E: Debug, IO: crate::io::IO<Error = E>> Directory<E, IO> {
                 }
                 skip = match entry_type.entry_type() {
                     Ok(EntryType::FileDirectory) => {
-                        let file_directory: &FileDirectory = unsafe { mem::transmute(entry) };
+                        let file_directory: &FileDirectory = bytemuck::from_bytes(entry);
                         file_directory.secondary_count
                     }
                     Ok(_) => 0,
-                    Err(_) => return Err(DataError::Metadata.into()),
+                    Err(_) => {
+                        let sector_id = sector_ref.id(&self.meta.fs_info);
+                        let directory = self.meta.sector_ref.cluster_id;
+                        let location = Location::entry(directory, sector_id, i as u8);
+                        return Err(DataError::Metadata(location).into());
+                    }
                 }
             }
             drop(io);
-            sector_ref = self.meta.next(sector_ref).await?;
+            sector_ref = match self.meta.next(sector_ref).await {
+                Ok(sector_ref) => sector_ref,
+                // Directory full without end of directory entry
+                Err(Error::Operation(OperationError::EOF)) => match best {
+                    Some(entry_ref) => return Ok((entry_ref, false)),
+                    None => {
+                        let sector_ref = self.next_sector_or_allocate(sector_ref).await?;
+                        return Ok((EntryRef::new(sector_ref, 0), true));
+                    }
+                },
+                Err(e) => return Err(e),
+            };
         }
     }
 
-    /// Create a file (directory not supported yet)
-    pub async fn create(&mut self, name: &str, directory: bool) -> Result<(), Error<E>> {
-        if directory {
-            return Err(ImplementationError::CreateDirectoryNotSupported.into());
+    /// Refuse to write entries starting at `entry_ref` if any of them is critical,
+    /// which protects root directory against bugs when placing entrysets
+    async fn ensure_unprotected(
+        &mut self,
+        entry_ref: EntryRef,
+        num_entries: usize,
+    ) -> Result<(), Error<E>> {
+        let mut iter = EntryIter::starts_at(&mut self.meta, entry_ref).await?;
+        for _ in 0..num_entries {
+            let entry = match iter.next().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) | Err(Error::Operation(OperationError::EOF)) => break,
+                Err(e) => return Err(e),
+            };
+            if RawEntryType::from(entry[0]).is_critical() {
+                warn!("Refused to overwrite critical entry{}", iter.location());
+                return Err(OperationError::Protected.into());
+            }
         }
-        trace!("Create file {}", name);
-        let name_length = name.chars().count();
-        if name_length > 255 {
+        Ok(())
+    }
+
+    /// Create a file or directory, an empty cluster is allocated for directory
+    pub async fn create(&mut self, name: &str, directory: bool) -> Result<(), Error<E>> {
+        trace!("Create {} {}", if directory { "directory" } else { "file" }, name);
+        if name.encode_utf16().count() > 255 {
             return Err(InputError::NameTooLong.into());
         }
+        let report = crate::name::check(name);
+        if !report.is_portable() {
+            warn!("Name {} not portable to Windows: {:?}", name, report);
+        }
         if self.find(name).await?.is_some() {
             return Err(OperationError::AlreadyExists.into());
         }
 
-        let num_entries = ((name.len() + 14) / 15) as u8 + 2;
-        let (free_entry_ref, tail) = self.lookup_free(num_entries).await?;
+        self.lookup_cache.clear();
+        let first_cluster = match directory {
+            true => Some(self.allocate_directory_cluster().await?),
+            false => None,
+        };
+        let entries = self.entryset_entries(name, first_cluster);
+        let result = self.insert_entries(&entries).await;
+        if let (Err(_), Some(cluster_id)) = (&result, first_cluster) {
+            acquire!(self.meta.context).allocation_bitmap.release(cluster_id, false).await?;
+        }
+        result
+    }
+
+    /// Write entries of an entryset to a free slot or end of directory
+    pub(crate) async fn insert_entries(&mut self, entries: &[RawEntry]) -> Result<(), Error<E>> {
+        self.ensure_upcase_table_valid().await?;
+        let num_entries = entries.len();
+        let (free_entry_ref, tail) = self.lookup_free(num_entries as u8).await?;
+        let fs_info = self.meta.fs_info;
+        let capacity = fs_info.sector_size() as usize / ENTRY_SIZE;
+        // Entryset never crosses sector boundary unless it is larger than a sector
+        let index = free_entry_ref.index as usize;
+        let out_of_capacity = index + num_entries > capacity && num_entries <= capacity;
         let mut write_entry_ref = free_entry_ref;
-        let sector_ref = free_entry_ref.sector_ref;
+        if out_of_capacity {
+            let sector_ref = self.next_sector_or_allocate(free_entry_ref.sector_ref).await?;
+            write_entry_ref = EntryRef::new(sector_ref, 0);
+        }
+
+        self.ensure_unprotected(write_entry_ref, num_entries).await?;
+        if out_of_capacity {
+            self.ensure_unprotected(free_entry_ref, capacity - index).await?;
+        }
+
+        debug!("Write entryset at entry-ref {}", write_entry_ref);
+
+        let mut sector_ref = write_entry_ref.sector_ref;
+        let mut index = write_entry_ref.index as usize;
+        for entry in entries.iter() {
+            if index == capacity {
+                sector_ref = self.next_sector_or_allocate(sector_ref).await?;
+                index = 0;
+            }
+            let sector_id = sector_ref.id(&fs_info);
+            acquire!(self.meta.io).write(sector_id, index * ENTRY_SIZE, entry).await?;
+            index += 1;
+        }
+        let mut io = acquire!(self.meta.io);
+        if tail && index < capacity {
+            io.write(sector_ref.id(&fs_info), index * ENTRY_SIZE, &[0]).await?;
+        };
+        // Fill free entries afterwards to avoid corrupting metadata
+        if out_of_capacity {
+            let sector_id = free_entry_ref.sector_ref.id(&fs_info);
+            let byte: u8 = RawEntryType::new(EntryType::Filename, false).into();
+            for i in free_entry_ref.index as usize..capacity {
+                io.write(sector_id, i * ENTRY_SIZE, &[byte]).await?;
+            }
+        }
+        io.auto_flush().await
+    }
+
+    /// Create many files in a single pass, entrysets are appended to the end of directory
+    /// and written sector by sector with only one flush, which is much faster than
+    /// calling `create` for each of them when importing lots of files.
+    #[cfg(feature = "alloc")]
+    pub async fn create_all(&mut self, names: &[&str]) -> Result<(), Error<E>> {
+        let mut upcase_names = Vec::with_capacity(names.len());
+        for &name in names {
+            if name.encode_utf16().count() > 255 {
+                return Err(InputError::NameTooLong.into());
+            }
+            upcase_names.push(self.upcase_table.to_key(name));
+        }
+        upcase_names.sort_unstable();
+        if upcase_names.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(OperationError::AlreadyExists.into());
+        }
+        let upcase_table = self.upcase_table.clone();
+        let exists = self
+            .walk_matches(
+                |file_directory, _| file_directory.entry_type.in_use(),
+                |entryset| upcase_names.binary_search(&upcase_table.to_key(entryset.name())).ok(),
+            )
+            .await?;
+        if exists.is_some() {
+            return Err(OperationError::AlreadyExists.into());
+        }
+        trace!("Create {} files", names.len());
+        self.ensure_upcase_table_valid().await?;
+
+        self.lookup_cache.clear();
+        let end_entry_ref = self.lookup_end().await?;
+        debug!("Append entrysets at entry-ref {}", end_entry_ref);
         let sector_size = self.meta.fs_info.sector_size() as usize;
         let capacity = sector_size / ENTRY_SIZE;
-        let out_of_capacity = free_entry_ref.index + num_entries + tail as u8 >= capacity as u8;
-        if out_of_capacity {
-            let sector_ref = match self.meta.next(sector_ref).await {
-                Ok(sector_ref) => sector_ref,
-                Err(Error::Operation(OperationError::EOF)) => {
-                    SectorRef::new(self.meta.allocate(sector_ref.cluster_id).await?, 0)
+        let mut sector_ref = end_entry_ref.sector_ref;
+        let mut offset = end_entry_ref.index as usize * ENTRY_SIZE;
+        let mut buf: Vec<u8> = Vec::with_capacity(sector_size);
+        let mut unused: RawEntry = [0; ENTRY_SIZE];
+        unused[0] = RawEntryType::new(EntryType::Filename, false).into();
+        for &name in names {
+            let entries = self.entryset_entries(name, None);
+            // Entryset never crosses sector boundary unless it is larger than a sector
+            let index = (offset + buf.len()) / ENTRY_SIZE;
+            if index + entries.len() > capacity && entries.len() <= capacity {
+                for _ in index..capacity {
+                    buf.extend_from_slice(&unused);
+                }
+            }
+            for entry in entries.iter() {
+                if offset + buf.len() == sector_size {
+                    let sector_id = sector_ref.id(&self.meta.fs_info);
+                    acquire!(self.meta.io).write(sector_id, offset, &buf).await?;
+                    sector_ref = self.next_sector_or_allocate(sector_ref).await?;
+                    offset = 0;
+                    buf.clear();
                 }
+                buf.extend_from_slice(entry);
+            }
+        }
+        if offset + buf.len() == sector_size {
+            let sector_id = sector_ref.id(&self.meta.fs_info);
+            acquire!(self.meta.io).write(sector_id, offset, &buf).await?;
+            sector_ref = self.next_sector_or_allocate(sector_ref).await?;
+            offset = 0;
+            buf.clear();
+        }
+        // Remaining entries of the last sector are marked as end of directory
+        buf.resize(sector_size - offset, 0);
+        let sector_id = sector_ref.id(&self.meta.fs_info);
+        let mut io = acquire!(self.meta.io);
+        io.write(sector_id, offset, &buf).await?;
+        io.auto_flush().await
+    }
+
+    /// Locate first entry of specified type, regardless of inuse or not
+    pub(crate) async fn lookup_entry(
+        &mut self,
+        entry_type: EntryType,
+    ) -> Result<Option<EntryRef>, Error<E>> {
+        let mut iter = EntryIter::new(&mut self.meta).await?;
+        loop {
+            let entry = match iter.next().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) | Err(Error::Operation(OperationError::EOF)) => return Ok(None),
                 Err(e) => return Err(e),
             };
-            write_entry_ref = EntryRef::new(sector_ref, 0);
+            if RawEntryType::from(entry[0]).entry_type() == Ok(entry_type) {
+                return Ok(Some(EntryRef::new(iter.sector_ref, iter.index)));
+            }
         }
+    }
 
-        debug!("Write entryset at entry-ref {}", write_entry_ref);
+    /// Locate end of directory entry
+    #[cfg(feature = "alloc")]
+    async fn lookup_end(&mut self) -> Result<EntryRef, Error<E>> {
+        let capacity = self.meta.fs_info.sector_size() as usize / ENTRY_SIZE;
+        let mut iter = EntryIter::new(&mut self.meta).await?;
+        loop {
+            match iter.next().await {
+                Ok(Some(_)) => (),
+                Ok(None) => return Ok(EntryRef::new(iter.sector_ref, iter.index)),
+                // Directory full, end is right after last entry of last sector
+                Err(Error::Operation(OperationError::EOF)) => {
+                    return Ok(EntryRef::new(iter.sector_ref, capacity as u8))
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn next_sector_or_allocate(
+        &mut self,
+        sector_ref: SectorRef,
+    ) -> Result<SectorRef, Error<E>> {
+        match self.meta.next(sector_ref).await {
+            Ok(sector_ref) => Ok(sector_ref),
+            Err(Error::Operation(OperationError::EOF)) => {
+                debug!("Directory full, grow with a new cluster");
+                let cluster_id = self.meta.allocate(sector_ref.cluster_id).await?;
+                // Entries of a zero filled cluster are end of directory entries
+                self.meta.fill_cluster(cluster_id, 0).await?;
+                self.meta.metadata.dirty = true;
+                self.meta.sync().await?;
+                Ok(SectorRef::new(cluster_id, 0))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Allocate a zero filled cluster as content of a new directory,
+    /// which is not part of this directory so no need to follow its clusters
+    async fn allocate_directory_cluster(&mut self) -> Result<ClusterID, Error<E>> {
+        let mut context = acquire!(self.meta.context);
+        let cluster_id = context.allocation_bitmap.allocate(ClusterID::default(), true).await?;
+        drop(context);
+        if let Err(e) = self.meta.fill_cluster(cluster_id, 0).await {
+            acquire!(self.meta.context).allocation_bitmap.release(cluster_id, false).await?;
+            return Err(e);
+        }
+        Ok(cluster_id)
+    }
 
+    /// Entries of a new entryset with specified name,
+    /// which is a directory occupying one cluster if first cluster specified
+    fn entryset_entries(&self, name: &str, first_cluster: Option<ClusterID>) -> Entries {
+        let mut stream_extension = Secondary::new(StreamExtension::default());
+        if let Some(cluster_id) = first_cluster {
+            let cluster_size = self.meta.fs_info.cluster_size() as u64;
+            stream_extension.general_secondary_flags.clear_fat_chain();
+            stream_extension.first_cluster = u32::from(cluster_id).into();
+            stream_extension.custom_defined.valid_data_length = cluster_size.into();
+            stream_extension.data_length = cluster_size.into();
+        }
+        let file_directory = FileDirectory::new(0, first_cluster.is_some());
+        self.build_entries(name, file_directory, stream_extension)
+    }
+
+    /// Entries of an entryset with specified name, along with name related fields
+    /// and checksum of primary and stream extension entry updated
+    fn build_entries(
+        &self,
+        name: &str,
+        mut file_directory: FileDirectory,
+        mut stream_extension: Secondary<StreamExtension>,
+    ) -> Entries {
+        let name_length = name.encode_utf16().count();
+        let num_entries = name_length.div_ceil(15) as u8 + 2;
         let hash = name_hash(&self.upcase_table.to_upper(name));
-        let stream_extension = Secondary::new(StreamExtension::new(name.len() as u8, hash));
-        let mut file_directory = FileDirectory::new(num_entries - 1, directory);
+        stream_extension.custom_defined.name_length = name_length as u8;
+        stream_extension.custom_defined.name_hash = hash.into();
+        file_directory.secondary_count = num_entries - 1;
         let sum = checksum(&file_directory, &stream_extension, name);
         file_directory.set_checksum = sum.into();
 
-        let sector_id = write_entry_ref.sector_ref.id(&self.meta.fs_info);
-        let offset = write_entry_ref.index as usize * ENTRY_SIZE;
-        let bytes: &[u8; ENTRY_SIZE] = unsafe { mem::transmute(&file_directory) };
-        let mut io = acquire!(self.meta.io);
-        io.write(sector_id, offset, bytes).await?;
-        let bytes: &[u8; ENTRY_SIZE] = unsafe { mem::transmute(&stream_extension) };
-        io.write(sector_id, offset + ENTRY_SIZE, bytes).await?;
-
-        let mut chars = name.chars();
+        let mut entries = Entries::new();
+        entries.push(bytemuck::cast(file_directory)).ok();
+        entries.push(bytemuck::cast(stream_extension)).ok();
+        let mut units = name.encode_utf16();
         let mut filename = Filename::default();
-        for index in 2..(num_entries as usize) {
-            let buf = unsafe { filename.filename.assume_init_mut() };
+        for _ in 2..num_entries {
             for i in 0..15 {
-                buf[i] = u16::to_le(chars.next().unwrap_or('\0') as u16)
+                filename.filename[i] = units.next().unwrap_or(0).into()
             }
-            let bytes: &[u8; ENTRY_SIZE] = unsafe { mem::transmute(&filename) };
-            io.write(sector_id, offset + index * ENTRY_SIZE, bytes).await?;
+            entries.push(bytemuck::cast(filename)).ok();
         }
-        if tail {
-            let offset = offset + (num_entries as usize + 2) * ENTRY_SIZE;
-            io.write(sector_id, offset, &[0]).await?;
+        entries
+    }
+
+    /// Delete a file or directory
+    pub async fn delete(&mut self, entryset: &EntrySet<N>) -> Result<(), Error<E>> {
+        self.remove(entryset, None).await
+    }
+
+    /// Delete a file or directory, overwrite its clusters with specified byte
+    /// and wipe its entryset so that data is unrecoverable after deletion
+    pub async fn delete_secure(
+        &mut self,
+        entryset: &EntrySet<N>,
+        byte: u8,
+    ) -> Result<(), Error<E>> {
+        self.remove(entryset, Some(byte)).await
+    }
+
+    /// Delete a file or directory, for directory everything inside is deleted as well
+    #[cfg(feature = "alloc")]
+    pub async fn delete_recursive(&mut self, entryset: &EntrySet<N>) -> Result<(), Error<E>> {
+        // Opened directories from top to bottom, along with their entryset
+        // and position to resume walking from
+        let mut stack: Vec<(Directory<E, IO, N>, EntrySet<N>, WalkPosition)> = Vec::new();
+        let mut entryset = entryset.clone();
+        loop {
+            // Between deletions, each of which leaves volume consistent
+            acquire!(self.meta.io).ensure_not_cancelled()?;
+            let parent = match stack.last_mut() {
+                Some((directory, _, _)) => directory,
+                None => &mut *self,
+            };
+            match entryset.is_dir() {
+                true => match parent.open(&entryset).await? {
+                    FileOrDirectory::Directory(directory) => {
+                        let entry_ref = EntryRef::new(directory.meta.sector_ref, 0);
+                        let position = WalkPosition(entry_ref, directory.relocations().await);
+                        stack.push((directory, entryset, position));
+                    }
+                    FileOrDirectory::File(_) => unreachable!(),
+                },
+                false => parent.delete(&entryset).await?,
+            }
+            entryset = loop {
+                let (directory, _, position) = match stack.last_mut() {
+                    Some(top) => top,
+                    None => return Ok(()),
+                };
+                if let Some(child) = directory.walk_from(*position, |e| e.in_use()).await? {
+                    *position = child.position();
+                    break child;
+                }
+                let (directory, entryset, _) = stack.pop().unwrap();
+                #[cfg(all(feature = "async", not(feature = "async-std")))]
+                directory.close().await?;
+                #[cfg(any(not(feature = "async"), feature = "async-std"))]
+                drop(directory);
+                let parent = match stack.last_mut() {
+                    Some((directory, _, _)) => directory,
+                    None => &mut *self,
+                };
+                parent.delete(&entryset).await?;
+            };
+        }
+    }
+
+    /// Restore a deleted file or directory walked through in this directory,
+    /// fails if its entries have been overwritten or its clusters reused since deletion
+    pub async fn undelete(&mut self, entryset: &EntrySet<N>) -> Result<(), Error<E>> {
+        debug!("Undelete {} entry-ref {}", entryset.name(), entryset.entry_ref);
+        let fs_info = self.meta.fs_info;
+        let id = entryset.id(&fs_info);
+        let entry_ref = entryset.entry_ref;
+        let option = self.walk_matches_from(entry_ref, |_, _| true, |e| Some(e.clone())).await?;
+        let entryset = match option {
+            Some(entryset) if entryset.id(&fs_info) == id && entryset.in_use() => {
+                return Err(OperationError::AlreadyExists.into())
+            }
+            Some(entryset) if entryset.id(&fs_info) == id => entryset,
+            _ => return Err(OperationError::NotFound.into()),
         };
-        // Fill free entries afterwards to avoid corrupting metadata
-        if out_of_capacity {
-            let sector_id = sector_ref.id(&self.meta.fs_info);
-            let byte: u8 = RawEntryType::new(EntryType::Filename, false).into();
-            for i in free_entry_ref.index as usize..(sector_size / ENTRY_SIZE) {
-                io.write(sector_id, i * ENTRY_SIZE, &[byte]).await?;
+        if self.find(entryset.name()).await?.is_some() {
+            return Err(OperationError::AlreadyExists.into());
+        }
+
+        // Entries must still form a deleted entryset, whose checksum matches once in use
+        let num_entries = entryset.file_directory.secondary_count as usize + 1;
+        let mut iter = EntryIter::starts_at(&mut self.meta, entry_ref).await?;
+        let mut sum = Checksum::new();
+        let mut location = None;
+        for i in 0..num_entries {
+            let mut entry = iter.next().await?.ok_or(OperationError::NotFound)?;
+            location.get_or_insert(iter.location());
+            let entry_type = RawEntryType::from(entry[0]);
+            let expected = match i {
+                0 => entry_type.entry_type() == Ok(EntryType::FileDirectory),
+                _ => is_secondary(&entry, i == 1, false),
+            };
+            if entry_type.in_use() || !expected {
+                return Err(OperationError::NotFound.into());
             }
+            entry[0] |= 0x80;
+            for (j, &byte) in entry.iter().enumerate() {
+                if i > 0 || (j != 2 && j != 3) {
+                    sum.write(byte as u16);
+                }
+            }
+        }
+        drop(iter);
+        if sum.sum() != entryset.file_directory.set_checksum.to_ne() {
+            return Err(DataError::Metadata(location.unwrap()).into());
         }
-        io.flush().await
+
+        let first_cluster = entryset.first_cluster();
+        let cluster_size = fs_info.cluster_size() as u64;
+        let num_clusters = entryset.data_length().div_ceil(cluster_size) as u32;
+        if first_cluster.valid() && num_clusters > 0 {
+            let mut context = acquire!(self.meta.context);
+            let bitmap = &mut context.allocation_bitmap;
+            if !bitmap.restore(first_cluster, num_clusters, entryset.fat_chain()).await? {
+                return Err(OperationError::Overwritten.into());
+            }
+        }
+
+        let io = self.meta.io.clone();
+        let mut iter = EntryIter::starts_at(&mut self.meta, entry_ref).await?;
+        for _ in 0..num_entries {
+            let entry = iter.next().await?.unwrap();
+            let offset = iter.index as usize * ENTRY_SIZE;
+            acquire!(io).write(iter.sector_ref.id(&fs_info), offset, &[entry[0] | 0x80]).await?;
+        }
+        drop(iter);
+        acquire!(self.meta.io).auto_flush().await
     }
 
-    /// Delete a file or directory
-    pub async fn delete(&mut self, entryset: &EntrySet) -> Result<(), Error<E>> {
+    async fn remove(&mut self, entryset: &EntrySet<N>, fill: Option<u8>) -> Result<(), Error<E>> {
         debug!("Delete file or directory {} entry-ref {}", entryset.name(), entryset.entry_ref);
         let file_or_directory = self.open(entryset).await?;
         let meta = match file_or_directory {
             FileOrDirectory::Directory(mut directory) => {
-                if directory.walk(|_| true).await?.is_some() {
-                    #[cfg(all(feature = "async", not(feature = "std")))]
+                if !directory.is_empty().await? {
+                    #[cfg(all(feature = "async", not(feature = "async-std")))]
                     directory.close().await?;
                     return Err(OperationError::DirectoryNotEmpty.into());
                 }
+                if let Some(byte) = fill {
+                    directory.meta.fill_all_clusters(byte).await?;
+                }
                 directory.meta.metadata.clone()
             }
-            FileOrDirectory::File(file) => file.meta.metadata.clone(),
-        };
-
-        let fs_info = self.meta.fs_info;
-        let mut sector_id = meta.entry_ref.sector_ref.id(&fs_info);
-        let secondary_count = meta.file_directory.secondary_count as usize;
-        let last_index = meta.entry_ref.index as usize + secondary_count;
-        let sector_size = fs_info.sector_size() as usize;
-        let next_sector_id = match last_index * ENTRY_SIZE > sector_size {
-            true => self.meta.next(meta.entry_ref.sector_ref).await?.id(&fs_info),
-            false => sector_id,
+            FileOrDirectory::File(mut file) => {
+                if let Some(byte) = fill {
+                    file.meta.fill_all_clusters(byte).await?;
+                }
+                file.meta.metadata.clone()
+            }
         };
 
-        let mut offset = meta.entry_ref.index as usize * ENTRY_SIZE;
-        let mut io = acquire!(self.meta.io);
-        io.write(sector_id, offset, &[EntryType::FileDirectory.into(); 1]).await?;
-        offset = (offset + ENTRY_SIZE) % sector_size;
-        if offset == 0 {
-            sector_id = next_sector_id;
-        }
-        io.write(sector_id, offset, &[EntryType::StreamExtension.into(); 1]).await?;
-        for _ in 0..(secondary_count - 1) {
-            offset = (offset + ENTRY_SIZE) % sector_size;
-            if offset == 0 {
-                sector_id = next_sector_id;
-            }
-            io.write(sector_id, offset, &[EntryType::Filename.into(); 1]).await?;
-        }
-        drop(io);
+        self.lookup_cache.clear();
+        let secondary_count = meta.file_directory.secondary_count;
+        self.clear_entries(meta.entry_ref, secondary_count, fill.is_some()).await?;
 
+        let fs_info = self.meta.fs_info;
         let stream_extension = &meta.stream_extension;
         let cluster_id: ClusterID = stream_extension.first_cluster.to_ne().into();
         let fat_chain = meta.stream_extension.general_secondary_flags.fat_chain();
         if cluster_id.valid() {
             let mut context = acquire!(self.meta.context);
-            context.allocation_bitmap.release(cluster_id, fat_chain).await?;
+            match fat_chain {
+                true => context.allocation_bitmap.release(cluster_id, true).await?,
+                // Clusters without FAT chain are all released, not only the first one
+                false => {
+                    let num_clusters = meta.capacity() / fs_info.cluster_size() as u64;
+                    let bitmap = &mut context.allocation_bitmap;
+                    bitmap.release_contiguous(cluster_id, num_clusters as u32).await?
+                }
+            }
+        }
+        acquire!(self.meta.io).auto_flush().await
+    }
+
+    /// Clear inuse bit of entries of an entryset, wipe whole entries if required
+    async fn clear_entries(
+        &mut self,
+        entry_ref: EntryRef,
+        secondary_count: u8,
+        wipe: bool,
+    ) -> Result<(), Error<E>> {
+        self.ensure_unprotected(entry_ref, secondary_count as usize + 1).await?;
+        let fs_info = self.meta.fs_info;
+        let io = self.meta.io.clone();
+        let length = if wipe { ENTRY_SIZE } else { 1 };
+        let mut entry: RawEntry = [0; ENTRY_SIZE];
+        let mut iter = EntryIter::starts_at(&mut self.meta, entry_ref).await?;
+        for i in 0..=secondary_count {
+            entry[0] = match i {
+                0 => EntryType::FileDirectory.into(),
+                1 => EntryType::StreamExtension.into(),
+                _ => EntryType::Filename.into(),
+            };
+            iter.next().await?;
+            let offset = iter.index as usize * ENTRY_SIZE;
+            acquire!(io).write(iter.sector_ref.id(&fs_info), offset, &entry[..length]).await?;
+        }
+        Ok(())
+    }
+
+    /// Entries of specified entryset renamed, which will be placed in this directory
+    async fn renamed_entries<const K: usize>(
+        &mut self,
+        entryset: &EntrySet<K>,
+        name: &str,
+    ) -> Result<Entries, Error<E>> {
+        if name.encode_utf16().count() > 255 {
+            return Err(InputError::NameTooLong.into());
+        }
+        if self.find(name).await?.is_some() {
+            return Err(OperationError::AlreadyExists.into());
+        }
+        let file_directory = entryset.file_directory;
+        let stream_extension = entryset.stream_extension;
+        Ok(self.build_entries(name, file_directory, stream_extension))
+    }
+
+    /// Rewrite entries of an entryset with a name differs only in case,
+    /// which has same length and name hash so that entries stay where they are.
+    /// Name must have same length in UTF-16 as the original one
+    async fn rename_in_place(
+        &mut self,
+        entryset: &EntrySet<N>,
+        name: &str,
+    ) -> Result<(), Error<E>> {
+        self.ensure_upcase_table_valid().await?;
+        let file_directory = entryset.file_directory;
+        let entries = self.build_entries(name, file_directory, entryset.stream_extension);
+        let fs_info = self.meta.fs_info;
+        let io = self.meta.io.clone();
+        let mut iter = EntryIter::starts_at(&mut self.meta, entryset.entry_ref).await?;
+        for entry in entries.iter() {
+            iter.next().await?;
+            let offset = iter.index as usize * ENTRY_SIZE;
+            acquire!(io).write(iter.sector_ref.id(&fs_info), offset, entry).await?;
         }
-        acquire!(self.meta.io).flush().await
+        drop(iter);
+        acquire!(self.meta.io).auto_flush().await
     }
 
-    #[cfg(all(feature = "async", not(feature = "std")))]
-    /// `no_std` async only which must be explicitly called
+    /// Rename a file or directory in this directory, which must not be opened.
+    /// Changing only case of name rewrites its entryset in place.
+    pub async fn rename(&mut self, entryset: &EntrySet<N>, name: &str) -> Result<(), Error<E>> {
+        debug!("Rename {} entry-ref {} to {}", entryset.name(), entryset.entry_ref, name);
+        if acquire!(self.meta.context).opened_entries.contains(entryset.id(&self.meta.fs_info)) {
+            return Err(OperationError::AlreadyOpen.into());
+        }
+        let length = name.encode_utf16().count();
+        if length > 255 {
+            return Err(InputError::NameTooLong.into());
+        }
+        let fs_info = self.meta.fs_info;
+        match self.find(name).await? {
+            Some(existing) if existing.id(&fs_info) == entryset.id(&fs_info) => {
+                // NFC and NFD forms match each other but might differ in length,
+                // taking different number of entries
+                let name_length = existing.stream_extension.custom_defined.name_length;
+                if name_length as usize == length {
+                    return self.rename_in_place(&existing, name).await;
+                }
+            }
+            Some(_) => return Err(OperationError::AlreadyExists.into()),
+            None => (),
+        }
+        let entries = self.build_entries(name, entryset.file_directory, entryset.stream_extension);
+        self.lookup_cache.clear();
+        self.relocate().await;
+        self.insert_entries(&entries).await?;
+        let secondary_count = entryset.file_directory.secondary_count;
+        self.clear_entries(entryset.entry_ref, secondary_count, false).await?;
+        acquire!(self.meta.io).auto_flush().await
+    }
+
+    /// Move a file or directory in this directory to target directory with specified name,
+    /// the file or directory must not be opened
+    pub async fn move_to<const M: usize>(
+        &mut self,
+        entryset: &EntrySet<N>,
+        target: &mut Directory<E, IO, M>,
+        name: &str,
+    ) -> Result<(), Error<E>> {
+        debug!("Move {} entry-ref {} to {}", entryset.name(), entryset.entry_ref, name);
+        if acquire!(self.meta.context).opened_entries.contains(entryset.id(&self.meta.fs_info)) {
+            return Err(OperationError::AlreadyOpen.into());
+        }
+        let entries = target.renamed_entries(entryset, name).await?;
+        target.lookup_cache.clear();
+        target.relocate().await;
+        target.insert_entries(&entries).await?;
+        self.lookup_cache.clear();
+        self.relocate().await;
+        let secondary_count = entryset.file_directory.secondary_count;
+        self.clear_entries(entryset.entry_ref, secondary_count, false).await?;
+        acquire!(self.meta.io).auto_flush().await
+    }
+
+    #[cfg(all(feature = "async", not(feature = "async-std")))]
+    /// Async without `async-std` runtime only, which must be explicitly called
     pub async fn close(mut self) -> Result<(), Error<E>> {
         self.meta.close().await
     }
 }
 
-#[cfg(any(not(feature = "async"), feature = "std"))]
-impl<E: core::fmt::Debug, IO: crate::io::IO<Error = E>> Drop for Directory<E, IO> {
+#[cfg(any(not(feature = "async"), feature = "async-std"))]
+impl<E: core::fmt::Debug, IO: crate::io::IO<Error = E>, const N: usize> Drop
+    for Directory<E, IO, N>
+{
     fn drop(&mut self) {
         match () {
-            #[cfg(all(feature = "async", not(feature = "std")))]
+            #[cfg(all(feature = "async", not(feature = "async-std")))]
             () => panic!("Close must be explicit called"),
-            #[cfg(all(feature = "async", feature = "std"))]
+            #[cfg(feature = "async-std")]
             () => async_std::task::block_on(self.meta.close()).unwrap(),
             #[cfg(not(feature = "async"))]
             () => self.meta.close().unwrap(),
         }
     }
 }
+
+#[cfg(all(test, feature = "std", not(feature = "async")))]
+mod test {
+    use super::EntryType;
+    use crate::error::{Error, OperationError};
+    use crate::fixture::Image;
+    use crate::region::data::entryset::primary::Checksum;
+    use crate::region::data::entryset::secondary::Filename;
+
+    #[test]
+    fn test_root_critical_entries_preserved() {
+        let image = Image::new("exfat-test-root-critical.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        for i in 0..32 {
+            directory.create(&format!("file-with-a-long-name-{}", i), i % 4 == 0).unwrap();
+        }
+        for i in (0..32).filter(|i| i % 3 != 0) {
+            let entryset = directory.find(&format!("file-with-a-long-name-{}", i)).unwrap();
+            directory.delete(&entryset.unwrap()).unwrap();
+        }
+        directory.compact().unwrap();
+        directory.create("created-after-compact", false).unwrap();
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let mut exfat = image.mount();
+        exfat.validate_checksum().unwrap();
+        let mut root = exfat.root_directory().unwrap();
+        root.validate_upcase_table_checksum().unwrap();
+        assert_eq!(root.volumn_label(), Some("PROTECTED"));
+        assert!(root.check(false).unwrap().is_consistent());
+        let mut directory = root.open().unwrap();
+        for i in 0..32 {
+            let found = directory.find(&format!("file-with-a-long-name-{}", i)).unwrap();
+            assert_eq!(found.is_some(), i % 3 == 0);
+        }
+        assert!(directory.find("created-after-compact").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_root_critical_entries_protected() {
+        let image = Image::new("exfat-test-root-protected.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        for entry_type in [EntryType::AllocationBitmap, EntryType::UpcaseTable] {
+            let entry_ref = directory.lookup_entry(entry_type).unwrap().unwrap();
+            let result = directory.clear_entries(entry_ref, 2, true);
+            assert!(matches!(result, Err(Error::Operation(OperationError::Protected))));
+        }
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        assert!(root.check(false).unwrap().is_consistent());
+    }
+
+    /// Image with entryset of `target` starting at second last entry of first sector of root
+    /// directory, so that its last entry is the first entry of the next sector
+    fn entryset_ending_on_sector_boundary(name: &str) -> Image {
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        let image = Image::new(name);
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        // Entrysets of four, four and three entries leave two entries free in first sector
+        directory.create("name-longer-than-15-a", false).unwrap();
+        directory.create("name-longer-than-15-b", false).unwrap();
+        directory.create("short", false).unwrap();
+        directory.create("target", false).unwrap();
+        let entryset = directory.find("target").unwrap().unwrap();
+        let fs_info = directory.meta.fs_info;
+        let sector_ref = entryset.entry_ref.sector_ref;
+        assert_eq!((sector_ref.sector_index, entryset.entry_ref.index), (1, 0));
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let sector_size = fs_info.sector_size() as u64;
+        let offset = u64::from(sector_ref.id(&fs_info)) * sector_size;
+        let mut file = std::fs::File::options().read(true).write(true).open(image.path()).unwrap();
+        let mut entries = [0u8; 5 * 32];
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.read_exact(&mut entries[2 * 32..]).unwrap();
+        file.seek(SeekFrom::Start(offset - 2 * 32)).unwrap();
+        file.write_all(&entries[2 * 32..]).unwrap();
+        file.write_all(&entries[..2 * 32]).unwrap();
+        image
+    }
+
+    #[test]
+    fn test_clear_entries_ending_on_sector_boundary() {
+        for delete in [true, false] {
+            let image = entryset_ending_on_sector_boundary("exfat-test-sector-boundary.img");
+            let mut exfat = image.mount();
+            let mut root = exfat.root_directory().unwrap();
+            let mut directory = root.open().unwrap();
+            let entryset = directory.find("target").unwrap().unwrap();
+            assert_eq!(
+                (entryset.entry_ref.sector_ref.sector_index, entryset.entry_ref.index),
+                (0, 14)
+            );
+            match delete {
+                true => directory.delete(&entryset).unwrap(),
+                false => directory.rename(&entryset, "renamed").unwrap(),
+            }
+            drop(directory);
+            drop(root);
+            drop(exfat);
+
+            let mut exfat = image.mount();
+            let mut root = exfat.root_directory().unwrap();
+            assert_eq!(root.volumn_label(), Some("PROTECTED"));
+            assert!(root.check(false).unwrap().is_consistent());
+            let mut directory = root.open().unwrap();
+            assert!(directory.find("target").unwrap().is_none());
+            assert_eq!(directory.find("renamed").unwrap().is_some(), !delete);
+            assert!(directory.find("short").unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn test_non_ascii_name_round_trip() {
+        let image = Image::new("exfat-test-non-ascii-name.img");
+        let mut exfat = image.mount();
+        // Name hash and length computed independently according to specification,
+        // the second name has a surrogate pair split across filename entries
+        let names = [("文件-😀.txt", 0x9D0D, 9), ("abcdefghijklmn😀.txt", 0x7EDC, 20)];
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        for (name, _, _) in names.iter() {
+            directory.create(name, false).unwrap();
+        }
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        for &(name, hash, length) in names.iter() {
+            let entryset = directory.find(name).unwrap().unwrap();
+            assert_eq!(entryset.name(), name);
+            let stream_extension = &entryset.stream_extension.custom_defined;
+            assert_eq!(stream_extension.name_hash.to_ne(), hash);
+            assert_eq!(stream_extension.name_length, length);
+
+            let entries = directory.raw_entries(&entryset).unwrap();
+            assert_eq!(entries.len(), 2 + length as usize / 15 + 1);
+            let mut units = name.encode_utf16();
+            let mut checksum = Checksum::new();
+            for (i, entry) in entries.iter().enumerate() {
+                for (j, &byte) in entry.iter().enumerate() {
+                    if i > 0 || (j != 2 && j != 3) {
+                        checksum.write(byte as u16);
+                    }
+                }
+                if i >= 2 {
+                    let filename: Filename = bytemuck::cast(*entry);
+                    for unit in filename.filename.iter() {
+                        assert_eq!(unit.to_ne(), units.next().unwrap_or(0));
+                    }
+                }
+            }
+            assert_eq!(checksum.sum(), entryset.file_directory.set_checksum.to_ne());
+        }
+        drop(directory);
+        assert!(root.check(false).unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_longest_name() {
+        let image = Image::new("exfat-test-longest-name.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        // 255 UTF-16 units taking 765 bytes in UTF-8
+        let name = "文".repeat(255);
+        directory.create(&name, false).unwrap();
+        directory.create("after", false).unwrap();
+        directory.lookup_cache.clear();
+        assert_eq!(directory.find(&name).unwrap().unwrap().name(), name);
+        assert!(directory.find("after").unwrap().is_some());
+        assert!(matches!(directory.create(&name, false), Err(Error::Operation(_))));
+        drop(directory);
+        assert!(root.check(false).unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_delete_contiguous() {
+        use super::FileOrDirectory;
+
+        let image = Image::new("exfat-test-delete-contiguous.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let free_extents = |root: &crate::RootDirectory<_, _>| -> Vec<_> {
+            root.free_extents().map(|extent| extent.unwrap().num_clusters).collect()
+        };
+        let initial = free_extents(&root);
+        let mut directory = root.open().unwrap();
+        directory.create("file", false).unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(mut file) => file.write_all(&[0x5A; 3 * 4096]).unwrap(),
+            FileOrDirectory::Directory(_) => unreachable!(),
+        }
+        let entryset = directory.find("file").unwrap().unwrap();
+        assert!(!entryset.fat_chain());
+        // All clusters of a file without FAT chain are released, not only the first one
+        directory.delete(&entryset).unwrap();
+        drop(directory);
+        assert_eq!(free_extents(&root), initial);
+        assert!(root.check(false).unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_lookup_cache_stats() {
+        let image = Image::new("exfat-test-lookup-cache-stats.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("file", false).unwrap();
+        for _ in 0..2 {
+            assert!(directory.find("file").unwrap().is_some());
+        }
+        let stats = exfat.stats();
+        assert_eq!((stats.cache_hits, stats.cache_misses), (0, 0));
+        directory.set_lookup_cache_capacity(1);
+        for _ in 0..2 {
+            assert!(directory.find("file").unwrap().is_some());
+        }
+        let stats = exfat.stats();
+        assert_eq!((stats.cache_hits, stats.cache_misses), (1, 1));
+    }
+
+    #[test]
+    fn test_vendor_extensions() {
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        let image = Image::new("exfat-test-vendor-extensions.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        let name = "a".repeat(255);
+        directory.create(&name, false).unwrap();
+        let entryset = directory.find(&name).unwrap().unwrap();
+        let id = entryset.id(&directory.meta.fs_info);
+        let sector_size = directory.meta.fs_info.sector_size() as u64;
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        // Longest name followed by 2 vendor extension entries, 20 secondaries in total
+        let offset = u64::from(id.sector_id) * sector_size + id.index as u64 * 32;
+        let mut file = std::fs::File::options().read(true).write(true).open(image.path()).unwrap();
+        let mut entries = [0u8; 21 * 32];
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.read_exact(&mut entries).unwrap();
+        entries[1] = 20;
+        entries[19 * 32] = 0xE0;
+        entries[20 * 32] = 0xE0;
+        let mut checksum = Checksum::new();
+        for (i, &byte) in entries.iter().enumerate() {
+            if i != 2 && i != 3 {
+                checksum.write(byte as u16);
+            }
+        }
+        entries[2..4].copy_from_slice(&checksum.sum().to_le_bytes());
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&entries).unwrap();
+        drop(file);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("after", false).unwrap();
+        directory.lookup_cache.clear();
+        assert_eq!(directory.find(&name).unwrap().unwrap().name(), name);
+        assert!(directory.find("after").unwrap().is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "unicode-normalization")]
+    fn test_rename_normalization_form() {
+        let image = Image::new("exfat-test-rename-normalization.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        // 15 UTF-16 units fit a single filename entry in NFC, 30 units in NFD need two
+        let (nfc, nfd) = ("\u{E9}".repeat(15), "e\u{301}".repeat(15));
+        for (from, to) in [(&nfc, &nfd), (&nfd, &nfc)] {
+            directory.create(from, false).unwrap();
+            directory.create("next", false).unwrap();
+            let entryset = directory.find(from).unwrap().unwrap();
+            directory.rename(&entryset, to).unwrap();
+            assert_eq!(directory.find(from).unwrap().unwrap().name(), to.as_str());
+            assert!(directory.find("next").unwrap().is_some());
+            let entryset = directory.find("next").unwrap().unwrap();
+            directory.delete(&entryset).unwrap();
+            let entryset = directory.find(to).unwrap().unwrap();
+            directory.delete(&entryset).unwrap();
+        }
+        drop(directory);
+        assert!(root.check(false).unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_create_directory() {
+        use super::FileOrDirectory;
+        use crate::error::AllocationError;
+
+        let image = Image::new("exfat-test-create-directory.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        // Cluster of new directory is not part of this directory
+        directory.change_options(|options| options.dont_fragment = true);
+        for name in ["a", "b", "c"] {
+            directory.create(name, true).unwrap();
+        }
+        directory.change_options(|options| options.dont_fragment = false);
+
+        // Sub directory cluster is full after 40 entrysets of 3 entries
+        let entryset = directory.find("a").unwrap().unwrap();
+        let mut subdir = match directory.open(&entryset).unwrap() {
+            FileOrDirectory::Directory(subdir) => subdir,
+            FileOrDirectory::File(_) => unreachable!(),
+        };
+        for i in 0..40 {
+            subdir.create(&format!("{}", i), false).unwrap();
+        }
+        directory.create("fill", false).unwrap();
+        let entryset = directory.find("fill").unwrap().unwrap();
+        match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(mut file) => {
+                let cluster = vec![0; 4096];
+                let result = loop {
+                    if let Err(e) = file.write_all(&cluster) {
+                        break e;
+                    }
+                };
+                assert!(matches!(result, Error::Allocation(AllocationError::NoMoreCluster)));
+            }
+            FileOrDirectory::Directory(_) => unreachable!(),
+        }
+        let entryset = directory.find("c").unwrap().unwrap();
+        directory.delete(&entryset).unwrap();
+        // Only cluster left allocated for new directory, but none left for growing entries
+        let result = subdir.create("d", true);
+        assert!(matches!(result, Err(Error::Allocation(AllocationError::NoMoreCluster))));
+        // Which is released for growing entries of file
+        subdir.create("d", false).unwrap();
+        drop(subdir);
+        drop(directory);
+        assert!(root.check(false).unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_directory_growth() {
+        let image = Image::new("exfat-test-directory-growth.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("sub", true).unwrap();
+        let entryset = directory.find("sub").unwrap().unwrap();
+        let mut sub = match directory.open(&entryset).unwrap() {
+            super::FileOrDirectory::Directory(sub) => sub,
+            super::FileOrDirectory::File(_) => unreachable!(),
+        };
+        // Both root and sub directory need more than one cluster
+        for i in 0..100 {
+            let name = format!("file-with-a-long-name-{}", i);
+            directory.create(&name, false).unwrap();
+            sub.create(&name, i % 10 == 0).unwrap();
+        }
+        drop(sub);
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        let entryset = directory.find("sub").unwrap().unwrap();
+        assert!(entryset.data_length() > 4096);
+        let mut sub = match directory.open(&entryset).unwrap() {
+            super::FileOrDirectory::Directory(sub) => sub,
+            super::FileOrDirectory::File(_) => unreachable!(),
+        };
+        assert_eq!(sub.capacity().unwrap(), entryset.data_length());
+        // 406 entries of root directory take 4 clusters
+        assert_eq!(directory.capacity().unwrap(), 4 * 4096);
+        for i in 0..100 {
+            let name = format!("file-with-a-long-name-{}", i);
+            assert!(directory.find(&name).unwrap().is_some());
+            assert_eq!(sub.find(&name).unwrap().unwrap().is_dir(), i % 10 == 0);
+        }
+        drop(sub);
+        drop(directory);
+        assert!(root.check(false).unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        use std::io::{Seek, Write};
+
+        use crate::error::DataError;
+        use crate::volume::ParseMode;
+
+        let image = Image::new("exfat-test-parse-mode.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("file", false).unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        let id = entryset.id(&directory.meta.fs_info);
+        let sector_size = directory.meta.fs_info.sector_size() as u64;
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let offset =
+            u64::from(id.sector_id) * sector_size + id.index as u64 * super::ENTRY_SIZE as u64;
+        let mut file = std::fs::File::options().write(true).open(image.path()).unwrap();
+        // Nonzero reserved byte, which stales set checksum as well
+        file.seek(std::io::SeekFrom::Start(offset + 6)).unwrap();
+        file.write_all(&[1]).unwrap();
+        // Unrecognized benign primary entry right after the entryset
+        file.seek(std::io::SeekFrom::Start(offset + 3 * super::ENTRY_SIZE as u64)).unwrap();
+        file.write_all(&[0xA3]).unwrap();
+        drop(file);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        assert!(directory.find("file").unwrap().is_some());
+        assert!(directory.find("missing").unwrap().is_none());
+        exfat.change_options(|options| options.parse_mode = ParseMode::Strict);
+        for name in ["file", "missing"] {
+            match directory.find(name) {
+                Err(Error::Data(DataError::Metadata(_))) => (),
+                _ => panic!("Deviation accepted in strict mode"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_skip_damaged() {
+        use std::io::{Seek, Write};
+        use std::sync::Mutex;
+
+        use crate::error::{DataError, Location};
+
+        let image = Image::new("exfat-test-skip-damaged.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        for name in ["a", "b", "c"] {
+            directory.create(name, false).unwrap();
+        }
+        let entryset = directory.find("b").unwrap().unwrap();
+        let id = entryset.id(&directory.meta.fs_info);
+        let sector_size = directory.meta.fs_info.sector_size() as u64;
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let offset = u64::from(id.sector_id) * sector_size + id.index as u64 * 32;
+        let mut file = std::fs::File::options().write(true).open(image.path()).unwrap();
+        // Secondary count less than 2
+        file.seek(std::io::SeekFrom::Start(offset + 1)).unwrap();
+        file.write_all(&[1]).unwrap();
+        drop(file);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        match directory.find("c") {
+            Err(Error::Data(DataError::Metadata(_))) => (),
+            _ => panic!("Damaged entryset not refused"),
+        }
+        let damaged: &'static Mutex<Vec<Location>> = Box::leak(Box::default());
+        let on_damaged = move |location| damaged.lock().unwrap().push(location);
+        exfat.change_options(|options| options.on_damaged = Some(Box::leak(Box::new(on_damaged))));
+        assert!(directory.find("c").unwrap().is_some());
+        assert!(directory.find("b").unwrap().is_none());
+        let expected = (Some(id.sector_id), Some(id.index as u8));
+        assert!(damaged.lock().unwrap().iter().any(|l| (l.sector_id, l.entry_index) == expected));
+    }
+
+    #[test]
+    fn test_batch_fetch() {
+        let image = Image::new("exfat-test-batch-fetch.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        for i in 0..32 {
+            directory.create(&format!("file{}", i), false).unwrap();
+        }
+        directory.lookup_cache.clear();
+        let stats = exfat.stats();
+        let mut num_entrysets = 0;
+        directory
+            .walk_matches(
+                |_, _| true,
+                |_| -> Option<()> {
+                    num_entrysets += 1;
+                    None
+                },
+            )
+            .unwrap();
+        assert_eq!(num_entrysets, 32);
+        // Rest of cluster fetched at once after first sector
+        assert_eq!(exfat.stats().reads - stats.reads, 2);
+        assert!(exfat.stats().sectors_read - stats.sectors_read > 2);
+    }
+
+    #[test]
+    fn test_cancel() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        static CANCEL: AtomicBool = AtomicBool::new(false);
+
+        let image = Image::new("exfat-test-cancel.img");
+        let mut exfat = image.mount();
+        exfat.change_options(|options| options.cancel = Some(&CANCEL));
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("tree", true).unwrap();
+        let entryset = directory.find("tree").unwrap().unwrap();
+        match directory.open(&entryset).unwrap() {
+            super::FileOrDirectory::Directory(mut tree) => {
+                for i in 0..4 {
+                    tree.create(&format!("{}", i), false).unwrap();
+                }
+            }
+            super::FileOrDirectory::File(_) => unreachable!(),
+        }
+
+        CANCEL.store(true, Ordering::Relaxed);
+        let result = directory.delete_recursive(&entryset);
+        assert!(matches!(result, Err(Error::Operation(OperationError::Cancelled))));
+        assert!(matches!(root.check(false), Err(Error::Operation(OperationError::Cancelled))));
+        assert!(directory.find("tree").unwrap().is_some());
+
+        CANCEL.store(false, Ordering::Relaxed);
+        assert!(root.check(false).unwrap().is_consistent());
+        directory.delete_recursive(&entryset).unwrap();
+        assert!(directory.find("tree").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_walk_while_modified() {
+        let image = Image::new("exfat-test-walk-modified.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("dir", true).unwrap();
+        let entryset = directory.find("dir").unwrap().unwrap();
+        let mut subdir = match directory.open(&entryset).unwrap() {
+            super::FileOrDirectory::Directory(subdir) => subdir,
+            super::FileOrDirectory::File(_) => unreachable!(),
+        };
+        for i in 0..8 {
+            subdir.create(&format!("file-{}", i), false).unwrap();
+        }
+
+        // Walk resumed from position of last returned entryset, which is skipped
+        let mut seen: Vec<String> = Vec::new();
+        let mut position = None;
+        loop {
+            let last = seen.last().cloned();
+            let mut h = |e: &super::EntrySet| e.in_use() && Some(e.name()) != last.as_deref();
+            let found = match position {
+                None => subdir.walk(&mut h).unwrap(),
+                Some(position) => subdir.walk_from(position, &mut h).unwrap(),
+            };
+            let Some(entryset) = found else { break };
+            position = Some(entryset.position());
+            seen.push(entryset.name().to_string());
+            if seen.len() == 3 {
+                let deleted = subdir.find("file-5").unwrap().unwrap();
+                subdir.delete(&deleted).unwrap();
+                subdir.create("created-in-between", false).unwrap();
+            }
+        }
+        let expected = ["file-0", "file-1", "file-2", "file-3", "file-4", "file-6", "file-7"];
+        for name in expected {
+            assert_eq!(seen.iter().filter(|seen| *seen == name).count(), 1, "{}", name);
+        }
+        assert!(!seen.iter().any(|name| name == "file-5"));
+
+        let entryset = subdir.find("file-0").unwrap().unwrap();
+        subdir.rename(&entryset, "renamed").unwrap();
+        let result = subdir.walk_from(position.unwrap(), |e| e.in_use());
+        assert!(matches!(result, Err(Error::Operation(OperationError::Relocated))));
+        let entryset = subdir.walk(|e| e.in_use()).unwrap().unwrap();
+        assert!(subdir.walk_from(entryset.position(), |e| e.in_use()).is_ok());
+    }
+}
@@ -1,11 +1,10 @@
-use core::fmt::Display;
-use core::mem::MaybeUninit;
+use core::fmt::{Debug, Display};
 
 use crate::file::MAX_FILENAME_SIZE;
 use crate::fs::{self, SectorRef};
-use crate::region::data::entryset::primary::FileDirectory;
+use crate::region::data::entryset::primary::{DateTime, FileDirectory};
 use crate::region::data::entryset::secondary::{Secondary, StreamExtension};
-use crate::types::SectorID;
+use crate::types::{ClusterID, SectorID};
 
 #[derive(Copy, Clone, Eq, Ord, PartialEq, PartialOrd)]
 pub(crate) struct EntryID {
@@ -31,29 +30,50 @@ impl EntryRef {
     }
 }
 
+/// Opaque position of an entryset within directory, for resuming a walk later,
+/// along with relocation generation of directory when taken
+#[derive(Copy, Clone, Debug)]
+pub struct WalkPosition(pub(crate) EntryRef, pub(crate) u32);
+
+/// Entryset of a file or directory, name is stored in a buffer of `N` bytes
 #[derive(Clone)]
-pub struct EntrySet {
-    pub(crate) name_bytes: [u8; MAX_FILENAME_SIZE],
-    pub(crate) name_length: u8,
+pub struct EntrySet<const N: usize = MAX_FILENAME_SIZE> {
+    pub(crate) name_bytes: [u8; N],
+    pub(crate) name_length: u16,
     pub file_directory: FileDirectory,
     pub stream_extension: Secondary<StreamExtension>,
     pub(crate) entry_ref: EntryRef,
+    /// Relocation generation of directory when walked through
+    pub(crate) relocations: u32,
 }
 
-impl Default for EntrySet {
+impl<const N: usize> Default for EntrySet<N> {
     fn default() -> Self {
-        let bytes: MaybeUninit<[u8; MAX_FILENAME_SIZE]> = MaybeUninit::uninit();
         Self {
-            name_bytes: unsafe { bytes.assume_init() },
+            name_bytes: [0; N],
             name_length: 0,
             file_directory: Default::default(),
             stream_extension: Default::default(),
             entry_ref: Default::default(),
+            relocations: 0,
         }
     }
 }
 
-impl EntrySet {
+impl<const N: usize> Debug for EntrySet<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EntrySet")
+            .field("name", &self.name())
+            .field("directory", &self.is_dir())
+            .field("in_use", &self.in_use())
+            .field("first_cluster", &format_args!("{}", self.first_cluster()))
+            .field("size", &self.valid_data_length())
+            .field("entry_ref", &format_args!("{}", self.entry_ref))
+            .finish()
+    }
+}
+
+impl<const N: usize> EntrySet<N> {
     pub fn name(&self) -> &str {
         unsafe { core::str::from_utf8_unchecked(&self.name_bytes[..self.name_length as usize]) }
     }
@@ -62,6 +82,26 @@ impl EntrySet {
         self.file_directory.entry_type.in_use()
     }
 
+    pub fn is_dir(&self) -> bool {
+        self.file_directory.file_attributes().directory() > 0
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.file_directory.file_attributes().read_only() > 0
+    }
+
+    pub fn created(&self) -> DateTime {
+        self.file_directory.create_timestamp()
+    }
+
+    pub fn modified(&self) -> DateTime {
+        self.file_directory.last_modified_timestamp()
+    }
+
+    pub fn accessed(&self) -> DateTime {
+        self.file_directory.last_accessed_timestamp()
+    }
+
     pub fn data_length(&self) -> u64 {
         self.stream_extension.data_length.to_ne()
     }
@@ -71,6 +111,20 @@ impl EntrySet {
         valid_data_length.to_ne()
     }
 
+    pub fn first_cluster(&self) -> ClusterID {
+        self.stream_extension.first_cluster.to_ne().into()
+    }
+
+    /// Whether clusters are chained by FAT, otherwise contiguous as NoFatChain flag set
+    pub fn fat_chain(&self) -> bool {
+        self.stream_extension.general_secondary_flags.fat_chain()
+    }
+
+    /// Position of this entryset, walking from which revisits this entryset first
+    pub fn position(&self) -> WalkPosition {
+        WalkPosition(self.entry_ref, self.relocations)
+    }
+
     pub(crate) fn id(&self, fs_info: &fs::Info) -> EntryID {
         EntryID { sector_id: self.entry_ref.sector_ref.id(fs_info), index: self.entry_ref.index }
     }
@@ -18,17 +18,36 @@ pub struct File<E: Debug, IO: crate::io::IO<Error = E>> {
     pub(crate) meta: MetaFileDirectory<IO>,
     pub(crate) sector_ref: SectorRef,
     pub(crate) size: u64,
-    cursor: u64,
+    pub(crate) cursor: u64,
     dirty: bool,
+    read_only: bool,
+    sequential: bool,
+}
+
+impl<E: Debug, IO: crate::io::IO<Error = E>> Debug for File<E, IO> {
+    /// Name is kept by handle with `alloc` feature only
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut debug = f.debug_struct("File");
+        #[cfg(feature = "alloc")]
+        debug.field("name", &self.meta.metadata.name);
+        debug
+            .field("entry_ref", &format_args!("{}", self.meta.metadata.entry_ref))
+            .field("first_cluster", &format_args!("{}", self.meta.sector_ref.cluster_id))
+            .field("size", &self.size)
+            .field("cursor", &self.cursor)
+            .field("read_only", &self.read_only)
+            .finish()
+    }
 }
 
 impl<E: Debug, IO: crate::io::IO<Error = E>> File<E, IO> {
     pub(crate) fn new(meta: MetaFileDirectory<IO>, sector_ref: SectorRef) -> Self {
         let size = meta.metadata.length();
-        Self { meta, sector_ref, size, cursor: 0, dirty: false }
+        let (dirty, read_only, sequential) = (false, false, false);
+        Self { meta, sector_ref, size, cursor: 0, dirty, read_only, sequential }
     }
 
-    pub fn change_options(&mut self, f: impl Fn(&mut FileOptions)) {
+    pub fn change_options(&mut self, f: impl FnOnce(&mut FileOptions)) {
         f(&mut self.meta.options)
     }
 }
@@ -39,16 +58,88 @@ impl<E: Debug, IO: crate::io::IO<Error = E>> File<E, IO> {
         self.size
     }
 
+    /// Clone a read only handle with independent cursor over the same file,
+    /// which sees file size at the moment of cloning
+    pub async fn try_clone(&self) -> Result<Self, Error<E>> {
+        let mut meta = self.meta.clone();
+        meta.metadata.dirty = false;
+        if !acquire!(self.meta.context).opened_entries.share(meta.id()) {
+            return Err(OperationError::NotFound.into());
+        }
+        let (sector_ref, size, cursor) = (self.sector_ref, self.size, self.cursor);
+        let (dirty, read_only, sequential) = (false, true, false);
+        Ok(Self { meta, sector_ref, size, cursor, dirty, read_only, sequential })
+    }
+
+    fn ensure_writable(&self) -> Result<(), Error<E>> {
+        match self.read_only {
+            true => Err(OperationError::ReadOnly.into()),
+            false => Ok(()),
+        }
+    }
+
     /// Change file timestamp, will not take effect immediately untill flush or sync_all called
     pub async fn touch(&mut self, datetime: DateTime, opts: TouchOptions) -> Result<(), Error<E>> {
+        self.ensure_writable()?;
         self.meta.touch(datetime, opts).await?;
-        acquire!(self.meta.io).flush().await
+        acquire!(self.meta.io).auto_flush().await
+    }
+
+    /// Prefetch sectors ahead when entering a readahead window boundary
+    async fn readahead(&mut self) -> Result<(), Error<E>> {
+        let count = self.meta.options.readahead_sectors as u32;
+        let sector_index = self.sector_ref.sector_index;
+        if count == 0 || !sector_index.is_multiple_of(count) {
+            return Ok(());
+        }
+        let count = core::cmp::min(count, self.meta.fs_info.sectors_per_cluster() - sector_index);
+        let sector_id = self.sector_ref.id(&self.meta.fs_info);
+        acquire!(self.meta.io).prefetch(sector_id, count)
+    }
+
+    /// Number of physically contiguous sectors starting from current sector
+    fn extent_sectors(&self) -> usize {
+        let sectors_per_cluster = self.meta.fs_info.sectors_per_cluster();
+        let num_sectors = (sectors_per_cluster - self.sector_ref.sector_index) as usize;
+        if self.meta.metadata.stream_extension.general_secondary_flags.fat_chain() {
+            return num_sectors;
+        }
+        let sector_size = self.meta.fs_info.sector_size() as u64;
+        let capacity = self.meta.metadata.capacity();
+        ((capacity - self.cursor) / sector_size) as usize
+    }
+
+    /// Fill zeros for region beyond valid data length which is uninitialized on device
+    async fn read_uninitialized(&mut self, buf: &mut [u8]) -> Result<usize, Error<E>> {
+        buf.fill(0);
+        let sector_size = self.meta.fs_info.sector_size() as u64;
+        let num_sectors = (self.cursor % sector_size + buf.len() as u64) / sector_size;
+        self.cursor += buf.len() as u64;
+        let capacity = self.meta.metadata.capacity();
+        for i in 0..num_sectors {
+            if i + 1 == num_sectors && self.cursor >= capacity {
+                break;
+            }
+            self.sector_ref = self.meta.next(self.sector_ref).await?;
+        }
+        Ok(buf.len())
     }
 
     /// Read some bytes
     /// If sector remain bytes fits in buf,
     /// all remain bytes will be read,
     /// Otherwise a sector size or a buf size will be read.
+    ///
+    /// Bytes beyond valid data length are read as zeros.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "read",
+            level = "trace",
+            skip_all,
+            fields(cursor = self.cursor, length = buf.len())
+        )
+    )]
     pub async fn read(&mut self, mut buf: &mut [u8]) -> Result<usize, Error<E>> {
         if self.cursor == self.size {
             return Err(OperationError::EOF.into());
@@ -56,51 +147,92 @@ impl<E: Debug, IO: crate::io::IO<Error = E>> File<E, IO> {
         if buf.len() > (self.size - self.cursor) as usize {
             buf = &mut buf[..(self.size - self.cursor) as usize];
         }
+        let valid_data_length = self.meta.metadata.length();
+        if self.cursor >= valid_data_length {
+            return self.read_uninitialized(buf).await;
+        }
+        if buf.len() > (valid_data_length - self.cursor) as usize {
+            buf = &mut buf[..(valid_data_length - self.cursor) as usize];
+        }
         let sector_size = self.meta.fs_info.sector_size() as usize;
-        let offset = self.cursor as usize % sector_size;
-        let sector_id = self.sector_ref.id(&self.meta.fs_info);
-        let sector_remain = sector_size - offset;
-        let mut io = acquire!(self.meta.io);
-        let sector = io.read(sector_id).await?;
-        let bytes = crate::io::flatten(sector);
-        if buf.len() <= sector_remain {
-            buf.copy_from_slice(&bytes[offset..offset + buf.len()]);
+        let capacity = self.meta.metadata.capacity();
+        let mut remain = &mut buf[..];
+        let shift = self.meta.fs_info.sectors_per_cluster_shift;
+        while !remain.is_empty() {
+            let offset = self.cursor as usize % sector_size;
+            // Whole sectors within current extent are transferred in a single run
+            let num_sectors = match offset {
+                0 => (remain.len() / sector_size).clamp(1, self.extent_sectors()),
+                _ => 1,
+            };
+            let length = core::cmp::min(remain.len(), num_sectors * sector_size - offset);
+            let sector_id = self.sector_ref.id(&self.meta.fs_info);
+            let mut io = acquire!(self.meta.io);
+            if length == num_sectors * sector_size {
+                io.read_many(sector_id, num_sectors, &mut remain[..length]).await?;
+            } else {
+                let sector = io.read(sector_id).await?;
+                remain[..length].copy_from_slice(&sector[offset..offset + length]);
+            }
             drop(io);
-            if buf.len() == sector_remain {
+            remain = &mut remain[length..];
+            self.cursor += length as u64;
+            for _ in 1..num_sectors {
+                self.sector_ref = self.sector_ref.next(shift);
+            }
+            if (offset + length).is_multiple_of(sector_size) && self.cursor < capacity {
                 self.sector_ref = self.meta.next(self.sector_ref).await?;
+                if self.sequential {
+                    self.readahead().await?;
+                }
             }
-            self.cursor += buf.len() as u64;
-            return Ok(buf.len());
-        }
-        buf[..sector_remain].copy_from_slice(&bytes[offset..]);
-        drop(io);
-        let mut remain = &mut buf[sector_remain..];
-        self.sector_ref = self.meta.next(self.sector_ref).await?;
-        for _ in 0..remain.len() / sector_size {
-            let mut io = acquire!(self.meta.io);
-            let sector = io.read(sector_id).await?;
-            let bytes = crate::io::flatten(sector);
-            remain[..sector_size].copy_from_slice(bytes);
-            drop(io);
-            self.sector_ref = self.meta.next(self.sector_ref).await?;
-            remain = &mut remain[sector_size..];
         }
-        let mut io = acquire!(self.meta.io);
-        let sector = io.read(sector_id).await?;
-        let bytes = crate::io::flatten(sector);
-        remain.copy_from_slice(&bytes[..remain.len()]);
-        self.cursor += buf.len() as u64;
+        self.sequential = true;
         Ok(buf.len())
     }
 
     /// Write some bytes
     /// If bytes length fits in current sector remain size,
     /// all bytes will be successfully written,
-    /// Otherwise a sector size will be written.
+    /// Otherwise remain of current sector, or whole sectors within current cluster
+    /// or contiguous extent will be written.
     ///
     /// Write operation will not apply file metadata change immediately until
     /// flush or sync_all called.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "write",
+            level = "trace",
+            skip_all,
+            fields(cursor = self.cursor, length = bytes.len())
+        )
+    )]
     pub async fn write(&mut self, bytes: &[u8]) -> Result<usize, Error<E>> {
+        self.ensure_writable()?;
+        self.initialize(self.cursor).await?;
+        self.write_initialized(bytes).await
+    }
+
+    /// Write zeros between valid data length and specified position on device,
+    /// so that region extended by `set_len` becomes valid data
+    async fn initialize(&mut self, end: u64) -> Result<(), Error<E>> {
+        let valid_data_length = self.meta.metadata.length();
+        if end <= valid_data_length {
+            return Ok(());
+        }
+        let cursor = self.cursor;
+        self.seek(SeekFrom::Start(valid_data_length)).await?;
+        let zeros = [0u8; 512];
+        while self.cursor < end {
+            let length = core::cmp::min(end - self.cursor, zeros.len() as u64) as usize;
+            self.write_initialized(&zeros[..length]).await?;
+        }
+        self.seek(SeekFrom::Start(cursor)).await?;
+        Ok(())
+    }
+
+    async fn write_initialized(&mut self, bytes: &[u8]) -> Result<usize, Error<E>> {
         if bytes.len() == 0 {
             return Ok(0);
         }
@@ -121,7 +253,9 @@ impl<E: Debug, IO: crate::io::IO<Error = E>> File<E, IO> {
             if length == sector_remain && self.cursor < capacity {
                 self.sector_ref = self.meta.next(self.sector_ref).await?;
             }
-            return Ok(sector_remain);
+            let valid_data_length = self.meta.metadata.length();
+            self.meta.metadata.set_length(core::cmp::max(self.cursor, valid_data_length));
+            return Ok(length);
         }
         if self.cursor >= capacity {
             let cluster_id = self.meta.allocate(self.sector_ref.cluster_id).await?;
@@ -130,28 +264,41 @@ impl<E: Debug, IO: crate::io::IO<Error = E>> File<E, IO> {
         }
         trace!("Write to sector-ref {}", self.sector_ref);
         let sector_id = self.sector_ref.id(&self.meta.fs_info);
-        let length = core::cmp::min(bytes.len(), sector_size);
+        // Whole sectors within current extent are transferred in a single run
+        let num_sectors = (bytes.len() / sector_size).clamp(1, self.extent_sectors());
+        let length = core::cmp::min(bytes.len(), num_sectors * sector_size);
         let chunk = &bytes[..length];
-        acquire!(self.meta.io).write(sector_id, 0, chunk).await?;
+        match num_sectors {
+            1 => acquire!(self.meta.io).write(sector_id, 0, chunk).await?,
+            _ => acquire!(self.meta.io).write_many(sector_id, num_sectors as u32, chunk).await?,
+        }
         self.cursor += length as u64;
         self.size = core::cmp::max(self.cursor, self.size);
-        if length == sector_size && self.cursor < capacity {
+        let shift = self.meta.fs_info.sectors_per_cluster_shift;
+        for _ in 1..num_sectors {
+            self.sector_ref = self.sector_ref.next(shift);
+        }
+        if length.is_multiple_of(sector_size) && self.cursor < capacity {
             self.sector_ref = self.meta.next(self.sector_ref).await?;
         }
-        self.meta.metadata.set_length(self.size);
+        let valid_data_length = self.meta.metadata.length();
+        self.meta.metadata.set_length(core::cmp::max(self.cursor, valid_data_length));
         Ok(length)
     }
 
-    pub async fn write_all(&mut self, bytes: &[u8]) -> Result<(), Error<E>> {
-        let written = self.write(bytes).await?; // Fill remain of current sector
-        for chunk in bytes[written..].chunks(self.meta.fs_info.sector_size() as usize) {
-            self.write(chunk).await?;
+    pub async fn write_all(&mut self, mut bytes: &[u8]) -> Result<(), Error<E>> {
+        while !bytes.is_empty() {
+            let written = self.write(bytes).await?;
+            bytes = &bytes[written..];
         }
         Ok(())
     }
 
-    /// Flush data write operations
+    /// Flush data write operations, region extended by `set_len` is filled with zeros
     pub async fn sync_data(&mut self) -> Result<(), Error<E>> {
+        if !self.read_only {
+            self.initialize(self.size).await?;
+        }
         if self.dirty {
             acquire!(self.meta.io).flush().await?;
             self.dirty = false;
@@ -162,7 +309,8 @@ impl<E: Debug, IO: crate::io::IO<Error = E>> File<E, IO> {
     /// Flush data write operations and metadata changes
     pub async fn sync_all(&mut self) -> Result<(), Error<E>> {
         self.sync_data().await?;
-        self.meta.sync().await
+        self.meta.sync().await?;
+        acquire!(self.meta.io).flush_deferred().await
     }
 
     /// Alias of sync_all
@@ -170,73 +318,480 @@ impl<E: Debug, IO: crate::io::IO<Error = E>> File<E, IO> {
         self.sync_all().await
     }
 
+    /// Index of sector where cursor locates, cursor at end of capacity stays in last sector
+    fn sector_index(&self, cursor: u64) -> u64 {
+        let sector_size = self.meta.fs_info.sector_size() as u64;
+        let index = cursor / sector_size;
+        let end_of_capacity = cursor >= self.meta.metadata.capacity();
+        match index > 0 && cursor.is_multiple_of(sector_size) && end_of_capacity {
+            true => index - 1,
+            false => index,
+        }
+    }
+
     /// Change current cursor position
     pub async fn seek(&mut self, seek_from: SeekFrom) -> Result<u64, Error<E>> {
         let option = match seek_from {
             SeekFrom::Start(cursor) => i64::try_from(cursor).ok(),
-            SeekFrom::End(offset) => Some((self.cursor as i64) + offset),
+            SeekFrom::End(offset) => (self.size as i64).checked_add(offset),
             SeekFrom::Current(offset) => (self.cursor as i64).checked_add(offset),
         };
         let cursor = option.ok_or(Error::Input(InputError::SeekPosition))?;
-        if cursor < 0 || cursor >= self.size as i64 {
+        if cursor < 0 || cursor > self.size as i64 {
             return Err(InputError::SeekPosition.into());
         }
         let cursor = cursor as u64;
-        let sector_size = self.meta.fs_info.sector_size() as u64;
-        let num_sectors = match () {
-            _ if cursor > self.cursor => (cursor - self.cursor + sector_size - 1) / sector_size,
-            _ if cursor < self.cursor => {
+        let (current, target) = (self.sector_index(self.cursor), self.sector_index(cursor));
+        let num_sectors = match target >= current {
+            true => target - current,
+            false => {
                 self.sector_ref = self.meta.sector_ref;
-                (cursor + sector_size - 1) / sector_size
+                target
             }
-            _ => 0,
         };
         for _ in 0..num_sectors {
             self.sector_ref = self.meta.next(self.sector_ref).await?;
         }
         self.cursor = cursor;
+        self.sequential = false;
         Ok(cursor)
     }
 
     /// Shrink current file size
     pub async fn truncate(&mut self, size: u64) -> Result<(), Error<E>> {
+        self.ensure_writable()?;
         if size > self.size {
             return Err(InputError::Size.into());
         }
         if self.cursor > size {
-            self.cursor = size;
             self.seek(SeekFrom::Start(size)).await?;
         }
-        self.meta.metadata.set_length(size);
+        let valid_data_length = self.meta.metadata.length();
+        self.meta.metadata.set_length(core::cmp::min(size, valid_data_length));
+        self.size = size;
+        Ok(())
+    }
+
+    /// Truncate or extend file size, extended region is allocated and reads as zeros,
+    /// which will not be written to device untill sync_data or sync_all called
+    pub async fn set_len(&mut self, size: u64) -> Result<(), Error<E>> {
+        if size <= self.size {
+            return self.truncate(size).await;
+        }
+        self.ensure_writable()?;
+        let capacity = self.meta.metadata.capacity();
+        let mut last = self.meta.last_cluster().await?;
+        while self.meta.metadata.capacity() < size {
+            last = self.meta.allocate(last).await?;
+        }
+        // Cursor at end of capacity stays in last sector, which is followed by new cluster now
+        if self.cursor == capacity {
+            self.sector_ref = match capacity {
+                0 => self.meta.sector_ref,
+                _ => self.meta.next(self.sector_ref).await?,
+            };
+        }
         self.size = size;
         Ok(())
     }
 
-    #[cfg(all(feature = "async", not(feature = "std")))]
-    /// `no_std` async only which must be explicitly called
+    /// Release allocated clusters beyond current file size,
+    /// will not take effect immediately untill flush or sync_all called
+    pub async fn shrink_to_fit(&mut self) -> Result<(), Error<E>> {
+        self.ensure_writable()?;
+        self.initialize(self.size).await?;
+        self.meta.shrink_to_fit().await?;
+        if self.size == 0 {
+            self.sector_ref = self.meta.sector_ref;
+        }
+        Ok(())
+    }
+
+    #[cfg(all(feature = "async", not(feature = "async-std")))]
+    /// Async without `async-std` runtime only, which must be explicitly called
     pub async fn close(mut self) -> Result<(), Error<E>> {
-        self.flush().await?;
+        self.sync_data().await?;
         self.meta.close().await
     }
 }
 
-#[cfg(any(not(feature = "async"), feature = "std"))]
+#[cfg(any(not(feature = "async"), feature = "async-std"))]
 impl<E: Debug, IO: crate::io::IO<Error = E>> Drop for File<E, IO> {
     fn drop(&mut self) {
         match () {
-            #[cfg(all(feature = "async", not(feature = "std")))]
+            #[cfg(all(feature = "async", not(feature = "async-std")))]
             () => panic!("Close must be explicit called"),
-            #[cfg(all(feature = "async", feature = "std"))]
+            #[cfg(feature = "async-std")]
             () => async_std::task::block_on(async {
-                self.flush().await?;
+                self.sync_data().await?;
                 self.meta.close().await
             })
             .unwrap(),
             #[cfg(not(feature = "async"))]
             () => {
-                self.flush().unwrap();
+                self.sync_data().unwrap();
                 self.meta.close().unwrap();
             }
         }
     }
 }
+
+#[cfg(all(test, feature = "std", not(feature = "async")))]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::fixture::Image;
+    use crate::io::std::FileIO;
+    use crate::io::{Block, IO};
+    use crate::types::SectorID;
+    use crate::FileOrDirectory;
+
+    /// Not `Send`, relies on default `read_many` and records prefetch hints
+    struct SharedIO(FileIO, Rc<RefCell<Vec<(SectorID, u32)>>>);
+
+    impl IO for SharedIO {
+        type Error = std::io::Error;
+        type Block = Block;
+
+        fn set_sector_size_shift(&mut self, shift: u8) -> Result<(), Self::Error> {
+            self.0.set_sector_size_shift(shift)
+        }
+
+        fn read(&mut self, id: SectorID) -> Result<&[Block], Self::Error> {
+            self.0.read(id)
+        }
+
+        fn write(&mut self, id: SectorID, offset: usize, data: &[u8]) -> std::io::Result<()> {
+            self.0.write(id, offset, data)
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            self.0.flush()
+        }
+
+        fn prefetch(&mut self, id: SectorID, count: u32) -> Result<(), Self::Error> {
+            self.1.borrow_mut().push((id, count));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sync_across_clusters() {
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        use crate::fs::SectorRef;
+
+        let image = Image::new("exfat-test-sync-across-clusters.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        let open = |directory: &mut crate::Directory<_, _>, name: &str| {
+            let entryset = directory.find(name).unwrap().unwrap();
+            match directory.open(&entryset).unwrap() {
+                FileOrDirectory::File(file) => file,
+                FileOrDirectory::Directory(_) => unreachable!(),
+            }
+        };
+        // Cluster following first cluster of root directory taken by another file
+        directory.create("pad", false).unwrap();
+        open(&mut directory, "pad").write_all(&[0xA5; 4096]).unwrap();
+        directory.create("name-of-sixteen-chars", false).unwrap();
+        for i in 0..37 {
+            directory.create(&format!("{}", i), false).unwrap();
+        }
+        // Root directory grows since entrysets created never cross sectors
+        directory.create("file", false).unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        let fs_info = directory.meta.fs_info;
+        let (first_cluster, cluster_id) = (directory.meta.sector_ref.cluster_id, 6u32.into());
+        let entry_ref = entryset.entry_ref;
+        assert_eq!(entry_ref.sector_ref.cluster_id, cluster_id);
+        assert_eq!((entry_ref.sector_ref.sector_index, entry_ref.index), (0, 0));
+        assert_eq!(first_cluster + 2u32, cluster_id);
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        // Move entryset one entry backward, so that it starts at the last entry of first cluster
+        let sector_size = fs_info.sector_size() as u64;
+        let last = SectorRef::new(first_cluster, fs_info.sectors_per_cluster() - 1);
+        let offset = u64::from(last.id(&fs_info)) * sector_size + sector_size - 32;
+        let next = u64::from(SectorRef::new(cluster_id, 0).id(&fs_info)) * sector_size;
+        let mut file = std::fs::File::options().read(true).write(true).open(image.path()).unwrap();
+        let mut entries = [0u8; 4 * 32];
+        file.seek(SeekFrom::Start(next)).unwrap();
+        file.read_exact(&mut entries[..3 * 32]).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&entries[..32]).unwrap();
+        file.seek(SeekFrom::Start(next)).unwrap();
+        file.write_all(&entries[32..]).unwrap();
+        drop(file);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        open(&mut directory, "file").write_all(&[0x5A; 100]).unwrap();
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        assert_eq!(open(&mut directory, "file").size(), 100);
+        let mut buf = [0u8; 4096];
+        open(&mut directory, "pad").read(&mut buf).unwrap();
+        assert_eq!(buf, [0xA5; 4096]);
+        drop(directory);
+        assert!(root.check(false).unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_write_partial_sector() {
+        let image = Image::new("exfat-test-write-partial-sector.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("file", false).unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        let mut file = match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(file) => file,
+            FileOrDirectory::Directory(_) => unreachable!(),
+        };
+        assert_eq!(file.write(&[1; 100]).unwrap(), 100);
+        // Only bytes written into remaining of current sector are counted
+        assert_eq!(file.write(&[2; 10]).unwrap(), 10);
+        assert_eq!(file.write(&[3; 1000]).unwrap(), 512 - 110);
+        drop(file);
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        assert_eq!(entryset.valid_data_length(), 512);
+        let mut file = match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(file) => file,
+            FileOrDirectory::Directory(_) => unreachable!(),
+        };
+        let mut buf = [0u8; 512];
+        assert_eq!(file.read(&mut buf).unwrap(), 512);
+        assert_eq!(buf[..100], [1; 100]);
+        assert_eq!(buf[100..110], [2; 10]);
+        assert!(buf[110..].iter().all(|&byte| byte == 3));
+    }
+
+    #[test]
+    fn test_atime_policy() {
+        use crate::file::TouchOptions;
+        use crate::region::data::entryset::primary::{DateTime, Timestamp};
+        use crate::volume::AtimePolicy;
+
+        let datetime = |day, hour| {
+            let mut timestamp = Timestamp::default();
+            timestamp.set_year(2024);
+            timestamp.set_month(1);
+            timestamp.set_day(day);
+            timestamp.set_hour(hour);
+            DateTime { timestamp, ..Default::default() }
+        };
+        let access = TouchOptions { access: true, modified: false };
+        let image = Image::new("exfat-test-atime-policy.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("file", false).unwrap();
+        let mut touch = |datetime: DateTime, opts: TouchOptions| {
+            let entryset = directory.find("file").unwrap().unwrap();
+            match directory.open(&entryset).unwrap() {
+                FileOrDirectory::File(mut file) => file.touch(datetime, opts).unwrap(),
+                FileOrDirectory::Directory(_) => panic!("Expect file"),
+            }
+            let entryset = directory.find("file").unwrap().unwrap();
+            (u32::from(entryset.accessed().timestamp), u32::from(entryset.modified().timestamp))
+        };
+
+        let (accessed, modified) = touch(datetime(1, 0), Default::default());
+        assert_eq!(accessed, u32::from(datetime(1, 0).timestamp));
+        assert_eq!(accessed, modified);
+
+        exfat.change_options(|options| options.atime_policy = AtimePolicy::Never);
+        assert_eq!(touch(datetime(1, 1), access), (accessed, modified));
+
+        exfat.change_options(|options| options.atime_policy = AtimePolicy::Relatime);
+        // Not later than last modified timestamp
+        let (accessed, _) = touch(datetime(1, 2), access);
+        assert_eq!(accessed, u32::from(datetime(1, 2).timestamp));
+        // Already accessed today since last modified
+        assert_eq!(touch(datetime(1, 3), access).0, accessed);
+        let (accessed, _) = touch(datetime(2, 0), access);
+        assert_eq!(accessed, u32::from(datetime(2, 0).timestamp));
+    }
+
+    #[test]
+    fn test_debug() {
+        let image = Image::new("exfat-test-debug.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        assert!(format!("{:?}", root).contains("PROTECTED"));
+        let mut directory = root.open().unwrap();
+        directory.create("file", false).unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        assert!(format!("{:?}", entryset).contains("name: \"file\""));
+        let mut file = match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(file) => file,
+            FileOrDirectory::Directory(_) => unreachable!(),
+        };
+        file.write_all(b"hello").unwrap();
+        let debug = format!("{:?}", file);
+        assert!(debug.contains("name: \"file\"") && debug.contains("size: 5, cursor: 5"));
+        drop(file);
+        directory.create("dir", true).unwrap();
+        let entryset = directory.find("dir").unwrap().unwrap();
+        let subdir = directory.open(&entryset).unwrap();
+        assert!(format!("{:?}", subdir).starts_with("Directory { name: \"dir\""));
+    }
+
+    #[test]
+    fn test_read_many_default() {
+        use crate::ExFAT;
+
+        let image = Image::new("exfat-test-read-many-default.img");
+        let hints = Rc::new(RefCell::new(Vec::new()));
+        let mut exfat = ExFAT::new(SharedIO(image.open(), hints.clone())).unwrap();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("file", false).unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        let mut file = match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(file) => file,
+            FileOrDirectory::Directory(_) => unreachable!(),
+        };
+        let bytes: Vec<u8> = (0..16384).map(|i| (i * 7 % 251) as u8).collect();
+        file.write_all(&bytes).unwrap();
+        drop(file);
+
+        let entryset = directory.find("file").unwrap().unwrap();
+        let mut file = match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(file) => file,
+            FileOrDirectory::Directory(_) => unreachable!(),
+        };
+        hints.borrow_mut().clear();
+        let mut buf = vec![0u8; bytes.len()];
+        assert_eq!(file.read(&mut buf).unwrap(), bytes.len());
+        assert_eq!(buf, bytes);
+        // Rest of each run is hinted once its first sector is read
+        assert!(hints.borrow().iter().any(|&(_, count)| count > 1));
+    }
+
+    #[test]
+    fn test_set_len_reads_zeros() {
+        use super::SeekFrom;
+
+        let image = Image::new("exfat-test-set-len-reads-zeros.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("file", false).unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        let mut file = match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(file) => file,
+            FileOrDirectory::Directory(_) => unreachable!(),
+        };
+        let cluster_size = file.meta.fs_info.cluster_size() as usize;
+        // Truncate keeps allocated clusters, leaving stale bytes beyond valid data length
+        file.write_all(&vec![0xA5; cluster_size]).unwrap();
+        file.truncate(100).unwrap();
+        let size = 2 * cluster_size + 100;
+        file.set_len(size as u64).unwrap();
+        assert_eq!(file.size(), size as u64);
+        assert_eq!(file.meta.metadata.length(), 100);
+        assert_eq!(file.meta.metadata.capacity(), 3 * cluster_size as u64);
+
+        let mut buf = vec![0xFF; size];
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let mut remain = &mut buf[..];
+        while !remain.is_empty() {
+            let length = file.read(remain).unwrap();
+            remain = &mut remain[length..];
+        }
+        assert_eq!(buf[..100], [0xA5; 100]);
+        assert!(buf[100..].iter().all(|&byte| byte == 0));
+
+        // Writing beyond valid data length makes the gap zeros on device
+        file.seek(SeekFrom::End(0)).unwrap();
+        file.write_all(&[1; 10]).unwrap();
+        drop(file);
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        assert_eq!(entryset.valid_data_length(), size as u64 + 10);
+        let mut file = match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(file) => file,
+            FileOrDirectory::Directory(_) => unreachable!(),
+        };
+        let mut buf = vec![0xFF; size + 10];
+        let mut remain = &mut buf[..];
+        while !remain.is_empty() {
+            let length = file.read(remain).unwrap();
+            remain = &mut remain[length..];
+        }
+        assert_eq!(buf[..100], [0xA5; 100]);
+        assert!(buf[100..size].iter().all(|&byte| byte == 0));
+        assert_eq!(buf[size..], [1; 10]);
+        drop(file);
+        drop(directory);
+        assert!(root.check(false).unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_readahead() {
+        use super::SeekFrom;
+        use crate::ExFAT;
+
+        let image = Image::new("exfat-test-readahead.img");
+        let hints = Rc::new(RefCell::new(Vec::new()));
+        let mut exfat = ExFAT::new(SharedIO(image.open(), hints.clone())).unwrap();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("file", false).unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        let mut file = match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(file) => file,
+            FileOrDirectory::Directory(_) => unreachable!(),
+        };
+        let fs_info = file.meta.fs_info;
+        let (sector_size, num_sectors) = (fs_info.sector_size() as usize, 32u32);
+        file.write_all(&vec![0x5A; sector_size * num_sectors as usize]).unwrap();
+        file.change_options(|options| options.readahead_sectors = 4);
+        let first = file.meta.sector_ref.id(&fs_info);
+
+        // Window following the first one is prefetched once reads are sequential
+        file.seek(SeekFrom::Start(0)).unwrap();
+        hints.borrow_mut().clear();
+        let mut buf = vec![0u8; sector_size];
+        for _ in 0..num_sectors {
+            assert_eq!(file.read(&mut buf).unwrap(), sector_size);
+        }
+        let expected: Vec<_> = (1..num_sectors / 4).map(|i| (first + i * 4, 4)).collect();
+        assert_eq!(*hints.borrow(), expected);
+
+        // Seeking breaks sequential access until next read
+        file.seek(SeekFrom::Start(3 * sector_size as u64)).unwrap();
+        hints.borrow_mut().clear();
+        file.read(&mut buf).unwrap();
+        assert!(hints.borrow().is_empty());
+        for _ in 0..4 {
+            file.read(&mut buf).unwrap();
+        }
+        assert_eq!(*hints.borrow(), [(first + 8u32, 4)]);
+    }
+}
@@ -1,20 +1,19 @@
-use core::mem::transmute;
-
 use super::context::Context;
 use super::entryset::EntryID;
 use super::metadata::Metadata;
-use crate::error::{AllocationError, DataError, Error, OperationError};
+use crate::error::{AllocationError, DataError, Error, Location, OperationError};
 use crate::fat;
 use crate::file::{FileOptions, TouchOptions};
 use crate::fs::{self, SectorRef};
 use crate::io::IOWrapper;
 use crate::region::data::entryset::primary::DateTime;
-use crate::region::data::entryset::{RawEntry, ENTRY_SIZE};
+use crate::region::data::entryset::{primary_checksum, RawEntry, ENTRY_SIZE};
 use crate::region::fat::Entry;
 use crate::sync::{acquire, Shared};
 use crate::types::ClusterID;
+use crate::volume::AtimePolicy;
 
-pub(crate) struct MetaFileDirectory<IO> {
+pub(crate) struct MetaFileDirectory<IO: crate::io::Lifetime> {
     pub io: Shared<IOWrapper<IO>>,
     pub context: Shared<Context<IO>>,
     pub fat_info: fat::Info,
@@ -22,9 +21,10 @@ pub(crate) struct MetaFileDirectory<IO> {
     pub metadata: Metadata,
     pub options: FileOptions,
     pub sector_ref: SectorRef,
+    pub fat_window: fat::Window,
 }
 
-impl<IO> Clone for MetaFileDirectory<IO> {
+impl<IO: crate::io::Lifetime> Clone for MetaFileDirectory<IO> {
     fn clone(&self) -> Self {
         Self {
             io: self.io.clone(),
@@ -35,7 +35,7 @@ impl<IO> Clone for MetaFileDirectory<IO> {
     }
 }
 
-impl<IO> MetaFileDirectory<IO> {
+impl<IO: crate::io::Lifetime> MetaFileDirectory<IO> {
     pub(crate) fn id(&self) -> EntryID {
         let entry_ref = &self.metadata.entry_ref;
         EntryID { sector_id: entry_ref.sector_ref.id(&self.fs_info), index: entry_ref.index }
@@ -46,44 +46,125 @@ impl<IO> MetaFileDirectory<IO> {
 impl<E, IO: crate::io::IO<Error = E>> MetaFileDirectory<IO> {
     pub async fn next(&mut self, sector_ref: SectorRef) -> Result<SectorRef, Error<E>> {
         let fat_chain = self.metadata.stream_extension.general_secondary_flags.fat_chain();
-        if sector_ref.sector_index != self.fs_info.sectors_per_cluster() {
+        if sector_ref.sector_index + 1 != self.fs_info.sectors_per_cluster() {
             return Ok(sector_ref.next(self.fs_info.sectors_per_cluster_shift));
         }
         if !fat_chain {
-            let num_clusters = (self.metadata.length() / self.fs_info.cluster_size() as u64) as u32;
+            let num_clusters =
+                (self.metadata.capacity() / self.fs_info.cluster_size() as u64) as u32;
             let max_cluster_id = self.sector_ref.cluster_id + num_clusters;
             if sector_ref.cluster_id + 1u32 >= max_cluster_id {
                 return Err(OperationError::EOF.into());
             }
             return Ok(sector_ref.next(self.fs_info.sectors_per_cluster_shift));
         }
-        let cluster_id = sector_ref.cluster_id;
+        match self.fat_entry(sector_ref.cluster_id).await? {
+            Entry::Next(cluster_id) => Ok(SectorRef::new(cluster_id, 0)),
+            _ => Err(OperationError::EOF.into()),
+        }
+    }
+
+    /// FAT entry of cluster, following entries in the same FAT sector are retained
+    /// and next FAT sector is prefetched once they run out
+    async fn fat_entry(&mut self, cluster_id: ClusterID) -> Result<Entry, Error<E>> {
         let option = self.fat_info.fat_sector_id(cluster_id);
-        let sector_id = option.ok_or(Error::Data(DataError::FATChain))?;
+        let sector_id = option.ok_or(DataError::FATChain(Location::cluster(cluster_id)))?;
         let mut io = acquire!(self.io);
-        let sector = io.read(sector_id).await?;
-        match self.fat_info.next_cluster_id(sector, sector_ref.cluster_id) {
-            Ok(Entry::Next(cluster_id)) => Ok(SectorRef::new(cluster_id, 0)),
-            Ok(Entry::Last) => Err(OperationError::EOF.into()),
-            _ => Err(DataError::FATChain.into()),
+        let value = match self.fat_window.get(cluster_id, io.fat_generation) {
+            Some(value) => value,
+            None => {
+                let generation = io.fat_generation;
+                let sector = io.read(sector_id).await?;
+                if self.fat_window.fill(sector, cluster_id, generation)
+                    && self.fat_info.fat_sector_id(cluster_id + (sector.len() / 4) as u32).is_some()
+                {
+                    io.prefetch(sector_id + 1u32, 1)?;
+                }
+                self.fat_window.get(cluster_id, generation).unwrap()
+            }
+        };
+        match Entry::try_from(value) {
+            Ok(Entry::BadCluster) | Err(_) => {
+                let location =
+                    Location { sector_id: Some(sector_id), ..Location::cluster(cluster_id) };
+                Err(DataError::FATChain(location).into())
+            }
+            Ok(entry) => Ok(entry),
         }
     }
 
     pub async fn touch(&mut self, datetime: DateTime, opts: TouchOptions) -> Result<(), Error<E>> {
-        let metadata = &mut self.metadata;
-        if opts.access {
-            metadata.file_directory.update_last_accessed_timestamp(datetime);
-        }
+        let policy = acquire!(self.io).options.atime_policy;
+        let file_directory = &mut self.metadata.file_directory;
         if opts.modified {
-            metadata.file_directory.update_last_modified_timestamp(datetime);
+            file_directory.update_last_modified_timestamp(datetime);
+        }
+        let accessed = u32::from(file_directory.last_accessed_timestamp().timestamp);
+        let modified = u32::from(file_directory.last_modified_timestamp().timestamp);
+        // Packed timestamp compares chronologically, date lies in upper half
+        let access = opts.access
+            && match policy {
+                AtimePolicy::Always => true,
+                AtimePolicy::Relatime => {
+                    accessed <= modified || accessed >> 16 < u32::from(datetime.timestamp) >> 16
+                }
+                AtimePolicy::Never => false,
+            };
+        if access {
+            file_directory.update_last_accessed_timestamp(datetime);
         }
-        metadata.update_checksum();
+        self.metadata.dirty |= opts.modified || access;
         Ok(())
     }
 }
 
 #[cfg_attr(not(feature = "async"), deasync::deasync)]
 impl<E, IO: crate::io::IO<Error = E>> MetaFileDirectory<IO> {
+    pub async fn fill_cluster(&mut self, cluster_id: ClusterID, byte: u8) -> Result<(), Error<E>> {
+        let first_sector = SectorRef::new(cluster_id, 0).id(&self.fs_info);
+        let bytes = [byte; 512];
+        let mut io = acquire!(self.io);
+        for i in 0..self.fs_info.sectors_per_cluster() {
+            for offset in (0..self.fs_info.sector_size() as usize).step_by(bytes.len()) {
+                io.write(first_sector + i, offset, &bytes).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn next_cluster_id(&mut self, cluster_id: ClusterID) -> Result<ClusterID, Error<E>> {
+        if !self.metadata.stream_extension.general_secondary_flags.fat_chain() {
+            return Ok(cluster_id + 1u32);
+        }
+        match self.fat_entry(cluster_id).await? {
+            Entry::Next(cluster_id) => Ok(cluster_id),
+            _ => Err(DataError::FATChain(Location::cluster(cluster_id)).into()),
+        }
+    }
+
+    /// Last allocated cluster, invalid if no cluster allocated
+    pub async fn last_cluster(&mut self) -> Result<ClusterID, Error<E>> {
+        let num_clusters = self.metadata.capacity() / self.fs_info.cluster_size() as u64;
+        let mut cluster_id = self.sector_ref.cluster_id;
+        for _ in 1..num_clusters {
+            cluster_id = self.next_cluster_id(cluster_id).await?;
+        }
+        Ok(cluster_id)
+    }
+
+    /// Fill all allocated clusters with specified byte
+    pub async fn fill_all_clusters(&mut self, byte: u8) -> Result<(), Error<E>> {
+        let num_clusters = self.metadata.capacity() / self.fs_info.cluster_size() as u64;
+        let mut cluster_id = self.sector_ref.cluster_id;
+        for i in 0..num_clusters {
+            if i > 0 {
+                cluster_id = self.next_cluster_id(cluster_id).await?;
+            }
+            self.fill_cluster(cluster_id, byte).await?;
+        }
+        acquire!(self.io).auto_flush().await
+    }
+
     pub async fn allocate(&mut self, last: ClusterID) -> Result<ClusterID, Error<E>> {
         trace!("Allocate cluster with last cluster {}", last);
         if !self.metadata.stream_extension.general_secondary_flags.allocation_possible() {
@@ -92,6 +173,11 @@ impl<E, IO: crate::io::IO<Error = E>> MetaFileDirectory<IO> {
         let fragment = !self.options.dont_fragment;
         let mut context = acquire!(self.context);
         let cluster_id = context.allocation_bitmap.allocate(last, fragment).await?;
+        drop(context);
+
+        if self.options.zero_fill {
+            self.fill_cluster(cluster_id, 0).await?;
+        }
 
         let cluster_size = self.fs_info.cluster_size() as u64;
         let metadata = &mut self.metadata;
@@ -100,62 +186,144 @@ impl<E, IO: crate::io::IO<Error = E>> MetaFileDirectory<IO> {
         if !last.valid() {
             metadata.stream_extension.first_cluster = u32::from(cluster_id).into();
             metadata.stream_extension.general_secondary_flags.clear_fat_chain();
+            self.sector_ref = SectorRef::new(cluster_id, 0);
         } else if last + 1u32 != cluster_id || fat_chain {
             let mut io = acquire!(self.io);
-            if !fat_chain && metadata.capacity() > cluster_size {
+            if !fat_chain {
                 let first = self.sector_ref.cluster_id;
                 for i in 0..(metadata.capacity() / cluster_size - 1) {
                     let cluster_id = first + i as u32;
                     let next = cluster_id + 1u32;
-                    let sector_id = self.fat_info.fat_sector_id(cluster_id).unwrap();
-                    let bytes = u32::to_le_bytes(next.into());
-                    io.write(sector_id, self.fat_info.offset(next), &bytes).await?;
+                    io.write_fat_entry(&self.fat_info, cluster_id, next.into()).await?;
                 }
                 metadata.stream_extension.general_secondary_flags.set_fat_chain();
             }
-            let sector_id = self.fat_info.fat_sector_id(last).unwrap();
-            let bytes = u32::to_le_bytes(cluster_id.into());
-            io.write(sector_id, self.fat_info.offset(last), &bytes).await?;
-            let bytes = u32::to_ne_bytes(Entry::Last.into());
-            io.write(sector_id, self.fat_info.offset(cluster_id), &bytes).await?;
+            io.write_fat_entry(&self.fat_info, last, cluster_id.into()).await?;
+            io.write_fat_entry(&self.fat_info, cluster_id, Entry::Last.into()).await?;
         }
         if metadata.file_directory.file_attributes().directory() > 0 {
             let length = metadata.length() + cluster_size;
             metadata.stream_extension.custom_defined.valid_data_length = length.into()
         }
         metadata.stream_extension.data_length = (metadata.capacity() + cluster_size).into();
-        metadata.update_checksum();
         Ok(cluster_id)
     }
 
-    pub async fn sync(&mut self) -> Result<(), Error<E>> {
+    /// Release allocated clusters beyond valid data length
+    pub async fn shrink_to_fit(&mut self) -> Result<(), Error<E>> {
+        let cluster_size = self.fs_info.cluster_size() as u64;
+        let num_clusters = self.metadata.length().div_ceil(cluster_size) as u32;
+        let capacity = (self.metadata.capacity() / cluster_size) as u32;
+        if num_clusters >= capacity {
+            return Ok(());
+        }
+        let first = self.sector_ref.cluster_id;
+        trace!("Shrink from {} clusters to {} clusters", capacity, num_clusters);
+        let fat_chain = self.metadata.stream_extension.general_secondary_flags.fat_chain();
+        if !fat_chain {
+            let mut context = acquire!(self.context);
+            let num_released = capacity - num_clusters;
+            context
+                .allocation_bitmap
+                .release_contiguous(first + num_clusters, num_released)
+                .await?;
+        } else if num_clusters == 0 {
+            acquire!(self.context).allocation_bitmap.release(first, true).await?;
+        } else {
+            let (mut last, mut next) = (first, first);
+            for _ in 0..num_clusters {
+                last = next;
+                next = self.next_cluster_id(last).await?;
+            }
+            let mut io = acquire!(self.io);
+            io.write_fat_entry(&self.fat_info, last, Entry::Last.into()).await?;
+            drop(io);
+            acquire!(self.context).allocation_bitmap.release(next, true).await?;
+        }
         let metadata = &mut self.metadata;
-        if !metadata.entry_ref.sector_ref.cluster_id.valid() {
+        if num_clusters == 0 {
+            metadata.stream_extension.first_cluster = 0.into();
+            self.sector_ref = SectorRef::default();
+        }
+        metadata.stream_extension.data_length = (num_clusters as u64 * cluster_size).into();
+        metadata.dirty = true;
+        Ok(())
+    }
+
+    /// Sector following specified one in parent directory, where entries of this file or
+    /// directory are located
+    async fn next_entry_sector(&mut self, sector_ref: SectorRef) -> Result<SectorRef, Error<E>> {
+        let shift = self.fs_info.sectors_per_cluster_shift;
+        if sector_ref.sector_index + 1 != self.fs_info.sectors_per_cluster()
+            || !self.metadata.parent_fat_chain
+        {
+            return Ok(sector_ref.next(shift));
+        }
+        match self.fat_entry(sector_ref.cluster_id).await? {
+            Entry::Next(cluster_id) => Ok(SectorRef::new(cluster_id, 0)),
+            _ => Err(DataError::FATChain(Location::cluster(sector_ref.cluster_id)).into()),
+        }
+    }
+
+    pub async fn sync(&mut self) -> Result<(), Error<E>> {
+        if !self.metadata.entry_ref.sector_ref.cluster_id.valid() {
             // Probably root directory
             return Ok(());
         }
-        if metadata.dirty {
+        if self.metadata.dirty {
             trace!("Flush metadatadata since dirty");
-            let mut sector_id = metadata.entry_ref.sector_ref.id(&self.fs_info);
-            let bytes: &RawEntry = unsafe { transmute(&metadata.file_directory) };
-            let offset = metadata.entry_ref.index as usize * ENTRY_SIZE;
-            let mut io = acquire!(self.io);
-            io.write(sector_id, offset, &bytes[..]).await?;
-            let mut offset = (metadata.entry_ref.index as usize + 1) * ENTRY_SIZE;
-            if offset == self.fs_info.sector_size() as usize {
-                offset = 0;
-                sector_id += 1u32;
+            let entry_ref = self.metadata.entry_ref;
+            let sector_size = self.fs_info.sector_size() as usize;
+            let mut sector_ref = entry_ref.sector_ref;
+            let mut offset = entry_ref.index as usize * ENTRY_SIZE;
+            // Entries of an entryset may cross sectors and clusters of parent directory
+            let mut locations: heapless::Vec<(SectorRef, usize), 2> = heapless::Vec::new();
+            let metadata = &self.metadata;
+            let mut checksum =
+                primary_checksum(&metadata.file_directory, &metadata.stream_extension);
+            for i in 0..=metadata.file_directory.secondary_count {
+                if i > 0 {
+                    offset += ENTRY_SIZE;
+                }
+                if offset == sector_size {
+                    offset = 0;
+                    sector_ref = self.next_entry_sector(sector_ref).await?;
+                }
+                if i < 2 {
+                    locations.push((sector_ref, offset)).ok();
+                    continue;
+                }
+                // Filename entries are never changed by metadata, so checksum continues with
+                // filename entries on disk instead of keeping a copy of name in memory
+                let mut io = acquire!(self.io);
+                let sector = io.read(sector_ref.id(&self.fs_info)).await?;
+                for &byte in sector[offset..offset + ENTRY_SIZE].iter() {
+                    checksum.write(byte as u16);
+                }
             }
-            let bytes: &RawEntry = unsafe { transmute(&metadata.stream_extension) };
-            io.write(sector_id, offset, &bytes[..]).await?;
-            io.flush().await?;
+            let metadata = &mut self.metadata;
+            metadata.file_directory.set_checksum = checksum.sum().into();
+
+            let mut io = acquire!(self.io);
+            let (sector_ref, offset) = locations[0];
+            let bytes: &RawEntry = bytemuck::cast_ref(&metadata.file_directory);
+            io.write(sector_ref.id(&self.fs_info), offset, &bytes[..]).await?;
+            let (sector_ref, offset) = locations[1];
+            let bytes: &RawEntry = bytemuck::cast_ref(&metadata.stream_extension);
+            io.write(sector_ref.id(&self.fs_info), offset, &bytes[..]).await?;
+            io.auto_flush().await?;
             metadata.dirty = false;
         }
         Ok(())
     }
 
+    /// Writes deferred by flush policy are flushed if metadata changed
     pub async fn close(&mut self) -> Result<(), Error<E>> {
+        let dirty = self.metadata.dirty;
         self.sync().await?;
+        if dirty {
+            acquire!(self.io).flush_deferred().await?;
+        }
         acquire!(self.context).opened_entries.remove(self.id());
         Ok(())
     }
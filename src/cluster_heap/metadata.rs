@@ -1,24 +1,34 @@
-use alloc::string::{String, ToString};
+#[cfg(feature = "alloc")]
+use alloc::string::String;
 
 use super::entryset::{EntryRef, EntrySet};
-use crate::region::data::entryset::checksum;
 use crate::region::data::entryset::primary::FileDirectory;
 use crate::region::data::entryset::secondary::{Secondary, StreamExtension};
 
 #[derive(Clone)]
 pub(crate) struct Metadata {
-    pub name: String,
     pub file_directory: FileDirectory,
     pub stream_extension: Secondary<StreamExtension>,
     pub entry_ref: EntryRef,
+    /// Whether parent directory follows FAT chain, which entries crossing clusters follow
+    pub parent_fat_chain: bool,
+    /// Entries of opened file or directory are never renamed, so name kept stays valid
+    #[cfg(feature = "alloc")]
+    pub name: String,
     pub dirty: bool,
 }
 
 impl Metadata {
-    pub fn new(entryset: EntrySet) -> Self {
-        let name = entryset.name().to_string();
-        let EntrySet { file_directory, stream_extension, entry_ref, .. } = entryset;
-        Self { name, file_directory, stream_extension, entry_ref, dirty: false }
+    pub fn new<const N: usize>(entryset: EntrySet<N>, parent_fat_chain: bool) -> Self {
+        Self {
+            file_directory: entryset.file_directory,
+            stream_extension: entryset.stream_extension,
+            entry_ref: entryset.entry_ref,
+            parent_fat_chain,
+            #[cfg(feature = "alloc")]
+            name: String::from(entryset.name()),
+            dirty: false,
+        }
     }
 
     pub fn length(&self) -> u64 {
@@ -31,12 +41,6 @@ impl Metadata {
 
     pub fn set_length(&mut self, length: u64) {
         self.stream_extension.custom_defined.valid_data_length = length.into();
-        self.update_checksum();
         self.dirty = true;
     }
-
-    pub fn update_checksum(&mut self) {
-        let sum = checksum(&self.file_directory, &self.stream_extension, &self.name);
-        self.file_directory.set_checksum = sum.into();
-    }
 }
@@ -1,5 +1,13 @@
 pub(crate) mod allocation_bitmap;
+#[cfg(feature = "alloc")]
+pub(crate) mod buffered;
+#[cfg(feature = "alloc")]
+pub(crate) mod check;
+#[cfg(feature = "async")]
+pub(crate) mod chunks;
 pub(crate) mod context;
+#[cfg(all(feature = "std", any(not(feature = "async"), feature = "async-std")))]
+pub(crate) mod copy;
 pub(crate) mod directory;
 pub(crate) mod entryset;
 pub(crate) mod file;
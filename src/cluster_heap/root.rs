@@ -1,32 +1,199 @@
 use core::fmt::Debug;
-use core::mem;
-
-use alloc::rc::Rc;
-use alloc::vec::Vec;
 
+use super::allocation_bitmap::{AllocationBitmap, UsageProgress};
+#[cfg(feature = "alloc")]
+use super::check::CheckReport;
+#[cfg(not(feature = "alloc"))]
+use super::context::OpenedEntries;
+use super::context::{Context, OpenEntries};
 use super::directory::Directory;
+use super::entryset::EntrySet;
+use super::meta::MetaFileDirectory;
 use super::metadata::Metadata;
-use super::{
-    allocation_bitmap::AllocationBitmap,
-    context::{Context, OpenedEntries},
-    meta::MetaFileDirectory,
-};
 use crate::endian::Little as LE;
-use crate::error::{DataError, Error, OperationError};
+use crate::error::{DataError, Error, InputError};
 use crate::fat;
 use crate::file::FileOptions;
 use crate::fs::{self, SectorRef};
 use crate::io::IOWrapper;
 use crate::region;
 use crate::region::data::entry_type::{EntryType, RawEntryType};
-use crate::region::data::entryset::RawEntry;
-use crate::sync::{acquire, shared, Shared};
+use crate::region::data::entryset::{RawEntry, ENTRY_SIZE};
+#[cfg(not(feature = "alloc"))]
+use crate::storage::Slots;
+use crate::sync::{acquire, shared, Ref, Shared};
 use crate::types::{ClusterID, SectorID};
 
+/// Allocation bitmap in use, as described by its entry in root directory
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AllocationBitmapInfo {
+    /// Lowest bit identifies which FAT the bitmap belongs to
+    pub bitmap_flags: u8,
+    pub first_cluster: ClusterID,
+    /// In bytes
+    pub data_length: u64,
+}
+
+/// Upcase table, as described by its entry in root directory
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UpcaseTableInfo {
+    pub table_checksum: u32,
+    pub first_cluster: ClusterID,
+    /// In bytes
+    pub data_length: u64,
+}
+
+/// Run of consecutive free clusters, see `RootDirectory::free_extents`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FreeExtent {
+    pub first_cluster: ClusterID,
+    pub num_clusters: u32,
+    /// First sector of first cluster, e.g. for discarding or overwriting
+    pub first_sector: SectorID,
+    pub num_sectors: u64,
+}
+
+/// Free cluster runs in ascending order, see `RootDirectory::free_extents`
+pub struct FreeExtents<IO: crate::io::Lifetime> {
+    context: Shared<Context<IO>>,
+    fs_info: fs::Info,
+    /// Cluster index to continue from
+    next: u32,
+}
+
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+impl<E, IO: crate::io::IO<Error = E>> FreeExtents<IO> {
+    pub async fn next_extent(&mut self) -> Result<Option<FreeExtent>, Error<E>> {
+        let mut context = acquire!(self.context);
+        let (index, num_clusters) = match context.allocation_bitmap.free_extent(self.next).await? {
+            Some(extent) => extent,
+            None => return Ok(None),
+        };
+        self.next = index + num_clusters;
+        let first_cluster = ClusterID::from(index + 2);
+        Ok(Some(FreeExtent {
+            first_cluster,
+            num_clusters,
+            first_sector: SectorRef::new(first_cluster, 0).id(&self.fs_info),
+            num_sectors: (num_clusters as u64) << self.fs_info.sectors_per_cluster_shift,
+        }))
+    }
+}
+
+#[cfg(not(feature = "async"))]
+impl<E, IO: crate::io::IO<Error = E>> Iterator for FreeExtents<IO> {
+    type Item = Result<FreeExtent, Error<E>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_extent().transpose()
+    }
+}
+
 pub struct RootDirectory<E: Debug, IO: crate::io::IO<Error = E>> {
     directory: Directory<E, IO>,
+    allocation_bitmap: region::data::AllocationBitmap,
+    /// Allocation bitmap of the other FAT on volumes with two FATs
+    #[cfg(feature = "alloc")]
+    inactive_allocation_bitmap: Option<region::data::AllocationBitmap>,
     upcase_table: region::data::UpcaseTable,
     volumn_label: Option<heapless::String<22>>,
+    volumn_label_entry: Option<RawEntry>,
+}
+
+impl<E: Debug, IO: crate::io::IO<Error = E>> Debug for RootDirectory<E, IO> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let first_cluster = self.directory.meta.sector_ref.cluster_id;
+        f.debug_struct("RootDirectory")
+            .field("first_cluster", &format_args!("{}", first_cluster))
+            .field("volumn_label", &self.volumn_label())
+            .finish()
+    }
+}
+
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+impl<E: Debug, IO: crate::io::IO<Error = E>, const N: usize> Directory<E, IO, N> {
+    async fn validate_upcase_table(
+        &mut self,
+        upcase_table: &region::data::UpcaseTable,
+    ) -> Result<(), Error<E>> {
+        let mut checksum = region::data::Checksum::default();
+        let first_cluster = upcase_table.first_cluster.to_ne();
+        let fs_info = &self.meta.fs_info;
+        let first_sector = SectorRef::new(first_cluster.into(), 0).id(fs_info);
+        let data_length = upcase_table.data_length.to_ne();
+        let sector_size = fs_info.sector_size();
+        let num_sectors = data_length / sector_size as u64;
+        let mut io = acquire!(self.meta.io);
+        for i in 0..num_sectors {
+            let sector = io.read(first_sector + i).await?;
+            checksum.write(sector);
+        }
+        let remain = (data_length - num_sectors * sector_size as u64) as usize;
+        if remain > 0 {
+            let sector_ref = first_sector + num_sectors;
+            let sector = io.read(sector_ref).await?;
+            checksum.write(&sector[..remain]);
+        }
+        if checksum.sum() != upcase_table.table_checksum.to_ne() {
+            if let Some(report) = io.forensic.as_mut() {
+                warn!("Ignored upcase table checksum mismatch");
+                report.upcase_table_checksum = true;
+                return Ok(());
+            }
+            return Err(DataError::UpcaseTableChecksum.into());
+        }
+        Ok(())
+    }
+
+    /// Validate upcase table checksum if deferred by `VolumeOptions::lazy_upcase_validation`
+    pub(crate) async fn ensure_upcase_table_valid(&mut self) -> Result<(), Error<E>> {
+        let pending = acquire!(self.meta.context).pending_upcase_table;
+        if let Some(upcase_table) = pending {
+            debug!("Validate deferred upcase table checksum");
+            self.validate_upcase_table(&upcase_table).await?;
+            acquire!(self.meta.context).pending_upcase_table = None;
+        }
+        Ok(())
+    }
+}
+
+/// Critical primary entries leading root directory, looked up in its first sector
+#[derive(Default)]
+pub(crate) struct CriticalEntries {
+    /// Entry index within the sector and allocation bitmap of active FAT
+    pub allocation_bitmap: Option<(usize, region::data::AllocationBitmap)>,
+    #[cfg(feature = "alloc")]
+    pub inactive_allocation_bitmap: Option<region::data::AllocationBitmap>,
+    pub upcase_table: Option<region::data::UpcaseTable>,
+    pub volumn_label_entry: Option<RawEntry>,
+}
+
+impl CriticalEntries {
+    pub(crate) fn lookup(sector: &[u8], active_fat: u8) -> Self {
+        let mut entries = Self::default();
+        for (index, entry) in sector.chunks_exact(ENTRY_SIZE).enumerate() {
+            let entry: &RawEntry = entry.try_into().unwrap();
+            match RawEntryType::from(entry[0]).entry_type() {
+                Ok(EntryType::AllocationBitmap) => {
+                    // Each FAT comes with its own allocation bitmap
+                    let bitmap: region::data::AllocationBitmap = *bytemuck::from_bytes(entry);
+                    match bitmap.bitmap_flags & 1 == active_fat {
+                        true => entries.allocation_bitmap = Some((index, bitmap)),
+                        #[cfg(feature = "alloc")]
+                        false => entries.inactive_allocation_bitmap = Some(bitmap),
+                        #[cfg(not(feature = "alloc"))]
+                        false => (),
+                    }
+                }
+                Ok(EntryType::VolumnLabel) => entries.volumn_label_entry = Some(*entry),
+                Ok(EntryType::UpcaseTable) => {
+                    entries.upcase_table = Some(*bytemuck::from_bytes(entry))
+                }
+                _ => break,
+            };
+        }
+        entries
+    }
 }
 
 #[cfg_attr(not(feature = "async"), deasync::deasync)]
@@ -36,100 +203,375 @@ impl<E: Debug, IO: crate::io::IO<Error = E>> RootDirectory<E, IO> {
         fat_info: fat::Info,
         fs_info: fs::Info,
         cluster_id: ClusterID,
+        #[cfg(not(feature = "alloc"))] slots: Slots<IO>,
     ) -> Result<Self, Error<E>> {
-        let mut volumn_label: Option<heapless::String<22>> = None;
-        let mut upcase_table: Option<region::data::UpcaseTable> = None;
-        let mut allocation_bitmap: Option<region::data::AllocationBitmap> = None;
         let sector_ref = SectorRef::new(cluster_id, 0);
         let mut borrow_io = acquire!(io);
         let sector = borrow_io.read(sector_ref.id(&fs_info)).await?;
-        let entries: &[RawEntry; 16] = unsafe { mem::transmute(&sector[0]) };
-        for entry in entries.iter() {
-            match RawEntryType::from(entry[0]).entry_type() {
-                Ok(EntryType::AllocationBitmap) => {
-                    let bitmap: &region::data::AllocationBitmap = unsafe { mem::transmute(entry) };
-                    allocation_bitmap = Some(*bitmap)
-                }
-                Ok(EntryType::VolumnLabel) => {
-                    let label: &region::data::VolumnLabel = unsafe { mem::transmute(entry) };
-                    volumn_label = Some((*label).into())
-                }
-                Ok(EntryType::UpcaseTable) => {
-                    let table: &region::data::UpcaseTable = unsafe { mem::transmute(entry) };
-                    upcase_table = Some(*table)
-                }
-                _ => break,
-            };
-        }
+        let entries = CriticalEntries::lookup(sector, fat_info.active());
         drop(borrow_io);
+        let CriticalEntries {
+            allocation_bitmap,
+            #[cfg(feature = "alloc")]
+            inactive_allocation_bitmap,
+            upcase_table,
+            volumn_label_entry,
+        } = entries;
+        let volumn_label: Option<heapless::String<22>> = volumn_label_entry.and_then(|entry| {
+            let label: &region::data::VolumnLabel = bytemuck::from_bytes(&entry);
+            label.entry_type.in_use().then(|| (*label).into())
+        });
 
         let upcase_table = upcase_table.ok_or(Error::Data(DataError::UpcaseTableMissing))?;
+        let option = allocation_bitmap.map(|(_, bitmap)| bitmap);
+        let allocation_bitmap = option.ok_or(Error::Data(DataError::AllocationBitmapMissing))?;
         let context = {
-            let region =
-                allocation_bitmap.ok_or(Error::Data(DataError::AllocationBitmapMissing))?;
+            let region = allocation_bitmap;
             let first_cluster = region.first_cluster.to_ne();
             let sector_offset = (first_cluster - 2) * fs_info.sectors_per_cluster();
             let base = SectorID::from((fs_info.heap_offset + sector_offset) as u64);
             let length = region.data_length.to_ne() as u32;
             debug!("Allocation bitmap found at cluster {} length {}", first_cluster, length);
             let bitmap = AllocationBitmap::new(io.clone(), base, fat_info, length).await?;
-            shared(Context {
+            let lazy = acquire!(io).options.lazy_upcase_validation;
+            let pending_upcase_table = lazy.then_some(upcase_table);
+            #[cfg(feature = "alloc")]
+            let opened_entries = Default::default();
+            #[cfg(not(feature = "alloc"))]
+            let opened_entries = OpenedEntries::new(slots.opened_entries);
+            let context = Context {
                 allocation_bitmap: bitmap,
-                opened_entries: OpenedEntries { entries: Vec::with_capacity(4) },
-            })
+                opened_entries,
+                pending_upcase_table,
+                relocations: Default::default(),
+            };
+            match () {
+                #[cfg(feature = "alloc")]
+                () => shared(context),
+                #[cfg(not(feature = "alloc"))]
+                () => shared(slots.context, context),
+            }
         };
         let cluster_id = upcase_table.first_cluster.to_ne();
         let length = upcase_table.data_length.to_ne();
         debug!("Upcase table found at cluster {} length {}", cluster_id, length);
         let mut borrow_io = acquire!(io);
         let sector = borrow_io.read(SectorRef::new(cluster_id.into(), 0).id(&fs_info)).await?;
-        let array: &[LE<u16>; 128] = unsafe { mem::transmute(&sector[0]) };
-        let mut metadata = Metadata::new(Default::default());
+        let array: &[LE<u16>; 128] = bytemuck::from_bytes(&sector[..256]);
+        let mut metadata = Metadata::new(EntrySet::<0>::default(), false);
         let options = FileOptions::default();
         metadata.stream_extension.general_secondary_flags.set_fat_chain();
-        let upcase_table_data = Rc::new((*array).into());
+        let upcase_table_data: Ref<_> = match () {
+            #[cfg(feature = "alloc")]
+            () => Ref::new((*array).into()),
+            #[cfg(not(feature = "alloc"))]
+            () => slots.upcase_table.insert((*array).into()),
+        };
         drop(borrow_io);
-        let meta =
-            MetaFileDirectory { io, context, fat_info, fs_info, metadata, options, sector_ref };
-        let directory = Directory { meta, upcase_table: upcase_table_data };
-        Ok(Self { directory, upcase_table, volumn_label })
+        let fat_window = Default::default();
+        let meta = MetaFileDirectory {
+            io,
+            context,
+            fat_info,
+            fs_info,
+            metadata,
+            options,
+            sector_ref,
+            fat_window,
+        };
+        let lookup_cache = Default::default();
+        let directory = Directory { meta, upcase_table: upcase_table_data, lookup_cache };
+        Ok(Self {
+            directory,
+            allocation_bitmap,
+            #[cfg(feature = "alloc")]
+            inactive_allocation_bitmap,
+            upcase_table,
+            volumn_label,
+            volumn_label_entry,
+        })
     }
 
     pub async fn validate_upcase_table_checksum(&mut self) -> Result<(), Error<E>> {
-        let mut checksum = region::data::Checksum::default();
-        let first_cluster = self.upcase_table.first_cluster.to_ne();
-        let fs_info = &self.directory.meta.fs_info;
-        let first_sector = SectorRef::new(first_cluster.into(), 0).id(&fs_info);
-        let data_length = self.upcase_table.data_length.to_ne();
-        let sector_size = fs_info.sector_size();
-        let num_sectors = data_length / sector_size as u64;
+        self.directory.validate_upcase_table(&self.upcase_table).await?;
+        acquire!(self.directory.meta.context).pending_upcase_table = None;
+        Ok(())
+    }
+
+    /// Recount allocated clusters for percent-in-use from up to `num_sectors` sectors of
+    /// allocation bitmap per call, resuming where last call stopped, so that large volumes
+    /// are recounted without blocking for long. Percent-in-use is updated once done.
+    pub async fn update_usage(&mut self, num_sectors: u32) -> Result<UsageProgress, Error<E>> {
+        acquire!(self.directory.meta.context).allocation_bitmap.update_usage(num_sectors).await
+    }
+
+    /// Check consistency of whole file system, repair inconsistencies if specified,
+    /// except for upcase table checksum and broken cluster chains. Orphan clusters are
+    /// kept if any chain is broken, since they might be used by files under it
+    #[cfg(feature = "alloc")]
+    pub async fn check(&mut self, repair: bool) -> Result<CheckReport, Error<E>> {
+        let mut report = CheckReport::default();
+        match self.validate_upcase_table_checksum().await {
+            Ok(_) => (),
+            Err(Error::Data(DataError::UpcaseTableChecksum)) => report.upcase_table_checksum = true,
+            Err(e) => return Err(e),
+        }
+        let cluster_size = self.directory.meta.fs_info.cluster_size() as u64;
+        let num_clusters = |length: u64| Some(length.div_ceil(cluster_size) as u32);
+        let bitmap_chain = |bitmap: &region::data::AllocationBitmap| {
+            (bitmap.first_cluster.to_ne().into(), num_clusters(bitmap.data_length.to_ne()))
+        };
+        let inactive_bitmap = self.inactive_allocation_bitmap.as_ref();
+        let upcase_table = &self.upcase_table;
+        let system_chains = [
+            bitmap_chain(&self.allocation_bitmap),
+            (
+                upcase_table.first_cluster.to_ne().into(),
+                num_clusters(upcase_table.data_length.to_ne()),
+            ),
+            (self.directory.meta.sector_ref.cluster_id, None),
+            inactive_bitmap.map(bitmap_chain).unwrap_or_default(),
+        ];
+        let system_chains = &system_chains[..3 + inactive_bitmap.is_some() as usize];
+        self.directory.check(system_chains, repair, &mut report).await?;
+        Ok(report)
+    }
+
+    /// Change options inherited by directory opened from root directory
+    pub fn change_options(&mut self, f: impl FnOnce(&mut FileOptions)) {
+        self.directory.change_options(f)
+    }
+
+    pub fn volumn_label(&self) -> Option<&str> {
+        self.volumn_label.as_ref().map(|label| label.as_str())
+    }
+
+    /// Volumn label entry as is, present even if label removed
+    pub fn volumn_label_entry(&self) -> Option<&[u8; ENTRY_SIZE]> {
+        self.volumn_label_entry.as_ref()
+    }
+
+    pub fn allocation_bitmap(&self) -> AllocationBitmapInfo {
+        let bitmap = &self.allocation_bitmap;
+        AllocationBitmapInfo {
+            bitmap_flags: bitmap.bitmap_flags,
+            first_cluster: bitmap.first_cluster.to_ne().into(),
+            data_length: bitmap.data_length.to_ne(),
+        }
+    }
+
+    /// First sector of specified cluster, which must be within cluster heap
+    #[cfg(feature = "raw-cluster")]
+    async fn cluster_sector(
+        &self,
+        cluster_id: ClusterID,
+        length: usize,
+    ) -> Result<SectorID, Error<E>> {
+        let num_clusters = acquire!(self.directory.meta.context).allocation_bitmap.num_clusters();
+        let index = u32::from(cluster_id);
+        if index < 2 || index - 2 >= num_clusters {
+            return Err(InputError::ClusterID.into());
+        }
+        if length != self.directory.meta.fs_info.cluster_size() as usize {
+            return Err(InputError::Size.into());
+        }
+        Ok(SectorRef::new(cluster_id, 0).id(&self.directory.meta.fs_info))
+    }
+
+    /// Read whole cluster into buf of cluster size, regardless of whether it is allocated
+    #[cfg(feature = "raw-cluster")]
+    pub async fn read_cluster(
+        &mut self,
+        cluster_id: ClusterID,
+        buf: &mut [u8],
+    ) -> Result<(), Error<E>> {
+        let sector_id = self.cluster_sector(cluster_id, buf.len()).await?;
+        let num_sectors = self.directory.meta.fs_info.sectors_per_cluster() as usize;
+        acquire!(self.directory.meta.io).read_many(sector_id, num_sectors, buf).await
+    }
+
+    /// Overwrite whole cluster with data of cluster size, allocation bitmap and FAT untouched.
+    /// Nothing stops overwriting metadata of files, directories or the filesystem itself,
+    /// caches should be invalidated by `ExFAT::invalidate_caches` if so.
+    #[cfg(feature = "raw-cluster")]
+    pub async fn write_cluster(
+        &mut self,
+        cluster_id: ClusterID,
+        data: &[u8],
+    ) -> Result<(), Error<E>> {
+        let sector_id = self.cluster_sector(cluster_id, data.len()).await?;
+        let num_sectors = self.directory.meta.fs_info.sectors_per_cluster();
         let mut io = acquire!(self.directory.meta.io);
-        for i in 0..num_sectors {
-            let sector = io.read(first_sector + i).await?;
-            checksum.write(crate::io::flatten(sector));
+        io.write_many(sector_id, num_sectors, data).await?;
+        io.auto_flush().await
+    }
+
+    /// Enumerate runs of free clusters from allocation bitmap, e.g. for imaging or wiping
+    /// unused regions only. Clusters allocated or released meanwhile may or may not be seen.
+    pub fn free_extents(&self) -> FreeExtents<IO> {
+        let context = self.directory.meta.context.clone();
+        FreeExtents { context, fs_info: self.directory.meta.fs_info, next: 0 }
+    }
+
+    pub fn upcase_table(&self) -> UpcaseTableInfo {
+        let upcase_table = &self.upcase_table;
+        UpcaseTableInfo {
+            table_checksum: upcase_table.table_checksum.to_ne(),
+            first_cluster: upcase_table.first_cluster.to_ne().into(),
+            data_length: upcase_table.data_length.to_ne(),
         }
-        let remain = (data_length - num_sectors * sector_size as u64) as usize;
-        if remain > 0 {
-            let sector_ref = first_sector + num_sectors;
-            let sector = io.read(sector_ref).await?;
-            checksum.write(&crate::io::flatten(sector)[..remain]);
+    }
+
+    /// Set volumn label up to 11 characters, empty label removes volumn label
+    pub async fn set_volumn_label(&mut self, label: &str) -> Result<(), Error<E>> {
+        if label.chars().count() > 11 {
+            return Err(InputError::NameTooLong.into());
         }
-        if checksum.sum() != self.upcase_table.table_checksum.to_ne() {
-            return Err(DataError::UpcaseTableChecksum.into());
+        let entry = region::data::VolumnLabel::new(label);
+        let bytes: &RawEntry = bytemuck::cast_ref(&entry);
+        match self.directory.lookup_entry(EntryType::VolumnLabel).await? {
+            Some(entry_ref) => {
+                let sector_id = entry_ref.sector_ref.id(&self.directory.meta.fs_info);
+                let offset = entry_ref.index as usize * ENTRY_SIZE;
+                let mut io = acquire!(self.directory.meta.io);
+                io.write(sector_id, offset, bytes).await?;
+                io.auto_flush().await?;
+            }
+            None if label.is_empty() => return Ok(()),
+            None => self.directory.insert_entries(&[*bytes]).await?,
         }
+        self.volumn_label_entry = Some(*bytes);
+        self.volumn_label = if label.is_empty() { None } else { Some(entry.into()) };
         Ok(())
     }
 
-    pub fn volumn_label(&self) -> Option<&str> {
-        self.volumn_label.as_ref().map(|label| label.as_str())
+    /// Files and directories currently opened, including root directory itself
+    pub async fn open_entries(&self) -> OpenEntries {
+        let root = self.directory.meta.id();
+        acquire!(self.directory.meta.context).opened_entries.list(root)
     }
 
     pub async fn open(&mut self) -> Result<Directory<E, IO>, Error<E>> {
+        self.open_with_name_size().await
+    }
+
+    /// Open root directory storing names in buffers of `N` bytes instead of the default,
+    /// sub directories opened from it inherit the same size
+    pub async fn open_with_name_size<const N: usize>(
+        &mut self,
+    ) -> Result<Directory<E, IO, N>, Error<E>> {
         let meta = self.directory.meta.clone();
         let mut context = acquire!(self.directory.meta.context);
-        if !context.opened_entries.add(meta.id()) {
-            return Err(OperationError::AlreadyOpen.into());
+        context.opened_entries.add(meta.id())?;
+        let upcase_table = self.directory.upcase_table.clone();
+        Ok(Directory { meta, upcase_table, lookup_cache: Default::default() })
+    }
+}
+
+#[cfg(all(test, feature = "std", not(feature = "async")))]
+mod test {
+    use crate::error::Error;
+    use crate::fixture::Image;
+    use crate::region::data::entry_type::EntryType;
+
+    #[test]
+    fn test_root_critical_entries_info() {
+        let image = Image::new("exfat-test-root-critical-info.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let bitmap = root.allocation_bitmap();
+        assert_eq!((bitmap.bitmap_flags, bitmap.first_cluster), (0, 2.into()));
+        // 4089 clusters
+        assert_eq!(bitmap.data_length, 512);
+        let upcase_table = root.upcase_table();
+        assert!(upcase_table.first_cluster > bitmap.first_cluster);
+        assert!(upcase_table.data_length > 0);
+        let entry = *root.volumn_label_entry().unwrap();
+        assert_eq!((entry[0], entry[1]), (0x83, 9));
+        root.set_volumn_label("").unwrap();
+        assert_eq!(root.volumn_label_entry().unwrap()[0], 0x03);
+    }
+
+    #[test]
+    fn test_lazy_upcase_validation() {
+        use std::io::{Seek, SeekFrom, Write};
+
+        let image = Image::new("exfat-test-lazy-upcase.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        let entry_ref = directory.lookup_entry(EntryType::UpcaseTable).unwrap().unwrap();
+        let sector_id = u64::from(entry_ref.sector_ref.id(&directory.meta.fs_info));
+        let offset = sector_id * 512 + entry_ref.index as u64 * super::ENTRY_SIZE as u64 + 4;
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let mount_lazy = || {
+            let mut exfat = image.mount();
+            exfat.change_options(|options| options.lazy_upcase_validation = true);
+            exfat
+        };
+        let mut exfat = mount_lazy();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        // Whole table read by first create only
+        let mut sectors_read = [0; 2];
+        for (i, name) in ["a", "b"].iter().enumerate() {
+            let before = exfat.stats().sectors_read;
+            directory.create(name, false).unwrap();
+            sectors_read[i] = exfat.stats().sectors_read - before;
         }
-        Ok(Directory { meta, upcase_table: self.directory.upcase_table.clone() })
+        assert!(sectors_read[0] > sectors_read[1]);
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let mut file = std::fs::OpenOptions::new().write(true).open(image.path()).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&[0xFF; 4]).unwrap();
+        drop(file);
+        let mut exfat = mount_lazy();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        assert!(directory.find("a").unwrap().is_some());
+        let result = directory.create("c", false);
+        assert!(matches!(result, Err(Error::Data(crate::error::DataError::UpcaseTableChecksum))));
+    }
+
+    #[test]
+    #[cfg(feature = "raw-cluster")]
+    fn test_raw_cluster() {
+        use crate::error::InputError;
+        use crate::FileOrDirectory;
+
+        let image = Image::new("exfat-test-raw-cluster.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("file", false).unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(mut file) => file.write_all(&[0xA5; 4096]).unwrap(),
+            FileOrDirectory::Directory(_) => unreachable!(),
+        }
+        let entryset = directory.find("file").unwrap().unwrap();
+        let cluster_id = entryset.first_cluster();
+        let mut buf = vec![0u8; 4096];
+        root.read_cluster(cluster_id, &mut buf).unwrap();
+        assert!(buf.iter().all(|&byte| byte == 0xA5));
+
+        root.write_cluster(cluster_id, &[0x5A; 4096]).unwrap();
+        let mut file = match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(file) => file,
+            FileOrDirectory::Directory(_) => unreachable!(),
+        };
+        assert_eq!(file.read(&mut buf).unwrap(), 4096);
+        assert!(buf.iter().all(|&byte| byte == 0x5A));
+
+        let result = root.read_cluster(1.into(), &mut buf);
+        assert!(matches!(result, Err(Error::Input(InputError::ClusterID))));
+        let result = root.read_cluster(cluster_id, &mut buf[..512]);
+        assert!(matches!(result, Err(Error::Input(InputError::Size))));
     }
 }
@@ -1,27 +1,49 @@
 use core::fmt::Debug;
 
-#[derive(Copy, Clone, Default, Debug, PartialEq, PartialOrd)]
-pub struct Little<T: Copy + Clone + Default + Debug + PartialEq + PartialOrd + Sized>(T);
+use bytemuck::{Pod, Zeroable};
+
+pub trait Primitive: Copy + 'static {
+    type Bytes: Copy + Default + PartialEq + Pod;
+}
+
+/// Little endian value stored as bytes, so that on-disk structures containing it
+/// are byte aligned and could be accessed at any offset of sector buffer
+#[derive(Copy, Clone, Default, PartialEq)]
+#[repr(transparent)]
+pub struct Little<T: Primitive>(T::Bytes);
+
+unsafe impl<T: Primitive> Zeroable for Little<T> {}
+unsafe impl<T: Primitive> Pod for Little<T> {}
 
 macro_rules! define {
     ($type:ty) => {
+        impl Primitive for $type {
+            type Bytes = [u8; core::mem::size_of::<$type>()];
+        }
+
         impl Little<$type> {
             pub fn to_ne(self) -> $type {
-                <$type>::from_le(self.0)
+                <$type>::from_le_bytes(self.0)
             }
         }
 
         impl Into<$type> for Little<$type> {
             #[inline]
             fn into(self) -> $type {
-                <$type>::from_le(self.0)
+                <$type>::from_le_bytes(self.0)
             }
         }
 
         impl From<$type> for Little<$type> {
             #[inline]
             fn from(t: $type) -> Self {
-                Self(<$type>::to_le(t))
+                Self(t.to_le_bytes())
+            }
+        }
+
+        impl Debug for Little<$type> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                write!(f, "{:?}", self.to_ne())
             }
         }
     };
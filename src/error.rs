@@ -1,5 +1,51 @@
 use core::fmt::{Debug, Display, Formatter, Result};
 
+use crate::types::{ClusterID, SectorID};
+
+/// Where a data error is found, fields unknown are left `None`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+    pub sector_id: Option<SectorID>,
+    pub cluster_id: Option<ClusterID>,
+    /// First cluster of directory containing the entry
+    pub directory: Option<ClusterID>,
+    /// Index of entry within sector
+    pub entry_index: Option<u8>,
+}
+
+impl Location {
+    pub(crate) fn cluster(cluster_id: ClusterID) -> Self {
+        Self { cluster_id: Some(cluster_id), ..Default::default() }
+    }
+
+    pub(crate) fn entry(directory: ClusterID, sector_id: SectorID, index: u8) -> Self {
+        Self {
+            sector_id: Some(sector_id),
+            directory: Some(directory),
+            entry_index: Some(index),
+            ..Default::default()
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if let Some(directory) = self.directory {
+            write!(f, " in directory at cluster {}", directory)?;
+        }
+        if let Some(cluster_id) = self.cluster_id {
+            write!(f, " at cluster {}", cluster_id)?;
+        }
+        if let Some(sector_id) = self.sector_id {
+            write!(f, " at sector {}", sector_id)?;
+        }
+        if let Some(index) = self.entry_index {
+            write!(f, " entry {}", index)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(displaydoc::Display)]
 pub enum DataError {
     /// Not exFAT filesystem
@@ -12,18 +58,24 @@ pub enum DataError {
     UpcaseTableMissing,
     /// Bad upcase table checksum
     UpcaseTableChecksum,
-    /// Broken FAT chain
-    FATChain,
-    /// Broken file or directory metadata
-    Metadata,
+    /// Broken FAT chain{0}
+    FATChain(Location),
+    /// Broken file or directory metadata{0}
+    Metadata(Location),
+    /// Volume serial number changed since mounted
+    VolumeChanged,
 }
 
 #[derive(displaydoc::Display)]
 pub enum ImplementationError {
     /// TexFAT not supported
     TexFATNotSupported,
-    /// Create directory not supported
-    CreateDirectoryNotSupported,
+    /// Sector size smaller than or not multiple of IO block size
+    BlockSize,
+    /// Major filesystem revision not supported
+    Revision,
+    /// Filesystem revision newer than supported, refused to write
+    NewerRevision,
 }
 
 #[derive(displaydoc::Display)]
@@ -34,6 +86,10 @@ pub enum InputError {
     SeekPosition,
     /// Size out of range
     Size,
+    /// FAT index out of range
+    FATIndex,
+    /// Cluster ID out of range
+    ClusterID,
 }
 
 #[derive(displaydoc::Display)]
@@ -44,12 +100,16 @@ pub enum AllocationError {
     Fragment,
     /// No more cluster available
     NoMoreCluster,
+    /// Clusters beyond new end of volume still in use
+    ClustersInUse,
 }
 
 #[derive(displaydoc::Display)]
 pub enum OperationError {
     /// File or directory already open
     AlreadyOpen,
+    /// Too many files or directories opened
+    TooManyOpened,
     /// File or directory not found
     NotFound,
     /// Not a file
@@ -60,8 +120,20 @@ pub enum OperationError {
     AlreadyExists,
     /// Directory not empty when deleting
     DirectoryNotEmpty,
+    /// File opened read only
+    ReadOnly,
     /// End of file
     EOF,
+    /// Clusters of deleted file or directory already reused
+    Overwritten,
+    /// Refused to overwrite critical entry of root directory
+    Protected,
+    /// IO failed earlier, volume must be reconnected
+    Degraded,
+    /// Cancelled by `VolumeOptions::cancel`
+    Cancelled,
+    /// Entries of directory relocated since walk position taken
+    Relocated,
 }
 
 pub enum Error<E> {
@@ -114,3 +186,110 @@ from_error!(ImplementationError, Implementation);
 from_error!(InputError, Input);
 from_error!(OperationError, Operation);
 from_error!(AllocationError, Allocation);
+
+/// Linux errno values errors are mapped to
+pub mod errno {
+    pub const EPERM: i32 = 1;
+    pub const ENOENT: i32 = 2;
+    pub const EIO: i32 = 5;
+    pub const EBADF: i32 = 9;
+    pub const EBUSY: i32 = 16;
+    pub const EEXIST: i32 = 17;
+    pub const ENOTDIR: i32 = 20;
+    pub const EISDIR: i32 = 21;
+    pub const EINVAL: i32 = 22;
+    pub const EMFILE: i32 = 24;
+    pub const ENOSPC: i32 = 28;
+    pub const EROFS: i32 = 30;
+    pub const ENAMETOOLONG: i32 = 36;
+    pub const ENOTEMPTY: i32 = 39;
+    pub const ENODATA: i32 = 61;
+    pub const EOPNOTSUPP: i32 = 95;
+    pub const ESTALE: i32 = 116;
+    pub const ECANCELED: i32 = 125;
+}
+
+impl<E> Error<E> {
+    /// Map to errno, IO errors are mapped to EIO
+    pub fn to_errno(&self) -> i32 {
+        match self {
+            Self::IO(_) => errno::EIO,
+            Self::Data(DataError::NotExFAT) => errno::EINVAL,
+            Self::Data(_) => errno::EIO,
+            Self::Implementation(e) => match e {
+                ImplementationError::TexFATNotSupported => errno::EOPNOTSUPP,
+                ImplementationError::Revision => errno::EOPNOTSUPP,
+                ImplementationError::BlockSize => errno::EINVAL,
+                ImplementationError::NewerRevision => errno::EROFS,
+            },
+            Self::Input(e) => match e {
+                InputError::NameTooLong => errno::ENAMETOOLONG,
+                InputError::SeekPosition | InputError::Size | InputError::FATIndex => errno::EINVAL,
+                InputError::ClusterID => errno::EINVAL,
+            },
+            Self::Operation(e) => match e {
+                OperationError::AlreadyOpen => errno::EBUSY,
+                OperationError::TooManyOpened => errno::EMFILE,
+                OperationError::NotFound => errno::ENOENT,
+                OperationError::NotFile => errno::EISDIR,
+                OperationError::NotDirectory => errno::ENOTDIR,
+                OperationError::AlreadyExists => errno::EEXIST,
+                OperationError::DirectoryNotEmpty => errno::ENOTEMPTY,
+                OperationError::ReadOnly => errno::EBADF,
+                OperationError::EOF | OperationError::Overwritten => errno::ENODATA,
+                OperationError::Protected => errno::EPERM,
+                OperationError::Degraded => errno::EIO,
+                OperationError::Cancelled => errno::ECANCELED,
+                OperationError::Relocated => errno::ESTALE,
+            },
+            Self::Allocation(_) => errno::ENOSPC,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error<std::io::Error> {
+    /// Like `std::io::Error::raw_os_error`, errno of underlying IO error if any,
+    /// otherwise errno mapped from this error
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            Self::IO(e) => e.raw_os_error(),
+            _ => Some(self.to_errno()),
+        }
+    }
+}
+
+/// Error of copying between a file and a std stream
+#[cfg(feature = "std")]
+pub enum CopyError<E> {
+    /// Reading from or writing to the stream failed
+    Stream(std::io::Error),
+    Filesystem(Error<E>),
+}
+
+#[cfg(feature = "std")]
+impl<E: Debug> Debug for CopyError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Stream(e) => write!(f, "Stream({:?})", e),
+            Self::Filesystem(e) => write!(f, "{:?}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: Display> Display for CopyError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Stream(e) => write!(f, "Stream({})", e),
+            Self::Filesystem(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E> From<Error<E>> for CopyError<E> {
+    fn from(e: Error<E>) -> Self {
+        Self::Filesystem(e)
+    }
+}
@@ -4,14 +4,31 @@ use crate::types::{ClusterID, SectorID};
 #[derive(Copy, Clone, Debug)]
 pub(crate) struct Info {
     sector_size_shift: u8,
+    /// Offset of first FAT
     offset: u32,
     length: u32,
+    number_of_fats: u8,
+    active: u8,
 }
 
 #[cfg_attr(not(feature = "async"), deasync::deasync)]
 impl Info {
-    pub fn new(sector_size_shift: u8, offset: u32, length: u32) -> Self {
-        Self { sector_size_shift, offset, length }
+    /// Second FAT exists only on TexFAT volumes, active one is selected by volume flags
+    pub fn new(sector_size_shift: u8, offset: u32, length: u32, number_of_fats: u8) -> Self {
+        Self { sector_size_shift, offset, length, number_of_fats, active: 0 }
+    }
+
+    pub fn number_of_fats(&self) -> u8 {
+        self.number_of_fats
+    }
+
+    pub fn active(&self) -> u8 {
+        self.active
+    }
+
+    /// Caller guarantees index < number_of_fats
+    pub fn select(&mut self, index: u8) {
+        self.active = index;
     }
 
     pub fn fat_sector_id(&self, cluster_id: ClusterID) -> Option<SectorID> {
@@ -20,7 +37,8 @@ impl Info {
         if sector_index >= self.length {
             return None;
         }
-        Some(SectorID::from((self.offset + sector_index) as u64))
+        let offset = self.offset as u64 + self.active as u64 * self.length as u64;
+        Some(SectorID::from(offset + sector_index as u64))
     }
 
     pub fn offset(&self, cluster_id: ClusterID) -> usize {
@@ -28,14 +46,122 @@ impl Info {
         index as usize * 4 % (1 << self.sector_size_shift)
     }
 
-    pub fn next_cluster_id(
-        &mut self,
-        sector: &[[u8; 512]],
-        cluster_id: ClusterID,
-    ) -> Result<Entry, u32> {
+    pub fn next_cluster_id(&mut self, sector: &[u8], cluster_id: ClusterID) -> Result<Entry, u32> {
         let index: u32 = cluster_id.into();
         let offset = index as usize % ((1 << self.sector_size_shift) / 4);
-        let array: &[u32; 128] = unsafe { core::mem::transmute(&sector[offset / 128]) };
-        Entry::try_from(u32::from_le(array[offset % 128]))
+        let bytes = &sector[offset * 4..][..4];
+        Entry::try_from(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+}
+
+/// Number of FAT entries retained by a `Window`
+const WINDOW_SIZE: usize = 32;
+
+/// Copy of consecutive FAT entries of a FAT sector read, retained by handles
+/// so that following a fragmented cluster chain doesn't read FAT for every cluster
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct Window {
+    first: u32,
+    length: u8,
+    generation: u32,
+    entries: [u32; WINDOW_SIZE],
+}
+
+impl Window {
+    /// Stale if FAT written or caches invalidated since retained
+    pub fn get(&self, cluster_id: ClusterID, generation: u32) -> Option<u32> {
+        let index = u32::from(cluster_id).wrapping_sub(self.first) as usize;
+        if generation != self.generation || index >= self.length as usize {
+            return None;
+        }
+        Some(self.entries[index])
+    }
+
+    /// Retain FAT entries in sector starting from cluster, returns whether the window
+    /// reaches end of sector, i.e. next FAT sector will be needed soon
+    pub fn fill(&mut self, sector: &[u8], cluster_id: ClusterID, generation: u32) -> bool {
+        let entries_per_sector = sector.len() / 4;
+        let index = u32::from(cluster_id) as usize % entries_per_sector;
+        let length = WINDOW_SIZE.min(entries_per_sector - index);
+        for (i, bytes) in sector[index * 4..].chunks(4).take(length).enumerate() {
+            self.entries[i] = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        self.first = cluster_id.into();
+        self.length = length as u8;
+        self.generation = generation;
+        index + length == entries_per_sector
+    }
+}
+
+#[cfg(all(test, feature = "std", not(feature = "async")))]
+mod test {
+    use crate::fixture::Image;
+    use crate::FileOrDirectory;
+
+    #[test]
+    fn test_fat_window() {
+        let image = Image::new("exfat-test-fat-window.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        let mut files = ["a", "b"].map(|name| {
+            directory.create(name, false).unwrap();
+            let entryset = directory.find(name).unwrap().unwrap();
+            match directory.open(&entryset).unwrap() {
+                FileOrDirectory::File(file) => file,
+                FileOrDirectory::Directory(_) => unreachable!(),
+            }
+        });
+        // Clusters of both files interleave, so that each of them is fragmented
+        let data: Vec<u8> = (0..8 * 4096).map(|i| (i * 7 % 251) as u8).collect();
+        for chunk in data.chunks(4096) {
+            for file in files.iter_mut() {
+                file.write_all(chunk).unwrap();
+            }
+        }
+        let [mut file, _] = files;
+        file.seek(crate::SeekFrom::Start(0)).unwrap();
+        let stats = exfat.stats();
+        let mut buf = Vec::new();
+        assert_eq!(file.copy_to(&mut buf).unwrap(), data.len() as u64);
+        assert_eq!(buf, data);
+        // Data sectors along with a single FAT sector
+        assert_eq!(exfat.stats().sectors_read - stats.sectors_read, 8 * 8 + 1);
+    }
+
+    #[test]
+    fn test_fat_window_shared_chain() {
+        let image = Image::new("exfat-test-fat-window-shared.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        let name = |i| format!("file-with-a-long-name-{}", i);
+        for i in 0..40 {
+            directory.create(&name(i), false).unwrap();
+        }
+        // Root directory retains FAT entries of the chain grown below by another handle
+        assert!(root.check(false).unwrap().is_consistent());
+        for i in 40..100 {
+            directory.create(&name(i), false).unwrap();
+        }
+        for i in 0..100 {
+            let entryset = directory.find(&name(i)).unwrap().unwrap();
+            match directory.open(&entryset).unwrap() {
+                FileOrDirectory::File(mut file) => file.write_all(&[0xA5; 5000]).unwrap(),
+                FileOrDirectory::Directory(_) => unreachable!(),
+            }
+        }
+        assert!(root.check(false).unwrap().is_consistent());
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        assert!(root.check(false).unwrap().is_consistent());
+        let mut directory = root.open().unwrap();
+        for i in 0..100 {
+            assert!(directory.find(&name(i)).unwrap().is_some());
+        }
     }
 }
@@ -1,7 +1,7 @@
-#[cfg(feature = "max-filename-size-30")]
-pub const MAX_FILENAME_SIZE: usize = 30;
-#[cfg(not(feature = "limit-filename-size"))]
-pub const MAX_FILENAME_SIZE: usize = 510;
+/// Default name buffer size of `EntrySet` and `Directory` in bytes, which fits 255 UTF-16
+/// units of up to 3 bytes each in UTF-8,
+/// a smaller buffer could be chosen through their const generic parameter
+pub const MAX_FILENAME_SIZE: usize = 765;
 
 #[derive(Copy, Clone)]
 pub struct TouchOptions {
@@ -21,4 +21,18 @@ pub struct FileOptions {
     /// enabling this option will indicate write operation
     /// returns Fragment error instead of filling FAT chain
     pub dont_fragment: bool,
+    /// Fill newly allocated clusters with zeros,
+    /// preventing leakage of previously deleted data
+    pub zero_fill: bool,
+    /// Number of sectors hinted by `IO::prefetch` when sequential read detected,
+    /// so that IO like `UringIO` reads them concurrently in advance,
+    /// prefetch never crosses cluster boundary, zero disables readahead
+    pub readahead_sectors: u16,
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+pub struct FindOptions {
+    /// Compare name exactly instead of folding case through upcase table,
+    /// useful for distinguishing entries created by case-sensitive systems
+    pub case_sensitive: bool,
 }
@@ -0,0 +1,106 @@
+//! Image files backing tests
+
+use std::path::{Path, PathBuf};
+
+use crate::io::std::FileIO;
+use crate::region::boot::BootChecksum;
+use crate::{format, ExFAT, FormatOptions};
+
+pub(crate) const IMAGE_SIZE: u64 = 16 << 20;
+
+/// Image file under temporary directory, removed when dropped so that panicking tests
+/// leave nothing behind
+pub(crate) struct Image(PathBuf);
+
+impl Image {
+    /// Plain file of specified size, not formatted
+    pub fn empty(name: &str, size: u64) -> Self {
+        let path = std::env::temp_dir().join(name);
+        std::fs::File::create(&path).unwrap().set_len(size).unwrap();
+        Self(path)
+    }
+
+    pub fn with_options(name: &str, size: u64, options: &FormatOptions) -> Self {
+        let image = Self::empty(name, size);
+        format(&mut image.open(), size / 512, options).unwrap();
+        image
+    }
+
+    /// 16MiB image labelled `PROTECTED`
+    pub fn new(name: &str) -> Self {
+        let options = FormatOptions { label: "PROTECTED", ..Default::default() };
+        Self::with_options(name, IMAGE_SIZE, &options)
+    }
+
+    /// 16MiB image with two FATs and allocation bitmaps as TexFAT would have, second ones
+    /// being copies of first ones. FAT room is reserved by formatting for twice the size,
+    /// then split in half.
+    pub fn with_two_fats(name: &str) -> Self {
+        let options =
+            FormatOptions { max_num_sectors: Some(IMAGE_SIZE / 512 * 2), ..Default::default() };
+        let image = Self::with_options(name, IMAGE_SIZE, &options);
+        let mut bytes = std::fs::read(image.path()).unwrap();
+        let u32_at = |bytes: &[u8], offset: usize| {
+            u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+        };
+        let sector_size = 1usize << bytes[108];
+        let cluster_size = sector_size << bytes[109];
+        let fat_offset = u32_at(&bytes, 80) * sector_size;
+        let fat_length = u32_at(&bytes, 84) / 2;
+        let heap_offset = u32_at(&bytes, 88) * sector_size;
+        let cluster = |id: usize| heap_offset + (id - 2) * cluster_size;
+        let root = cluster(u32_at(&bytes, 96));
+
+        bytes[84..88].copy_from_slice(&(fat_length as u32).to_le_bytes());
+        bytes[110] = 2;
+        bytes.copy_within(0..12 * sector_size, 12 * sector_size);
+        for base in [0, 12] {
+            let mut checksum = BootChecksum::default();
+            for index in 0..11 {
+                checksum.write(index, &bytes[(base + index) * sector_size..][..sector_size]);
+            }
+            let sum = checksum.sum().to_le_bytes();
+            let sector = &mut bytes[(base + 11) * sector_size..][..sector_size];
+            sector.iter_mut().enumerate().for_each(|(i, byte)| *byte = sum[i % 4]);
+        }
+
+        let entries = &bytes[root..root + cluster_size];
+        let index = entries.chunks(32).position(|entry| entry[0] == 0x81).unwrap();
+        let entry: [u8; 32] = entries[index * 32..][..32].try_into().unwrap();
+        let unused = entries.chunks(32).position(|entry| entry[0] == 0).unwrap();
+        let (first_cluster, length) = (u32_at(&entry, 20), u32_at(&entry, 24));
+        let bitmap = cluster(first_cluster);
+        let free = (0..length * 8).find(|i| bytes[bitmap + i / 8] & (1 << (i % 8)) == 0).unwrap();
+        bytes[bitmap + free / 8] |= 1 << (free % 8);
+        let fat_length = fat_length * sector_size;
+        for fat in [fat_offset, fat_offset + fat_length] {
+            bytes[fat + (free + 2) * 4..][..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        }
+        bytes.copy_within(fat_offset..fat_offset + fat_length, fat_offset + fat_length);
+        bytes.copy_within(bitmap..bitmap + length, cluster(free + 2));
+        let mut entry = entry;
+        entry[1] = 1;
+        entry[20..24].copy_from_slice(&(free as u32 + 2).to_le_bytes());
+        bytes[root + unused * 32..][..32].copy_from_slice(&entry);
+        std::fs::write(image.path(), bytes).unwrap();
+        image
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn open(&self) -> FileIO {
+        FileIO::open(&self.0).unwrap()
+    }
+
+    pub fn mount(&self) -> ExFAT<FileIO> {
+        ExFAT::new(self.open()).unwrap()
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
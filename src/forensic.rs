@@ -0,0 +1,38 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::error::Location;
+
+/// Maximum number of skipped locations kept without `alloc` feature
+pub const MAX_FORENSIC_LOCATIONS: usize = 16;
+
+/// What forensic mode skipped or ignored, see `ExFAT::set_forensic`
+#[derive(Clone, Debug, Default)]
+pub struct ForensicReport {
+    /// Boot sector checksum mismatched
+    pub boot_checksum: bool,
+    /// Upcase table checksum mismatched
+    pub upcase_table_checksum: bool,
+    /// Number of damaged entries or entrysets skipped while walking through directories
+    pub skipped: usize,
+    /// Locations of skipped entries, up to `MAX_FORENSIC_LOCATIONS` without `alloc` feature
+    #[cfg(feature = "alloc")]
+    pub locations: Vec<Location>,
+    #[cfg(not(feature = "alloc"))]
+    pub locations: heapless::Vec<Location, MAX_FORENSIC_LOCATIONS>,
+    /// Number of entrysets walked through regardless of mismatched set checksum
+    pub checksum_mismatches: usize,
+}
+
+impl ForensicReport {
+    pub(crate) fn skip(&mut self, location: Location) {
+        warn!("Skipped damaged entry{}", location);
+        self.skipped += 1;
+        match () {
+            #[cfg(feature = "alloc")]
+            () => self.locations.push(location),
+            #[cfg(not(feature = "alloc"))]
+            () => self.locations.push(location).unwrap_or_default(),
+        }
+    }
+}
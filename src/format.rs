@@ -0,0 +1,389 @@
+use core::fmt::Debug;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use bytemuck::Zeroable;
+
+use crate::error::{Error, InputError};
+use crate::io::{flatten, IO};
+use crate::region::boot::{BootChecksum, BootSector};
+use crate::region::data::entry_type::{EntryType, RawEntryType};
+use crate::region::data::{AllocationBitmap, Checksum, UpcaseTable, VolumnLabel};
+use crate::types::SectorID;
+
+const NUM_BOOT_SECTORS: u64 = 24;
+const MAX_CLUSTER_COUNT: u64 = 0xFFFFFFF5;
+
+/// Content of 390 bytes boot code region of boot sector, unused by exFAT itself
+#[derive(Copy, Clone, Debug, Default)]
+pub enum BootCode<'a> {
+    #[default]
+    Zero,
+    /// Kept from boot sector being overwritten, if it is exFAT
+    Preserve,
+    Custom(&'a [u8; 390]),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FormatOptions<'a> {
+    /// Sector size shift, in range [9, 12]
+    pub sector_size_shift: u8,
+    /// Cluster size in bytes, derived from volume size when not specified
+    pub cluster_size: Option<u32>,
+    /// Volumn label, at most 11 characters
+    pub label: &'a str,
+    pub serial_number: u32,
+    /// Sector offset of partition, 0 when not partitioned
+    pub partition_offset: u64,
+    pub bootcode: BootCode<'a>,
+    /// Size FAT for volume of specified number of sectors, so that `ExFAT::resize` could
+    /// grow volume up to it later, at the cost of cluster heap placed further,
+    /// default to size of volume being formatted
+    pub max_num_sectors: Option<u64>,
+}
+
+impl<'a> Default for FormatOptions<'a> {
+    fn default() -> Self {
+        Self {
+            sector_size_shift: 9,
+            cluster_size: None,
+            label: "",
+            serial_number: 0,
+            partition_offset: 0,
+            bootcode: BootCode::Zero,
+            max_num_sectors: None,
+        }
+    }
+}
+
+/// Cluster size recommended by specification
+fn default_cluster_size(volume_size: u64) -> u32 {
+    match volume_size {
+        0..=0x1000_0000 => 4096,
+        0x1000_0001..=0x8_0000_0000 => 32768,
+        _ => 131072,
+    }
+}
+
+fn upcase(code: u32) -> u16 {
+    let ch = match char::from_u32(code) {
+        Some(ch) => ch,
+        None => return code as u16,
+    };
+    let mut upper = ch.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(upper), None) if upper as u32 <= 0xFFFF => upper as u16,
+        _ => code as u16,
+    }
+}
+
+/// Upcase table with first 128 entries uncompressed, identity mappings of
+/// remaining characters are compressed
+fn upcase_table() -> Vec<u16> {
+    let mut table = Vec::new();
+    let mut identical = 0u32;
+    for code in 0..=0xFFFFu32 {
+        let upper = upcase(code);
+        if code >= 128 && upper as u32 == code {
+            identical += 1;
+            continue;
+        }
+        match identical {
+            0 => (),
+            1..=2 => table.extend((code - identical..code).map(|code| code as u16)),
+            _ => table.extend([0xFFFF, identical as u16]),
+        }
+        identical = 0;
+        table.push(upper);
+    }
+    if identical > 0 {
+        table.extend([0xFFFF, identical as u16]);
+    }
+    table
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Layout {
+    sector_size_shift: u8,
+    sectors_per_cluster_shift: u8,
+    fat_offset: u32,
+    fat_length: u32,
+    heap_offset: u32,
+    cluster_count: u32,
+}
+
+impl Layout {
+    /// FAT is sized for `max_num_sectors` while cluster heap covers `num_sectors`
+    fn new(
+        num_sectors: u64,
+        max_num_sectors: u64,
+        sector_size_shift: u8,
+        cluster_size: u32,
+    ) -> Option<Self> {
+        let sector_size = 1u64 << sector_size_shift;
+        let sectors_per_cluster = cluster_size as u64 / sector_size;
+        // Align FAT and cluster heap to cluster boundary
+        let fat_offset = NUM_BOOT_SECTORS.next_multiple_of(sectors_per_cluster);
+        let mut cluster_count = max_num_sectors.checked_sub(fat_offset)? / sectors_per_cluster;
+        cluster_count = cluster_count.min(MAX_CLUSTER_COUNT);
+        let (mut fat_length, mut heap_offset);
+        loop {
+            fat_length = ((cluster_count + 2) * 4).div_ceil(sector_size);
+            heap_offset = (fat_offset + fat_length).next_multiple_of(sectors_per_cluster);
+            let count = max_num_sectors.checked_sub(heap_offset)? / sectors_per_cluster;
+            if count >= cluster_count {
+                break;
+            }
+            cluster_count = count;
+        }
+        let count = num_sectors.checked_sub(heap_offset)? / sectors_per_cluster;
+        cluster_count = cluster_count.min(count);
+        Some(Self {
+            sector_size_shift,
+            sectors_per_cluster_shift: sectors_per_cluster.trailing_zeros() as u8,
+            fat_offset: fat_offset as u32,
+            fat_length: fat_length as u32,
+            heap_offset: u32::try_from(heap_offset).ok()?,
+            cluster_count: cluster_count as u32,
+        })
+    }
+
+    fn sector_size(&self) -> usize {
+        1 << self.sector_size_shift
+    }
+
+    fn cluster_size(&self) -> usize {
+        1 << (self.sector_size_shift + self.sectors_per_cluster_shift)
+    }
+
+    fn num_clusters(&self, length: usize) -> u32 {
+        length.div_ceil(self.cluster_size()) as u32
+    }
+
+    fn cluster_sector_id(&self, cluster_id: u32) -> SectorID {
+        let offset = (cluster_id as u64 - 2) << self.sectors_per_cluster_shift;
+        SectorID::from(self.heap_offset as u64 + offset)
+    }
+}
+
+struct Writer<'a, IO> {
+    io: &'a mut IO,
+    sector_size: usize,
+}
+
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+impl<'a, E: Debug, T: IO<Error = E>> Writer<'a, T> {
+    /// Write bytes starting from specified sector, may span multiple sectors
+    async fn write(&mut self, sector_id: SectorID, bytes: &[u8]) -> Result<(), Error<E>> {
+        for (i, chunk) in bytes.chunks(512).enumerate() {
+            let sector_id = sector_id + (i * 512 / self.sector_size) as u64;
+            let offset = i * 512 % self.sector_size;
+            self.io.write(sector_id, offset, chunk).await.map_err(|e| Error::IO(e))?;
+        }
+        Ok(())
+    }
+
+    async fn fill_zero(&mut self, sector_id: SectorID, num_sectors: u64) -> Result<(), Error<E>> {
+        let zeros = [0u8; 512];
+        for i in 0..num_sectors {
+            for offset in (0..self.sector_size).step_by(zeros.len()) {
+                let result = self.io.write(sector_id + i, offset, &zeros).await;
+                result.map_err(|e| Error::IO(e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Format specified IO as an exFAT volume of specified number of sectors
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+pub async fn format<E: Debug, T: IO<Error = E>>(
+    io: &mut T,
+    num_sectors: u64,
+    options: &FormatOptions<'_>,
+) -> Result<(), Error<E>> {
+    let sector_size_shift = options.sector_size_shift;
+    if !(9..=12).contains(&sector_size_shift) {
+        return Err(InputError::Size.into());
+    }
+    let volume_size = num_sectors << sector_size_shift;
+    let cluster_size = options.cluster_size.unwrap_or(default_cluster_size(volume_size));
+    if !cluster_size.is_power_of_two() || cluster_size >> sector_size_shift == 0 {
+        return Err(InputError::Size.into());
+    }
+    if cluster_size > 32 * 1024 * 1024 {
+        return Err(InputError::Size.into());
+    }
+    if options.label.chars().count() > 11 {
+        return Err(InputError::NameTooLong.into());
+    }
+    let bootcode = match options.bootcode {
+        BootCode::Zero => [0u8; 390],
+        BootCode::Custom(bootcode) => *bootcode,
+        BootCode::Preserve => {
+            let blocks = io.read(0.into()).await.map_err(|e| Error::IO(e))?;
+            let boot_sector: &BootSector = bytemuck::from_bytes(&flatten(blocks)[..512]);
+            match boot_sector.is_exfat() {
+                true => boot_sector.bootcode,
+                false => [0u8; 390],
+            }
+        }
+    };
+    let max_num_sectors = options.max_num_sectors.unwrap_or(num_sectors);
+    if max_num_sectors < num_sectors {
+        return Err(InputError::Size.into());
+    }
+    let option = Layout::new(num_sectors, max_num_sectors, sector_size_shift, cluster_size);
+    let layout = option.ok_or(Error::Input(InputError::Size))?;
+    debug!("Format layout {:?}", layout);
+
+    let upcase_table = upcase_table();
+    let upcase_table: Vec<u8> = upcase_table.iter().flat_map(|&c| u16::to_le_bytes(c)).collect();
+    let bitmap_length = (layout.cluster_count as usize).div_ceil(8);
+    let bitmap_clusters = layout.num_clusters(bitmap_length);
+    let upcase_clusters = layout.num_clusters(upcase_table.len());
+    let (bitmap_cluster, upcase_cluster) = (2, 2 + bitmap_clusters);
+    let root_cluster = upcase_cluster + upcase_clusters;
+    let used_clusters = bitmap_clusters + upcase_clusters + 1;
+    if used_clusters > layout.cluster_count {
+        return Err(InputError::Size.into());
+    }
+
+    io.set_sector_size_shift(sector_size_shift).map_err(|e| Error::IO(e))?;
+    let sector_size = layout.sector_size();
+    let mut writer = Writer { io, sector_size };
+
+    // FAT region
+    let fat_offset = SectorID::from(layout.fat_offset as u64);
+    writer.fill_zero(fat_offset, layout.fat_length as u64).await?;
+    let mut fat: Vec<u32> = vec![0xFFFFFFF8, 0xFFFFFFFF];
+    for (first, count) in
+        [(2, bitmap_clusters), (upcase_cluster, upcase_clusters), (root_cluster, 1)]
+    {
+        fat.extend(first + 1..first + count);
+        fat.push(0xFFFFFFFF);
+    }
+    let fat: Vec<u8> = fat.iter().flat_map(|&entry| u32::to_le_bytes(entry)).collect();
+    writer.write(fat_offset, &fat).await?;
+
+    // Allocation bitmap
+    let count = (bitmap_clusters as u64) << layout.sectors_per_cluster_shift;
+    writer.fill_zero(layout.cluster_sector_id(bitmap_cluster), count).await?;
+    let mut bitmap = vec![0u8; (used_clusters as usize).div_ceil(8)];
+    for index in 0..used_clusters as usize {
+        bitmap[index / 8] |= 1 << (index % 8);
+    }
+    writer.write(layout.cluster_sector_id(bitmap_cluster), &bitmap).await?;
+
+    // Upcase table
+    let count = (upcase_clusters as u64) << layout.sectors_per_cluster_shift;
+    writer.fill_zero(layout.cluster_sector_id(upcase_cluster), count).await?;
+    writer.write(layout.cluster_sector_id(upcase_cluster), &upcase_table).await?;
+    let mut checksum = Checksum::default();
+    checksum.write(&upcase_table);
+
+    // Root directory
+    let count = 1u64 << layout.sectors_per_cluster_shift;
+    writer.fill_zero(layout.cluster_sector_id(root_cluster), count).await?;
+    let mut entries: Vec<[u8; 32]> = Vec::with_capacity(3);
+    if !options.label.is_empty() {
+        entries.push(bytemuck::cast(VolumnLabel::new(options.label)));
+    }
+    let mut entry: AllocationBitmap = Zeroable::zeroed();
+    entry.entry_type = RawEntryType::new(EntryType::AllocationBitmap, true);
+    entry.first_cluster = bitmap_cluster.into();
+    entry.data_length = (bitmap_length as u64).into();
+    entries.push(bytemuck::cast(entry));
+    let mut entry: UpcaseTable = Zeroable::zeroed();
+    entry.entry_type = RawEntryType::new(EntryType::UpcaseTable, true);
+    entry.table_checksum = checksum.sum().into();
+    entry.first_cluster = upcase_cluster.into();
+    entry.data_length = (upcase_table.len() as u64).into();
+    entries.push(bytemuck::cast(entry));
+    writer.write(layout.cluster_sector_id(root_cluster), entries.as_flattened()).await?;
+
+    // Main and backup boot region
+    let mut boot_sector: BootSector = Zeroable::zeroed();
+    boot_sector.jump_boot = hex!("EB 76 90");
+    boot_sector.filesystem_name = *b"EXFAT   ";
+    boot_sector.partition_offset = options.partition_offset.into();
+    boot_sector.volumn_length = num_sectors.into();
+    boot_sector.fat_offset = layout.fat_offset.into();
+    boot_sector.fat_length = layout.fat_length.into();
+    boot_sector.cluster_heap_offset = layout.heap_offset.into();
+    boot_sector.cluster_count = layout.cluster_count.into();
+    boot_sector.first_cluster_of_root_directory = root_cluster.into();
+    boot_sector.volumn_serial_number = options.serial_number.into();
+    boot_sector.filesystem_revision = 0x0100u16.into();
+    boot_sector.bytes_per_sector_shift = sector_size_shift;
+    boot_sector.sectors_per_cluster_shift = layout.sectors_per_cluster_shift;
+    boot_sector.number_of_fats = 1;
+    boot_sector.drive_select = 0x80;
+    boot_sector.percent_inuse = (used_clusters as u64 * 100 / layout.cluster_count as u64) as u8;
+    boot_sector.bootcode = bootcode;
+    boot_sector.boot_signature = [0x55, 0xAA];
+    let bytes = bytemuck::cast::<BootSector, [u8; 512]>(boot_sector);
+
+    let mut sectors = vec![vec![0u8; sector_size]; 12];
+    sectors[0][..512].copy_from_slice(&bytes);
+    for sector in sectors[1..=8].iter_mut() {
+        sector[sector_size - 4..].copy_from_slice(&hex!("00 00 55 AA"));
+    }
+    let mut checksum = BootChecksum::default();
+    for (index, sector) in sectors[..11].iter().enumerate() {
+        checksum.write(index, sector);
+    }
+    let sum = u32::to_le_bytes(checksum.sum());
+    for chunk in sectors[11].chunks_mut(4) {
+        chunk.copy_from_slice(&sum);
+    }
+    for base in [0, 12] {
+        for (index, sector) in sectors.iter().enumerate() {
+            writer.write(SectorID::from(base + index as u64), sector).await?;
+        }
+    }
+    writer.io.flush().await.map_err(|e| Error::IO(e))
+}
+
+#[cfg(all(test, feature = "std", not(feature = "async")))]
+mod test {
+    use crate::fixture::{Image, IMAGE_SIZE};
+    use crate::FormatOptions;
+
+    #[test]
+    fn test_format_bootcode() {
+        use crate::BootCode;
+
+        let image = Image::empty("exfat-test-format-bootcode.img", IMAGE_SIZE);
+        let bootcode: [u8; 390] = core::array::from_fn(|i| i as u8);
+        for option in [BootCode::Custom(&bootcode), BootCode::Preserve] {
+            let options = FormatOptions { bootcode: option, ..Default::default() };
+            super::format(&mut image.open(), IMAGE_SIZE / 512, &options).unwrap();
+            let bytes = std::fs::read(image.path()).unwrap();
+            assert_eq!(bytes[120..510], bootcode);
+            assert_eq!(bytes[12 * 512 + 120..12 * 512 + 510], bootcode);
+        }
+        let mut exfat = image.mount();
+        exfat.validate_checksum().unwrap();
+    }
+
+    #[test]
+    fn test_format_boot_checksum() {
+        use crate::region::boot::{BootChecksum, BootSector};
+
+        let image = Image::empty("exfat-test-format-boot-checksum.img", IMAGE_SIZE);
+        super::format(&mut image.open(), IMAGE_SIZE / 512, &Default::default()).unwrap();
+        let bytes = std::fs::read(image.path()).unwrap();
+        for region in [&bytes[..12 * 512], &bytes[12 * 512..24 * 512]] {
+            let sectors: Vec<&[u8]> = region.chunks(512).collect();
+            let boot_sector: BootSector = bytemuck::pod_read_unaligned(sectors[0]);
+            assert!(boot_sector.is_exfat());
+            let mut checksum = BootChecksum::default();
+            for (i, sector) in sectors[..11].iter().enumerate() {
+                checksum.write(i, sector);
+            }
+            let sum = checksum.sum().to_le_bytes();
+            assert!(sectors[11].chunks(4).all(|chunk| chunk == sum));
+        }
+    }
+}
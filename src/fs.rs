@@ -42,8 +42,11 @@ impl Display for SectorRef {
 impl SectorRef {
     pub fn id(&self, fs_info: &Info) -> SectorID {
         let index: u32 = self.cluster_id.into();
-        let num_sectors = (index as u64 - 2) * fs_info.sectors_per_cluster() as u64;
-        SectorID::from(fs_info.heap_offset as u64 + num_sectors + self.sector_index as u64)
+        // Wraps for root directory metadata which has no entry, consistently as identifier
+        let num_sectors =
+            (index as u64).wrapping_sub(2).wrapping_mul(fs_info.sectors_per_cluster() as u64);
+        let offset = fs_info.heap_offset as u64 + self.sector_index as u64;
+        SectorID::from(num_sectors.wrapping_add(offset))
     }
 
     pub fn new(cluster_id: ClusterID, sector_index: u32) -> Self {
@@ -51,7 +54,7 @@ impl SectorRef {
     }
 
     pub fn next(&self, sectors_per_cluster_shift: u8) -> Self {
-        if self.sector_index + 1 > (1 << sectors_per_cluster_shift) {
+        if self.sector_index + 1 >= (1 << sectors_per_cluster_shift) {
             return Self { cluster_id: self.cluster_id + 1u32, sector_index: 0, ..*self };
         }
         Self { sector_index: self.sector_index + 1, ..*self }
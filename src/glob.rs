@@ -0,0 +1,51 @@
+/// Match name against pattern with `*` and `?` wildcards,
+/// `*` matches any sequence of characters and `?` matches exactly one character.
+pub(crate) fn matches<F: Fn(char, char) -> bool>(pattern: &str, name: &str, equals: F) -> bool {
+    let (mut pattern_offset, mut name_offset) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while let Some(name_ch) = name[name_offset..].chars().next() {
+        match pattern[pattern_offset..].chars().next() {
+            Some('*') => {
+                pattern_offset += 1;
+                backtrack = Some((pattern_offset, name_offset));
+                continue;
+            }
+            Some('?') => {
+                pattern_offset += 1;
+                name_offset += name_ch.len_utf8();
+                continue;
+            }
+            Some(ch) if equals(ch, name_ch) => {
+                pattern_offset += ch.len_utf8();
+                name_offset += name_ch.len_utf8();
+                continue;
+            }
+            _ => (),
+        }
+        let (offset, star_name_offset) = match backtrack {
+            Some(backtrack) => backtrack,
+            None => return false,
+        };
+        let skipped = name[star_name_offset..].chars().next().unwrap();
+        pattern_offset = offset;
+        name_offset = star_name_offset + skipped.len_utf8();
+        backtrack = Some((offset, name_offset));
+    }
+    pattern[pattern_offset..].chars().all(|ch| ch == '*')
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_matches() {
+        let equals = |a: char, b: char| a.eq_ignore_ascii_case(&b);
+        assert!(super::matches("*.log", "trace.log", equals));
+        assert!(super::matches("*.LOG", "trace.log", equals));
+        assert!(super::matches("trace-202?*", "trace-2023-01.log", equals));
+        assert!(super::matches("*", "", equals));
+        assert!(super::matches("a*b*c", "aXbYbZc", equals));
+        assert!(!super::matches("*.log", "trace.txt", equals));
+        assert!(!super::matches("?", "", equals));
+        assert!(!super::matches("a*b", "aXbY", equals));
+    }
+}
@@ -0,0 +1,12 @@
+/// Record fields of current tracing span, does nothing without `tracing` feature
+macro_rules! record {
+    ($($field:ident = $value:expr),+) => {
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            $(span.record(stringify!($field), $value);)+
+        }
+    };
+}
+
+pub(crate) use record;
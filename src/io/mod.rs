@@ -1,46 +1,282 @@
 #[cfg(all(feature = "async", not(feature = "std")))]
 use alloc::boxed::Box;
+use core::sync::atomic::Ordering;
 
 #[cfg(feature = "async")]
 use async_trait::async_trait;
 
-use crate::error::Error;
-use crate::types::SectorID;
+use bytemuck::Pod;
+use memoffset::offset_of;
 
+use crate::error::{Error, ImplementationError, Location, OperationError};
+use crate::fat;
+use crate::forensic::ForensicReport;
+use crate::region::boot::{BootChecksum, BootSector};
+use crate::stats::Stats;
+use crate::types::{ClusterID, SectorID};
+use crate::volume::{FlushPolicy, VolumeOptions};
+
+/// Block of 512 bytes, suitable for most devices
 pub type Block = [u8; 512];
 
-pub(crate) fn flatten(sector: &[Block]) -> &[u8] {
-    unsafe { core::slice::from_raw_parts(&sector[0][0], sector.len() * 512) }
+/// View blocks returned by `IO::read` as bytes
+pub fn flatten<B: Pod>(blocks: &[B]) -> &[u8] {
+    bytemuck::cast_slice(blocks)
 }
 
+/// Without `alloc` feature shared state lives in [`Storage`](crate::Storage)
+/// which is static, hence IO must be `'static` as well
+#[cfg(feature = "alloc")]
+pub trait Lifetime {}
+#[cfg(feature = "alloc")]
+impl<T> Lifetime for T {}
+#[cfg(not(feature = "alloc"))]
+pub trait Lifetime: 'static {}
+#[cfg(not(feature = "alloc"))]
+impl<T: 'static> Lifetime for T {}
+
+/// With `async` feature futures of IO are `Send` and hold `&mut` of IO, hence IO must be
+/// `Send`, which implementing `IO::read` requires anyway. Without `async` feature there
+/// is no such bound, IO like one holding `Rc` is fine.
+#[cfg(feature = "async")]
+pub trait MaybeSend: Send {}
+#[cfg(feature = "async")]
+impl<T: Send> MaybeSend for T {}
+#[cfg(not(feature = "async"))]
+pub trait MaybeSend {}
+#[cfg(not(feature = "async"))]
+impl<T> MaybeSend for T {}
+
+/// With `async` feature streams copied from or to are polled in place,
+/// hence must be `Unpin`
+#[cfg(feature = "async")]
+pub trait MaybeUnpin: Unpin {}
+#[cfg(feature = "async")]
+impl<T: Unpin + ?Sized> MaybeUnpin for T {}
+#[cfg(not(feature = "async"))]
+pub trait MaybeUnpin {}
+#[cfg(not(feature = "async"))]
+impl<T: ?Sized> MaybeUnpin for T {}
+
+/// Sector device backing a volume, see `MaybeSend` for whether it must be `Send`
 #[cfg_attr(feature = "async", async_trait)]
 #[cfg_attr(not(feature = "async"), deasync::deasync)]
-pub trait IO {
+pub trait IO: Lifetime + MaybeSend {
     type Error: core::fmt::Debug;
+    /// Granularity of sector buffer returned by `read`, typically `Block`,
+    /// or `[u8; 4096]` for devices with 4K native logical sectors,
+    /// sector size of filesystem must be multiple of it
+    type Block: Pod;
     /// Default to 9, which means 512B
     fn set_sector_size_shift(&mut self, shift: u8) -> Result<(), Self::Error>;
-    async fn read<'a>(&'a mut self, id: SectorID) -> Result<&'a [Block], Self::Error>;
+    async fn read(&mut self, id: SectorID) -> Result<&[Self::Block], Self::Error>;
     /// Caller guarantees bytes.len() <= SECTOR_SIZE - offset
     async fn write(&mut self, id: SectorID, offset: usize, data: &[u8]) -> Result<(), Self::Error>;
     async fn flush(&mut self) -> Result<(), Self::Error>;
+    /// Hint that specified number of sectors starting from id will be read soon,
+    /// issued for next FAT sector while following FAT chain and for file readahead,
+    /// implementations supporting multi-sector IO may fetch them in advance
+    fn prefetch(&mut self, id: SectorID, count: u32) -> Result<(), Self::Error> {
+        let _ = (id, count);
+        Ok(())
+    }
+    /// Drop sector content kept by implementations caching or prefetching sectors,
+    /// since volume may have been modified elsewhere
+    fn invalidate(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// Error reported when IO stalls longer than `VolumeOptions::io_timeout`,
+    /// no timeout applied if `None`, so `io_timeout` takes no effect unless implemented
+    #[cfg(feature = "async-std")]
+    fn timed_out(&self) -> Option<Self::Error> {
+        None
+    }
+    /// Read consecutive sectors starting from id into buf whose length is multiple of
+    /// sector size. By default the run is hinted by `prefetch` then read sector by sector,
+    /// so that requests are in flight concurrently only if `prefetch` queues them;
+    /// implementations capable of multi-sector IO should read the run at once instead.
+    async fn read_many(&mut self, id: SectorID, buf: &mut [u8]) -> Result<(), Self::Error> {
+        let sector = flatten(self.read(id).await?);
+        let sector_size = sector.len();
+        buf[..sector_size].copy_from_slice(sector);
+        let count = buf.len() / sector_size;
+        if count > 1 {
+            self.prefetch(id + 1u64, count as u32 - 1)?;
+        }
+        for index in 1..count {
+            let sector = flatten(self.read(id + index as u64).await?);
+            buf[index * sector_size..][..sector_size].copy_from_slice(sector);
+        }
+        Ok(())
+    }
+    /// Write `count` consecutive whole sectors starting from id,
+    /// implementations capable of multi-sector IO should write them at once
+    async fn write_many(
+        &mut self,
+        id: SectorID,
+        count: u32,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        let sector_size = data.len() / count as usize;
+        for (index, chunk) in data.chunks(sector_size).enumerate() {
+            self.write(id + index as u64, 0, chunk).await?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct IOWrapper<IO> {
+    io: IO,
+    pub(crate) stats: Stats,
+    /// Present in forensic mode, in which nothing is written to IO
+    pub(crate) forensic: Option<ForensicReport>,
+    pub(crate) options: VolumeOptions,
+    /// Percent-in-use deferred until next flush
+    pub(crate) percent_inuse: Option<u8>,
+    /// Percent-in-use left unwritten since drift is below threshold, written by unmount
+    pub(crate) drifted_percent_inuse: Option<u8>,
+    /// Number of writes since last flush
+    pub(crate) dirty_writes: u32,
+    /// Volume flags and percent-in-use last read or written by this mount,
+    /// a difference on media means volume modified elsewhere
+    pub(crate) boot_fields: (u16, u8),
+    /// Increased whenever caches invalidated, caches filled earlier are discarded on use
+    pub(crate) generation: u32,
+    /// Increased whenever FAT written or caches invalidated, FAT entries retained by
+    /// handles are discarded on use
+    pub(crate) fat_generation: u32,
+    /// Filesystem revision newer than `SUPPORTED_REVISION`
+    pub(crate) newer_revision: bool,
+    /// IO failed while `VolumeOptions::degrade_on_io_error` set
+    pub(crate) degraded: bool,
+}
+
+/// Bound IO future with `VolumeOptions::io_timeout` using async-std timer
+#[cfg(feature = "async-std")]
+macro_rules! timed {
+    ($self:ident, $future:expr) => {{
+        let timeout = $self.options.io_timeout;
+        let error = timeout.and_then(|_| $self.io.timed_out());
+        let future = $future;
+        async move {
+            match (timeout, error) {
+                (Some(duration), Some(error)) => {
+                    async_std::future::timeout(duration, future).await.unwrap_or(Err(error))
+                }
+                _ => future.await,
+            }
+        }
+    }};
+}
+
+#[cfg(not(feature = "async-std"))]
+macro_rules! timed {
+    ($self:ident, $future:expr) => {
+        $future
+    };
 }
 
-pub(crate) struct IOWrapper<IO>(IO);
+/// Map IO error, marking wrapper degraded if configured so
+macro_rules! io_error {
+    ($self:ident) => {
+        |e| {
+            $self.degraded |= $self.options.degrade_on_io_error;
+            Error::IO(e)
+        }
+    };
+}
 
 impl<IO> IOWrapper<IO> {
     pub(crate) fn new(io: IO) -> Self {
-        Self(io)
+        let (forensic, options) = (None, VolumeOptions::default());
+        let stats = Stats::default();
+        let (percent_inuse, drifted_percent_inuse) = (None, None);
+        Self {
+            io,
+            stats,
+            forensic,
+            options,
+            percent_inuse,
+            drifted_percent_inuse,
+            dirty_writes: 0,
+            boot_fields: (0, 0),
+            generation: 0,
+            fat_generation: 0,
+            newer_revision: false,
+            degraded: false,
+        }
+    }
+
+    /// Record damaged entry skipped in forensic mode or report it through `on_damaged`
+    pub(crate) fn skipped(&mut self, location: Location) {
+        match self.forensic.as_mut() {
+            Some(report) => report.skip(location),
+            None => warn!("Skipped damaged entry{}", location),
+        }
+        if let Some(on_damaged) = self.options.on_damaged {
+            on_damaged.report(location)
+        }
+    }
+
+    /// Replace failed IO with a new one, writes not flushed to failed IO are lost
+    pub(crate) fn replace(&mut self, io: IO) {
+        if self.dirty_writes > 0 {
+            warn!("{} writes not flushed before reconnect", self.dirty_writes);
+        }
+        self.io = io;
+        self.dirty_writes = 0;
+        self.degraded = false;
     }
 
+    #[cfg(feature = "alloc")]
     pub(crate) fn unwrap(self) -> IO {
-        self.0
+        self.io
     }
 }
 
 #[cfg_attr(not(feature = "async"), deasync::deasync)]
 impl<E, T: IO<Error = E>> IOWrapper<T> {
-    pub(crate) async fn read<'a>(&'a mut self, sector: SectorID) -> Result<&'a [Block], Error<E>> {
-        self.0.read(sector).await.map_err(|e| Error::IO(e))
+    pub(crate) fn change_options(&mut self, f: impl FnOnce(&mut VolumeOptions)) {
+        f(&mut self.options);
+        #[cfg(feature = "async-std")]
+        if self.options.io_timeout.is_some() && self.io.timed_out().is_none() {
+            warn!("IO timeout ignored since IO does not implement timed_out");
+        }
+    }
+
+    /// Read whole sector as bytes regardless of block size
+    pub(crate) async fn read(&mut self, sector: SectorID) -> Result<&[u8], Error<E>> {
+        self.ensure_connected()?;
+        let blocks = timed!(self, self.io.read(sector)).await.map_err(io_error!(self))?;
+        self.stats.sectors_read += 1;
+        self.stats.reads += 1;
+        Ok(flatten(blocks))
+    }
+
+    fn ensure_connected(&self) -> Result<(), Error<E>> {
+        match self.degraded {
+            true => Err(OperationError::Degraded.into()),
+            false => Ok(()),
+        }
+    }
+
+    /// Fails with `Cancelled` if `VolumeOptions::cancel` is set
+    pub(crate) fn ensure_not_cancelled(&self) -> Result<(), Error<E>> {
+        match self.options.cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+            true => Err(OperationError::Cancelled.into()),
+            false => Ok(()),
+        }
+    }
+
+    fn ensure_writable(&self) -> Result<(), Error<E>> {
+        self.ensure_connected()?;
+        if self.forensic.is_some() {
+            return Err(OperationError::ReadOnly.into());
+        }
+        if self.newer_revision && !self.options.write_newer_revision {
+            return Err(ImplementationError::NewerRevision.into());
+        }
+        Ok(())
     }
 
     pub(crate) async fn write(
@@ -49,14 +285,236 @@ impl<E, T: IO<Error = E>> IOWrapper<T> {
         offset: usize,
         data: &[u8],
     ) -> Result<(), Error<E>> {
-        let result = self.0.write(id, offset, data).await;
-        result.map_err(|e| Error::IO(e))
+        self.ensure_writable()?;
+        timed!(self, self.io.write(id, offset, data)).await.map_err(io_error!(self))?;
+        self.stats.sectors_written += 1;
+        self.dirty_writes += 1;
+        Ok(())
+    }
+
+    /// Write `count` whole sectors starting from id, see `IO::write_many`
+    pub(crate) async fn write_many(
+        &mut self,
+        id: SectorID,
+        count: u32,
+        data: &[u8],
+    ) -> Result<(), Error<E>> {
+        self.ensure_writable()?;
+        timed!(self, self.io.write_many(id, count, data)).await.map_err(io_error!(self))?;
+        self.stats.sectors_written += count as u64;
+        self.dirty_writes += count;
+        Ok(())
+    }
+
+    /// Flush issued by operation itself, which is subject to flush policy
+    pub(crate) async fn auto_flush(&mut self) -> Result<(), Error<E>> {
+        match self.options.flush_policy {
+            FlushPolicy::Eager => self.flush().await,
+            FlushPolicy::DirtyWrites(num) if self.dirty_writes >= num => self.flush().await,
+            FlushPolicy::DirtyWrites(_) | FlushPolicy::OnSync => Ok(()),
+        }
+    }
+
+    /// Flush writes deferred by flush policy if any
+    pub(crate) async fn flush_deferred(&mut self) -> Result<(), Error<E>> {
+        match self.dirty_writes > 0 || self.percent_inuse.is_some() {
+            true => self.flush().await,
+            false => Ok(()),
+        }
     }
 
     pub(crate) async fn flush(&mut self) -> Result<(), Error<E>> {
-        self.0.flush().await.map_err(|e| Error::IO(e))
+        if self.forensic.is_some() {
+            return Ok(());
+        }
+        self.ensure_connected()?;
+        if let Some(percent_inuse) = self.percent_inuse.take() {
+            self.write_percent_inuse(percent_inuse).await?;
+        }
+        timed!(self, self.io.flush()).await.map_err(io_error!(self))?;
+        self.stats.flushes += 1;
+        self.dirty_writes = 0;
+        Ok(())
+    }
+
+    /// Read `count` whole sectors starting from id into buf, see `IO::read_many`
+    pub(crate) async fn read_many(
+        &mut self,
+        id: SectorID,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<(), Error<E>> {
+        self.ensure_connected()?;
+        timed!(self, self.io.read_many(id, buf)).await.map_err(io_error!(self))?;
+        self.stats.sectors_read += count as u64;
+        self.stats.reads += 1;
+        Ok(())
+    }
+
+    pub(crate) fn prefetch(&mut self, id: SectorID, count: u32) -> Result<(), Error<E>> {
+        self.ensure_connected()?;
+        self.io.prefetch(id, count).map_err(io_error!(self))
+    }
+
+    pub(crate) async fn write_percent_inuse(&mut self, percent_inuse: u8) -> Result<(), Error<E>> {
+        let offset = offset_of!(BootSector, percent_inuse);
+        self.write(0.into(), offset, &[percent_inuse]).await?;
+        self.boot_fields.1 = percent_inuse;
+        Ok(())
+    }
+
+    /// Recompute checksum of main or backup boot region starting from specified sector
+    pub(crate) async fn write_boot_checksum(&mut self, base: u64) -> Result<(), Error<E>> {
+        let mut checksum = BootChecksum::default();
+        let mut sector_size = 0;
+        for i in 0..=10 {
+            let sector = self.read((base + i).into()).await?;
+            sector_size = sector.len();
+            checksum.write(i as usize, sector);
+        }
+        let sum = checksum.sum().to_le_bytes();
+        let chunk: [u8; 512] = core::array::from_fn(|i| sum[i % 4]);
+        for offset in (0..sector_size).step_by(chunk.len()) {
+            self.write((base + 11).into(), offset, &chunk).await?;
+        }
+        Ok(())
+    }
+
+    /// Write FAT entry of cluster, FAT entries retained by any handle become stale
+    pub(crate) async fn write_fat_entry(
+        &mut self,
+        fat_info: &fat::Info,
+        cluster_id: ClusterID,
+        value: u32,
+    ) -> Result<(), Error<E>> {
+        let sector_id = fat_info.fat_sector_id(cluster_id).unwrap();
+        self.fat_generation = self.fat_generation.wrapping_add(1);
+        self.write(sector_id, fat_info.offset(cluster_id), &value.to_le_bytes()).await
+    }
+
+    pub(crate) async fn write_volume_flags(&mut self, volume_flags: u16) -> Result<(), Error<E>> {
+        let offset = offset_of!(BootSector, volume_flags);
+        self.write(0.into(), offset, &volume_flags.to_le_bytes()).await?;
+        self.boot_fields.0 = volume_flags;
+        Ok(())
+    }
+
+    /// Drop sectors cached by IO, and caches of handles if `all`
+    pub(crate) fn invalidate(&mut self, all: bool) -> Result<(), Error<E>> {
+        self.ensure_connected()?;
+        self.io.invalidate().map_err(io_error!(self))?;
+        if all {
+            self.generation = self.generation.wrapping_add(1);
+            self.fat_generation = self.fat_generation.wrapping_add(1);
+        }
+        Ok(())
     }
 }
 
-#[cfg(feature = "std")]
+/// Async `FileIO` relies on `async-std` runtime
+#[cfg(all(feature = "std", any(not(feature = "async"), feature = "async-std")))]
 pub mod std;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(all(feature = "io-uring", target_os = "linux", not(feature = "async")))]
+pub mod uring;
+
+#[cfg(all(test, feature = "async-std"))]
+mod test {
+    use std::io::ErrorKind;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use async_std::task::block_on;
+    use async_trait::async_trait;
+
+    use super::std::FileIO;
+    use super::{Block, IO};
+    use crate::error::{Error, OperationError};
+    use crate::types::SectorID;
+    use crate::{format, ExFAT, FormatOptions};
+
+    /// Never completes any request while stalled, like a wedged bus
+    struct StallingIO(FileIO, Arc<AtomicBool>);
+
+    impl StallingIO {
+        async fn stall(&self) {
+            if self.1.load(Ordering::Relaxed) {
+                std::future::pending::<()>().await
+            }
+        }
+    }
+
+    #[async_trait]
+    impl IO for StallingIO {
+        type Error = std::io::Error;
+        type Block = Block;
+
+        fn set_sector_size_shift(&mut self, shift: u8) -> Result<(), Self::Error> {
+            self.0.set_sector_size_shift(shift)
+        }
+
+        async fn read(&mut self, id: SectorID) -> Result<&[Block], Self::Error> {
+            self.stall().await;
+            self.0.read(id).await
+        }
+
+        async fn write(&mut self, id: SectorID, offset: usize, data: &[u8]) -> std::io::Result<()> {
+            self.stall().await;
+            self.0.write(id, offset, data).await
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            self.stall().await;
+            self.0.flush().await
+        }
+
+        fn timed_out(&self) -> Option<Self::Error> {
+            self.0.timed_out()
+        }
+    }
+
+    #[test]
+    fn test_io_timeout() {
+        let path = std::env::temp_dir().join("exfat-test-io-timeout.img");
+        std::fs::File::create(&path).unwrap().set_len(16 << 20).unwrap();
+        let stalled = Arc::new(AtomicBool::new(false));
+        let open = || async { StallingIO(FileIO::open(&path).await.unwrap(), stalled.clone()) };
+        block_on(async {
+            let mut io = FileIO::open(&path).await.unwrap();
+            format(&mut io, (16 << 20) / 512, &FormatOptions::default()).await.unwrap();
+            let mut exfat = ExFAT::new(open().await).await.unwrap();
+            let timeout = Some(Duration::from_millis(50));
+            exfat.change_options(|options| options.io_timeout = timeout).await;
+            let mut root = exfat.root_directory().await.unwrap();
+            let mut directory = root.open().await.unwrap();
+
+            // Volume remains usable once stall cleared
+            stalled.store(true, Ordering::Relaxed);
+            match directory.create("a", false).await {
+                Err(Error::IO(e)) => assert_eq!(e.kind(), ErrorKind::TimedOut),
+                _ => panic!("Stalled IO not timed out"),
+            }
+            stalled.store(false, Ordering::Relaxed);
+            assert!(!exfat.is_degraded().await);
+            directory.create("b", false).await.unwrap();
+
+            // Timeout degrades volume like any other IO error till reconnected
+            exfat.change_options(|options| options.degrade_on_io_error = true).await;
+            stalled.store(true, Ordering::Relaxed);
+            match directory.create("c", false).await {
+                Err(Error::IO(e)) => assert_eq!(e.kind(), ErrorKind::TimedOut),
+                _ => panic!("Stalled IO not timed out"),
+            }
+            stalled.store(false, Ordering::Relaxed);
+            assert!(exfat.is_degraded().await);
+            let result = directory.create("c", false).await;
+            assert!(matches!(result, Err(Error::Operation(OperationError::Degraded))));
+            exfat.reconnect(open().await).await.unwrap();
+            directory.create("c", false).await.unwrap();
+            assert!(directory.find("b").await.unwrap().is_some());
+        });
+        std::fs::remove_file(&path).ok();
+    }
+}
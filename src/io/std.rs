@@ -30,8 +30,12 @@ impl FileIO {
             #[cfg(not(feature = "async"))]
             () => File::options(),
         };
-        let result = options.read(true).write(true).open(filepath).await;
-        result.map(|file| Self { file, sector_size_shift: 9, buffer: MaybeUninit::uninit() })
+        options.read(true).write(true).open(filepath).await.map(Self::new)
+    }
+
+    /// Wrap file already opened for read and write, e.g. with custom flags
+    pub fn new(file: File) -> Self {
+        Self { file, sector_size_shift: 9, buffer: MaybeUninit::uninit() }
     }
 }
 
@@ -39,13 +43,14 @@ impl FileIO {
 #[cfg_attr(not(feature = "async"), deasync::deasync)]
 impl super::IO for FileIO {
     type Error = std::io::Error;
+    type Block = super::Block;
 
     fn set_sector_size_shift(&mut self, shift: u8) -> Result<(), Self::Error> {
         self.sector_size_shift = shift;
         Ok(())
     }
 
-    async fn read<'a>(&'a mut self, sector: SectorID) -> Result<&'a [[u8; 512]], Self::Error> {
+    async fn read(&mut self, sector: SectorID) -> Result<&[super::Block], Self::Error> {
         let sector_size: usize = 1 << self.sector_size_shift;
         let seek = SeekFrom::Start(u64::from(sector) * sector_size as u64);
 
@@ -68,7 +73,26 @@ impl super::IO for FileIO {
         self.file.write_all(buf).await.map(|_| ())
     }
 
+    #[cfg(feature = "async-std")]
+    fn timed_out(&self) -> Option<Self::Error> {
+        Some(std::io::ErrorKind::TimedOut.into())
+    }
+
     async fn flush(&mut self) -> Result<(), Self::Error> {
         self.file.flush().await
     }
+
+    /// Consecutive sectors are written in one go
+    async fn write_many(&mut self, id: SectorID, _: u32, data: &[u8]) -> Result<(), Self::Error> {
+        let sector_size = 1 << self.sector_size_shift;
+        self.file.seek(SeekFrom::Start(u64::from(id) * sector_size)).await?;
+        self.file.write_all(data).await
+    }
+
+    /// Consecutive sectors are read in one go
+    async fn read_many(&mut self, sector: SectorID, buf: &mut [u8]) -> Result<(), Self::Error> {
+        let sector_size = 1 << self.sector_size_shift;
+        self.file.seek(SeekFrom::Start(u64::from(sector) * sector_size)).await?;
+        self.file.read_exact(buf).await
+    }
 }
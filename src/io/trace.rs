@@ -0,0 +1,121 @@
+use std::time::Instant;
+
+#[cfg(feature = "async")]
+use async_trait::async_trait;
+
+use crate::types::SectorID;
+
+/// Logs every read, write and flush of underlying IO with latency,
+/// useful to find out what device traffic an operation generates
+#[derive(Debug)]
+pub struct TracingIO<IO> {
+    io: IO,
+    level: log::Level,
+    sector_size: usize,
+}
+
+impl<IO> TracingIO<IO> {
+    /// Traffic is logged at trace level by default
+    pub fn new(io: IO) -> Self {
+        Self { io, level: log::Level::Trace, sector_size: 512 }
+    }
+
+    pub fn with_level(mut self, level: log::Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn into_inner(self) -> IO {
+        self.io
+    }
+}
+
+#[cfg_attr(feature = "async", async_trait)]
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+impl<IO: super::IO + super::MaybeSend> super::IO for TracingIO<IO> {
+    type Error = IO::Error;
+    type Block = IO::Block;
+
+    fn set_sector_size_shift(&mut self, shift: u8) -> Result<(), Self::Error> {
+        self.sector_size = 1 << shift;
+        self.io.set_sector_size_shift(shift)
+    }
+
+    async fn read(&mut self, id: SectorID) -> Result<&[Self::Block], Self::Error> {
+        let now = Instant::now();
+        let result = self.io.read(id).await;
+        let status = if result.is_ok() { "" } else { " failed" };
+        let (length, elapsed) = (self.sector_size, now.elapsed());
+        log!(self.level, "Read sector {} length {} in {:?}{}", id, length, elapsed, status);
+        result
+    }
+
+    async fn write(&mut self, id: SectorID, offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+        let now = Instant::now();
+        let result = self.io.write(id, offset, data).await;
+        let status = if result.is_ok() { "" } else { " failed" };
+        let (length, elapsed) = (data.len(), now.elapsed());
+        log!(
+            self.level,
+            "Write sector {} offset {} length {} in {:?}{}",
+            id,
+            offset,
+            length,
+            elapsed,
+            status
+        );
+        result
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        let now = Instant::now();
+        let result = self.io.flush().await;
+        let status = if result.is_ok() { "" } else { " failed" };
+        log!(self.level, "Flush in {:?}{}", now.elapsed(), status);
+        result
+    }
+
+    fn prefetch(&mut self, id: SectorID, count: u32) -> Result<(), Self::Error> {
+        log!(self.level, "Prefetch {} sectors from sector {}", count, id);
+        self.io.prefetch(id, count)
+    }
+
+    fn invalidate(&mut self) -> Result<(), Self::Error> {
+        log!(self.level, "Invalidate");
+        self.io.invalidate()
+    }
+
+    #[cfg(feature = "async-std")]
+    fn timed_out(&self) -> Option<Self::Error> {
+        self.io.timed_out()
+    }
+
+    async fn write_many(
+        &mut self,
+        id: SectorID,
+        count: u32,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        let now = Instant::now();
+        let result = self.io.write_many(id, count, data).await;
+        let status = if result.is_ok() { "" } else { " failed" };
+        log!(
+            self.level,
+            "Write {} sectors from sector {} in {:?}{}",
+            count,
+            id,
+            now.elapsed(),
+            status
+        );
+        result
+    }
+
+    async fn read_many(&mut self, id: SectorID, buf: &mut [u8]) -> Result<(), Self::Error> {
+        let now = Instant::now();
+        let result = self.io.read_many(id, buf).await;
+        let status = if result.is_ok() { "" } else { " failed" };
+        let (count, elapsed) = (buf.len() / self.sector_size, now.elapsed());
+        log!(self.level, "Read {} sectors from sector {} in {:?}{}", count, id, elapsed, status);
+        result
+    }
+}
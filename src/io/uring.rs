@@ -0,0 +1,400 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use io_uring::{opcode, squeue, types, IoUring};
+
+use crate::types::SectorID;
+
+/// Maximum number of requests in flight at the same time
+const QUEUE_DEPTH: usize = 32;
+
+enum Kind {
+    Read(SectorID),
+    /// Read into buffer of caller, who waits for its completion
+    ReadInto,
+    /// Read of a sector written afterwards, discarded once completed
+    Stale,
+    /// Number of sectors written starting from sector
+    Write(SectorID, u32),
+    Fsync,
+}
+
+struct Request {
+    kind: Kind,
+    buffer: Vec<u8>,
+    result: Option<i32>,
+}
+
+impl Request {
+    /// Whether this read or write request covers any of specified sectors
+    fn overlaps(&self, id: SectorID, count: u32) -> bool {
+        let (start, end) = match self.kind {
+            Kind::Read(start) => (u64::from(start), u64::from(start) + 1),
+            Kind::Write(start, n) => (u64::from(start), u64::from(start) + n as u64),
+            _ => return false,
+        };
+        start < u64::from(id) + count as u64 && u64::from(id) < end
+    }
+}
+
+/// Linux io_uring backed IO, writes are queued without waiting for completion
+/// and sectors hinted by `prefetch` are read concurrently,
+/// failed writes are reported by next flush.
+///
+/// Blocks while waiting for completion, so only available without `async` feature.
+pub struct UringIO {
+    file: File,
+    ring: IoUring,
+    sector_size_shift: u8,
+    requests: Vec<Option<Request>>,
+    /// Sector returned by last read
+    buffer: Vec<u8>,
+    num_writes: usize,
+    error: Option<Error>,
+}
+
+impl UringIO {
+    pub fn open<P: AsRef<Path>>(filepath: P) -> Result<Self> {
+        Self::new(File::options().read(true).write(true).open(filepath)?)
+    }
+
+    /// Wrap file already opened for read and write, e.g. with custom flags
+    pub fn new(file: File) -> Result<Self> {
+        let ring = IoUring::new(QUEUE_DEPTH as u32)?;
+        let requests = (0..QUEUE_DEPTH).map(|_| None).collect();
+        let buffer = Vec::new();
+        Ok(Self { file, ring, sector_size_shift: 9, requests, buffer, num_writes: 0, error: None })
+    }
+
+    fn sector_size(&self) -> usize {
+        1 << self.sector_size_shift
+    }
+
+    /// Submit queued requests, retried if interrupted by signal
+    fn submit(&mut self) -> Result<()> {
+        self.submit_and_wait(0)
+    }
+
+    /// Submit queued requests and wait for specified number of completions,
+    /// retried if interrupted by signal
+    fn submit_and_wait(&mut self, want: usize) -> Result<()> {
+        loop {
+            match self.ring.submit_and_wait(want) {
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                result => return result.map(|_| ()),
+            }
+        }
+    }
+
+    fn position(&self, pred: impl Fn(&Request) -> bool) -> Option<usize> {
+        self.requests.iter().position(|r| r.as_ref().is_some_and(&pred))
+    }
+
+    /// Move completions into requests, completed writes are released immediately
+    fn reap(&mut self) {
+        for entry in self.ring.completion() {
+            let index = entry.user_data() as usize;
+            let request = self.requests[index].as_mut().unwrap();
+            match request.kind {
+                Kind::Write(..) => {
+                    let length = request.buffer.len();
+                    let result = match entry.result() {
+                        result if result < 0 => Err(Error::from_raw_os_error(-result)),
+                        result if (result as usize) < length => Err(ErrorKind::WriteZero.into()),
+                        _ => Ok(()),
+                    };
+                    if let Err(error) = result {
+                        self.error.get_or_insert(error);
+                    }
+                    self.requests[index] = None;
+                    self.num_writes -= 1;
+                }
+                Kind::Stale => self.requests[index] = None,
+                _ => request.result = Some(entry.result()),
+            }
+        }
+    }
+
+    fn wait(&mut self, index: usize) -> Result<Request> {
+        loop {
+            self.reap();
+            if self.requests[index].as_ref().unwrap().result.is_some() {
+                return Ok(self.requests[index].take().unwrap());
+            }
+            self.submit_and_wait(1)?;
+        }
+    }
+
+    /// Wait for every request in flight, so that kernel no longer writes into their buffers
+    fn drain(&mut self) -> Result<()> {
+        self.reap();
+        while self.requests.iter().flatten().any(|r| r.result.is_none()) {
+            self.submit_and_wait(1)?;
+            self.reap();
+        }
+        Ok(())
+    }
+
+    /// A completed read not yet consumed is evicted if no request slot is available
+    fn free_slot(&mut self, evict: bool) -> Result<Option<usize>> {
+        loop {
+            if let Some(index) = self.requests.iter().position(|r| r.is_none()) {
+                return Ok(Some(index));
+            }
+            if !evict {
+                return Ok(None);
+            }
+            let completed = |r: &Request| matches!(r.kind, Kind::Read(_)) && r.result.is_some();
+            if let Some(index) = self.position(completed) {
+                self.requests[index] = None;
+                return Ok(Some(index));
+            }
+            self.submit_and_wait(1)?;
+            self.reap();
+        }
+    }
+
+    fn push(&mut self, index: usize, request: Request, entry: squeue::Entry) -> Result<()> {
+        let entry = entry.user_data(index as u64);
+        // Requests are not ordered by io_uring, a sector being written must not be overtaken
+        let entry = match request.kind {
+            Kind::Write(id, count) if self.position(|r| r.overlaps(id, count)).is_some() => {
+                entry.flags(squeue::Flags::IO_DRAIN)
+            }
+            Kind::Read(_) | Kind::ReadInto | Kind::Fsync if self.num_writes > 0 => {
+                entry.flags(squeue::Flags::IO_DRAIN)
+            }
+            _ => entry,
+        };
+        // Request recorded only once queued, otherwise it would be waited for forever
+        while unsafe { self.ring.submission().push(&entry) }.is_err() {
+            self.submit()?;
+        }
+        if let Kind::Write(..) = request.kind {
+            self.num_writes += 1;
+        }
+        self.requests[index] = Some(request);
+        Ok(())
+    }
+
+    /// Prefetched reads matching predicate are discarded
+    fn discard_reads(&mut self, pred: impl Fn(&Request) -> bool) {
+        self.reap();
+        for slot in self.requests.iter_mut() {
+            match slot {
+                Some(r) if matches!(r.kind, Kind::Read(_)) && pred(r) => match r.result {
+                    Some(_) => *slot = None,
+                    None => r.kind = Kind::Stale,
+                },
+                _ => (),
+            }
+        }
+    }
+
+    fn push_write(&mut self, id: SectorID, count: u32, offset: usize, data: &[u8]) -> Result<()> {
+        // Prefetched content of these sectors is outdated
+        self.discard_reads(|r| r.overlaps(id, count));
+        let index = self.free_slot(true)?.unwrap();
+        let buffer = data.to_vec();
+        let fd = types::Fd(self.file.as_raw_fd());
+        let position = u64::from(id) * self.sector_size() as u64 + offset as u64;
+        let entry = opcode::Write::new(fd, buffer.as_ptr(), buffer.len() as u32);
+        let request = Request { kind: Kind::Write(id, count), buffer, result: None };
+        self.push(index, request, entry.offset(position).build())?;
+        self.submit()
+    }
+
+    fn push_read(&mut self, index: usize, id: SectorID) -> Result<()> {
+        let size = self.sector_size();
+        let mut buffer = vec![0u8; size];
+        let fd = types::Fd(self.file.as_raw_fd());
+        let offset = u64::from(id) * size as u64;
+        let entry = opcode::Read::new(fd, buffer.as_mut_ptr(), size as u32).offset(offset).build();
+        self.push(index, Request { kind: Kind::Read(id), buffer, result: None }, entry)
+    }
+}
+
+impl super::IO for UringIO {
+    type Error = Error;
+    type Block = super::Block;
+
+    fn set_sector_size_shift(&mut self, shift: u8) -> Result<()> {
+        self.sector_size_shift = shift;
+        Ok(())
+    }
+
+    fn read(&mut self, id: SectorID) -> Result<&[super::Block]> {
+        let index = match self.position(|r| matches!(r.kind, Kind::Read(i) if i == id)) {
+            Some(index) => index,
+            None => {
+                let index = self.free_slot(true)?.unwrap();
+                self.push_read(index, id)?;
+                index
+            }
+        };
+        let request = self.wait(index)?;
+        let result = request.result.unwrap();
+        if result < 0 {
+            return Err(Error::from_raw_os_error(-result));
+        }
+        if (result as usize) < self.sector_size() {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        self.buffer = request.buffer;
+        Ok(bytemuck::cast_slice(&self.buffer))
+    }
+
+    fn write(&mut self, id: SectorID, offset: usize, data: &[u8]) -> Result<()> {
+        self.push_write(id, 1, offset, data)
+    }
+
+    /// Sectors are written by a single request
+    fn write_many(&mut self, id: SectorID, count: u32, data: &[u8]) -> Result<()> {
+        self.push_write(id, count, 0, data)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let index = self.free_slot(true)?.unwrap();
+        let entry = opcode::Fsync::new(types::Fd(self.file.as_raw_fd())).build();
+        let request = Request { kind: Kind::Fsync, buffer: Vec::new(), result: None };
+        self.push(index, request, entry)?;
+        let result = self.wait(index)?.result.unwrap();
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        match result {
+            result if result < 0 => Err(Error::from_raw_os_error(-result)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sectors are read by a single request
+    fn read_many(&mut self, id: SectorID, buf: &mut [u8]) -> Result<()> {
+        let index = self.free_slot(true)?.unwrap();
+        let fd = types::Fd(self.file.as_raw_fd());
+        let offset = u64::from(id) * self.sector_size() as u64;
+        let entry = opcode::Read::new(fd, buf.as_mut_ptr(), buf.len() as u32).offset(offset);
+        let request = Request { kind: Kind::ReadInto, buffer: Vec::new(), result: None };
+        self.push(index, request, entry.build())?;
+        let result = match self.wait(index) {
+            Ok(request) => request.result.unwrap(),
+            Err(error) => {
+                // Buffer of caller must not be returned while kernel may still read into it
+                if self.drain().is_err() {
+                    std::process::abort();
+                }
+                self.requests[index] = None;
+                return Err(error);
+            }
+        };
+        match result {
+            result if result < 0 => Err(Error::from_raw_os_error(-result)),
+            result if (result as usize) < buf.len() => Err(ErrorKind::UnexpectedEof.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn prefetch(&mut self, id: SectorID, count: u32) -> Result<()> {
+        self.reap();
+        for i in 0..count {
+            let id = id + i;
+            if self.position(|r| matches!(r.kind, Kind::Read(i) if i == id)).is_some() {
+                continue;
+            }
+            match self.free_slot(false)? {
+                Some(index) => self.push_read(index, id)?,
+                None => break,
+            }
+        }
+        self.submit()
+    }
+
+    fn invalidate(&mut self) -> Result<()> {
+        self.discard_reads(|_| true);
+        Ok(())
+    }
+}
+
+impl Drop for UringIO {
+    /// Buffers of requests in flight must outlive them
+    fn drop(&mut self) {
+        if self.drain().is_err() {
+            // Never free buffers kernel may still write into
+            core::mem::forget(core::mem::take(&mut self.requests));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UringIO;
+    use crate::fixture::Image;
+    use crate::io::IO;
+    use crate::types::SectorID;
+    use crate::{ExFAT, FileOrDirectory};
+
+    #[test]
+    fn test_read_after_write() {
+        let image = Image::empty("exfat-test-uring-io.img", 64 << 10);
+        let mut io = UringIO::open(image.path()).unwrap();
+        let sector = |id: u64| SectorID::from(id);
+        io.prefetch(sector(0), 8).unwrap();
+        // Prefetched sector overwritten, written sector read in the middle of writes
+        io.write(sector(1), 0, &[0xA5; 512]).unwrap();
+        io.write(sector(1), 100, &[0x5A; 12]).unwrap();
+        let bytes: &[u8] = bytemuck::cast_slice(io.read(sector(1)).unwrap());
+        assert!(bytes[..100].iter().chain(&bytes[112..]).all(|&byte| byte == 0xA5));
+        assert!(bytes[100..112].iter().all(|&byte| byte == 0x5A));
+        io.write_many(sector(2), 4, &[0x11; 2048]).unwrap();
+        let mut buf = vec![0u8; 3 * 512];
+        io.read_many(sector(1), &mut buf).unwrap();
+        assert_eq!(buf[100], 0x5A);
+        assert!(buf[512..].iter().all(|&byte| byte == 0x11));
+        io.flush().unwrap();
+        assert!(bytemuck::cast_slice::<_, u8>(io.read(sector(0)).unwrap()).iter().all(|&b| b == 0));
+
+        let mut buf = vec![0u8; 512];
+        let result = io.read_many(sector(128), &mut buf);
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        drop(io);
+        let bytes = std::fs::read(image.path()).unwrap();
+        assert!(bytes[1024..3072].iter().all(|&byte| byte == 0x11));
+    }
+
+    #[test]
+    fn test_mount() {
+        let image = Image::new("exfat-test-uring-mount.img");
+        let mut exfat = ExFAT::new(UringIO::open(image.path()).unwrap()).unwrap();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        let data: Vec<u8> = (0..5 * 4096 + 100).map(|i| (i * 7 % 251) as u8).collect();
+        for i in 0..16 {
+            let name = format!("file-{}", i);
+            directory.create(&name, false).unwrap();
+            let entryset = directory.find(&name).unwrap().unwrap();
+            match directory.open(&entryset).unwrap() {
+                FileOrDirectory::File(mut file) => file.write_all(&data).unwrap(),
+                FileOrDirectory::Directory(_) => unreachable!(),
+            }
+        }
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        assert!(root.check(false).unwrap().is_consistent());
+        let mut directory = root.open().unwrap();
+        let entryset = directory.find("file-15").unwrap().unwrap();
+        match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(mut file) => {
+                let mut buf = Vec::new();
+                file.copy_to(&mut buf).unwrap();
+                assert_eq!(buf, data);
+            }
+            FileOrDirectory::Directory(_) => unreachable!(),
+        }
+    }
+}
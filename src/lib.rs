@@ -1,6 +1,9 @@
 #![doc = include_str!("../README.md")]
 #![cfg_attr(not(any(test, feature = "std")), no_std)]
+// Shared handles are plain static references without `alloc` feature
+#![cfg_attr(not(feature = "alloc"), allow(noop_method_call))]
 
+#[cfg(feature = "alloc")]
 extern crate alloc;
 
 #[macro_use]
@@ -14,30 +17,66 @@ mod endian;
 pub mod error;
 mod fat;
 pub mod file;
+#[cfg(all(test, feature = "std", not(feature = "async")))]
+mod fixture;
+mod forensic;
+#[cfg(feature = "alloc")]
+mod format;
 pub(crate) mod fs;
+mod glob;
+mod instrument;
 pub mod io;
+pub mod name;
 mod region;
+mod resize;
+mod stats;
+#[cfg(not(feature = "alloc"))]
+mod storage;
 pub(crate) mod sync;
 pub mod types;
 mod upcase_table;
+pub mod volume;
 
 use core::fmt::Debug;
-use core::mem;
+use core::mem::size_of;
 
 use memoffset::offset_of;
 
+pub use cluster_heap::allocation_bitmap::UsageProgress;
+#[cfg(feature = "alloc")]
+pub use cluster_heap::buffered::{BufReader, BufWriter};
+#[cfg(feature = "alloc")]
+pub use cluster_heap::check::CheckReport;
+#[cfg(feature = "async")]
+pub use cluster_heap::chunks::Chunks;
+pub use cluster_heap::context::{OpenEntries, OpenEntry, MAX_OPENED_ENTRIES};
+#[cfg(not(feature = "alloc"))]
+pub use cluster_heap::directory::lookup_cache::MAX_LOOKUP_CACHE_ENTRIES;
 pub use cluster_heap::directory::{Directory, FileOrDirectory};
-pub use cluster_heap::file::SeekFrom;
-pub use cluster_heap::root::RootDirectory;
-use error::{DataError, Error, ImplementationError};
+pub use cluster_heap::entryset::{EntrySet, WalkPosition};
+pub use cluster_heap::file::{File, SeekFrom};
+pub use cluster_heap::root::{AllocationBitmapInfo, RootDirectory, UpcaseTableInfo};
+pub use cluster_heap::root::{FreeExtent, FreeExtents};
+use error::{DataError, Error, ImplementationError, InputError, OperationError};
+pub use forensic::{ForensicReport, MAX_FORENSIC_LOCATIONS};
+#[cfg(feature = "alloc")]
+pub use format::{format, BootCode, FormatOptions};
 use io::IOWrapper;
 pub use region::data::entryset::primary::DateTime;
+pub use resize::ResizeLimits;
+pub use stats::Stats;
+#[cfg(not(feature = "alloc"))]
+pub use storage::Storage;
 use sync::{shared, Shared};
 use types::ClusterID;
+use volume::{VolumeOptions, SUPPORTED_REVISION};
 
-pub struct ExFAT<IO> {
+pub struct ExFAT<IO: io::Lifetime> {
     io: Shared<IOWrapper<IO>>,
+    #[cfg(not(feature = "alloc"))]
+    slots: Option<storage::Slots<IO>>,
     serial_number: u32,
+    revision: (u8, u8),
     fat_info: fat::Info,
     fs_info: fs::Info,
     root: ClusterID,
@@ -45,33 +84,81 @@ pub struct ExFAT<IO> {
 
 #[cfg_attr(not(feature = "async"), deasync::deasync)]
 impl<E: Debug, IO: io::IO<Error = E>> ExFAT<IO> {
-    pub async fn new(mut io: IO) -> Result<Self, Error<E>> {
+    /// Without `alloc` feature, state shared between handles is placed in specified storage
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "mount",
+            level = "debug",
+            skip_all,
+            fields(serial_number, sector_size, cluster_size)
+        )
+    )]
+    pub async fn new<#[cfg(not(feature = "alloc"))] const N: usize>(
+        mut io: IO,
+        #[cfg(not(feature = "alloc"))] storage: &'static mut Storage<IO, N>,
+    ) -> Result<Self, Error<E>> {
         let blocks = io.read(0.into()).await.map_err(|e| Error::IO(e))?;
-        let boot_sector: &region::boot::BootSector = unsafe { mem::transmute(&blocks[0]) };
+        let bytes = io::flatten(blocks);
+        let boot_sector: region::boot::BootSector = *bytemuck::from_bytes(&bytes[..512]);
         if !boot_sector.is_exfat() {
             return Err(DataError::NotExFAT.into());
         }
-        if boot_sector.number_of_fats > 1 {
+        let revision = boot_sector.filesystem_revision.to_ne().to_be_bytes();
+        let revision = (revision[0], revision[1]);
+        debug!("Filesystem revision {}.{:02}", revision.0, revision.1);
+        if revision.0 != SUPPORTED_REVISION.0 {
+            return Err(ImplementationError::Revision.into());
+        }
+        if boot_sector.number_of_fats > 2 {
             return Err(ImplementationError::TexFATNotSupported.into());
         }
         let fat_offset = boot_sector.fat_offset.to_ne();
         let fat_length = boot_sector.fat_length.to_ne();
         debug!("FAT offset {} length {}", fat_offset, fat_length);
 
+        if !(1usize << boot_sector.bytes_per_sector_shift).is_multiple_of(size_of::<IO::Block>()) {
+            return Err(ImplementationError::BlockSize.into());
+        }
         io.set_sector_size_shift(boot_sector.bytes_per_sector_shift).map_err(|e| Error::IO(e))?;
         let root = ClusterID::from(boot_sector.first_cluster_of_root_directory.to_ne());
         debug!("Root directory on cluster {}", root);
         let sector_size_shift = boot_sector.bytes_per_sector_shift;
-        let fat_info = fat::Info::new(sector_size_shift, fat_offset, fat_length);
+        let number_of_fats = boot_sector.number_of_fats.max(1);
+        let mut fat_info =
+            fat::Info::new(sector_size_shift, fat_offset, fat_length, number_of_fats);
+        // Ignored if there is only one FAT
+        let active_fat = boot_sector.volume_flags().active_fat() as u8;
+        if active_fat < number_of_fats {
+            fat_info.select(active_fat);
+        }
+        debug!("Active FAT {} of {}", fat_info.active(), number_of_fats);
         let fs_info = fs::Info {
             heap_offset: boot_sector.cluster_heap_offset.to_ne(),
             sectors_per_cluster_shift: boot_sector.sectors_per_cluster_shift,
             sector_size_shift,
         };
         debug!("Filesystem info: {:?}", fs_info);
+        let serial_number = boot_sector.volumn_serial_number.to_ne();
+        instrument::record!(
+            serial_number = serial_number,
+            sector_size = fs_info.sector_size(),
+            cluster_size = fs_info.cluster_size()
+        );
+        #[cfg(not(feature = "alloc"))]
+        let (io_slot, slots) = storage.split();
+        let mut io = IOWrapper::new(io);
+        io.boot_fields = (boot_sector.volume_flags().0, boot_sector.percent_inuse);
+        io.newer_revision = revision > SUPPORTED_REVISION;
         Ok(Self {
-            io: shared(IOWrapper::new(io)),
-            serial_number: boot_sector.volumn_serial_number.to_ne(),
+            #[cfg(feature = "alloc")]
+            io: shared(io),
+            #[cfg(not(feature = "alloc"))]
+            io: shared(io_slot, io),
+            #[cfg(not(feature = "alloc"))]
+            slots: Some(slots),
+            serial_number,
+            revision,
             fs_info,
             fat_info,
             root,
@@ -81,26 +168,32 @@ impl<E: Debug, IO: io::IO<Error = E>> ExFAT<IO> {
     pub async fn is_dirty(&mut self) -> Result<bool, Error<E>> {
         let mut io = acquire!(self.io);
         let blocks = io.read(0.into()).await?;
-        let boot_sector: &region::boot::BootSector = unsafe { mem::transmute(&blocks[0]) };
+        let boot_sector: &region::boot::BootSector = bytemuck::from_bytes(&blocks[..512]);
         Ok(boot_sector.volume_flags().volume_dirty() > 0)
     }
 
     pub async fn percent_inuse(&mut self) -> Result<u8, Error<E>> {
         let mut io = acquire!(self.io);
         let blocks = io.read(0.into()).await?;
-        let boot_sector: &region::boot::BootSector = unsafe { mem::transmute(&blocks[0]) };
+        let boot_sector: &region::boot::BootSector = bytemuck::from_bytes(&blocks[..512]);
         Ok(boot_sector.percent_inuse)
     }
 
+    /// Main boot sector as currently on volume
+    pub async fn boot_sector(&mut self) -> Result<volume::BootSectorInfo, Error<E>> {
+        let mut io = acquire!(self.io);
+        let blocks = io.read(0.into()).await?;
+        let boot_sector: &region::boot::BootSector = bytemuck::from_bytes(&blocks[..512]);
+        Ok(boot_sector.into())
+    }
+
     pub async fn set_dirty(&mut self, dirty: bool) -> Result<(), Error<E>> {
         let mut io = acquire!(self.io);
         let sector = io.read(0.into()).await?;
-        let boot_sector: &region::boot::BootSector = unsafe { mem::transmute(&sector[0]) };
+        let boot_sector: &region::boot::BootSector = bytemuck::from_bytes(&sector[..512]);
         let mut volume_flags = boot_sector.volume_flags();
         volume_flags.set_volume_dirty(dirty as u16);
-        let offset = offset_of!(region::boot::BootSector, volume_flags);
-        let bytes: [u8; 2] = unsafe { mem::transmute(volume_flags) };
-        io.write(0.into(), offset, &bytes).await?;
+        io.write_volume_flags(volume_flags.0).await?;
         io.flush().await
     }
 
@@ -109,32 +202,571 @@ impl<E: Debug, IO: io::IO<Error = E>> ExFAT<IO> {
         let mut checksum = region::boot::BootChecksum::default();
         for i in 0..=10 {
             let sector = io.read(i.into()).await?;
-            for block in sector.iter() {
-                checksum.write(i as usize, block);
-            }
+            checksum.write(i as usize, sector);
         }
         let sector = io.read(11.into()).await?;
-        let array: &[u32; 128] = unsafe { core::mem::transmute(&sector[0]) };
-        if u32::from_le(array[0]) != checksum.sum() {
+        if u32::from_le_bytes(sector[..4].try_into().unwrap()) != checksum.sum() {
+            if let Some(report) = io.forensic.as_mut() {
+                warn!("Ignored boot sector checksum mismatch");
+                report.boot_checksum = true;
+                return Ok(());
+            }
             return Err(DataError::BootChecksum.into());
         }
         Ok(())
     }
 
+    /// Forensic mode never writes to IO and flushes nothing, instead of failing
+    /// checksum mismatches and damaged entries are skipped and reported by `forensic_report`,
+    /// enabling or disabling it resets the report
+    pub async fn set_forensic(&mut self, forensic: bool) {
+        acquire!(self.io).forensic = forensic.then(ForensicReport::default);
+    }
+
+    pub async fn forensic_report(&self) -> Option<ForensicReport> {
+        acquire!(self.io).forensic.clone()
+    }
+
+    /// Discard sectors cached by IO, allocation counters and name lookups cached by handles,
+    /// so that changes made elsewhere, e.g. by a host PC, are picked up.
+    /// Metadata of opened files and directories is not reloaded, they should be reopened.
+    pub async fn invalidate_caches(&mut self) -> Result<(), Error<E>> {
+        acquire!(self.io).invalidate(true)
+    }
+
+    /// Cheap check whether volume is modified elsewhere since mounted, by comparing
+    /// volume flags and percent-in-use on media with ones last read or written,
+    /// caches are invalidated and true returned if so.
+    /// Fails with `VolumeChanged` if serial number differs, i.e. another volume is inserted.
+    pub async fn revalidate(&mut self) -> Result<bool, Error<E>> {
+        let mut io = acquire!(self.io);
+        io.invalidate(false)?;
+        let sector = io.read(0.into()).await?;
+        let boot_sector: &region::boot::BootSector = bytemuck::from_bytes(&sector[..512]);
+        if boot_sector.volumn_serial_number.to_ne() != self.serial_number {
+            return Err(DataError::VolumeChanged.into());
+        }
+        let boot_fields = (boot_sector.volume_flags().0, boot_sector.percent_inuse);
+        if boot_fields == io.boot_fields {
+            return Ok(false);
+        }
+        debug!("Volume modified elsewhere, volume flags and percent-in-use {:?}", boot_fields);
+        io.boot_fields = boot_fields;
+        io.invalidate(true)?;
+        Ok(true)
+    }
+
+    /// Whether IO failed with `VolumeOptions::degrade_on_io_error` set,
+    /// any further IO is refused until reconnected
+    pub async fn is_degraded(&self) -> bool {
+        acquire!(self.io).degraded
+    }
+
+    /// Resume on a new IO after the old one failed, e.g. SD card pulled out and reinserted.
+    /// Fails with `VolumeChanged` if serial number differs, i.e. another volume is inserted,
+    /// in which case the old IO is kept.
+    /// Writes not yet flushed are lost and all caches invalidated,
+    /// opened files and directories remain usable but should better be reopened.
+    pub async fn reconnect(&mut self, mut io: IO) -> Result<(), Error<E>> {
+        let blocks = io.read(0.into()).await.map_err(|e| Error::IO(e))?;
+        let bytes = io::flatten(blocks);
+        let boot_sector: &region::boot::BootSector = bytemuck::from_bytes(&bytes[..512]);
+        if !boot_sector.is_exfat() {
+            return Err(DataError::NotExFAT.into());
+        }
+        if boot_sector.volumn_serial_number.to_ne() != self.serial_number {
+            return Err(DataError::VolumeChanged.into());
+        }
+        let boot_fields = (boot_sector.volume_flags().0, boot_sector.percent_inuse);
+        io.set_sector_size_shift(self.fs_info.sector_size_shift).map_err(|e| Error::IO(e))?;
+        let mut wrapper = acquire!(self.io);
+        wrapper.replace(io);
+        wrapper.boot_fields = boot_fields;
+        wrapper.invalidate(true)
+    }
+
+    pub async fn change_options(&mut self, f: impl FnOnce(&mut VolumeOptions)) {
+        acquire!(self.io).change_options(f)
+    }
+
+    /// Flush writes deferred by `FlushPolicy`, opened files should be synced beforehand
+    pub async fn flush(&mut self) -> Result<(), Error<E>> {
+        acquire!(self.io).flush_deferred().await
+    }
+
+    /// IO, lookup cache and allocation counters accumulated since mount
+    pub async fn stats(&self) -> Stats {
+        acquire!(self.io).stats
+    }
+
     pub fn serial_number(&self) -> u32 {
         self.serial_number
     }
 
+    /// Filesystem revision as major and minor, volume is read only if newer than
+    /// `SUPPORTED_REVISION` unless `VolumeOptions::write_newer_revision` set
+    pub fn revision(&self) -> (u8, u8) {
+        self.revision
+    }
+
+    /// Index of FAT and allocation bitmap in use, always 0 unless volume has two FATs
+    pub fn active_fat(&self) -> u8 {
+        self.fat_info.active()
+    }
+
+    pub fn number_of_fats(&self) -> u8 {
+        self.fat_info.number_of_fats()
+    }
+
+    /// Switch to another FAT and its allocation bitmap, e.g. when active one is damaged.
+    /// Root directory must not be opened, since it keeps FAT in use.
+    pub async fn set_active_fat(&mut self, index: u8) -> Result<(), Error<E>> {
+        if index >= self.fat_info.number_of_fats() {
+            return Err(InputError::FATIndex.into());
+        }
+        if self.is_opened() {
+            return Err(OperationError::AlreadyOpen.into());
+        }
+        let mut io = acquire!(self.io);
+        let sector = io.read(0.into()).await?;
+        let boot_sector: &region::boot::BootSector = bytemuck::from_bytes(&sector[..512]);
+        let mut volume_flags = boot_sector.volume_flags();
+        volume_flags.set_active_fat(index as u16);
+        io.write_volume_flags(volume_flags.0).await?;
+        io.flush().await?;
+        self.fat_info.select(index);
+        Ok(())
+    }
+
+    /// Range of volume length `resize` accepts, see `ResizeLimits`; fails with
+    /// `TexFATNotSupported` on volumes with two FATs as `resize` does
+    pub async fn resize_limits(&mut self) -> Result<ResizeLimits, Error<E>> {
+        let mut io = acquire!(self.io);
+        resize::limits(&mut io, self.fs_info, self.root).await
+    }
+
+    /// Grow or shrink volume in place to specified number of sectors, e.g. after an image
+    /// is written to a larger card, IO must be able to hold them.
+    /// Since cluster heap stays where it is, growth is bounded by room left between FAT and
+    /// cluster heap, fails with `Size` beyond `ResizeLimits::max_sectors`, which is about
+    /// the formatted size unless reserved by `FormatOptions::max_num_sectors`;
+    /// allocation bitmap is relocated if outgrown.
+    /// Shrinking fails with `ClustersInUse` if any cluster beyond new end is allocated.
+    /// Root directory must not be opened, volumes with two FATs, i.e. TexFAT,
+    /// are refused with `TexFATNotSupported`.
+    pub async fn resize(&mut self, num_sectors: u64) -> Result<(), Error<E>> {
+        if self.is_opened() {
+            return Err(OperationError::AlreadyOpen.into());
+        }
+        let mut io = acquire!(self.io);
+        self.fat_info = resize::resize(&mut io, self.fs_info, self.root, num_sectors).await?;
+        io.invalidate(true)
+    }
+
+    fn is_opened(&self) -> bool {
+        #[cfg(feature = "alloc")]
+        return sync::Ref::strong_count(&self.io) > 1;
+        #[cfg(not(feature = "alloc"))]
+        return self.slots.is_none();
+    }
+
+    /// Update serial number in both main and backup boot sectors along with their checksums
+    pub async fn set_serial_number(&mut self, serial_number: u32) -> Result<(), Error<E>> {
+        let offset = offset_of!(region::boot::BootSector, volumn_serial_number);
+        let mut io = acquire!(self.io);
+        for base in [0u64, 12] {
+            io.write(base.into(), offset, &serial_number.to_le_bytes()).await?;
+            io.write_boot_checksum(base).await?;
+        }
+        io.flush().await?;
+        self.serial_number = serial_number;
+        Ok(())
+    }
+
+    /// Derive a new serial number from current time like format does,
+    /// so that cloned volumes are distinguishable
+    #[cfg(feature = "std")]
+    pub async fn regenerate_serial(&mut self) -> Result<u32, Error<E>> {
+        let now = chrono::Utc::now();
+        let serial_number = (now.timestamp() as u32) ^ now.timestamp_subsec_nanos();
+        self.set_serial_number(serial_number).await?;
+        Ok(serial_number)
+    }
+
+    /// Without `alloc` feature root directory could only be taken once
     pub async fn root_directory(&mut self) -> Result<RootDirectory<E, IO>, Error<E>> {
         let io = self.io.clone();
-        RootDirectory::new(io, self.fat_info, self.fs_info, self.root).await
+        #[cfg(not(feature = "alloc"))]
+        let slots = self.slots.take().ok_or(OperationError::AlreadyOpen)?;
+        RootDirectory::new(
+            io,
+            self.fat_info,
+            self.fs_info,
+            self.root,
+            #[cfg(not(feature = "alloc"))]
+            slots,
+        )
+        .await
     }
 
+    /// Write percent-in-use left behind by `PercentInuseUpdate::Delta`, flush deferred writes
+    /// and clear volume dirty flag, then return IO.
+    /// Every file and directory including root directory must be closed beforehand,
+    /// otherwise fails with `AlreadyOpen`, volume is returned along with error anyway.
+    #[cfg(feature = "alloc")]
+    pub async fn unmount(mut self) -> Result<IO, (Self, Error<E>)> {
+        if sync::Ref::strong_count(&self.io) > 1 {
+            return Err((self, OperationError::AlreadyOpen.into()));
+        }
+        if let Err(error) = self.finalize().await {
+            return Err((self, error));
+        }
+        match try_unwrap!(self.io) {
+            Ok(io) => Ok(io.unwrap()),
+            Err(io) => {
+                let ExFAT { serial_number, revision, fat_info, fs_info, root, .. } = self;
+                let exfat = Self { io, serial_number, revision, fat_info, fs_info, root };
+                Err((exfat, OperationError::AlreadyOpen.into()))
+            }
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    async fn finalize(&mut self) -> Result<(), Error<E>> {
+        if acquire!(self.io).forensic.is_none() {
+            let percent_inuse = acquire!(self.io).drifted_percent_inuse.take();
+            if let Some(percent_inuse) = percent_inuse {
+                acquire!(self.io).write_percent_inuse(percent_inuse).await?;
+            }
+            if self.is_dirty().await? {
+                self.set_dirty(false).await?;
+            }
+        }
+        acquire!(self.io).flush_deferred().await
+    }
+
+    #[cfg(feature = "alloc")]
     pub fn try_free(self) -> Result<IO, Self> {
-        let ExFAT { io, serial_number, fat_info, fs_info, root } = self;
+        let ExFAT { io, serial_number, revision, fat_info, fs_info, root } = self;
         match try_unwrap!(io) {
             Ok(io) => Ok(io.unwrap()),
-            Err(io) => Err(Self { io, serial_number, fat_info, fs_info, root }),
+            Err(io) => Err(Self { io, serial_number, revision, fat_info, fs_info, root }),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std", not(feature = "async")))]
+mod test {
+    use crate::error::{Error, OperationError};
+    use crate::fixture::Image;
+    use crate::FileOrDirectory;
+
+    #[test]
+    fn test_percent_inuse_update() {
+        use crate::volume::PercentInuseUpdate;
+
+        let image = Image::new("exfat-test-percent-inuse.img");
+        let mut exfat = image.mount();
+        let initial = exfat.percent_inuse().unwrap();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        let data = vec![0x5Au8; 1 << 20];
+        for (name, update) in
+            [("never", PercentInuseUpdate::Never), ("flush", PercentInuseUpdate::OnFlush)]
+        {
+            exfat.change_options(|options| options.percent_inuse_update = update);
+            directory.create(name, false).unwrap();
+            let entryset = directory.find(name).unwrap().unwrap();
+            let mut file = match directory.open(&entryset).unwrap() {
+                FileOrDirectory::File(file) => file,
+                FileOrDirectory::Directory(_) => unreachable!(),
+            };
+            file.write_all(&data).unwrap();
+            // Not available since allocation changed
+            assert_eq!(exfat.percent_inuse().unwrap(), 0xFF);
+            file.sync_all().unwrap();
+        }
+        // Deferred percentage written by flush counts both files
+        assert_eq!(exfat.percent_inuse().unwrap(), initial + 12);
+    }
+
+    #[test]
+    fn test_flush_policy() {
+        use crate::volume::FlushPolicy;
+
+        let image = Image::new("exfat-test-flush-policy.img");
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        exfat.change_options(|options| options.flush_policy = FlushPolicy::OnSync);
+        let flushes = exfat.stats().flushes;
+        for i in 0..8 {
+            directory.create(&format!("file{}", i), false).unwrap();
+        }
+        let entryset = directory.find("file0").unwrap().unwrap();
+        directory.delete(&entryset).unwrap();
+        assert_eq!(exfat.stats().flushes, flushes);
+        exfat.flush().unwrap();
+        assert_eq!(exfat.stats().flushes, flushes + 1);
+        // Nothing deferred since last flush
+        exfat.flush().unwrap();
+        assert_eq!(exfat.stats().flushes, flushes + 1);
+
+        exfat.change_options(|options| options.flush_policy = FlushPolicy::DirtyWrites(16));
+        for i in 8..24 {
+            directory.create(&format!("file{}", i), false).unwrap();
         }
+        let num_flushes = exfat.stats().flushes - flushes - 1;
+        assert!(num_flushes > 0 && num_flushes < 16);
+    }
+
+    #[test]
+    fn test_unmount() {
+        use crate::volume::PercentInuseUpdate;
+
+        let image = Image::new("exfat-test-unmount.img");
+        let mut exfat = image.mount();
+        exfat
+            .change_options(|options| options.percent_inuse_update = PercentInuseUpdate::Delta(50));
+        exfat.set_dirty(true).unwrap();
+        let initial = exfat.percent_inuse().unwrap();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("file", false).unwrap();
+        let entryset = directory.find("file").unwrap().unwrap();
+        match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(mut file) => file.write_all(&vec![0u8; 1 << 20]).unwrap(),
+            FileOrDirectory::Directory(_) => unreachable!(),
+        };
+        drop(root);
+        let exfat = match exfat.unmount() {
+            Err((exfat, Error::Operation(OperationError::AlreadyOpen))) => exfat,
+            _ => panic!("Unmounted with directory opened"),
+        };
+        drop(directory);
+        exfat.unmount().map_err(|(_, e)| e).unwrap();
+
+        let mut exfat = image.mount();
+        assert!(!exfat.is_dirty().unwrap());
+        // Drift below threshold written by unmount
+        assert_eq!(exfat.percent_inuse().unwrap(), initial + 6);
+    }
+
+    #[test]
+    fn test_revalidate() {
+        let image = Image::new("exfat-test-revalidate.img");
+        let mut exfat = image.mount();
+        let initial = exfat.percent_inuse().unwrap();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        let data = vec![0xA5u8; 1 << 20];
+        let write = |directory: &mut crate::Directory<_, _>, name: &str| {
+            directory.create(name, false).unwrap();
+            let entryset = directory.find(name).unwrap().unwrap();
+            match directory.open(&entryset).unwrap() {
+                FileOrDirectory::File(mut file) => file.write_all(&data).unwrap(),
+                FileOrDirectory::Directory(_) => unreachable!(),
+            };
+        };
+        write(&mut directory, "mine");
+        assert!(!exfat.revalidate().unwrap());
+
+        // Same volume mounted elsewhere meanwhile
+        let mut other = image.mount();
+        let mut other_root = other.root_directory().unwrap();
+        write(&mut other_root.open().unwrap(), "theirs");
+        drop(other_root);
+        drop(other);
+
+        assert!(exfat.revalidate().unwrap());
+        assert!(!exfat.revalidate().unwrap());
+        assert!(directory.find("theirs").unwrap().is_some());
+        write(&mut directory, "mine-again");
+        // Allocation counters reloaded, clusters allocated elsewhere are counted
+        assert_eq!(exfat.percent_inuse().unwrap(), initial + 18);
+    }
+
+    #[test]
+    fn test_set_serial_number() {
+        use crate::region::boot::BootChecksum;
+
+        let image = Image::new("exfat-test-serial-number.img");
+        let mut exfat = image.mount();
+        exfat.set_serial_number(0x12345678).unwrap();
+        exfat.validate_checksum().unwrap();
+        drop(exfat);
+
+        let bytes = std::fs::read(image.path()).unwrap();
+        for base in [0, 12] {
+            let region: Vec<&[u8]> = bytes[base * 512..(base + 12) * 512].chunks(512).collect();
+            assert_eq!(region[0][100..104], 0x12345678u32.to_le_bytes());
+            let mut checksum = BootChecksum::default();
+            for (index, sector) in region[..11].iter().enumerate() {
+                checksum.write(index, sector);
+            }
+            let sum = checksum.sum().to_le_bytes();
+            assert!(region[11].chunks(4).all(|chunk| chunk == sum));
+        }
+        let exfat = image.mount();
+        assert_eq!(exfat.serial_number(), 0x12345678);
+    }
+
+    #[test]
+    fn test_newer_revision() {
+        use std::io::{Seek, Write};
+
+        use crate::error::ImplementationError;
+
+        let image = Image::new("exfat-test-newer-revision.img");
+        let exfat = image.mount();
+        assert_eq!(exfat.revision(), (1, 0));
+        drop(exfat);
+        let set_revision = |revision: u16| {
+            let mut file = std::fs::File::options().write(true).open(image.path()).unwrap();
+            file.seek(std::io::SeekFrom::Start(104)).unwrap();
+            file.write_all(&revision.to_le_bytes()).unwrap();
+        };
+
+        set_revision(0x0101);
+        let mut exfat = image.mount();
+        assert_eq!(exfat.revision(), (1, 1));
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        assert!(directory.find("file").unwrap().is_none());
+        match directory.create("file", false) {
+            Err(Error::Implementation(ImplementationError::NewerRevision)) => (),
+            _ => panic!("Written to volume of newer revision"),
+        }
+        exfat.change_options(|options| options.write_newer_revision = true);
+        directory.create("file", false).unwrap();
+        drop(directory);
+        drop(root);
+        drop(exfat);
+
+        set_revision(0x0200);
+        match super::ExFAT::new(image.open()) {
+            Err(Error::Implementation(ImplementationError::Revision)) => (),
+            _ => panic!("Mounted volume of unsupported major revision"),
+        }
+    }
+
+    #[test]
+    fn test_active_fat() {
+        use crate::error::InputError;
+
+        let image = Image::new("exfat-test-active-fat.img");
+        let mut exfat = image.mount();
+        assert_eq!((exfat.active_fat(), exfat.number_of_fats()), (0, 1));
+        match exfat.set_active_fat(1) {
+            Err(Error::Input(InputError::FATIndex)) => (),
+            _ => panic!("Switched to FAT not exists"),
+        }
+        let root = exfat.root_directory().unwrap();
+        match exfat.set_active_fat(0) {
+            Err(Error::Operation(OperationError::AlreadyOpen)) => (),
+            _ => panic!("Switched FAT with root directory opened"),
+        }
+        drop(root);
+        exfat.set_active_fat(0).unwrap();
+    }
+
+    #[test]
+    fn test_two_fats() {
+        use crate::error::ImplementationError;
+
+        let image = Image::with_two_fats("exfat-test-two-fats.img");
+        let mut exfat = image.mount();
+        assert_eq!((exfat.active_fat(), exfat.number_of_fats()), (0, 2));
+        let mut bitmaps = Vec::new();
+        for index in 0..2 {
+            exfat.set_active_fat(index).unwrap();
+            let bitmap = exfat.root_directory().unwrap().allocation_bitmap();
+            assert_eq!(bitmap.bitmap_flags & 1, index);
+            bitmaps.push(bitmap);
+        }
+        assert_ne!(bitmaps[0].first_cluster, bitmaps[1].first_cluster);
+        let boot_sector = exfat.boot_sector().unwrap();
+        drop(exfat);
+
+        let sector_size = 1usize << boot_sector.bytes_per_sector_shift;
+        let cluster_size = sector_size << boot_sector.sectors_per_cluster_shift;
+        let fat_length = boot_sector.fat_length as usize * sector_size;
+        let fats: Vec<_> = (0..2)
+            .map(|index| boot_sector.fat_offset as usize * sector_size + index * fat_length)
+            .map(|offset| offset..offset + fat_length)
+            .collect();
+        let heap_offset = boot_sector.cluster_heap_offset as usize * sector_size;
+        let clusters: Vec<_> = bitmaps
+            .iter()
+            .map(|bitmap| {
+                heap_offset + (u32::from(bitmap.first_cluster) as usize - 2) * cluster_size
+            })
+            .map(|offset| offset..offset + cluster_size)
+            .collect();
+
+        // Root directory grows across clusters, which goes through FAT 1 and bitmap 1 only
+        let before = std::fs::read(image.path()).unwrap();
+        let mut exfat = image.mount();
+        assert_eq!(exfat.active_fat(), 1);
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        for i in 0..cluster_size / 32 {
+            directory.create(&format!("file-{}", i), false).unwrap();
+        }
+        drop(directory);
+        let report = root.check(false).unwrap();
+        assert!(report.is_consistent(), "{:?}", report);
+        drop(root);
+        drop(exfat);
+        let after = std::fs::read(image.path()).unwrap();
+        assert_eq!(before[fats[0].clone()], after[fats[0].clone()]);
+        assert_ne!(before[fats[1].clone()], after[fats[1].clone()]);
+        assert_eq!(before[clusters[0].clone()], after[clusters[0].clone()]);
+        assert_ne!(before[clusters[1].clone()], after[clusters[1].clone()]);
+
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        assert!(directory.find(&format!("file-{}", cluster_size / 32 - 1)).unwrap().is_some());
+        drop(directory);
+        drop(root);
+        match exfat.resize(boot_sector.volume_length / 2) {
+            Err(Error::Implementation(ImplementationError::TexFATNotSupported)) => (),
+            _ => panic!("Resized volume with two FATs"),
+        }
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let image = Image::new("exfat-test-reconnect.img");
+        let mut exfat = image.mount();
+        exfat.change_options(|options| options.degrade_on_io_error = true);
+        let mut root = exfat.root_directory().unwrap();
+        let mut directory = root.open().unwrap();
+        directory.create("a", false).unwrap();
+        exfat.flush().unwrap();
+
+        // Media pulled out
+        let backup = Image::empty("exfat-test-reconnect.bak", 0);
+        std::fs::copy(image.path(), backup.path()).unwrap();
+        std::fs::OpenOptions::new().write(true).open(image.path()).unwrap().set_len(0).unwrap();
+        assert!(matches!(directory.create("b", false), Err(Error::IO(_))));
+        assert!(exfat.is_degraded());
+        let result = directory.create("b", false);
+        assert!(matches!(result, Err(Error::Operation(OperationError::Degraded))));
+
+        // Another volume inserted
+        let other = Image::new("exfat-test-reconnect-other.img");
+        other.mount().set_serial_number(exfat.serial_number() ^ 1).unwrap();
+        let result = exfat.reconnect(other.open());
+        assert!(matches!(result, Err(Error::Data(crate::error::DataError::VolumeChanged))));
+        assert!(exfat.is_degraded());
+
+        // Same media reinserted
+        std::fs::copy(backup.path(), image.path()).unwrap();
+        exfat.reconnect(image.open()).unwrap();
+        assert!(!exfat.is_degraded());
+        assert!(directory.find("a").unwrap().is_some());
+        directory.create("b", false).unwrap();
     }
 }
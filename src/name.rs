@@ -0,0 +1,79 @@
+/// Characters disallowed in file names by exFAT specification, refused by Windows as well
+const ILLEGAL_CHARACTERS: [char; 9] = ['"', '*', '/', ':', '<', '>', '?', '\\', '|'];
+
+/// Device names reserved by Windows regardless of extension
+const DEVICE_NAMES: [&str; 4] = ["CON", "PRN", "AUX", "NUL"];
+
+/// Windows MAX_PATH, including drive prefix like `D:\` and terminating NUL
+const MAX_PATH: usize = 260;
+
+/// Portability problems of a name or path on Windows, see `check`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct NameReport {
+    /// Trailing dot or space, which Windows silently strips
+    pub trailing_dot_or_space: bool,
+    /// Reserved device name like `CON` or `COM1`, with or without extension
+    pub device_name: bool,
+    /// Control character or one of `"*/:<>?\|`
+    pub illegal_character: bool,
+    /// Path exceeds MAX_PATH once placed on a drive like `D:\`
+    pub path_too_long: bool,
+}
+
+impl NameReport {
+    pub fn is_portable(&self) -> bool {
+        !self.trailing_dot_or_space
+            && !self.device_name
+            && !self.illegal_character
+            && !self.path_too_long
+    }
+}
+
+fn is_device_name(name: &str) -> bool {
+    let base = name.split('.').next().unwrap_or(name).trim_end_matches(' ');
+    if DEVICE_NAMES.iter().any(|device| base.eq_ignore_ascii_case(device)) {
+        return true;
+    }
+    let bytes = base.as_bytes();
+    match bytes.len() == 4 && bytes[3].is_ascii_digit() {
+        true => ["COM", "LPT"].iter().any(|prefix| base[..3].eq_ignore_ascii_case(prefix)),
+        false => false,
+    }
+}
+
+/// Check a name or path separated by `/` for names exFAT permits but Windows mishandles,
+/// so that removable media stays portable
+pub fn check(path: &str) -> NameReport {
+    let mut report = NameReport::default();
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        if name.ends_with(['.', ' ']) && name != "." && name != ".." {
+            report.trailing_dot_or_space = true;
+        }
+        report.device_name |= is_device_name(name);
+        let illegal = |ch: char| ch < ' ' || ILLEGAL_CHARACTERS.contains(&ch);
+        report.illegal_character |= name.chars().any(illegal);
+    }
+    let length = path.trim_start_matches('/').encode_utf16().count();
+    report.path_too_long = "D:\\".len() + length + 1 > MAX_PATH;
+    report
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_check() {
+        assert!(super::check("/dir/file.txt").is_portable());
+        assert!(super::check("console.txt").is_portable());
+        assert!(super::check("COM10").is_portable());
+        assert!(super::check("file.").trailing_dot_or_space);
+        assert!(super::check("/dir /file").trailing_dot_or_space);
+        assert!(super::check("con").device_name);
+        assert!(super::check("/dir/Nul.txt").device_name);
+        assert!(super::check("LPT1").device_name);
+        assert!(super::check("a:b").illegal_character);
+        assert!(super::check("a\u{7}").illegal_character);
+        let path = "/".to_owned() + &["a"; 129].join("/");
+        assert!(super::check(&path).path_too_long);
+        assert!(!super::check(&path[..254]).path_too_long);
+    }
+}
@@ -1,8 +1,10 @@
 // Main boot region
 
 use bitfield::bitfield;
+use bytemuck::{Pod, Zeroable};
 
 use crate::endian::Little as LE;
+use crate::volume::BootSectorInfo;
 
 bitfield! {
     #[derive(Copy, Clone, Debug, Default)]
@@ -12,7 +14,7 @@ bitfield! {
     pub active_fat, set_active_fat: 0, 0;
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
 #[repr(C)]
 pub(crate) struct BootSector {
     pub jump_boot: [u8; 3],
@@ -48,6 +50,32 @@ impl BootSector {
     }
 }
 
+impl From<&BootSector> for BootSectorInfo {
+    fn from(boot_sector: &BootSector) -> Self {
+        let revision = boot_sector.filesystem_revision.to_ne().to_be_bytes();
+        Self {
+            jump_boot: boot_sector.jump_boot,
+            filesystem_name: boot_sector.filesystem_name,
+            partition_offset: boot_sector.partition_offset.to_ne(),
+            volume_length: boot_sector.volumn_length.to_ne(),
+            fat_offset: boot_sector.fat_offset.to_ne(),
+            fat_length: boot_sector.fat_length.to_ne(),
+            cluster_heap_offset: boot_sector.cluster_heap_offset.to_ne(),
+            cluster_count: boot_sector.cluster_count.to_ne(),
+            first_cluster_of_root_directory: boot_sector.first_cluster_of_root_directory.to_ne(),
+            volume_serial_number: boot_sector.volumn_serial_number.to_ne(),
+            filesystem_revision: (revision[0], revision[1]),
+            volume_flags: boot_sector.volume_flags.to_ne(),
+            bytes_per_sector_shift: boot_sector.bytes_per_sector_shift,
+            sectors_per_cluster_shift: boot_sector.sectors_per_cluster_shift,
+            number_of_fats: boot_sector.number_of_fats,
+            drive_select: boot_sector.drive_select,
+            percent_inuse: boot_sector.percent_inuse,
+            boot_signature: boot_sector.boot_signature,
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 pub(crate) struct BootChecksum(u32);
 
@@ -75,16 +103,25 @@ mod test {
         use std::io::Read;
         use std::process::Command as CMD;
 
-        let args = ["-s", "4194304", "test.img"];
+        let path = std::env::temp_dir().join("exfat-test-mkfs-exfat.img");
+        let path = path.to_str().unwrap();
+        let args = ["-s", "4194304", path];
         let output = CMD::new("truncate").args(args).output().unwrap();
         assert!(output.status.success());
-        let output = CMD::new("mkfs.exfat").args(["test.img"]).output().unwrap();
+        let output = match CMD::new("mkfs.exfat").args([path]).output() {
+            Ok(output) => output,
+            Err(_) => {
+                println!("mkfs.exfat not available, skipped");
+                CMD::new("rm").args(["-f", path]).output().unwrap();
+                return;
+            }
+        };
         assert!(output.status.success());
 
-        let mut file = std::fs::File::open("test.img").unwrap();
+        let mut file = std::fs::File::open(path).unwrap();
         let mut bytes = [0u8; 512];
         file.read(&mut bytes).unwrap();
-        let boot_sector: super::BootSector = unsafe { std::mem::transmute(bytes) };
+        let boot_sector: super::BootSector = bytemuck::cast(bytes);
         let mut checksum = super::BootChecksum::default();
         checksum.write(0, &bytes);
         for i in 1..11 {
@@ -93,9 +130,23 @@ mod test {
         }
         let mut bytes = [0u8; 4];
         file.read(&mut bytes).unwrap();
-        CMD::new("rm").args(["-f", "test.img"]).output().unwrap();
+        CMD::new("rm").args(["-f", path]).output().unwrap();
         assert_eq!(u32::from_le_bytes(bytes), checksum.sum());
         println!("{:?} {:?}", boot_sector.jump_boot, boot_sector.filesystem_name);
         assert!(boot_sector.is_exfat());
     }
+
+    #[cfg(all(feature = "std", not(feature = "async")))]
+    #[test]
+    fn test_boot_sector_info() {
+        let image = crate::fixture::Image::new("exfat-test-boot-sector-info.img");
+        let bytes = std::fs::read(image.path()).unwrap();
+        let info = crate::volume::BootSectorInfo::decode(bytes[..512].try_into().unwrap());
+        assert!(info.is_exfat());
+        assert_eq!(info.volume_length, crate::fixture::IMAGE_SIZE / 512);
+        assert_eq!(info.filesystem_revision, (1, 0));
+        assert_eq!(info.bytes_per_sector_shift, 9);
+        let mut exfat = image.mount();
+        assert_eq!(exfat.boot_sector().unwrap(), info);
+    }
 }
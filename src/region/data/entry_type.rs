@@ -1,3 +1,4 @@
+use bytemuck::{Pod, Zeroable};
 use derive_more::{From, Into};
 
 #[derive(Copy, Clone, PartialEq)]
@@ -55,7 +56,8 @@ impl TryFrom<u8> for EntryType {
     }
 }
 
-#[derive(Copy, Clone, Default, Debug, From, Into)]
+#[derive(Copy, Clone, Default, Debug, From, Into, Pod, Zeroable)]
+#[repr(transparent)]
 pub(crate) struct RawEntryType(u8);
 
 impl RawEntryType {
@@ -72,7 +74,22 @@ impl RawEntryType {
         EntryType::try_from(self.0 & 0x7F)
     }
 
+    /// Unrecognized benign entries are allowed to be ignored
+    pub(crate) fn is_benign(&self) -> bool {
+        self.0 & 0x20 > 0
+    }
+
     pub(crate) fn is_end_of_directory(&self) -> bool {
         self.0 == 0
     }
+
+    /// Inuse allocation bitmap, upcase table or volume label entry of root directory,
+    /// volume becomes unusable if any of them is overwritten
+    pub(crate) fn is_critical(&self) -> bool {
+        let critical = matches!(
+            self.entry_type(),
+            Ok(EntryType::AllocationBitmap | EntryType::UpcaseTable | EntryType::VolumnLabel)
+        );
+        self.in_use() && critical
+    }
 }
@@ -4,35 +4,40 @@ pub(crate) mod secondary;
 pub(crate) const ENTRY_SIZE: usize = 32;
 pub(crate) type RawEntry = [u8; ENTRY_SIZE];
 
-use core::mem::transmute;
-
 use super::entry_type::{EntryType, RawEntryType};
 use primary::{Checksum, FileDirectory};
 use secondary::{Secondary, StreamExtension};
 
-pub(crate) fn checksum(fd: &FileDirectory, ext: &Secondary<StreamExtension>, name: &str) -> u16 {
+/// Checksum of file directory and stream extension entry, to be continued with filename entries
+pub(crate) fn primary_checksum(fd: &FileDirectory, ext: &Secondary<StreamExtension>) -> Checksum {
     let mut checksum = Checksum::new();
-    let array: &[u8; ENTRY_SIZE] = unsafe { transmute(fd) };
+    let array: &[u8; ENTRY_SIZE] = bytemuck::cast_ref(fd);
     for (i, &value) in array.iter().enumerate() {
         if i == 2 || i == 3 {
             continue;
         }
         checksum.write(value as u16);
     }
-    let array: &[u8; ENTRY_SIZE] = unsafe { transmute(ext) };
+    let array: &[u8; ENTRY_SIZE] = bytemuck::cast_ref(ext);
     for &value in array.iter() {
         checksum.write(value as u16);
     }
+    checksum
+}
+
+pub(crate) fn checksum(fd: &FileDirectory, ext: &Secondary<StreamExtension>, name: &str) -> u16 {
+    let mut checksum = primary_checksum(fd, ext);
     let entry_type = RawEntryType::new(EntryType::Filename, true);
-    for (i, ch) in name.chars().enumerate() {
+    let mut name_length = 0;
+    for (i, unit) in name.encode_utf16().enumerate() {
         if i % 15 == 0 {
             checksum.write(u8::from(entry_type) as u16);
             checksum.write(0);
         }
-        checksum.write(ch as u8 as u16);
-        checksum.write(ch as u16 >> 8);
+        unit.to_le_bytes().iter().for_each(|&byte| checksum.write(byte as u16));
+        name_length += 1;
     }
-    for _ in 0..(15 - name.chars().count() % 15) * 2 {
+    for _ in 0..(15 - name_length % 15) % 15 * 2 {
         checksum.write(0);
     }
     checksum.sum()
@@ -1,4 +1,5 @@
 use bitfield::bitfield;
+use bytemuck::{Pod, Zeroable};
 #[cfg(all(feature = "chrono", feature = "std"))]
 use chrono::Local;
 #[cfg(feature = "chrono")]
@@ -70,6 +71,40 @@ impl Timestamp {
     }
 }
 
+#[cfg(feature = "time")]
+impl Timestamp {
+    fn time_with_millis(&self, millis: u16) -> Result<time::PrimitiveDateTime, ()> {
+        let month = time::Month::try_from(self.month() as u8).map_err(|_| ())?;
+        let date = time::Date::from_calendar_date(self.year() as i32, month, self.day() as u8);
+        let (hour, minute, second) = (self.hour() as u8, self.minute() as u8, self.second() as u8);
+        let second = second + (millis / 1000) as u8;
+        let time = time::Time::from_hms_milli(hour, minute, second, millis % 1000);
+        Ok(time::PrimitiveDateTime::new(date.map_err(|_| ())?, time.map_err(|_| ())?))
+    }
+}
+
+#[cfg(feature = "time")]
+impl TryFrom<Timestamp> for time::PrimitiveDateTime {
+    type Error = ();
+    fn try_from(timestamp: Timestamp) -> Result<Self, ()> {
+        timestamp.time_with_millis(0)
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<time::PrimitiveDateTime> for Timestamp {
+    fn from(datetime: time::PrimitiveDateTime) -> Self {
+        let mut timestamp = Self::default();
+        timestamp.set_year(datetime.year() as u32);
+        timestamp.set_month(u8::from(datetime.month()) as u32);
+        timestamp.set_day(datetime.day() as u32);
+        timestamp.set_hour(datetime.hour() as u32);
+        timestamp.set_minute(datetime.minute() as u32);
+        timestamp.set_second(datetime.second() as u32);
+        timestamp
+    }
+}
+
 bitfield! {
     #[derive(Copy, Clone, Default, Debug, Into)]
     pub struct FileAttributes(u16);
@@ -92,7 +127,8 @@ impl FileAttributes {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
+#[repr(transparent)]
 pub struct UTCOffset(u8);
 
 impl UTCOffset {
@@ -149,6 +185,28 @@ impl DateTime {
     }
 }
 
+#[cfg(feature = "time")]
+impl TryFrom<DateTime> for time::OffsetDateTime {
+    type Error = ();
+    fn try_from(datetime: DateTime) -> Result<Self, ()> {
+        let utc = datetime.timestamp.time_with_millis(datetime.millisecond)?.assume_utc();
+        let seconds = datetime.utc_offset.minutes() as i32 * 60;
+        let offset = time::UtcOffset::from_whole_seconds(seconds).map_err(|_| ())?;
+        Ok(utc.to_offset(offset))
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<time::OffsetDateTime> for DateTime {
+    fn from(datetime: time::OffsetDateTime) -> Self {
+        let utc_offset = UTCOffset::new((datetime.offset().whole_seconds() / 60) as i16);
+        let utc = datetime.to_offset(time::UtcOffset::UTC);
+        let millisecond = (utc.second() % 2) as u16 * 1000 + utc.millisecond();
+        let naive = time::PrimitiveDateTime::new(utc.date(), utc.time());
+        Self { timestamp: naive.into(), millisecond, utc_offset }
+    }
+}
+
 #[cfg(feature = "chrono")]
 impl<TZ: chrono::Offset + chrono::TimeZone> From<chrono::DateTime<TZ>> for DateTime {
     fn from(datetime: chrono::DateTime<TZ>) -> Self {
@@ -161,7 +219,7 @@ impl<TZ: chrono::Offset + chrono::TimeZone> From<chrono::DateTime<TZ>> for DateT
     }
 }
 
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, Default, Debug, Pod, Zeroable)]
 #[repr(C, packed(1))]
 pub struct FileDirectory {
     pub(crate) entry_type: RawEntryType,
@@ -181,6 +239,10 @@ pub struct FileDirectory {
 }
 
 impl FileDirectory {
+    pub(crate) fn reserved_clear(&self) -> bool {
+        self._reserved1 == [0; 2] && self._reserved2 == [0; 7]
+    }
+
     pub(crate) fn new(secondary_count: u8, directory: bool) -> Self {
         let now = DateTime::now();
         let timestamp: LE<u32> = u32::from(now.timestamp).into();
@@ -249,7 +311,8 @@ impl Checksum {
     }
 
     pub(crate) fn write(&mut self, value: u16) {
-        self.0 = if self.0 & 1 > 0 { 0x8000 } else { 0 } + (self.0 >> 1) + value
+        self.0 = if self.0 & 1 > 0 { 0x8000 } else { 0 } + (self.0 >> 1);
+        self.0 = self.0.wrapping_add(value)
     }
 
     pub(crate) fn sum(&self) -> u16 {
@@ -259,9 +322,27 @@ impl Checksum {
 
 pub(crate) fn name_hash(name: &str) -> u16 {
     let mut checksum = Checksum::new();
-    for ch in name.chars() {
-        checksum.write(ch as u16);
-        checksum.write(((ch as u32) >> 16) as u16);
+    for unit in name.encode_utf16() {
+        unit.to_le_bytes().iter().for_each(|&byte| checksum.write(byte as u16));
     }
     checksum.sum()
 }
+
+#[cfg(all(test, feature = "time"))]
+mod test {
+    use super::DateTime;
+
+    #[test]
+    fn test_time_round_trip() {
+        let date = time::Date::from_calendar_date(2024, time::Month::March, 9).unwrap();
+        let offset = time::UtcOffset::from_hms(8, 0, 0).unwrap();
+        for second in [46, 47] {
+            let time = time::Time::from_hms_milli(12, 34, second, 560).unwrap();
+            let datetime = time::PrimitiveDateTime::new(date, time).assume_offset(offset);
+            let encoded = DateTime::from(datetime);
+            assert_eq!(encoded.timestamp.second(), 46);
+            assert_eq!(encoded.millisecond, (second as u16 - 46) * 1000 + 560);
+            assert_eq!(time::OffsetDateTime::try_from(encoded), Ok(datetime));
+        }
+    }
+}
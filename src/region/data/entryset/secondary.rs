@@ -1,9 +1,12 @@
-use core::{fmt::Debug, mem::MaybeUninit};
+use core::fmt::Debug;
+
+use bytemuck::{Pod, Zeroable};
 
 use super::super::entry_type::RawEntryType;
 use crate::{endian::Little as LE, region::data::entry_type::EntryType};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(transparent)]
 pub struct GeneralSecondaryFlags(u8);
 
 impl Default for GeneralSecondaryFlags {
@@ -30,9 +33,9 @@ impl GeneralSecondaryFlags {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Pod, Zeroable)]
 #[repr(C, packed(1))]
-pub struct Secondary<T: Default> {
+pub struct Secondary<T> {
     pub(crate) entry_type: RawEntryType,
     pub(crate) general_secondary_flags: GeneralSecondaryFlags,
     pub(crate) custom_defined: T,
@@ -40,7 +43,7 @@ pub struct Secondary<T: Default> {
     pub(crate) data_length: LE<u64>,
 }
 
-impl<T: Default> Secondary<T> {
+impl<T: Default + Pod> Secondary<T> {
     pub fn new(custom_defined: T) -> Self {
         Self {
             entry_type: RawEntryType::new(EntryType::StreamExtension, true),
@@ -50,13 +53,15 @@ impl<T: Default> Secondary<T> {
     }
 }
 
-impl<T: Sized + Default> Clone for Secondary<T> {
+impl<T: Pod> Clone for Secondary<T> {
     fn clone(&self) -> Self {
-        unsafe { core::ptr::read(self) }
+        *self
     }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+impl<T: Pod> Copy for Secondary<T> {}
+
+#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
 #[repr(C, packed(1))]
 pub struct StreamExtension {
     _reserved1: u8,
@@ -71,14 +76,18 @@ impl StreamExtension {
     pub fn new(name_length: u8, name_hash: u16) -> Self {
         Self { name_length, name_hash: name_hash.into(), ..Default::default() }
     }
+
+    pub(crate) fn reserved_clear(&self) -> bool {
+        self._reserved1 == 0 && self._reserved2 == [0; 2] && self._reserved3 == [0; 4]
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
-#[repr(C, packed(2))]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
 pub(crate) struct Filename {
     pub entry_type: RawEntryType,
     general_secondary_flags: u8,
-    pub filename: MaybeUninit<[u16; 15]>,
+    pub filename: [LE<u16>; 15],
 }
 
 impl Default for Filename {
@@ -86,7 +95,7 @@ impl Default for Filename {
         Self {
             entry_type: RawEntryType::new(EntryType::Filename, true),
             general_secondary_flags: 0,
-            filename: MaybeUninit::uninit(),
+            filename: [LE::default(); 15],
         }
     }
 }
@@ -3,10 +3,12 @@ pub(crate) mod entryset;
 
 use core::fmt::Debug;
 
+use bytemuck::{Pod, Zeroable};
+
 use crate::endian::Little as LE;
-use entry_type::RawEntryType;
+use entry_type::{EntryType, RawEntryType};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
 #[repr(C)]
 pub(crate) struct AllocationBitmap {
     pub entry_type: RawEntryType,
@@ -16,7 +18,7 @@ pub(crate) struct AllocationBitmap {
     pub data_length: LE<u64>,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
 #[repr(C)]
 pub(crate) struct UpcaseTable {
     pub entry_type: RawEntryType,
@@ -28,7 +30,7 @@ pub(crate) struct UpcaseTable {
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
 pub(crate) struct VolumnLabel {
     pub entry_type: RawEntryType,
     pub character_count: u8,
@@ -36,12 +38,28 @@ pub(crate) struct VolumnLabel {
     _reserved: [u8; 8],
 }
 
+impl VolumnLabel {
+    pub fn new(label: &str) -> Self {
+        let mut volumn_label = [LE::default(); 11];
+        for (i, ch) in label.chars().take(volumn_label.len()).enumerate() {
+            volumn_label[i] = (ch as u16).into();
+        }
+        Self {
+            entry_type: RawEntryType::new(EntryType::VolumnLabel, !label.is_empty()),
+            character_count: volumn_label.len().min(label.chars().count()) as u8,
+            volumn_label,
+            _reserved: [0; 8],
+        }
+    }
+}
+
 impl Into<heapless::String<22>> for VolumnLabel {
     fn into(self) -> heapless::String<22> {
         let mut label: heapless::String<22> = heapless::String::new();
-        for i in 0..self.character_count {
-            let ch = self.volumn_label[i as usize].to_ne();
-            label.push(unsafe { char::from_u32_unchecked(ch as u32) }).ok();
+        let count = (self.character_count as usize).min(self.volumn_label.len());
+        let units = self.volumn_label[..count].iter().map(|ch| ch.to_ne());
+        for ch in char::decode_utf16(units) {
+            label.push(ch.unwrap_or(char::REPLACEMENT_CHARACTER)).ok();
         }
         label
     }
@@ -10,3 +10,17 @@ pub(crate) mod fat;
 
 /// Data region
 pub(crate) mod data;
+
+// On-disk structures are accessed at arbitrary offsets of sector buffers,
+// which faults on strict alignment targets unless they are byte aligned
+const _: () = {
+    use core::mem::align_of;
+    assert!(align_of::<boot::BootSector>() == 1);
+    assert!(align_of::<data::AllocationBitmap>() == 1);
+    assert!(align_of::<data::UpcaseTable>() == 1);
+    assert!(align_of::<data::VolumnLabel>() == 1);
+    assert!(align_of::<data::entryset::primary::FileDirectory>() == 1);
+    use data::entryset::secondary::{Filename, Secondary, StreamExtension};
+    assert!(align_of::<Secondary<StreamExtension>>() == 1);
+    assert!(align_of::<Filename>() == 1);
+};
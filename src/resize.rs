@@ -0,0 +1,477 @@
+use memoffset::offset_of;
+
+use crate::cluster_heap::root::CriticalEntries;
+use crate::error::{AllocationError, DataError, Error, ImplementationError, InputError};
+use crate::fat;
+use crate::fs::{self, SectorRef};
+use crate::io::IOWrapper;
+use crate::region::boot::BootSector;
+use crate::region::data::entryset::ENTRY_SIZE;
+use crate::region::data::AllocationBitmap;
+use crate::types::{ClusterID, SectorID};
+
+const MAX_CLUSTER_COUNT: u64 = 0xFFFFFFF5;
+
+/// Write zeros to bytes in range [from, to) of consecutive sectors starting from base
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+async fn fill_zero<E, IO: crate::io::IO<Error = E>>(
+    io: &mut IOWrapper<IO>,
+    base: SectorID,
+    sector_size: u64,
+    from: u64,
+    to: u64,
+) -> Result<(), Error<E>> {
+    let zeros = [0u8; 512];
+    let mut offset = from;
+    while offset < to {
+        let sector_offset = offset % sector_size;
+        let length = (zeros.len() as u64 - sector_offset % 512).min(to - offset);
+        let zeros = &zeros[..length as usize];
+        io.write(base + offset / sector_size, sector_offset as usize, zeros).await?;
+        offset += length;
+    }
+    Ok(())
+}
+
+/// Allocation bitmap located by resize, clusters beyond `num_clusters` are considered free
+struct Bitmap {
+    base: SectorID,
+    sector_size: u32,
+    num_clusters: u32,
+}
+
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+impl Bitmap {
+    async fn byte<E, IO: crate::io::IO<Error = E>>(
+        &self,
+        io: &mut IOWrapper<IO>,
+        byte_offset: u32,
+    ) -> Result<u8, Error<E>> {
+        if byte_offset >= self.num_clusters.div_ceil(8) {
+            return Ok(0);
+        }
+        let sector = io.read(self.base + byte_offset / self.sector_size).await?;
+        let byte = sector[(byte_offset % self.sector_size) as usize];
+        let num_bits = (self.num_clusters - byte_offset * 8).min(8);
+        Ok(byte & (u16::MAX >> (16 - num_bits)) as u8)
+    }
+
+    async fn set<E, IO: crate::io::IO<Error = E>>(
+        &self,
+        io: &mut IOWrapper<IO>,
+        index: u32,
+        allocated: bool,
+    ) -> Result<(), Error<E>> {
+        let (sector_id, offset) =
+            (self.base + index / 8 / self.sector_size, index / 8 % self.sector_size);
+        let mut byte = io.read(sector_id).await?[offset as usize];
+        match allocated {
+            true => byte |= 1 << (index % 8),
+            false => byte &= !(1 << (index % 8)),
+        }
+        io.write(sector_id, offset as usize, &[byte]).await
+    }
+
+    /// First allocated cluster index in range [from, to)
+    async fn find_allocated<E, IO: crate::io::IO<Error = E>>(
+        &self,
+        io: &mut IOWrapper<IO>,
+        from: u32,
+        to: u32,
+    ) -> Result<Option<u32>, Error<E>> {
+        let mut index = from;
+        while index < to.min(self.num_clusters) {
+            let byte = self.byte(io, index / 8).await?;
+            if byte == 0 && index.is_multiple_of(8) {
+                index += 8;
+                continue;
+            }
+            if byte & (1 << (index % 8)) != 0 {
+                return Ok(Some(index));
+            }
+            index += 1;
+        }
+        Ok(None)
+    }
+
+    /// Last allocated cluster index below `to`
+    async fn find_last_allocated<E, IO: crate::io::IO<Error = E>>(
+        &self,
+        io: &mut IOWrapper<IO>,
+        to: u32,
+    ) -> Result<Option<u32>, Error<E>> {
+        let mut index = to.min(self.num_clusters);
+        while index > 0 {
+            let byte = self.byte(io, (index - 1) / 8).await?;
+            if byte == 0 && index.is_multiple_of(8) {
+                index -= 8;
+                continue;
+            }
+            if byte & (1 << ((index - 1) % 8)) != 0 {
+                return Ok(Some(index - 1));
+            }
+            index -= 1;
+        }
+        Ok(None)
+    }
+
+    /// First run of specified number of free clusters below `limit`
+    async fn find_free_run<E, IO: crate::io::IO<Error = E>>(
+        &self,
+        io: &mut IOWrapper<IO>,
+        count: u32,
+        limit: u32,
+    ) -> Result<Option<u32>, Error<E>> {
+        let (mut start, mut index) = (0, 0);
+        while index < limit && index - start < count {
+            let byte = self.byte(io, index / 8).await?;
+            match byte {
+                u8::MAX if index.is_multiple_of(8) => (start, index) = (index + 8, index + 8),
+                0 if index.is_multiple_of(8) => index += 8,
+                _ if byte & (1 << (index % 8)) != 0 => (start, index) = (index + 1, index + 1),
+                _ => index += 1,
+            }
+        }
+        Ok((limit.saturating_sub(start) >= count).then_some(start))
+    }
+
+    async fn count_allocated<E, IO: crate::io::IO<Error = E>>(
+        &self,
+        io: &mut IOWrapper<IO>,
+    ) -> Result<u32, Error<E>> {
+        let mut count = 0;
+        for byte_offset in 0..self.num_clusters.div_ceil(8) {
+            count += self.byte(io, byte_offset).await?.count_ones();
+        }
+        Ok(count)
+    }
+}
+
+struct Fat {
+    offset: u32,
+    sector_size: u32,
+}
+
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+impl Fat {
+    async fn set<E, IO: crate::io::IO<Error = E>>(
+        &self,
+        io: &mut IOWrapper<IO>,
+        cluster_id: u32,
+        value: u32,
+    ) -> Result<(), Error<E>> {
+        let (byte_offset, sector_size) = (cluster_id as u64 * 4, self.sector_size as u64);
+        let sector_id = SectorID::from(self.offset as u64 + byte_offset / sector_size);
+        let offset = (byte_offset % sector_size) as usize;
+        io.write(sector_id, offset, &value.to_le_bytes()).await
+    }
+
+    /// Chain specified number of contiguous clusters
+    async fn chain<E, IO: crate::io::IO<Error = E>>(
+        &self,
+        io: &mut IOWrapper<IO>,
+        first_cluster: u32,
+        count: u32,
+    ) -> Result<(), Error<E>> {
+        for cluster_id in first_cluster..first_cluster + count {
+            let next = match cluster_id + 1 < first_cluster + count {
+                true => cluster_id + 1,
+                false => 0xFFFFFFFF,
+            };
+            self.set(io, cluster_id, next).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Range of volume length in sectors accepted by `ExFAT::resize`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ResizeLimits {
+    /// Clusters in use would be cut off below it
+    pub min_sectors: u64,
+    /// Cluster heap stays where it is, so FAT could only grow into room left before
+    /// cluster heap, which runs out beyond it
+    pub max_sectors: u64,
+}
+
+/// Volume regions resize works on
+struct Volume {
+    boot_sector: BootSector,
+    root_sector: SectorID,
+    /// Index of allocation bitmap entry in first sector of root directory
+    entry_index: usize,
+    entry: AllocationBitmap,
+    bitmap: Bitmap,
+}
+
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+impl Volume {
+    async fn load<E, IO: crate::io::IO<Error = E>>(
+        io: &mut IOWrapper<IO>,
+        fs_info: fs::Info,
+        root: ClusterID,
+    ) -> Result<Self, Error<E>> {
+        let sector = io.read(0.into()).await?;
+        let boot_sector: BootSector = *bytemuck::from_bytes(&sector[..512]);
+        if boot_sector.number_of_fats > 1 {
+            return Err(ImplementationError::TexFATNotSupported.into());
+        }
+        let root_sector = SectorRef::new(root, 0).id(&fs_info);
+        let sector = io.read(root_sector).await?;
+        let active_fat = boot_sector.volume_flags().active_fat() as u8;
+        let option = CriticalEntries::lookup(sector, active_fat).allocation_bitmap;
+        let (entry_index, entry) = option.ok_or(DataError::AllocationBitmapMissing)?;
+        let base = SectorRef::new(entry.first_cluster.to_ne().into(), 0).id(&fs_info);
+        let sector_size = fs_info.sector_size() as u32;
+        let num_clusters = boot_sector.cluster_count.to_ne();
+        let bitmap = Bitmap { base, sector_size, num_clusters };
+        Ok(Self { boot_sector, root_sector, entry_index, entry, bitmap })
+    }
+
+    /// Number of clusters FAT room before cluster heap could address
+    fn max_clusters(&self, fs_info: fs::Info) -> u32 {
+        let fat_room = fs_info.heap_offset as u64 - self.boot_sector.fat_offset.to_ne() as u64;
+        let num_entries = fat_room * fs_info.sector_size() as u64 / 4;
+        num_entries.saturating_sub(2).min(MAX_CLUSTER_COUNT) as u32
+    }
+}
+
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+pub(crate) async fn limits<E, IO: crate::io::IO<Error = E>>(
+    io: &mut IOWrapper<IO>,
+    fs_info: fs::Info,
+    root: ClusterID,
+) -> Result<ResizeLimits, Error<E>> {
+    let volume = Volume::load(io, fs_info, root).await?;
+    let num_clusters = volume.bitmap.num_clusters;
+    let option = volume.bitmap.find_last_allocated(io, num_clusters).await?;
+    let (heap_offset, shift) = (fs_info.heap_offset as u64, fs_info.sectors_per_cluster_shift);
+    let min_sectors = heap_offset + (option.map(|index| index as u64 + 1).unwrap_or(0) << shift);
+    let max_clusters = volume.max_clusters(fs_info) as u64;
+    let max_sectors = heap_offset + ((max_clusters + 1) << shift) - 1;
+    Ok(ResizeLimits { min_sectors, max_sectors })
+}
+
+/// Grow or shrink volume in place, see `ExFAT::resize`, returns FAT info with new FAT length
+#[cfg_attr(not(feature = "async"), deasync::deasync)]
+pub(crate) async fn resize<E, IO: crate::io::IO<Error = E>>(
+    io: &mut IOWrapper<IO>,
+    fs_info: fs::Info,
+    root: ClusterID,
+    num_sectors: u64,
+) -> Result<fat::Info, Error<E>> {
+    let volume = Volume::load(io, fs_info, root).await?;
+    let max_clusters = volume.max_clusters(fs_info);
+    let Volume { boot_sector, root_sector, entry_index, mut entry, mut bitmap } = volume;
+    let sector_size = fs_info.sector_size() as u64;
+    let cluster_size = fs_info.cluster_size() as u64;
+    let fat_offset = boot_sector.fat_offset.to_ne();
+    let heap_offset = fs_info.heap_offset as u64;
+    let old_count = boot_sector.cluster_count.to_ne();
+    let option = num_sectors.checked_sub(heap_offset);
+    let num_clusters = option.ok_or(InputError::Size)? >> fs_info.sectors_per_cluster_shift;
+    if num_clusters > max_clusters as u64 {
+        return Err(InputError::Size.into());
+    }
+    let num_clusters = num_clusters as u32;
+    let fat_length = ((num_clusters as u64 + 2) * 4).div_ceil(sector_size);
+    let fat_length = boot_sector.fat_length.to_ne().max(fat_length as u32);
+    debug!("Resize from {} to {} clusters", old_count, num_clusters);
+
+    let first_cluster = entry.first_cluster.to_ne();
+    let data_length = entry.data_length.to_ne();
+    let base = bitmap.base;
+    let length = (num_clusters as u64).div_ceil(8);
+    let old_span = data_length.div_ceil(cluster_size) as u32;
+    let span = length.div_ceil(cluster_size) as u32;
+    if num_clusters < old_count {
+        if let Some(index) = bitmap.find_allocated(io, num_clusters, old_count).await? {
+            debug!("Cluster {} beyond new end still in use", index + 2);
+            return Err(AllocationError::ClustersInUse.into());
+        }
+    }
+    // Bitmap is extended in place if clusters following it are free, otherwise relocated
+    let mut new_first_cluster = first_cluster;
+    if span > old_span {
+        let (from, to) = (first_cluster - 2 + old_span, first_cluster - 2 + span);
+        let extendable = to <= num_clusters && bitmap.find_allocated(io, from, to).await?.is_none();
+        if !extendable {
+            let option = bitmap.find_free_run(io, span, num_clusters).await?;
+            new_first_cluster = option.ok_or(AllocationError::NoMoreCluster)? + 2;
+            debug!("Relocate allocation bitmap to cluster {}", new_first_cluster);
+        }
+    }
+
+    // Left dirty if interrupted halfway
+    let volume_flags = boot_sector.volume_flags();
+    let mut dirty = volume_flags;
+    dirty.set_volume_dirty(1);
+    io.write_volume_flags(dirty.0).await?;
+    io.flush().await?;
+
+    let fat = Fat { offset: fat_offset, sector_size: sector_size as u32 };
+    if num_clusters > old_count {
+        let fat_base = SectorID::from(fat_offset as u64);
+        let (from, to) = ((old_count as u64 + 2) * 4, (num_clusters as u64 + 2) * 4);
+        fill_zero(io, fat_base, sector_size, from, to).await?;
+    }
+    if new_first_cluster != first_cluster {
+        let mut buf = [0u8; 4096];
+        let buf = &mut buf[..sector_size as usize];
+        let new_base = SectorRef::new(new_first_cluster.into(), 0).id(&fs_info);
+        let num_sectors = data_length.div_ceil(sector_size);
+        for i in 0..num_sectors {
+            buf.copy_from_slice(io.read(base + i).await?);
+            io.write(new_base + i, 0, buf).await?;
+        }
+        bitmap.base = new_base;
+    }
+    if num_clusters > old_count {
+        // Bits beyond cluster count are not necessarily zero
+        for index in old_count..old_count.next_multiple_of(8).min(num_clusters) {
+            bitmap.set(io, index, false).await?;
+        }
+        let from = old_count.div_ceil(8) as u64;
+        fill_zero(io, bitmap.base, sector_size, from, length).await?;
+    }
+    bitmap.num_clusters = num_clusters;
+    let new_index = new_first_cluster - 2;
+    for i in 0..span.max(old_span) {
+        if new_first_cluster != first_cluster && i < old_span {
+            bitmap.set(io, first_cluster - 2 + i, false).await?;
+            fat.set(io, first_cluster + i, 0).await?;
+        }
+        match i < span {
+            true => bitmap.set(io, new_index + i, true).await?,
+            false => {
+                bitmap.set(io, new_index + i, false).await?;
+                fat.set(io, new_first_cluster + i, 0).await?;
+            }
+        }
+    }
+    fat.chain(io, new_first_cluster, span).await?;
+
+    entry.first_cluster = new_first_cluster.into();
+    entry.data_length = length.into();
+    let bytes: [u8; ENTRY_SIZE] = bytemuck::cast(entry);
+    io.write(root_sector, entry_index * ENTRY_SIZE, &bytes).await?;
+
+    let percent_inuse = match num_clusters {
+        0 => 0,
+        _ => (bitmap.count_allocated(io).await? as u64 * 100 / num_clusters as u64) as u8,
+    };
+    for base in [0u64, 12] {
+        let offset = offset_of!(BootSector, volumn_length);
+        io.write(base.into(), offset, &num_sectors.to_le_bytes()).await?;
+        let offset = offset_of!(BootSector, fat_length);
+        io.write(base.into(), offset, &fat_length.to_le_bytes()).await?;
+        let offset = offset_of!(BootSector, cluster_count);
+        io.write(base.into(), offset, &num_clusters.to_le_bytes()).await?;
+        let offset = offset_of!(BootSector, percent_inuse);
+        io.write(base.into(), offset, &[percent_inuse]).await?;
+        io.write_boot_checksum(base).await?;
+    }
+    io.boot_fields.1 = percent_inuse;
+    io.write_volume_flags(volume_flags.0).await?;
+    io.flush().await?;
+    Ok(fat::Info::new(fs_info.sector_size_shift, fat_offset, fat_length, 1))
+}
+
+#[cfg(all(test, feature = "std", not(feature = "async")))]
+mod test {
+    use crate::error::{Error, OperationError};
+    use crate::fixture::Image;
+    use crate::FileOrDirectory;
+
+    #[test]
+    fn test_resize() {
+        // Cluster heap aligned to 4K leaves FAT room for more clusters, while allocation
+        // bitmap of 32767 clusters fills up its only cluster
+        let num_sectors = 288 + 32767 * 8;
+        let image =
+            Image::with_options("exfat-test-resize.img", num_sectors << 9, &Default::default());
+        let mut exfat = image.mount();
+        let mut root = exfat.root_directory().unwrap();
+        let bitmap = root.allocation_bitmap();
+        let mut directory = root.open().unwrap();
+        directory.create("a", false).unwrap();
+        let entryset = directory.find("a").unwrap().unwrap();
+        match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(mut file) => file.write_all(&[0xA5; 65536]).unwrap(),
+            FileOrDirectory::Directory(_) => unreachable!(),
+        }
+        let result = exfat.resize(num_sectors + 800);
+        assert!(matches!(result, Err(Error::Operation(OperationError::AlreadyOpen))));
+        drop(directory);
+        drop(root);
+
+        let result = exfat.resize(num_sectors * 2);
+        assert!(matches!(result, Err(Error::Input(crate::error::InputError::Size))));
+        // Outgrown allocation bitmap is relocated since upcase table follows it
+        exfat.resize(num_sectors + 800).unwrap();
+        exfat.validate_checksum().unwrap();
+        let mut root = exfat.root_directory().unwrap();
+        assert_ne!(root.allocation_bitmap().first_cluster, bitmap.first_cluster);
+        assert_eq!(root.allocation_bitmap().data_length, (32767 + 100u64).div_ceil(8));
+        assert!(root.check(false).unwrap().is_consistent());
+        let mut directory = root.open().unwrap();
+        let entryset = directory.find("a").unwrap().unwrap();
+        match directory.open(&entryset).unwrap() {
+            FileOrDirectory::File(mut file) => {
+                let mut buf = vec![0; 65536];
+                assert_eq!(file.read(&mut buf).unwrap(), buf.len());
+                assert!(buf.iter().all(|&byte| byte == 0xA5));
+            }
+            FileOrDirectory::Directory(_) => unreachable!(),
+        }
+        drop(directory);
+        drop(root);
+
+        let result = exfat.resize(288 + 8 * 8);
+        use crate::error::AllocationError;
+        assert!(matches!(result, Err(Error::Allocation(AllocationError::ClustersInUse))));
+        exfat.resize(num_sectors).unwrap();
+        drop(exfat);
+
+        let mut exfat = image.mount();
+        exfat.validate_checksum().unwrap();
+        let mut root = exfat.root_directory().unwrap();
+        assert_eq!(root.allocation_bitmap().data_length, bitmap.data_length);
+        assert!(root.check(false).unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_resize_limits() {
+        use crate::error::{AllocationError, InputError};
+        use crate::{format, FormatOptions};
+
+        let (num_sectors, max_num_sectors) = (32768, 131072);
+        let image = Image::empty("exfat-test-resize-limits.img", max_num_sectors << 9);
+        let options =
+            FormatOptions { max_num_sectors: Some(max_num_sectors), ..Default::default() };
+        format(&mut image.open(), num_sectors, &options).unwrap();
+        let mut exfat = image.mount();
+        let limits = exfat.resize_limits().unwrap();
+        assert!(limits.max_sectors >= max_num_sectors);
+        assert!(limits.min_sectors > 0 && limits.min_sectors < num_sectors);
+
+        let result = exfat.resize(limits.max_sectors + 1);
+        assert!(matches!(result, Err(Error::Input(InputError::Size))));
+        exfat.resize(max_num_sectors).unwrap();
+        exfat.validate_checksum().unwrap();
+        let mut root = exfat.root_directory().unwrap();
+        assert!(root.check(false).unwrap().is_consistent());
+        drop(root);
+
+        let limits = exfat.resize_limits().unwrap();
+        let result = exfat.resize(limits.min_sectors - 1);
+        assert!(matches!(result, Err(Error::Allocation(AllocationError::ClustersInUse))));
+        exfat.resize(limits.min_sectors).unwrap();
+        drop(exfat);
+
+        let mut exfat = image.mount();
+        exfat.validate_checksum().unwrap();
+        let mut root = exfat.root_directory().unwrap();
+        assert!(root.check(false).unwrap().is_consistent());
+    }
+}
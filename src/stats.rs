@@ -0,0 +1,21 @@
+/// Per-volume counters accumulated since mount, see `ExFAT::stats`
+#[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Stats {
+    /// Number of sectors read from IO
+    pub sectors_read: u64,
+    /// Number of read calls issued to IO, consecutive sectors read at once count once
+    pub reads: u64,
+    /// Number of sector writes issued to IO, including partial sector writes
+    pub sectors_written: u64,
+    /// Number of flushes issued to IO
+    pub flushes: u64,
+    /// Number of name lookups resolved by directory lookup cache
+    pub cache_hits: u64,
+    /// Number of name lookups which had to walk directory while lookup cache enabled
+    pub cache_misses: u64,
+    /// Number of clusters allocated
+    pub allocations: u64,
+    /// Number of clusters released
+    pub frees: u64,
+}
@@ -0,0 +1,96 @@
+use core::ops::{Deref, DerefMut};
+
+use crate::cluster_heap::context::{Context, MAX_OPENED_ENTRIES};
+use crate::cluster_heap::entryset::EntryID;
+use crate::io::IOWrapper;
+use crate::sync::Cell;
+use crate::types::SectorID;
+use crate::upcase_table::UpcaseTable;
+
+/// State shared between handles of a mounted filesystem when `alloc` feature disabled,
+/// must outlive all handles so typically declared as static, one storage per mount.
+///
+/// Up to `N` files and directories could be opened at the same time,
+/// each of them costs 16B of storage.
+pub struct Storage<IO: crate::io::Lifetime, const N: usize = MAX_OPENED_ENTRIES> {
+    io: Option<Cell<IOWrapper<IO>>>,
+    context: Option<Cell<Context<IO>>>,
+    upcase_table: Option<UpcaseTable>,
+    opened_entries: [(EntryID, usize); N],
+}
+
+impl<IO: crate::io::Lifetime, const N: usize> Storage<IO, N> {
+    pub const fn new() -> Self {
+        let unused = (EntryID { sector_id: SectorID::ZERO, index: 0 }, 0);
+        Self { io: None, context: None, upcase_table: None, opened_entries: [unused; N] }
+    }
+
+    pub(crate) fn split(
+        &'static mut self,
+    ) -> (&'static mut Option<Cell<IOWrapper<IO>>>, Slots<IO>) {
+        let Self { io, context, upcase_table, opened_entries } = self;
+        (io, Slots { context, upcase_table, opened_entries })
+    }
+}
+
+impl<IO: crate::io::Lifetime, const N: usize> Default for Storage<IO, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Storage for root directory, taken when opening root directory
+pub(crate) struct Slots<IO: 'static> {
+    pub context: &'static mut Option<Cell<Context<IO>>>,
+    pub upcase_table: &'static mut Option<UpcaseTable>,
+    pub opened_entries: &'static mut [(EntryID, usize)],
+}
+
+/// Vector backed by a buffer of storage, capacity is length of the buffer
+pub(crate) struct StaticVec<T: 'static> {
+    buffer: &'static mut [T],
+    len: usize,
+}
+
+impl<T: Copy> StaticVec<T> {
+    pub fn new(buffer: &'static mut [T]) -> Self {
+        Self { buffer, len: 0 }
+    }
+
+    /// Gives back the value if buffer is full
+    pub fn insert(&mut self, index: usize, value: T) -> Result<(), T> {
+        if self.len == self.buffer.len() {
+            return Err(value);
+        }
+        self.buffer[self.len] = value;
+        self.buffer[index..=self.len].rotate_right(1);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        self.buffer[index..self.len].rotate_left(1);
+        self.len -= 1;
+        self.buffer[self.len]
+    }
+}
+
+impl<T> Default for StaticVec<T> {
+    fn default() -> Self {
+        Self { buffer: &mut [], len: 0 }
+    }
+}
+
+impl<T> Deref for StaticVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl<T> DerefMut for StaticVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.buffer[..self.len]
+    }
+}
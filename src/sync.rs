@@ -1,34 +1,58 @@
 #[cfg(all(feature = "sync", not(feature = "async"), feature = "std"))]
 pub(crate) use std::sync::Mutex;
 
-#[cfg(all(feature = "sync", feature = "async", feature = "std"))]
+#[cfg(all(feature = "sync", feature = "async-std"))]
 pub(crate) use async_std::sync::Mutex;
-#[cfg(all(feature = "sync", not(feature = "std")))]
+/// Async without `async-std` runtime falls back to spin lock as well
+#[cfg(all(
+    feature = "sync",
+    not(feature = "async-std"),
+    any(feature = "async", not(feature = "std"))
+))]
 pub(crate) use spin::Mutex;
 
 #[cfg(feature = "sync")]
-pub(crate) type Shared<T> = alloc::sync::Arc<Mutex<T>>;
+pub(crate) type Cell<T> = Mutex<T>;
 #[cfg(not(feature = "sync"))]
-pub(crate) type Shared<T> = alloc::rc::Rc<core::cell::RefCell<T>>;
+pub(crate) type Cell<T> = core::cell::RefCell<T>;
 
+#[cfg(feature = "alloc")]
+pub(crate) type Shared<T> = Ref<Cell<T>>;
+#[cfg(not(feature = "alloc"))]
+pub(crate) type Shared<T> = &'static Cell<T>;
+
+/// Shared ownership of immutable data
+#[cfg(all(feature = "alloc", feature = "sync"))]
+pub(crate) type Ref<T> = alloc::sync::Arc<T>;
+#[cfg(all(feature = "alloc", not(feature = "sync")))]
+pub(crate) type Ref<T> = alloc::rc::Rc<T>;
+#[cfg(not(feature = "alloc"))]
+pub(crate) type Ref<T> = &'static T;
+
+#[cfg(feature = "alloc")]
 pub(crate) fn shared<T>(t: T) -> Shared<T> {
-    match () {
-        #[cfg(feature = "sync")]
-        () => alloc::sync::Arc::new(Mutex::new(t)),
-        #[cfg(not(feature = "sync"))]
-        () => alloc::rc::Rc::new(core::cell::RefCell::new(t)),
-    }
+    Ref::new(Cell::new(t))
+}
+
+/// Without `alloc` shared state is placed in static storage provided by user
+#[cfg(not(feature = "alloc"))]
+pub(crate) fn shared<T>(slot: &'static mut Option<Cell<T>>, t: T) -> Shared<T> {
+    slot.insert(Cell::new(t))
 }
 
 #[macro_export]
 macro_rules! acquire {
     ($shared: expr) => {
         match () {
-            #[cfg(all(feature = "sync", feature = "std", feature = "async"))]
+            #[cfg(all(feature = "sync", feature = "async-std"))]
             () => $shared.lock().await,
             #[cfg(all(feature = "sync", feature = "std", not(feature = "async")))]
             () => $shared.lock().unwrap(),
-            #[cfg(all(feature = "sync", not(feature = "std")))]
+            #[cfg(all(
+                feature = "sync",
+                not(feature = "async-std"),
+                any(feature = "async", not(feature = "std"))
+            ))]
             () => $shared.lock(),
             #[cfg(not(feature = "sync"))]
             () => $shared.borrow_mut(),
@@ -40,9 +64,9 @@ macro_rules! acquire {
 macro_rules! try_unwrap {
     ($shared: expr) => {
         match () {
-            #[cfg(all(feature = "sync", any(feature = "async-std", not(feature = "std"))))]
+            #[cfg(all(feature = "sync", any(feature = "async", not(feature = "std"))))]
             () => alloc::sync::Arc::try_unwrap($shared).map(|mutex| mutex.into_inner()),
-            #[cfg(all(feature = "sync", feature = "std", not(feature = "async-std")))]
+            #[cfg(all(feature = "sync", feature = "std", not(feature = "async")))]
             () => alloc::sync::Arc::try_unwrap($shared).map(|mutex| mutex.into_inner().unwrap()),
             #[cfg(not(feature = "sync"))]
             () => alloc::rc::Rc::try_unwrap($shared).map(|cell| cell.into_inner()),
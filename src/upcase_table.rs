@@ -1,37 +1,76 @@
-use core::mem::MaybeUninit;
-
 use crate::file::MAX_FILENAME_SIZE;
 
 pub(crate) struct UpcaseTable(pub [u16; 128]);
 
 impl UpcaseTable {
-    fn lookup(&self, ch: u16) -> u16 {
-        match ch > self.0.len() as u16 {
-            true => ch,
-            false => self.0[ch as usize],
+    fn lookup(&self, ch: char) -> char {
+        match self.0.get(ch as usize) {
+            Some(&upper) => char::from_u32(upper as u32).unwrap_or(ch),
+            None => ch,
         }
     }
 
     pub fn to_upper(&self, name: &str) -> heapless::String<{ MAX_FILENAME_SIZE }> {
         let mut upcase = heapless::String::new();
         for ch in name.chars() {
-            let ch = unsafe { char::from_u32_unchecked(self.lookup(ch as u16) as u32) };
-            upcase.push(ch).ok();
+            upcase.push(self.lookup(ch)).ok();
         }
         upcase
     }
 
+    pub fn char_equals(&self, left: char, right: char) -> bool {
+        self.lookup(left) == self.lookup(right)
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn starts_with(&self, name: &str, prefix: &str) -> bool {
+        let mut chars = name.chars();
+        prefix.chars().all(|ch| chars.next().map(|c| self.char_equals(c, ch)).unwrap_or(false))
+    }
+
     pub fn equals(&self, left: &str, right: &str) -> bool {
         if left.len() != right.len() {
             return false;
         }
         for (left_ch, right_ch) in left.chars().zip(right.chars()) {
-            if self.lookup(left_ch as u16) != self.lookup(right_ch as u16) {
+            if self.lookup(left_ch) != self.lookup(right_ch) {
                 return false;
             }
         }
         true
     }
+
+    /// Upcased name in NFC form, names matching each other have same key
+    #[cfg(all(feature = "alloc", feature = "unicode-normalization"))]
+    pub fn to_key(&self, name: &str) -> heapless::String<{ MAX_FILENAME_SIZE }> {
+        use unicode_normalization::UnicodeNormalization;
+        let mut key = heapless::String::new();
+        for ch in self.to_upper(name).nfc() {
+            key.push(ch).ok();
+        }
+        key
+    }
+
+    /// Upcased name, names matching each other have same key
+    #[cfg(all(feature = "alloc", not(feature = "unicode-normalization")))]
+    pub fn to_key(&self, name: &str) -> heapless::String<{ MAX_FILENAME_SIZE }> {
+        self.to_upper(name)
+    }
+
+    /// Compare names after NFC normalization, case insensitive unless `case_sensitive`
+    #[cfg(feature = "unicode-normalization")]
+    pub fn normalized_equals(&self, left: &str, right: &str, case_sensitive: bool) -> bool {
+        use unicode_normalization::UnicodeNormalization;
+        let (mut left, mut right) = (left.nfc(), right.nfc());
+        loop {
+            match (left.next(), right.next()) {
+                (None, None) => return true,
+                (Some(l), Some(r)) if l == r => (),
+                (Some(l), Some(r)) if !case_sensitive && self.char_equals(l, r) => (),
+                _ => return false,
+            }
+        }
+    }
 }
 
 impl Default for UpcaseTable {
@@ -53,11 +92,6 @@ impl Default for UpcaseTable {
 
 impl From<[crate::endian::Little<u16>; 128]> for UpcaseTable {
     fn from(array: [crate::endian::Little<u16>; 128]) -> Self {
-        let table: MaybeUninit<[u16; 128]> = MaybeUninit::uninit();
-        let mut table = unsafe { table.assume_init() };
-        for i in 0..array.len() {
-            table[i] = array[i].to_ne();
-        }
-        Self(table)
+        Self(array.map(|value| value.to_ne()))
     }
 }
@@ -0,0 +1,151 @@
+use core::sync::atomic::AtomicBool;
+
+use crate::error::Location;
+use crate::region::boot::BootSector;
+
+/// Latest filesystem revision implemented as major and minor, volumes with a newer
+/// minor revision are mounted read only unless `VolumeOptions::write_newer_revision` set
+pub const SUPPORTED_REVISION: (u8, u8) = (1, 0);
+
+/// When percent-in-use field of boot sector is rewritten,
+/// which is informational only but rewriting it frequently wears sector 0 out
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PercentInuseUpdate {
+    /// Whenever percentage changes
+    #[default]
+    OnChange,
+    /// When percentage drifts from the recorded one by at least specified points
+    Delta(u8),
+    /// When IO flushed
+    OnFlush,
+    /// Mark the field as not available, i.e. 0xFF, once allocation changes
+    Never,
+}
+
+/// When writes of operations changing metadata, like `create`, `delete`, `touch`
+/// and cluster allocation, are flushed to IO
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// As soon as each operation finishes
+    #[default]
+    Eager,
+    /// Only when `sync_data`, `sync_all` or `ExFAT::flush` called or a modified handle closed
+    OnSync,
+    /// Once specified number of sector writes are pending
+    DirtyWrites(u32),
+}
+
+/// Whether last accessed timestamp is updated by `touch`,
+/// each update is a metadata write which wears flash out
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AtimePolicy {
+    /// Whenever requested
+    #[default]
+    Always,
+    /// Only if not later than last modified timestamp or from an earlier day, like relatime
+    Relatime,
+    /// Never, like noatime
+    Never,
+}
+
+/// How deviations from specification found while walking through directories are handled,
+/// forensic mode skips damaged entries regardless
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Refuse with `DataError::Metadata` on any deviation, including unrecognized
+    /// benign entries, mismatched set checksum and nonzero reserved bytes
+    Strict,
+    /// Log and continue for recoverable deviations, refuse broken entrysets only
+    #[default]
+    Lenient,
+}
+
+/// Receives location of each damaged entryset skipped, see `VolumeOptions::on_damaged`,
+/// implemented for closures so that reports could be collected into captured state
+pub trait OnDamaged: Sync {
+    fn report(&self, location: Location);
+}
+
+impl<F: Fn(Location) + Sync> OnDamaged for F {
+    fn report(&self, location: Location) {
+        self(location)
+    }
+}
+
+impl core::fmt::Debug for dyn OnDamaged {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("OnDamaged")
+    }
+}
+
+/// Options applied to whole mounted volume, see `ExFAT::change_options`
+#[derive(Copy, Clone, Default, Debug)]
+pub struct VolumeOptions {
+    pub percent_inuse_update: PercentInuseUpdate,
+    pub flush_policy: FlushPolicy,
+    pub atime_policy: AtimePolicy,
+    pub parse_mode: ParseMode,
+    /// Skip damaged entrysets while walking through directories instead of failing with
+    /// `DataError::Metadata`, each of them is reported to this receiver
+    pub on_damaged: Option<&'static dyn OnDamaged>,
+    /// Token polled between sectors by long running operations, i.e. counting allocated
+    /// clusters, `delete_recursive` and `check`, which abort with `OperationError::Cancelled`
+    /// once it is set, leaving volume consistent. Cleared by caller before retrying.
+    pub cancel: Option<&'static AtomicBool>,
+    /// Validate upcase table checksum right before first creating or renaming, which relies
+    /// on upcase table for name hash, instead of reading whole table beforehand.
+    /// Takes effect when root directory opened, skipped if already validated
+    /// by `RootDirectory::validate_upcase_table_checksum`, e.g. called when idle.
+    pub lazy_upcase_validation: bool,
+    /// Write to volume of a newer minor revision anyway
+    pub write_newer_revision: bool,
+    /// Once IO fails, e.g. removable media pulled out, refuse any further IO with
+    /// `OperationError::Degraded` until `ExFAT::reconnect` succeeds
+    pub degrade_on_io_error: bool,
+    /// Fail each IO request stalled longer than this with `Error::IO` of `IO::timed_out`,
+    /// ignored with a warning if IO leaves `IO::timed_out` unimplemented
+    #[cfg(feature = "async-std")]
+    pub io_timeout: Option<core::time::Duration>,
+}
+
+/// Fields of main boot sector, see `BootSectorInfo::decode` and `ExFAT::boot_sector`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BootSectorInfo {
+    pub jump_boot: [u8; 3],
+    pub filesystem_name: [u8; 8],
+    /// Sector offset of partition, 0 when ignored
+    pub partition_offset: u64,
+    /// In sectors
+    pub volume_length: u64,
+    /// In sectors
+    pub fat_offset: u32,
+    /// In sectors
+    pub fat_length: u32,
+    /// In sectors
+    pub cluster_heap_offset: u32,
+    pub cluster_count: u32,
+    pub first_cluster_of_root_directory: u32,
+    pub volume_serial_number: u32,
+    /// Major and minor
+    pub filesystem_revision: (u8, u8),
+    pub volume_flags: u16,
+    pub bytes_per_sector_shift: u8,
+    pub sectors_per_cluster_shift: u8,
+    pub number_of_fats: u8,
+    pub drive_select: u8,
+    pub percent_inuse: u8,
+    pub boot_signature: [u8; 2],
+}
+
+impl BootSectorInfo {
+    /// Decode first 512 bytes of boot sector, which is not necessarily exFAT
+    pub fn decode(bytes: &[u8; 512]) -> Self {
+        let boot_sector: BootSector = bytemuck::pod_read_unaligned(bytes);
+        (&boot_sector).into()
+    }
+
+    pub fn is_exfat(&self) -> bool {
+        self.jump_boot == [0xEB, 0x76, 0x90] && &self.filesystem_name == b"EXFAT   "
+    }
+}